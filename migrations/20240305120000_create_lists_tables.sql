@@ -0,0 +1,22 @@
+-- Add migration script here
+CREATE TABLE lists(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    slug TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
+
+CREATE TABLE list_memberships(
+    subscriber_id uuid NOT NULL REFERENCES subscriptions(id),
+    list_id uuid NOT NULL REFERENCES lists(id),
+    status TEXT NOT NULL,
+    subscribed_at timestamptz NOT NULL,
+    PRIMARY KEY (subscriber_id, list_id)
+);
+
+CREATE TABLE subscription_token_lists(
+    subscription_token TEXT NOT NULL,
+    list_id uuid NOT NULL REFERENCES lists(id),
+    PRIMARY KEY (subscription_token, list_id)
+);
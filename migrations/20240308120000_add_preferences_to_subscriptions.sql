@@ -0,0 +1,12 @@
+-- Add migration script here
+ALTER TABLE subscriptions ADD COLUMN digest_frequency TEXT NOT NULL DEFAULT 'immediate';
+ALTER TABLE subscriptions ADD COLUMN preferences_updated_at timestamptz NULL;
+
+CREATE TABLE preference_changes(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions(id),
+    field TEXT NOT NULL,
+    value TEXT NOT NULL,
+    changed_at timestamptz NOT NULL
+);
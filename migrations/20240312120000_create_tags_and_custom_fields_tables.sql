@@ -0,0 +1,24 @@
+-- Add migration script here
+CREATE TABLE subscriber_tags(
+    subscriber_id uuid NOT NULL REFERENCES subscriptions(id),
+    tag TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY (subscriber_id, tag)
+);
+
+CREATE TABLE custom_field_definitions(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    key TEXT NOT NULL UNIQUE,
+    field_type TEXT NOT NULL CHECK (field_type IN ('string', 'number', 'date', 'boolean')),
+    public BOOLEAN NOT NULL DEFAULT false,
+    created_at timestamptz NOT NULL
+);
+
+CREATE TABLE subscriber_custom_fields(
+    subscriber_id uuid NOT NULL REFERENCES subscriptions(id),
+    field_id uuid NOT NULL REFERENCES custom_field_definitions(id),
+    value TEXT NOT NULL,
+    updated_at timestamptz NOT NULL,
+    PRIMARY KEY (subscriber_id, field_id)
+);
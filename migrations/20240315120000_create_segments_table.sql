@@ -0,0 +1,10 @@
+-- Add migration script here
+ALTER TABLE subscriptions ADD COLUMN last_engaged_at timestamptz NULL;
+
+CREATE TABLE segments(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    name TEXT NOT NULL UNIQUE,
+    expression TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
@@ -0,0 +1,12 @@
+-- Add migration script here
+CREATE TABLE email_events(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    subscriber_id uuid NULL REFERENCES subscriptions (id),
+    email TEXT NOT NULL,
+    event_type TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    occurred_at timestamptz NOT NULL,
+    received_at timestamptz NOT NULL
+);
+CREATE INDEX email_events_subscriber_id_idx ON email_events (subscriber_id);
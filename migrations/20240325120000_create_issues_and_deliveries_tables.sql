@@ -0,0 +1,25 @@
+-- Add migration script here
+CREATE TABLE newsletter_issues(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    title TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    list_id uuid NULL REFERENCES lists (id),
+    segment_id uuid NULL REFERENCES segments (id),
+    published_at timestamptz NOT NULL
+);
+
+CREATE TABLE deliveries(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    issue_id uuid NOT NULL REFERENCES newsletter_issues (id),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    status TEXT NOT NULL CHECK (status IN ('sent', 'failed', 'bounced')),
+    provider_message_id TEXT NULL,
+    error TEXT NULL,
+    attempted_at timestamptz NOT NULL,
+    updated_at timestamptz NOT NULL
+);
+CREATE INDEX deliveries_issue_id_idx ON deliveries (issue_id);
+CREATE INDEX deliveries_provider_message_id_idx ON deliveries (provider_message_id);
@@ -0,0 +1,14 @@
+-- Add migration script here
+ALTER TABLE lists ADD COLUMN open_tracking BOOLEAN NOT NULL DEFAULT TRUE;
+
+ALTER TABLE deliveries ADD COLUMN tracking_token TEXT NULL UNIQUE;
+ALTER TABLE deliveries ADD COLUMN first_opened_at timestamptz NULL;
+
+CREATE TABLE delivery_opens(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    delivery_id uuid NOT NULL REFERENCES deliveries (id),
+    opened_at timestamptz NOT NULL,
+    user_agent TEXT NULL
+);
+CREATE INDEX delivery_opens_delivery_id_idx ON delivery_opens (delivery_id);
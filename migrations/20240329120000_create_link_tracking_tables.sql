@@ -0,0 +1,19 @@
+-- Add migration script here
+CREATE TABLE issue_links(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    issue_id uuid NOT NULL REFERENCES newsletter_issues (id),
+    url TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    UNIQUE (issue_id, url)
+);
+
+CREATE TABLE link_clicks(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    link_id uuid NOT NULL REFERENCES issue_links (id),
+    delivery_id uuid NOT NULL REFERENCES deliveries (id),
+    clicked_at timestamptz NOT NULL,
+    user_agent TEXT NULL
+);
+CREATE INDEX link_clicks_link_id_idx ON link_clicks (link_id);
@@ -0,0 +1,39 @@
+-- Add migration script here
+CREATE TABLE ab_tests(
+    issue_id uuid NOT NULL REFERENCES newsletter_issues (id),
+    PRIMARY KEY (issue_id),
+    metric TEXT NOT NULL CHECK (metric IN ('opens', 'clicks')),
+    sample_percentage INTEGER NOT NULL,
+    decide_at timestamptz NOT NULL,
+    winning_variant_id uuid NULL,
+    decided_at timestamptz NULL
+);
+
+CREATE TABLE ab_test_variants(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    issue_id uuid NOT NULL REFERENCES ab_tests (issue_id),
+    position INTEGER NOT NULL,
+    subject TEXT NOT NULL
+);
+
+ALTER TABLE ab_tests
+    ADD CONSTRAINT ab_tests_winning_variant_id_fkey
+    FOREIGN KEY (winning_variant_id) REFERENCES ab_test_variants (id);
+
+-- Recipients held back until the test is decided.
+CREATE TABLE ab_test_holdouts(
+    issue_id uuid NOT NULL REFERENCES ab_tests (issue_id),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    PRIMARY KEY (issue_id, subscriber_id)
+);
+
+CREATE TABLE issue_delivery_queue(
+    issue_id uuid NOT NULL REFERENCES newsletter_issues (id),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    variant_id uuid NULL REFERENCES ab_test_variants (id),
+    enqueued_at timestamptz NOT NULL,
+    PRIMARY KEY (issue_id, subscriber_id)
+);
+
+ALTER TABLE deliveries ADD COLUMN variant_id uuid NULL REFERENCES ab_test_variants (id);
@@ -0,0 +1,14 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues ADD COLUMN slug TEXT NULL;
+ALTER TABLE newsletter_issues ADD COLUMN visibility TEXT NOT NULL DEFAULT 'public'
+    CHECK (visibility IN ('public', 'subscribers_only', 'hidden'));
+
+-- Existing issues get a slug made unique by their id.
+UPDATE newsletter_issues
+SET slug = COALESCE(
+        NULLIF(trim(both '-' from lower(regexp_replace(left(title, 48), '[^a-zA-Z0-9]+', '-', 'g'))), ''),
+        'issue'
+    ) || '-' || left(id::text, 8);
+
+ALTER TABLE newsletter_issues ALTER COLUMN slug SET NOT NULL;
+ALTER TABLE newsletter_issues ADD CONSTRAINT newsletter_issues_slug_key UNIQUE (slug);
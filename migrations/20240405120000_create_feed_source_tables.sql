@@ -0,0 +1,39 @@
+-- Add migration script here
+CREATE TABLE feed_sources(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    url TEXT NOT NULL UNIQUE,
+    mode TEXT NOT NULL CHECK (mode IN ('draft', 'send')),
+    list_id uuid NULL REFERENCES lists (id),
+    subject_template TEXT NOT NULL,
+    html_template TEXT NOT NULL,
+    text_template TEXT NOT NULL,
+    poll_interval_minutes INTEGER NOT NULL,
+    etag TEXT NULL,
+    last_modified TEXT NULL,
+    last_polled_at timestamptz NULL,
+    -- The first successful fetch only records the items already in the feed.
+    last_fetched_at timestamptz NULL,
+    last_error TEXT NULL,
+    created_at timestamptz NOT NULL
+);
+
+CREATE TABLE newsletter_drafts(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    title TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    list_id uuid NULL REFERENCES lists (id),
+    feed_source_id uuid NULL REFERENCES feed_sources (id),
+    created_at timestamptz NOT NULL
+);
+
+-- Items already turned into a newsletter, or seen on the first poll of a source.
+CREATE TABLE feed_source_items(
+    feed_source_id uuid NOT NULL REFERENCES feed_sources (id),
+    guid TEXT NOT NULL,
+    PRIMARY KEY (feed_source_id, guid),
+    seen_at timestamptz NOT NULL,
+    issue_id uuid NULL REFERENCES newsletter_issues (id)
+);
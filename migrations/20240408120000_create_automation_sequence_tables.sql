@@ -0,0 +1,49 @@
+-- Add migration script here
+CREATE TABLE automation_sequences(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    name TEXT NOT NULL UNIQUE,
+    -- Sequences without a list enrol every confirmed subscriber.
+    list_id uuid NULL REFERENCES lists (id),
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at timestamptz NOT NULL
+);
+
+CREATE TABLE automation_steps(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    sequence_id uuid NOT NULL REFERENCES automation_sequences (id),
+    position INTEGER NOT NULL,
+    -- Counted from enrolment, not from the previous step.
+    delay_minutes INTEGER NOT NULL,
+    subject TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    UNIQUE (sequence_id, position)
+);
+
+CREATE TABLE automation_enrolments(
+    sequence_id uuid NOT NULL REFERENCES automation_sequences (id),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    PRIMARY KEY (sequence_id, subscriber_id),
+    enrolled_at timestamptz NOT NULL,
+    next_step_position INTEGER NOT NULL,
+    next_send_at timestamptz NULL,
+    completed_at timestamptz NULL,
+    exited_at timestamptz NULL,
+    exit_reason TEXT NULL
+);
+
+CREATE INDEX automation_enrolments_due_idx ON automation_enrolments (next_send_at)
+    WHERE completed_at IS NULL AND exited_at IS NULL;
+
+CREATE TABLE automation_deliveries(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    step_id uuid NOT NULL REFERENCES automation_steps (id),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    status TEXT NOT NULL CHECK (status IN ('sent', 'failed')),
+    provider_message_id TEXT NULL,
+    error TEXT NULL,
+    attempted_at timestamptz NOT NULL
+);
@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE issue_delivery_queue ADD COLUMN n_retries INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE issue_delivery_queue ADD COLUMN execute_after timestamptz NOT NULL DEFAULT now();
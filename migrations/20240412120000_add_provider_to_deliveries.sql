@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE deliveries ADD COLUMN provider TEXT NULL;
+ALTER TABLE automation_deliveries ADD COLUMN provider TEXT NULL;
@@ -0,0 +1,10 @@
+-- Add migration script here
+ALTER TABLE subscriptions ADD COLUMN locale TEXT NOT NULL DEFAULT 'en';
+CREATE TABLE issue_translations(
+    issue_id uuid NOT NULL REFERENCES newsletter_issues (id),
+    locale TEXT NOT NULL,
+    title TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    PRIMARY KEY (issue_id, locale)
+);
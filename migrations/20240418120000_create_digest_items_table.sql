@@ -0,0 +1,8 @@
+-- Add migration script here
+-- Issues held back for subscribers who get them in a digest rather than one by one.
+CREATE TABLE digest_items(
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    issue_id uuid NOT NULL REFERENCES newsletter_issues (id),
+    PRIMARY KEY (subscriber_id, issue_id),
+    enqueued_at timestamptz NOT NULL
+);
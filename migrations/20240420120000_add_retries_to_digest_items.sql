@@ -0,0 +1,4 @@
+-- Add migration script here
+-- A digest that could not be sent yet waits before it is retried, and is retried a bounded number of times.
+ALTER TABLE digest_items ADD COLUMN n_retries INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE digest_items ADD COLUMN execute_after timestamptz NOT NULL DEFAULT now();
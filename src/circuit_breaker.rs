@@ -0,0 +1,200 @@
+use std::{sync::Mutex, time::Duration};
+
+use tokio::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests go through.
+    Closed,
+    /// Requests fail fast until the cool-down is over.
+    Open,
+    /// One trial request decides whether to close or open again.
+    HalfOpen,
+}
+
+impl CircuitState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CircuitState::Closed => "closed",
+            CircuitState::Open => "open",
+            CircuitState::HalfOpen => "half_open",
+        }
+    }
+}
+
+impl std::fmt::Display for CircuitState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+/// Opens after `failure_threshold` consecutive failures and stays open for
+/// `cool_down`, after which a single trial request is let through.
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cool_down: Duration,
+    state: Mutex<State>,
+}
+
+enum State {
+    Closed { consecutive_failures: u32 },
+    Open { until: Instant },
+    HalfOpen { trial_started: Instant },
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, cool_down: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            cool_down,
+            state: Mutex::new(State::Closed {
+                consecutive_failures: 0,
+            }),
+        }
+    }
+
+    /// Whether a request may go through now. If not, returns how long until the
+    /// circuit lets a request through again.
+    pub fn try_acquire(&self) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        match *state {
+            State::Closed { .. } => Ok(()),
+            State::Open { until } if now < until => Err(until - now),
+            State::Open { .. } => {
+                tracing::info!("The email provider circuit is half-open, sending a trial request.");
+                *state = State::HalfOpen { trial_started: now };
+                Ok(())
+            }
+            // A trial that never reported back is given up on after a cool-down.
+            State::HalfOpen { trial_started } if now < trial_started + self.cool_down => {
+                Err(trial_started + self.cool_down - now)
+            }
+            State::HalfOpen { .. } => {
+                *state = State::HalfOpen { trial_started: now };
+                Ok(())
+            }
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if !matches!(*state, State::Closed { .. }) {
+            tracing::info!("The email provider circuit is closed again.");
+        }
+        *state = State::Closed {
+            consecutive_failures: 0,
+        };
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        let consecutive_failures = match *state {
+            State::Closed {
+                consecutive_failures,
+            } => consecutive_failures + 1,
+            State::HalfOpen { .. } => self.failure_threshold,
+            State::Open { .. } => return,
+        };
+        if consecutive_failures >= self.failure_threshold {
+            tracing::warn!(
+                consecutive_failures,
+                cool_down_ms = self.cool_down.as_millis() as u64,
+                "The email provider circuit is open, failing fast."
+            );
+            *state = State::Open {
+                until: Instant::now() + self.cool_down,
+            };
+        } else {
+            *state = State::Closed {
+                consecutive_failures,
+            };
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        match *self.state.lock().unwrap() {
+            State::Closed { .. } => CircuitState::Closed,
+            State::Open { until } if Instant::now() >= until => CircuitState::HalfOpen,
+            State::Open { .. } => CircuitState::Open,
+            State::HalfOpen { .. } => CircuitState::HalfOpen,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use claims::{assert_err, assert_ok};
+
+    use crate::circuit_breaker::{CircuitBreaker, CircuitState};
+
+    fn open_breaker() -> CircuitBreaker {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(30));
+        for _ in 0..3 {
+            breaker.record_failure();
+        }
+        breaker
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn the_circuit_opens_after_consecutive_failures() {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(30));
+
+        breaker.record_failure();
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert_ok!(breaker.try_acquire());
+        breaker.record_failure();
+
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert_err!(breaker.try_acquire());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_success_resets_the_failure_count() {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(30));
+
+        breaker.record_failure();
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+        breaker.record_failure();
+
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn an_open_circuit_says_how_long_to_wait() {
+        let breaker = open_breaker();
+        tokio::time::advance(Duration::from_secs(10)).await;
+
+        assert_eq!(breaker.try_acquire(), Err(Duration::from_secs(20)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn after_the_cool_down_a_single_trial_goes_through() {
+        let breaker = open_breaker();
+        tokio::time::advance(Duration::from_secs(30)).await;
+
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert_ok!(breaker.try_acquire());
+        assert_err!(breaker.try_acquire());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn the_trial_outcome_closes_or_reopens_the_circuit() {
+        let breaker = open_breaker();
+        tokio::time::advance(Duration::from_secs(30)).await;
+        breaker.try_acquire().unwrap();
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        tokio::time::advance(Duration::from_secs(30)).await;
+        breaker.try_acquire().unwrap();
+        breaker.record_success();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert_ok!(breaker.try_acquire());
+    }
+}
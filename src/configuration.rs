@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use secrecy::{ExposeSecret, Secret};
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::{
@@ -5,7 +7,14 @@ use sqlx::{
     ConnectOptions,
 };
 
-use crate::domain::SubscriberEmail;
+use crate::{
+    circuit_breaker::CircuitBreaker,
+    dkim::{Canonicalization, DkimError, DkimKey, DkimSigner},
+    domain::SenderIdentity,
+    email_client::{EmailClient, EmailProvider, SenderIdentities},
+    rate_limiter::RateLimiter,
+    smtp::{SmtpRelay, SmtpTls},
+};
 
 enum Environment {
     Local,
@@ -45,16 +54,110 @@ pub struct Settings {
 
 #[derive(serde::Deserialize, Clone)]
 pub struct EmailClientSettings {
+    /// The primary provider, recorded against the messages it sends.
+    pub provider_name: String,
     pub base_url: String,
-    pub sender_email: String,
+    pub sender_identities: Vec<SenderIdentitySettings>,
+    /// The id of the identity confirmations are sent from.
+    pub transactional_sender: String,
+    /// The id of the identity issues are sent from unless they pick another.
+    pub newsletter_sender: String,
     pub api_key_public: Secret<String>,
     pub api_key_private: Secret<String>,
+    /// Tried in order when the providers before them fail with a retryable error,
+    /// send APIs and SMTP relays alike.
+    pub fallback_providers: Vec<EmailProviderSettings>,
     pub timeout_ms: u64,
+    pub webhook_secret: Secret<String>,
+    pub messages_per_second: u32,
+    pub max_concurrent_requests: usize,
+    /// Consecutive failures after which requests fail fast for `circuit_cool_down_ms`.
+    pub circuit_failure_threshold: u32,
+    pub circuit_cool_down_ms: u64,
+    /// Signs mail we relay ourselves, providers sign what they send.
+    pub dkim: Option<DkimSettings>,
 }
 
 impl EmailClientSettings {
-    pub fn sender(&self) -> Result<SubscriberEmail, String> {
-        SubscriberEmail::parse(self.sender_email.clone())
+    /// Each client has its own rate limiters and circuit breakers, build it once
+    /// and share it.
+    pub fn client(self) -> EmailClient {
+        let senders = self
+            .senders()
+            .unwrap_or_else(|e| panic!("Invalid sender identities: {}", e));
+        // Built up front so that a bad key stops the application from starting.
+        let dkim = self.dkim.as_ref().map(|dkim| {
+            Arc::new(
+                dkim.signer()
+                    .unwrap_or_else(|e| panic!("Invalid DKIM settings: {}", e)),
+            )
+        });
+        let timeout = self.timeout();
+        let primary = EmailProviderSettings::Api {
+            name: self.provider_name.clone(),
+            base_url: self.base_url.clone(),
+            api_key_public: self.api_key_public.clone(),
+            api_key_private: self.api_key_private.clone(),
+        };
+        let providers = std::iter::once(&primary)
+            .chain(&self.fallback_providers)
+            .map(|provider| {
+                let rate_limiter =
+                    RateLimiter::new(self.messages_per_second, self.max_concurrent_requests);
+                let circuit_breaker = CircuitBreaker::new(
+                    self.circuit_failure_threshold,
+                    std::time::Duration::from_millis(self.circuit_cool_down_ms),
+                );
+                match provider {
+                    EmailProviderSettings::Api {
+                        name,
+                        base_url,
+                        api_key_public,
+                        api_key_private,
+                    } => EmailProvider::new(
+                        name.clone(),
+                        base_url.clone(),
+                        api_key_public.clone(),
+                        api_key_private.clone(),
+                        rate_limiter,
+                        circuit_breaker,
+                    ),
+                    EmailProviderSettings::Smtp(settings) => {
+                        let mut relay = settings.relay(timeout);
+                        if let Some(signer) = &dkim {
+                            relay = relay.with_dkim(signer.clone());
+                        }
+                        EmailProvider::smtp(
+                            settings.name.clone(),
+                            relay,
+                            rate_limiter,
+                            circuit_breaker,
+                        )
+                    }
+                }
+            })
+            .collect();
+        EmailClient::new(senders, timeout, providers)
+    }
+
+    pub fn senders(&self) -> Result<SenderIdentities, String> {
+        let identities = self
+            .sender_identities
+            .iter()
+            .map(|identity| {
+                SenderIdentity::parse(
+                    identity.id.clone(),
+                    identity.email.clone(),
+                    identity.name.clone(),
+                    identity.reply_to.clone(),
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        SenderIdentities::new(
+            identities,
+            &self.transactional_sender,
+            &self.newsletter_sender,
+        )
     }
 
     pub fn timeout(&self) -> std::time::Duration {
@@ -62,6 +165,78 @@ impl EmailClientSettings {
     }
 }
 
+#[derive(serde::Deserialize, Clone)]
+pub struct SenderIdentitySettings {
+    pub id: String,
+    pub email: String,
+    /// The display name recipients see.
+    pub name: String,
+    pub reply_to: Option<String>,
+}
+
+/// A fallback provider, picked by its `kind`.
+#[derive(serde::Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EmailProviderSettings {
+    /// A Mailjet-compatible send API.
+    Api {
+        name: String,
+        base_url: String,
+        api_key_public: Secret<String>,
+        api_key_private: Secret<String>,
+    },
+    Smtp(SmtpSettings),
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub name: String,
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    pub password: Option<Secret<String>>,
+    /// The name we greet the relay with, usually our own host name.
+    pub hello_name: String,
+}
+
+impl SmtpSettings {
+    pub fn relay(&self, timeout: std::time::Duration) -> SmtpRelay {
+        let credentials = self.username.clone().zip(self.password.clone());
+        SmtpRelay::new(
+            self.host.clone(),
+            self.port,
+            self.tls,
+            credentials,
+            self.hello_name.clone(),
+            timeout,
+        )
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct DkimSettings {
+    pub domain: String,
+    pub selector: String,
+    /// A PEM encoded RSA or Ed25519 private key.
+    pub private_key: Secret<String>,
+    pub header_canonicalization: Canonicalization,
+    pub body_canonicalization: Canonicalization,
+}
+
+impl DkimSettings {
+    pub fn signer(&self) -> Result<DkimSigner, DkimError> {
+        Ok(DkimSigner::new(
+            self.domain.clone(),
+            self.selector.clone(),
+            DkimKey::from_pem(&self.private_key)?,
+            self.header_canonicalization,
+            self.body_canonicalization,
+        ))
+    }
+}
+
 #[derive(serde::Deserialize, Clone)]
 pub struct DatabaseSettings {
     pub username: String,
@@ -103,6 +278,7 @@ pub struct ApplicationSettings {
     pub port: u16,
     pub host: String,
     pub base_url: String,
+    pub hmac_secret: Secret<String>,
 }
 
 impl DatabaseSettings {
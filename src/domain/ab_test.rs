@@ -0,0 +1,155 @@
+/// How the winning subject of an A/B test is chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AbTestMetric {
+    /// Share of the variant's deliveries opened at least once.
+    Opens,
+    /// Share of the variant's deliveries with at least one tracked click.
+    Clicks,
+}
+
+impl AbTestMetric {
+    pub fn parse(s: &str) -> Result<AbTestMetric, String> {
+        match s {
+            "opens" => Ok(AbTestMetric::Opens),
+            "clicks" => Ok(AbTestMetric::Clicks),
+            other => Err(format!(
+                "{} is not a valid A/B test metric. Use either 'opens' or 'clicks'.",
+                other
+            )),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AbTestMetric::Opens => "opens",
+            AbTestMetric::Clicks => "clicks",
+        }
+    }
+}
+
+/// A validated A/B test: the subjects to try on a sample, and when and how to pick one.
+#[derive(Debug, Clone)]
+pub struct AbTest {
+    pub subjects: Vec<String>,
+    pub sample_percentage: u8,
+    pub wait: chrono::Duration,
+    pub metric: AbTestMetric,
+}
+
+impl AbTest {
+    pub const MAX_VARIANTS: usize = 10;
+    pub const MAX_WAIT_MINUTES: u32 = 7 * 24 * 60;
+
+    pub fn parse(
+        subjects: Vec<String>,
+        sample_percentage: u8,
+        wait_minutes: u32,
+        metric: &str,
+    ) -> Result<AbTest, String> {
+        if subjects.len() < 2 || subjects.len() > Self::MAX_VARIANTS {
+            return Err(format!(
+                "An A/B test needs between 2 and {} subjects.",
+                Self::MAX_VARIANTS
+            ));
+        }
+        let subjects: Vec<String> = subjects.into_iter().map(|s| s.trim().to_string()).collect();
+        if subjects.iter().any(|s| s.is_empty()) {
+            return Err("A/B test subjects cannot be empty.".to_string());
+        }
+        if !(1..=99).contains(&sample_percentage) {
+            return Err("The A/B test sample must be between 1 and 99 percent.".to_string());
+        }
+        if !(1..=Self::MAX_WAIT_MINUTES).contains(&wait_minutes) {
+            return Err(format!(
+                "The A/B test wait window must be between 1 and {} minutes.",
+                Self::MAX_WAIT_MINUTES
+            ));
+        }
+
+        Ok(AbTest {
+            subjects,
+            sample_percentage,
+            wait: chrono::Duration::minutes(wait_minutes.into()),
+            metric: AbTestMetric::parse(metric)?,
+        })
+    }
+
+    /// How many of `recipients` receive a variant. Every variant gets at least one
+    /// recipient when there are enough of them.
+    pub fn sample_size(&self, recipients: usize) -> usize {
+        let sample = (recipients * self.sample_percentage as usize).div_ceil(100);
+        sample.max(self.subjects.len()).min(recipients)
+    }
+}
+
+/// Index of the variant with the best engaged/sent ratio. Ties go to the earlier variant.
+pub fn pick_ab_test_winner(results: &[(i64, i64)]) -> usize {
+    let rate = |(engaged, sent): (i64, i64)| {
+        if sent == 0 {
+            0.0
+        } else {
+            engaged as f64 / sent as f64
+        }
+    };
+
+    let mut winner = 0;
+    for (index, result) in results.iter().enumerate() {
+        if rate(*result) > rate(results[winner]) {
+            winner = index;
+        }
+    }
+    winner
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::{pick_ab_test_winner, AbTest, AbTestMetric};
+    use claims::{assert_err, assert_ok};
+
+    fn subjects(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("Subject {}", i)).collect()
+    }
+
+    #[test]
+    fn a_valid_test_is_accepted() {
+        let test = AbTest::parse(subjects(3), 20, 60, "clicks").unwrap();
+
+        assert_eq!(test.metric, AbTestMetric::Clicks);
+        assert_eq!(test.wait, chrono::Duration::hours(1));
+    }
+
+    #[test]
+    fn invalid_tests_are_rejected() {
+        assert_err!(AbTest::parse(subjects(1), 20, 60, "opens"));
+        assert_err!(AbTest::parse(subjects(11), 20, 60, "opens"));
+        assert_err!(AbTest::parse(vec!["A".into(), " ".into()], 20, 60, "opens"));
+        assert_err!(AbTest::parse(subjects(2), 0, 60, "opens"));
+        assert_err!(AbTest::parse(subjects(2), 100, 60, "opens"));
+        assert_err!(AbTest::parse(subjects(2), 20, 0, "opens"));
+        assert_err!(AbTest::parse(subjects(2), 20, 60, "revenue"));
+        assert_ok!(AbTest::parse(
+            subjects(2),
+            99,
+            AbTest::MAX_WAIT_MINUTES,
+            "opens"
+        ));
+    }
+
+    #[test]
+    fn the_sample_is_rounded_up_and_covers_every_variant() {
+        let test = AbTest::parse(subjects(3), 10, 60, "opens").unwrap();
+
+        assert_eq!(test.sample_size(1000), 100);
+        assert_eq!(test.sample_size(101), 11);
+        assert_eq!(test.sample_size(10), 3);
+        assert_eq!(test.sample_size(2), 2);
+        assert_eq!(test.sample_size(0), 0);
+    }
+
+    #[test]
+    fn the_best_rate_wins_and_ties_go_to_the_first_variant() {
+        assert_eq!(pick_ab_test_winner(&[(1, 10), (3, 10), (2, 10)]), 1);
+        assert_eq!(pick_ab_test_winner(&[(1, 2), (5, 10)]), 0);
+        assert_eq!(pick_ab_test_winner(&[(0, 0), (0, 5)]), 0);
+    }
+}
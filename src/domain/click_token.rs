@@ -0,0 +1,113 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// Bytes of the HMAC-SHA256 tag kept in a token.
+const SIGNATURE_LENGTH: usize = 16;
+
+/// Identifies a click on one link of an issue by one recipient. The token in
+/// tracked links is signed, so it cannot be forged to point anywhere else.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClickToken {
+    pub link_id: Uuid,
+    pub delivery_id: Uuid,
+}
+
+impl ClickToken {
+    /// URL-safe base64 of the link id, the delivery id and a truncated signature of both.
+    pub fn sign(&self, key: &Secret<String>) -> String {
+        let mut bytes = self.payload().to_vec();
+        bytes.extend_from_slice(
+            &mac(key, &self.payload()).finalize().into_bytes()[..SIGNATURE_LENGTH],
+        );
+
+        URL_SAFE_NO_PAD.encode(bytes)
+    }
+
+    pub fn verify(token: &str, key: &Secret<String>) -> Result<ClickToken, String> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(token)
+            .map_err(|_| "The click token is not valid base64.".to_string())?;
+        if bytes.len() != 32 + SIGNATURE_LENGTH {
+            return Err("The click token has the wrong length.".to_string());
+        }
+        let (payload, signature) = bytes.split_at(32);
+        mac(key, payload)
+            .verify_truncated_left(signature)
+            .map_err(|_| "The click token signature is invalid.".to_string())?;
+
+        Ok(ClickToken {
+            link_id: Uuid::from_slice(&payload[..16]).expect("16 bytes make a UUID"),
+            delivery_id: Uuid::from_slice(&payload[16..]).expect("16 bytes make a UUID"),
+        })
+    }
+
+    fn payload(&self) -> [u8; 32] {
+        let mut payload = [0; 32];
+        payload[..16].copy_from_slice(self.link_id.as_bytes());
+        payload[16..].copy_from_slice(self.delivery_id.as_bytes());
+        payload
+    }
+}
+
+fn mac(key: &Secret<String>, payload: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size");
+    mac.update(payload);
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::ClickToken;
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use claims::{assert_err, assert_ok_eq};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn key() -> Secret<String> {
+        Secret::new("a-very-secret-key".to_string())
+    }
+
+    fn token() -> ClickToken {
+        ClickToken {
+            link_id: Uuid::new_v4(),
+            delivery_id: Uuid::new_v4(),
+        }
+    }
+
+    #[test]
+    fn a_signed_token_verifies() {
+        let token = token();
+
+        assert_ok_eq!(ClickToken::verify(&token.sign(&key()), &key()), token);
+    }
+
+    #[test]
+    fn a_token_signed_with_another_key_is_rejected() {
+        let signed = token().sign(&Secret::new("another-key".to_string()));
+
+        assert_err!(ClickToken::verify(&signed, &key()));
+    }
+
+    #[test]
+    fn a_tampered_token_is_rejected() {
+        let mut bytes = URL_SAFE_NO_PAD.decode(token().sign(&key())).unwrap();
+        bytes[0] ^= 1;
+
+        assert_err!(ClickToken::verify(&URL_SAFE_NO_PAD.encode(bytes), &key()));
+    }
+
+    #[test]
+    fn malformed_tokens_are_rejected() {
+        for token in ["", "not base64!", "c2hvcnQ"] {
+            assert_err!(
+                ClickToken::verify(token, &key()),
+                "{} should be rejected",
+                token
+            );
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use chrono::NaiveDate;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomFieldKey(String);
+
+impl CustomFieldKey {
+    pub fn parse(s: String) -> Result<CustomFieldKey, String> {
+        let is_empty = s.is_empty();
+        let is_too_long = s.len() > 64;
+        let starts_with_letter = s.chars().next().is_some_and(|c| c.is_ascii_lowercase());
+        let contains_forbidden_characters = s
+            .chars()
+            .any(|c| !(c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'));
+
+        if !(is_empty || is_too_long || contains_forbidden_characters) && starts_with_letter {
+            Ok(CustomFieldKey(s))
+        } else {
+            Err(format!("{} is not a valid custom field key.", s))
+        }
+    }
+}
+
+impl std::fmt::Display for CustomFieldKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl AsRef<str> for CustomFieldKey {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CustomFieldType {
+    String,
+    Number,
+    Date,
+    Boolean,
+}
+
+impl CustomFieldType {
+    pub fn parse(s: &str) -> Result<CustomFieldType, String> {
+        match s {
+            "string" => Ok(CustomFieldType::String),
+            "number" => Ok(CustomFieldType::Number),
+            "date" => Ok(CustomFieldType::Date),
+            "boolean" => Ok(CustomFieldType::Boolean),
+            other => Err(format!("{} is not a valid custom field type.", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CustomFieldType::String => "string",
+            CustomFieldType::Number => "number",
+            CustomFieldType::Date => "date",
+            CustomFieldType::Boolean => "boolean",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CustomFieldValue {
+    String(String),
+    Number(f64),
+    Date(NaiveDate),
+    Boolean(bool),
+}
+
+impl CustomFieldValue {
+    /// Parses a raw value, as found in forms and CSV files, according to the field type.
+    pub fn parse(field_type: CustomFieldType, s: &str) -> Result<CustomFieldValue, String> {
+        let s = s.trim();
+        match field_type {
+            CustomFieldType::String => {
+                if s.chars().count() > 1024 {
+                    Err("Custom field values cannot be longer than 1024 characters.".to_string())
+                } else {
+                    Ok(CustomFieldValue::String(s.to_string()))
+                }
+            }
+            CustomFieldType::Number => s
+                .parse::<f64>()
+                .ok()
+                .filter(|n| n.is_finite())
+                .map(CustomFieldValue::Number)
+                .ok_or_else(|| format!("{} is not a valid number.", s)),
+            CustomFieldType::Date => NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .map(CustomFieldValue::Date)
+                .map_err(|_| format!("{} is not a valid date, expected YYYY-MM-DD.", s)),
+            CustomFieldType::Boolean => match s.to_lowercase().as_str() {
+                "true" | "yes" | "1" => Ok(CustomFieldValue::Boolean(true)),
+                "false" | "no" | "0" => Ok(CustomFieldValue::Boolean(false)),
+                _ => Err(format!("{} is not a valid boolean.", s)),
+            },
+        }
+    }
+
+    pub fn from_json(
+        field_type: CustomFieldType,
+        value: &serde_json::Value,
+    ) -> Result<CustomFieldValue, String> {
+        match (field_type, value) {
+            (CustomFieldType::Number, serde_json::Value::Number(n)) => {
+                Self::parse(field_type, &n.to_string())
+            }
+            (CustomFieldType::Boolean, serde_json::Value::Bool(b)) => {
+                Ok(CustomFieldValue::Boolean(*b))
+            }
+            (_, serde_json::Value::String(s)) => Self::parse(field_type, s),
+            (_, other) => Err(format!(
+                "{} is not a valid {} value.",
+                other,
+                field_type.as_str()
+            )),
+        }
+    }
+
+    /// The canonical text form stored in the database.
+    pub fn to_canonical_string(&self) -> String {
+        match self {
+            CustomFieldValue::String(s) => s.clone(),
+            CustomFieldValue::Number(n) => n.to_string(),
+            CustomFieldValue::Date(d) => d.format("%Y-%m-%d").to_string(),
+            CustomFieldValue::Boolean(b) => b.to_string(),
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            CustomFieldValue::String(s) => serde_json::Value::String(s.clone()),
+            CustomFieldValue::Number(n) => serde_json::Number::from_f64(*n)
+                .map(serde_json::Value::Number)
+                .unwrap_or(serde_json::Value::Null),
+            CustomFieldValue::Date(_) => serde_json::Value::String(self.to_canonical_string()),
+            CustomFieldValue::Boolean(b) => serde_json::Value::Bool(*b),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::{CustomFieldKey, CustomFieldType, CustomFieldValue};
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn keys_must_be_lowercase_identifiers() {
+        assert_ok!(CustomFieldKey::parse("signup_source".to_string()));
+        assert_err!(CustomFieldKey::parse("".to_string()));
+        assert_err!(CustomFieldKey::parse("Company".to_string()));
+        assert_err!(CustomFieldKey::parse("1st_plan".to_string()));
+        assert_err!(CustomFieldKey::parse("plan-name".to_string()));
+    }
+
+    #[test]
+    fn numbers_are_validated() {
+        assert_eq!(
+            CustomFieldValue::parse(CustomFieldType::Number, "12.5"),
+            Ok(CustomFieldValue::Number(12.5))
+        );
+        assert_err!(CustomFieldValue::parse(CustomFieldType::Number, "twelve"));
+        assert_err!(CustomFieldValue::parse(CustomFieldType::Number, "NaN"));
+    }
+
+    #[test]
+    fn dates_must_be_iso_formatted() {
+        assert_ok!(CustomFieldValue::parse(CustomFieldType::Date, "2024-02-29"));
+        assert_err!(CustomFieldValue::parse(CustomFieldType::Date, "2023-02-29"));
+        assert_err!(CustomFieldValue::parse(CustomFieldType::Date, "29/02/2024"));
+    }
+
+    #[test]
+    fn booleans_accept_common_spellings() {
+        for raw in ["true", "YES", "1"] {
+            assert_eq!(
+                CustomFieldValue::parse(CustomFieldType::Boolean, raw),
+                Ok(CustomFieldValue::Boolean(true))
+            );
+        }
+        assert_err!(CustomFieldValue::parse(CustomFieldType::Boolean, "maybe"));
+    }
+
+    #[test]
+    fn json_values_must_match_the_field_type() {
+        let number = serde_json::json!(3);
+        let boolean = serde_json::json!(true);
+
+        assert_ok!(CustomFieldValue::from_json(
+            CustomFieldType::Number,
+            &number
+        ));
+        assert_ok!(CustomFieldValue::from_json(
+            CustomFieldType::Boolean,
+            &boolean
+        ));
+        assert_err!(CustomFieldValue::from_json(CustomFieldType::Date, &number));
+        assert_err!(CustomFieldValue::from_json(
+            CustomFieldType::Number,
+            &boolean
+        ));
+    }
+
+    #[test]
+    fn canonical_strings_round_trip() {
+        for (field_type, raw) in [
+            (CustomFieldType::String, "Acme"),
+            (CustomFieldType::Number, "42"),
+            (CustomFieldType::Date, "2024-03-01"),
+            (CustomFieldType::Boolean, "false"),
+        ] {
+            let value = CustomFieldValue::parse(field_type, raw).unwrap();
+            assert_eq!(value.to_canonical_string(), raw);
+        }
+    }
+}
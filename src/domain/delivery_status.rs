@@ -0,0 +1,55 @@
+/// What happened to a newsletter issue sent to one recipient.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    /// Accepted by the email provider.
+    Sent,
+    /// Rejected by the email provider or never reached it.
+    Failed,
+    /// Accepted, then reported as bounced or blocked by the provider.
+    Bounced,
+}
+
+impl DeliveryStatus {
+    pub const ALL: [DeliveryStatus; 3] = [
+        DeliveryStatus::Sent,
+        DeliveryStatus::Failed,
+        DeliveryStatus::Bounced,
+    ];
+
+    pub fn parse(s: &str) -> Result<DeliveryStatus, String> {
+        match s {
+            "sent" => Ok(DeliveryStatus::Sent),
+            "failed" => Ok(DeliveryStatus::Failed),
+            "bounced" => Ok(DeliveryStatus::Bounced),
+            other => Err(format!("{} is not a valid delivery status.", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Sent => "sent",
+            DeliveryStatus::Failed => "failed",
+            DeliveryStatus::Bounced => "bounced",
+        }
+    }
+}
+
+impl std::fmt::Display for DeliveryStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::DeliveryStatus;
+    use claims::assert_err;
+
+    #[test]
+    fn every_status_round_trips_through_its_string_form() {
+        for status in DeliveryStatus::ALL {
+            assert_eq!(DeliveryStatus::parse(status.as_str()), Ok(status));
+        }
+        assert_err!(DeliveryStatus::parse("delivered"));
+    }
+}
@@ -0,0 +1,67 @@
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DigestFrequency {
+    Immediate,
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl DigestFrequency {
+    pub const ALL: [DigestFrequency; 4] = [
+        DigestFrequency::Immediate,
+        DigestFrequency::Daily,
+        DigestFrequency::Weekly,
+        DigestFrequency::Monthly,
+    ];
+
+    pub fn parse(s: String) -> Result<DigestFrequency, String> {
+        match s.trim().to_lowercase().as_str() {
+            "immediate" => Ok(DigestFrequency::Immediate),
+            "daily" => Ok(DigestFrequency::Daily),
+            "weekly" => Ok(DigestFrequency::Weekly),
+            "monthly" => Ok(DigestFrequency::Monthly),
+            _ => Err(format!("{} is not a valid digest frequency.", s)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DigestFrequency::Immediate => "immediate",
+            DigestFrequency::Daily => "daily",
+            DigestFrequency::Weekly => "weekly",
+            DigestFrequency::Monthly => "monthly",
+        }
+    }
+}
+
+impl std::fmt::Display for DigestFrequency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::DigestFrequency;
+    use claims::assert_err;
+
+    #[test]
+    fn every_frequency_round_trips_through_its_string_form() {
+        for frequency in DigestFrequency::ALL {
+            let parsed = DigestFrequency::parse(frequency.as_str().to_string()).unwrap();
+            assert_eq!(parsed, frequency);
+        }
+    }
+
+    #[test]
+    fn parsing_ignores_case_and_surrounding_whitespace() {
+        let parsed = DigestFrequency::parse(" Weekly ".to_string()).unwrap();
+        assert_eq!(parsed, DigestFrequency::Weekly);
+    }
+
+    #[test]
+    fn unknown_frequencies_are_rejected() {
+        assert_err!(DigestFrequency::parse("hourly".to_string()));
+        assert_err!(DigestFrequency::parse("".to_string()));
+    }
+}
@@ -0,0 +1,169 @@
+use chrono::{DateTime, Utc};
+
+use crate::domain::SubscriptionStatus;
+
+/// A single event from a Mailjet event webhook, e.g.
+/// `{"event": "bounce", "time": 1430812195, "email": "someone@example.com", "hard_bounce": true}`.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct EmailEvent {
+    pub event: EmailEventKind,
+    pub time: i64,
+    pub email: String,
+    /// The id `EmailClient::send_email` returned for the message, if the event is about one.
+    #[serde(rename = "MessageID", default)]
+    pub message_id: Option<u64>,
+    #[serde(default)]
+    pub hard_bounce: bool,
+    #[serde(default)]
+    pub error_related_to: Option<String>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailEventKind {
+    Bounce,
+    Blocked,
+    Spam,
+    Unsub,
+    /// Events we do not act on (sent, open, click, ...).
+    #[serde(other)]
+    Other,
+}
+
+impl EmailEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailEventKind::Bounce => "bounce",
+            EmailEventKind::Blocked => "blocked",
+            EmailEventKind::Spam => "spam",
+            EmailEventKind::Unsub => "unsub",
+            EmailEventKind::Other => "other",
+        }
+    }
+}
+
+impl EmailEvent {
+    /// Bounces and blocks mean the message never reached the recipient, however
+    /// the subscriber's status is affected.
+    pub fn is_delivery_failure(&self) -> bool {
+        matches!(self.event, EmailEventKind::Bounce | EmailEventKind::Blocked)
+    }
+
+    pub fn occurred_at(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp(self.time, 0).unwrap_or_else(Utc::now)
+    }
+
+    /// The status the subscriber should move to, if any. Soft bounces and blocks
+    /// that are not about the recipient address (e.g. our content) are only recorded.
+    pub fn resulting_status(&self) -> Option<SubscriptionStatus> {
+        match self.event {
+            EmailEventKind::Bounce if self.hard_bounce => Some(SubscriptionStatus::Bounced),
+            EmailEventKind::Blocked if self.error_related_to.as_deref() == Some("recipient") => {
+                Some(SubscriptionStatus::Bounced)
+            }
+            EmailEventKind::Spam => Some(SubscriptionStatus::Complained),
+            EmailEventKind::Unsub => Some(SubscriptionStatus::Unsubscribed),
+            _ => None,
+        }
+    }
+
+    /// Why the address must never be emailed again, if it must not: it does not
+    /// exist, or its owner reported us as spam.
+    pub fn suppression_reason(&self) -> Option<&'static str> {
+        match self.resulting_status()? {
+            SubscriptionStatus::Bounced => Some("Hard bounce"),
+            SubscriptionStatus::Complained => Some("Spam complaint"),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::{EmailEvent, EmailEventKind, SubscriptionStatus};
+    use claims::{assert_none, assert_some_eq};
+
+    fn parse(payload: serde_json::Value) -> EmailEvent {
+        serde_json::from_value(payload).unwrap()
+    }
+
+    #[test]
+    fn hard_bounces_mark_the_subscriber_as_bounced() {
+        let event = parse(serde_json::json!({
+            "event": "bounce",
+            "time": 1430812195,
+            "MessageID": 13792286917004336u64,
+            "email": "bounce@mailjet.com",
+            "blocked": false,
+            "hard_bounce": true,
+            "error_related_to": "recipient",
+            "error": "user unknown"
+        }));
+
+        assert_some_eq!(event.resulting_status(), SubscriptionStatus::Bounced);
+        assert_some_eq!(event.suppression_reason(), "Hard bounce");
+    }
+
+    #[test]
+    fn soft_bounces_are_only_recorded() {
+        let event = parse(serde_json::json!({
+            "event": "bounce",
+            "time": 1430812195,
+            "email": "bounce@mailjet.com",
+            "hard_bounce": false,
+            "error_related_to": "mailbox",
+            "error": "quota exceeded"
+        }));
+
+        assert_none!(event.resulting_status());
+        assert_none!(event.suppression_reason());
+    }
+
+    #[test]
+    fn only_recipient_blocks_mark_the_subscriber_as_bounced() {
+        let recipient = parse(serde_json::json!({
+            "event": "blocked",
+            "time": 1430812195,
+            "email": "blocked@mailjet.com",
+            "error_related_to": "recipient",
+            "error": "user unknown"
+        }));
+        let content = parse(serde_json::json!({
+            "event": "blocked",
+            "time": 1430812195,
+            "email": "blocked@mailjet.com",
+            "error_related_to": "content",
+            "error": "spam"
+        }));
+
+        assert_some_eq!(recipient.resulting_status(), SubscriptionStatus::Bounced);
+        assert_none!(content.resulting_status());
+    }
+
+    #[test]
+    fn spam_reports_mark_the_subscriber_as_complained() {
+        let event = parse(serde_json::json!({
+            "event": "spam",
+            "time": 1430812195,
+            "email": "spam@mailjet.com",
+            "source": "JMRPP"
+        }));
+
+        assert_some_eq!(event.resulting_status(), SubscriptionStatus::Complained);
+        assert_some_eq!(event.suppression_reason(), "Spam complaint");
+    }
+
+    #[test]
+    fn unknown_events_are_accepted_and_ignored() {
+        let event = parse(serde_json::json!({
+            "event": "open",
+            "time": 1430812195,
+            "email": "open@mailjet.com"
+        }));
+
+        assert_eq!(event.event, EmailEventKind::Other);
+        assert_none!(event.resulting_status());
+    }
+}
@@ -0,0 +1,484 @@
+use crate::domain::subscriber_email::SubscriberEmail;
+use crate::domain::subscriber_name::SubscriberName;
+
+/// Mailjet accepts at most this many recipients per message, across To, Cc and Bcc.
+pub const MAX_RECIPIENTS: usize = 50;
+/// Mailjet rejects messages whose attachments add up to more than 15 MB.
+pub const MAX_ATTACHMENTS_SIZE: usize = 15 * 1024 * 1024;
+
+/// Headers the provider sets from the message itself.
+const RESERVED_HEADERS: [&str; 9] = [
+    "from",
+    "sender",
+    "to",
+    "cc",
+    "bcc",
+    "reply-to",
+    "subject",
+    "content-type",
+    "content-transfer-encoding",
+];
+
+/// An address with an optional display name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mailbox {
+    pub email: SubscriberEmail,
+    pub name: Option<SubscriberName>,
+}
+
+impl Mailbox {
+    pub fn new(email: SubscriberEmail) -> Self {
+        Self { email, name: None }
+    }
+
+    pub fn named(email: SubscriberEmail, name: SubscriberName) -> Self {
+        Self {
+            email,
+            name: Some(name),
+        }
+    }
+}
+
+/// Who a message is from, picked by `id`, e.g. `noreply` for confirmations and
+/// `editor` for issues.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SenderIdentity {
+    pub id: String,
+    pub from: Mailbox,
+    pub reply_to: Option<Mailbox>,
+}
+
+impl SenderIdentity {
+    pub fn parse(
+        id: String,
+        email: String,
+        name: String,
+        reply_to: Option<String>,
+    ) -> Result<Self, String> {
+        let is_valid_id = !id.is_empty()
+            && id.len() <= 64
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !is_valid_id {
+            return Err(format!("{} is not a valid sender identity id.", id));
+        }
+        let from = Mailbox::named(SubscriberEmail::parse(email)?, SubscriberName::parse(name)?);
+        let reply_to = reply_to
+            .map(SubscriberEmail::parse)
+            .transpose()?
+            .map(Mailbox::new);
+
+        Ok(Self { id, from, reply_to })
+    }
+}
+
+/// A file sent along with the message, or an image the HTML part refers to as
+/// `cid:<content_id>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attachment {
+    filename: String,
+    content_type: String,
+    content: Vec<u8>,
+    content_id: Option<String>,
+}
+
+impl Attachment {
+    pub fn file(filename: String, content_type: String, content: Vec<u8>) -> Result<Self, String> {
+        Ok(Self {
+            filename: parse_filename(filename)?,
+            content_type: parse_content_type(content_type)?,
+            content,
+            content_id: None,
+        })
+    }
+
+    pub fn inline_image(
+        content_id: String,
+        filename: String,
+        content_type: String,
+        content: Vec<u8>,
+    ) -> Result<Self, String> {
+        let content_type = parse_content_type(content_type)?;
+        if !content_type.starts_with("image/") {
+            return Err(format!("{} is not an image content type.", content_type));
+        }
+        let is_valid_content_id = !content_id.is_empty()
+            && content_id.len() <= 255
+            && content_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_.@".contains(c));
+        if !is_valid_content_id {
+            return Err(format!("{} is not a valid content id.", content_id));
+        }
+
+        Ok(Self {
+            filename: parse_filename(filename)?,
+            content_type,
+            content,
+            content_id: Some(content_id),
+        })
+    }
+
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    pub fn content_type(&self) -> &str {
+        &self.content_type
+    }
+
+    pub fn content(&self) -> &[u8] {
+        &self.content
+    }
+
+    /// Set for inline images only.
+    pub fn content_id(&self) -> Option<&str> {
+        self.content_id.as_deref()
+    }
+}
+
+fn parse_filename(filename: String) -> Result<String, String> {
+    let is_empty_or_whitespace = filename.trim().is_empty();
+    let is_too_long = filename.len() > 255;
+    let contains_forbidden_characters = filename
+        .chars()
+        .any(|c| c == '/' || c == '\\' || c.is_control());
+
+    if !(is_empty_or_whitespace || is_too_long || contains_forbidden_characters) {
+        Ok(filename)
+    } else {
+        Err(format!("{} is not a valid attachment filename.", filename))
+    }
+}
+
+/// A `type/subtype` MIME type, lowercased.
+fn parse_content_type(content_type: String) -> Result<String, String> {
+    let content_type = content_type.trim().to_lowercase();
+    let is_token = |s: &str| {
+        !s.is_empty()
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || "!#$&-^_.+".contains(c))
+    };
+    match content_type.split_once('/') {
+        Some((kind, subtype)) if is_token(kind) && is_token(subtype) => Ok(content_type),
+        _ => Err(format!("{} is not a valid MIME type.", content_type)),
+    }
+}
+
+/// One email to one recipient, with whoever else is copied in. Sent from the
+/// email client's transactional identity unless another sender is given.
+#[derive(Debug, Clone)]
+pub struct EmailMessage {
+    sender: Option<SenderIdentity>,
+    to: Mailbox,
+    cc: Vec<Mailbox>,
+    bcc: Vec<Mailbox>,
+    reply_to: Option<Mailbox>,
+    subject: String,
+    html_part: String,
+    text_part: String,
+    headers: Vec<(String, String)>,
+    attachments: Vec<Attachment>,
+}
+
+impl EmailMessage {
+    pub fn new(to: Mailbox, subject: &str, html_part: &str, text_part: &str) -> Self {
+        Self {
+            sender: None,
+            to,
+            cc: Vec::new(),
+            bcc: Vec::new(),
+            reply_to: None,
+            subject: subject.into(),
+            html_part: html_part.into(),
+            text_part: text_part.into(),
+            headers: Vec::new(),
+            attachments: Vec::new(),
+        }
+    }
+
+    pub fn builder(
+        to: Mailbox,
+        subject: &str,
+        html_part: &str,
+        text_part: &str,
+    ) -> EmailMessageBuilder {
+        EmailMessageBuilder {
+            message: Self::new(to, subject, html_part, text_part),
+        }
+    }
+
+    pub fn sender(&self) -> Option<&SenderIdentity> {
+        self.sender.as_ref()
+    }
+
+    /// Sends from `sender`, replying to its reply-to address unless the message
+    /// has its own.
+    pub fn with_sender(mut self, sender: SenderIdentity) -> Self {
+        self.sender = Some(sender);
+        self
+    }
+
+    pub fn to(&self) -> &Mailbox {
+        &self.to
+    }
+
+    pub fn cc(&self) -> &[Mailbox] {
+        &self.cc
+    }
+
+    pub fn bcc(&self) -> &[Mailbox] {
+        &self.bcc
+    }
+
+    pub fn reply_to(&self) -> Option<&Mailbox> {
+        self.reply_to.as_ref()
+    }
+
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    pub fn html_part(&self) -> &str {
+        &self.html_part
+    }
+
+    pub fn text_part(&self) -> &str {
+        &self.text_part
+    }
+
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// Files and inline images, in the order they were added.
+    pub fn attachments(&self) -> &[Attachment] {
+        &self.attachments
+    }
+}
+
+pub struct EmailMessageBuilder {
+    message: EmailMessage,
+}
+
+impl EmailMessageBuilder {
+    pub fn cc(mut self, mailbox: Mailbox) -> Self {
+        self.message.cc.push(mailbox);
+        self
+    }
+
+    pub fn bcc(mut self, mailbox: Mailbox) -> Self {
+        self.message.bcc.push(mailbox);
+        self
+    }
+
+    pub fn reply_to(mut self, mailbox: Mailbox) -> Self {
+        self.message.reply_to = Some(mailbox);
+        self
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.message.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn attachment(mut self, attachment: Attachment) -> Self {
+        self.message.attachments.push(attachment);
+        self
+    }
+
+    /// Checks what could not be checked piece by piece: the headers, and the
+    /// limits the provider puts on a whole message.
+    pub fn build(self) -> Result<EmailMessage, String> {
+        let message = self.message;
+        let mut seen: Vec<String> = Vec::new();
+        for (name, value) in &message.headers {
+            let lowercase = name.to_lowercase();
+            let is_token = !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_graphic() && c != ':' && c != ',');
+            if !is_token || RESERVED_HEADERS.contains(&lowercase.as_str()) {
+                return Err(format!("{} is not a header that can be set.", name));
+            }
+            if value.chars().any(|c| c == '\r' || c == '\n') {
+                return Err(format!("The {} header spans several lines.", name));
+            }
+            if seen.contains(&lowercase) {
+                return Err(format!("The {} header is set more than once.", name));
+            }
+            seen.push(lowercase);
+        }
+
+        let recipients = 1 + message.cc.len() + message.bcc.len();
+        if recipients > MAX_RECIPIENTS {
+            return Err(format!(
+                "A message can have at most {} recipients, not {}.",
+                MAX_RECIPIENTS, recipients
+            ));
+        }
+        let attachments_size: usize = message.attachments.iter().map(|a| a.content.len()).sum();
+        if attachments_size > MAX_ATTACHMENTS_SIZE {
+            return Err(format!(
+                "Attachments can add up to at most {} bytes, not {}.",
+                MAX_ATTACHMENTS_SIZE, attachments_size
+            ));
+        }
+
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use crate::domain::{
+        Attachment, EmailMessage, Mailbox, SenderIdentity, SubscriberEmail, MAX_ATTACHMENTS_SIZE,
+        MAX_RECIPIENTS,
+    };
+
+    fn mailbox() -> Mailbox {
+        Mailbox::new(SubscriberEmail::parse("ursula@example.com".into()).unwrap())
+    }
+
+    fn builder() -> crate::domain::EmailMessageBuilder {
+        EmailMessage::builder(mailbox(), "Hello", "<p>Hello</p>", "Hello")
+    }
+
+    #[test]
+    fn a_message_with_every_part_is_valid() {
+        let message = builder()
+            .cc(mailbox())
+            .bcc(mailbox())
+            .reply_to(mailbox())
+            .header("X-Campaign", "spring")
+            .attachment(
+                Attachment::file("report.pdf".into(), "application/pdf".into(), vec![1]).unwrap(),
+            )
+            .attachment(
+                Attachment::inline_image(
+                    "logo".into(),
+                    "logo.png".into(),
+                    "image/png".into(),
+                    vec![1],
+                )
+                .unwrap(),
+            )
+            .build();
+
+        assert_ok!(message);
+    }
+
+    #[test]
+    fn headers_must_be_single_line_tokens() {
+        for (name, value) in [
+            ("X Campaign", "spring"),
+            ("X-Campaign:", "spring"),
+            ("", "spring"),
+            ("X-Campaign", "spring\r\nBcc: everyone@example.com"),
+        ] {
+            assert_err!(builder().header(name, value).build());
+        }
+    }
+
+    #[test]
+    fn headers_set_from_the_message_are_rejected() {
+        for name in ["From", "subject", "Reply-To", "BCC"] {
+            assert_err!(builder().header(name, "value").build());
+        }
+    }
+
+    #[test]
+    fn a_header_cannot_be_set_twice() {
+        let message = builder()
+            .header("X-Campaign", "spring")
+            .header("x-campaign", "summer")
+            .build();
+
+        assert_err!(message);
+    }
+
+    #[test]
+    fn too_many_recipients_are_rejected() {
+        let message = (1..MAX_RECIPIENTS).fold(builder(), |builder, _| builder.cc(mailbox()));
+        assert_ok!(message.build());
+
+        let message = (0..MAX_RECIPIENTS).fold(builder(), |builder, _| builder.bcc(mailbox()));
+        assert_err!(message.build());
+    }
+
+    #[test]
+    fn attachments_beyond_the_size_limit_are_rejected() {
+        let attachment = |size: usize| {
+            Attachment::file(
+                "data.bin".into(),
+                "application/octet-stream".into(),
+                vec![0; size],
+            )
+            .unwrap()
+        };
+
+        let message = builder()
+            .attachment(attachment(MAX_ATTACHMENTS_SIZE / 2))
+            .attachment(attachment(MAX_ATTACHMENTS_SIZE / 2 + 1))
+            .build();
+
+        assert_err!(message);
+    }
+
+    #[test]
+    fn attachments_need_a_filename_and_a_mime_type() {
+        assert_err!(Attachment::file("".into(), "text/plain".into(), vec![]));
+        assert_err!(Attachment::file(
+            "../passwd".into(),
+            "text/plain".into(),
+            vec![]
+        ));
+        assert_err!(Attachment::file("notes.txt".into(), "text".into(), vec![]));
+        assert_err!(Attachment::file("notes.txt".into(), "text/".into(), vec![]));
+        let attachment = Attachment::file("notes.txt".into(), " Text/Plain ".into(), vec![]);
+        assert_eq!(attachment.unwrap().content_type(), "text/plain");
+    }
+
+    #[test]
+    fn inline_images_need_an_image_type_and_a_content_id() {
+        let image = |content_id: &str, content_type: &str| {
+            Attachment::inline_image(
+                content_id.into(),
+                "logo.png".into(),
+                content_type.into(),
+                vec![],
+            )
+        };
+
+        assert_ok!(image("logo", "image/png"));
+        assert_err!(image("logo", "application/pdf"));
+        assert_err!(image("", "image/png"));
+        assert_err!(image("<logo>", "image/png"));
+    }
+
+    #[test]
+    fn sender_identities_are_parsed_with_their_addresses() {
+        let identity = |id: &str, email: &str, reply_to: Option<&str>| {
+            SenderIdentity::parse(
+                id.into(),
+                email.into(),
+                "The Editor".into(),
+                reply_to.map(Into::into),
+            )
+        };
+
+        assert_ok!(identity("editor", "editor@example.com", None));
+        assert_ok!(identity(
+            "editor",
+            "editor@example.com",
+            Some("desk@example.com")
+        ));
+        assert_err!(identity("", "editor@example.com", None));
+        assert_err!(identity("the editor", "editor@example.com", None));
+        assert_err!(identity("editor", "editor.example.com", None));
+        assert_err!(identity("editor", "editor@example.com", Some("desk")));
+    }
+}
@@ -0,0 +1,222 @@
+use chrono::{DateTime, Utc};
+
+/// What happens to the newsletter built from a new feed item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedSourceMode {
+    /// Stored as a draft for an editor to publish.
+    Draft,
+    /// Published right away.
+    Send,
+}
+
+impl FeedSourceMode {
+    pub fn parse(s: &str) -> Result<FeedSourceMode, String> {
+        match s {
+            "draft" => Ok(FeedSourceMode::Draft),
+            "send" => Ok(FeedSourceMode::Send),
+            other => Err(format!(
+                "{} is not a valid feed source mode. Use either 'draft' or 'send'.",
+                other
+            )),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FeedSourceMode::Draft => "draft",
+            FeedSourceMode::Send => "send",
+        }
+    }
+}
+
+/// An entry of an external feed. `summary` and `content` are HTML.
+#[derive(Debug, Clone)]
+pub struct FeedItem {
+    pub guid: String,
+    pub title: String,
+    pub link: String,
+    pub summary: String,
+    pub content: String,
+    pub published_at: Option<DateTime<Utc>>,
+}
+
+/// Newsletter text with `{{placeholder}}`s filled from a feed item.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewsletterTemplate(String);
+
+impl NewsletterTemplate {
+    pub const PLACEHOLDERS: [&'static str; 5] =
+        ["title", "link", "summary", "content", "published_at"];
+
+    pub fn parse(s: String) -> Result<NewsletterTemplate, String> {
+        if s.trim().is_empty() {
+            return Err("A template cannot be empty.".to_string());
+        }
+        for placeholder in placeholders(&s) {
+            let name = placeholder?;
+            if !Self::PLACEHOLDERS.contains(&name) {
+                return Err(format!(
+                    "{{{{{}}}}} is not a known placeholder. Use one of {}.",
+                    name,
+                    Self::PLACEHOLDERS.join(", ")
+                ));
+            }
+        }
+        Ok(NewsletterTemplate(s))
+    }
+
+    /// Fills an HTML template: the title and link are escaped, the item's own HTML is not.
+    pub fn render_html(&self, item: &FeedItem, escape: impl Fn(&str) -> String) -> String {
+        self.render(|name| match name {
+            "title" => escape(&item.title),
+            "link" => escape(&item.link),
+            "summary" => item.summary.clone(),
+            "content" => item.content.clone(),
+            _ => escape(&published_at(item)),
+        })
+    }
+
+    /// Fills a plain text template or a subject, with tags removed from the item's HTML.
+    pub fn render_text(&self, item: &FeedItem) -> String {
+        self.render(|name| match name {
+            "title" => item.title.clone(),
+            "link" => item.link.clone(),
+            "summary" => without_tags(&item.summary),
+            "content" => without_tags(&item.content),
+            _ => published_at(item),
+        })
+    }
+
+    fn render(&self, value: impl Fn(&str) -> String) -> String {
+        let mut rendered = String::with_capacity(self.0.len());
+        let mut rest = self.0.as_str();
+        while let Some(start) = rest.find("{{") {
+            // Parsing guarantees every placeholder is closed.
+            let end = start + rest[start..].find("}}").expect("Unclosed placeholder.");
+            rendered.push_str(&rest[..start]);
+            rendered.push_str(&value(rest[start + 2..end].trim()));
+            rest = &rest[end + 2..];
+        }
+        rendered.push_str(rest);
+        rendered
+    }
+}
+
+impl AsRef<str> for NewsletterTemplate {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+fn placeholders(s: &str) -> impl Iterator<Item = Result<&str, String>> {
+    let mut rest = s;
+    std::iter::from_fn(move || {
+        let start = rest.find("{{")?;
+        let Some(length) = rest[start..].find("}}") else {
+            rest = "";
+            return Some(Err("A template has an unclosed {{.".to_string()));
+        };
+        let name = rest[start + 2..start + length].trim();
+        rest = &rest[start + length + 2..];
+        Some(Ok(name))
+    })
+}
+
+fn published_at(item: &FeedItem) -> String {
+    item.published_at
+        .map(|at| at.format("%B %-d, %Y").to_string())
+        .unwrap_or_default()
+}
+
+/// Drops tags and collapses whitespace. Good enough for short feed summaries.
+fn without_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::{FeedItem, FeedSourceMode, NewsletterTemplate};
+    use chrono::{TimeZone, Utc};
+    use claims::{assert_err, assert_ok};
+
+    fn item() -> FeedItem {
+        FeedItem {
+            guid: "post-1".to_string(),
+            title: "Fish & chips".to_string(),
+            link: "https://blog.example.com/fish?a=1&b=2".to_string(),
+            summary: "<p>A <em>crispy</em> &amp; salty post.</p>".to_string(),
+            content: "<p>Full post</p>".to_string(),
+            published_at: Some(Utc.with_ymd_and_hms(2024, 4, 5, 10, 0, 0).unwrap()),
+        }
+    }
+
+    fn escape(s: &str) -> String {
+        s.replace('&', "&amp;")
+    }
+
+    #[test]
+    fn modes_round_trip_through_their_string_form() {
+        for mode in [FeedSourceMode::Draft, FeedSourceMode::Send] {
+            assert_eq!(FeedSourceMode::parse(mode.as_str()), Ok(mode));
+        }
+        assert_err!(FeedSourceMode::parse("publish"));
+    }
+
+    #[test]
+    fn known_placeholders_are_accepted() {
+        assert_ok!(NewsletterTemplate::parse(
+            "{{title}} {{ link }} {{summary}} {{content}} {{published_at}}".to_string()
+        ));
+    }
+
+    #[test]
+    fn unknown_placeholders_unclosed_braces_and_empty_templates_are_rejected() {
+        for template in ["{{author}}", "Hello {{title", "  "] {
+            assert_err!(NewsletterTemplate::parse(template.to_string()));
+        }
+    }
+
+    #[test]
+    fn html_rendering_escapes_the_title_and_link_but_keeps_the_items_html() {
+        let template =
+            NewsletterTemplate::parse(r#"<a href="{{link}}">{{title}}</a>{{summary}}"#.to_string())
+                .unwrap();
+
+        assert_eq!(
+            template.render_html(&item(), escape),
+            r#"<a href="https://blog.example.com/fish?a=1&amp;b=2">Fish &amp; chips</a><p>A <em>crispy</em> &amp; salty post.</p>"#
+        );
+    }
+
+    #[test]
+    fn text_rendering_strips_tags_from_the_items_html() {
+        let template =
+            NewsletterTemplate::parse("{{title}} ({{published_at}})\n{{summary}}".to_string())
+                .unwrap();
+
+        assert_eq!(
+            template.render_text(&item()),
+            "Fish & chips (April 5, 2024)\nA crispy & salty post."
+        );
+    }
+}
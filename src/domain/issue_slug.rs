@@ -0,0 +1,99 @@
+/// The URL segment of an issue in the web archive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IssueSlug(String);
+
+impl IssueSlug {
+    const MAX_LENGTH: usize = 64;
+
+    /// Lowercases the title and joins its ASCII words with dashes.
+    pub fn from_title(title: &str) -> IssueSlug {
+        let mut slug = String::new();
+        for word in title
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|word| !word.is_empty())
+        {
+            if slug.len() + word.len() + 1 > Self::MAX_LENGTH {
+                break;
+            }
+            if !slug.is_empty() {
+                slug.push('-');
+            }
+            slug.push_str(&word.to_ascii_lowercase());
+        }
+        if slug.is_empty() {
+            slug.push_str("issue");
+        }
+        IssueSlug(slug)
+    }
+
+    pub fn parse(s: String) -> Result<IssueSlug, String> {
+        let is_empty = s.is_empty();
+        let is_too_long = s.len() > Self::MAX_LENGTH + 8;
+        let contains_forbidden_characters = s
+            .chars()
+            .any(|c| !(c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-'));
+
+        if !(is_empty || is_too_long || contains_forbidden_characters) {
+            Ok(IssueSlug(s))
+        } else {
+            Err(format!("{} is not a valid issue slug.", s))
+        }
+    }
+
+    /// Disambiguates issues sharing a title: `title`, `title-2`, `title-3`...
+    pub fn with_suffix(&self, n: u32) -> IssueSlug {
+        IssueSlug(format!("{}-{}", self.0, n))
+    }
+}
+
+impl std::fmt::Display for IssueSlug {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl AsRef<str> for IssueSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::IssueSlug;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn titles_become_lowercase_dashed_words() {
+        assert_eq!(
+            IssueSlug::from_title("  Issue #12: What's new?").as_ref(),
+            "issue-12-what-s-new"
+        );
+    }
+
+    #[test]
+    fn titles_without_ascii_words_fall_back_to_issue() {
+        assert_eq!(IssueSlug::from_title("¿…?").as_ref(), "issue");
+    }
+
+    #[test]
+    fn long_titles_are_cut_between_words() {
+        let slug = IssueSlug::from_title(&"word ".repeat(30));
+        assert!(slug.as_ref().len() <= 64);
+        assert!(slug.as_ref().ends_with("word"));
+    }
+
+    #[test]
+    fn generated_slugs_parse() {
+        let slug = IssueSlug::from_title("Hello, world");
+        assert_ok!(IssueSlug::parse(slug.to_string()));
+        assert_ok!(IssueSlug::parse(slug.with_suffix(12).to_string()));
+    }
+
+    #[test]
+    fn slugs_with_forbidden_characters_are_rejected() {
+        for slug in ["", "Hello", "hello world", "../etc", "a%2Fb"] {
+            assert_err!(IssueSlug::parse(slug.to_string()));
+        }
+    }
+}
@@ -0,0 +1,64 @@
+/// Who can read a newsletter issue in the web archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssueVisibility {
+    Public,
+    /// Listed in the archive, readable with a confirmed subscriber's token.
+    SubscribersOnly,
+    /// Never shown in the archive.
+    Hidden,
+}
+
+impl IssueVisibility {
+    pub const ALL: [IssueVisibility; 3] = [
+        IssueVisibility::Public,
+        IssueVisibility::SubscribersOnly,
+        IssueVisibility::Hidden,
+    ];
+
+    /// Used when an issue is published without a visibility. Issues sent to a list or
+    /// a segment may not be meant for everyone, so they stay out of the archive.
+    pub fn default_for(is_targeted: bool) -> IssueVisibility {
+        if is_targeted {
+            IssueVisibility::Hidden
+        } else {
+            IssueVisibility::Public
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<IssueVisibility, String> {
+        match s {
+            "public" => Ok(IssueVisibility::Public),
+            "subscribers_only" => Ok(IssueVisibility::SubscribersOnly),
+            "hidden" => Ok(IssueVisibility::Hidden),
+            other => Err(format!("{} is not a valid issue visibility.", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IssueVisibility::Public => "public",
+            IssueVisibility::SubscribersOnly => "subscribers_only",
+            IssueVisibility::Hidden => "hidden",
+        }
+    }
+}
+
+impl std::fmt::Display for IssueVisibility {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::IssueVisibility;
+    use claims::assert_err;
+
+    #[test]
+    fn every_visibility_round_trips_through_its_string_form() {
+        for visibility in IssueVisibility::ALL {
+            assert_eq!(IssueVisibility::parse(visibility.as_str()), Ok(visibility));
+        }
+        assert_err!(IssueVisibility::parse("private"));
+    }
+}
@@ -0,0 +1,92 @@
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListSlug(String);
+
+impl ListSlug {
+    pub fn parse(s: String) -> Result<ListSlug, String> {
+        let s = s.trim().to_lowercase();
+        let is_empty = s.is_empty();
+        let is_too_long = s.len() > 64;
+        let contains_forbidden_characters = s
+            .chars()
+            .any(|c| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+
+        if !(is_empty || is_too_long || contains_forbidden_characters) {
+            Ok(ListSlug(s))
+        } else {
+            Err(format!("{} is not a valid list slug.", s))
+        }
+    }
+
+    /// Parses a comma separated list of slugs, as submitted by the subscribe form.
+    pub fn parse_many(s: &str) -> Result<Vec<ListSlug>, String> {
+        let mut slugs: Vec<ListSlug> = Vec::new();
+
+        for slug in s.split(',').filter(|slug| !slug.trim().is_empty()) {
+            let slug = ListSlug::parse(slug.to_string())?;
+            if !slugs.contains(&slug) {
+                slugs.push(slug);
+            }
+        }
+
+        Ok(slugs)
+    }
+}
+
+impl std::fmt::Display for ListSlug {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl AsRef<str> for ListSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::ListSlug;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn empty_slugs_are_rejected() {
+        assert_err!(ListSlug::parse("".to_string()));
+        assert_err!(ListSlug::parse("   ".to_string()));
+    }
+
+    #[test]
+    fn slugs_longer_than_64_characters_are_rejected() {
+        assert_err!(ListSlug::parse("a".repeat(65)));
+    }
+
+    #[test]
+    fn slugs_containing_invalid_characters_are_rejected() {
+        for slug in &["rust news", "rust/news", "<rust>", "ñews"] {
+            assert_err!(ListSlug::parse(slug.to_string()));
+        }
+    }
+
+    #[test]
+    fn slugs_are_normalised_to_lowercase() {
+        let slug = ListSlug::parse(" Rust-News ".to_string()).unwrap();
+        assert_eq!(slug.as_ref(), "rust-news");
+    }
+
+    #[test]
+    fn a_valid_slug_is_parsed_successfully() {
+        assert_ok!(ListSlug::parse("rust_news-2024".to_string()));
+    }
+
+    #[test]
+    fn many_slugs_are_parsed_and_deduplicated() {
+        let slugs = ListSlug::parse_many("rust,go,,rust").unwrap();
+        assert_eq!(
+            slugs,
+            vec![
+                ListSlug::parse("rust".to_string()).unwrap(),
+                ListSlug::parse("go".to_string()).unwrap()
+            ]
+        );
+    }
+}
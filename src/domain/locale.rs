@@ -0,0 +1,106 @@
+/// A language system emails and newsletter variants can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Locale {
+    #[default]
+    English,
+    French,
+    German,
+    Spanish,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 4] = [
+        Locale::English,
+        Locale::French,
+        Locale::German,
+        Locale::Spanish,
+    ];
+
+    /// Accepts a language tag, falling back from a regional variant like `fr-CA`
+    /// to its language.
+    pub fn parse(s: &str) -> Result<Locale, String> {
+        let language = s.trim().split(['-', '_']).next().unwrap_or_default();
+        match language.to_lowercase().as_str() {
+            "en" => Ok(Locale::English),
+            "fr" => Ok(Locale::French),
+            "de" => Ok(Locale::German),
+            "es" => Ok(Locale::Spanish),
+            _ => Err(format!("{} is not a supported locale.", s)),
+        }
+    }
+
+    /// The supported locale the client prefers the most in an `Accept-Language`
+    /// header, if any.
+    pub fn from_accept_language(header: &str) -> Option<Locale> {
+        let mut preferred: Option<(Locale, f32)> = None;
+        for range in header.split(',') {
+            let mut parts = range.split(';').map(str::trim);
+            let Ok(locale) = Locale::parse(parts.next().unwrap_or_default()) else {
+                continue;
+            };
+            let quality = parts
+                .find_map(|p| p.strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.parse::<f32>().ok());
+            match quality {
+                Some(quality) if quality > 0.0 && preferred.is_none_or(|(_, q)| quality > q) => {
+                    preferred = Some((locale, quality));
+                }
+                _ => {}
+            }
+        }
+        preferred.map(|(locale, _)| locale)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Locale::English => "en",
+            Locale::French => "fr",
+            Locale::German => "de",
+            Locale::Spanish => "es",
+        }
+    }
+}
+
+impl std::fmt::Display for Locale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::assert_err;
+
+    use crate::domain::Locale;
+
+    #[test]
+    fn every_locale_round_trips_through_its_string_form() {
+        for locale in Locale::ALL {
+            assert_eq!(Locale::parse(locale.as_str()).unwrap(), locale);
+        }
+    }
+
+    #[test]
+    fn regional_variants_fall_back_to_their_language() {
+        assert_eq!(Locale::parse("fr-CA").unwrap(), Locale::French);
+        assert_eq!(Locale::parse("DE_at").unwrap(), Locale::German);
+        assert_err!(Locale::parse("pt-BR"));
+        assert_err!(Locale::parse(""));
+    }
+
+    #[test]
+    fn the_most_preferred_supported_language_is_picked() {
+        let cases = [
+            ("de-DE,de;q=0.9,en;q=0.8", Some(Locale::German)),
+            ("pt-BR, es;q=0.5, fr;q=0.7", Some(Locale::French)),
+            ("en;q=0.2, es", Some(Locale::Spanish)),
+            ("fr;q=0, en;q=0.1", Some(Locale::English)),
+            ("pt, *;q=0.5", None),
+            ("", None),
+        ];
+
+        for (header, expected) in cases {
+            assert_eq!(Locale::from_accept_language(header), expected, "{}", header);
+        }
+    }
+}
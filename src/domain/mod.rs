@@ -1,7 +1,46 @@
+mod ab_test;
+mod click_token;
+mod custom_field;
+mod delivery_status;
+mod digest_frequency;
+mod email_event;
+mod email_message;
+mod feed_source;
+mod issue_slug;
+mod issue_visibility;
+mod list_slug;
+mod locale;
 mod new_subscriber;
+mod newsletter_content;
+mod segment;
+mod sequence;
 mod subscriber_email;
 mod subscriber_name;
+mod subscriber_tag;
+mod subscription_status;
+mod system_email;
 
+pub use ab_test::{pick_ab_test_winner, AbTest, AbTestMetric};
+pub use click_token::ClickToken;
+pub use custom_field::{CustomFieldKey, CustomFieldType, CustomFieldValue};
+pub use delivery_status::DeliveryStatus;
+pub use digest_frequency::DigestFrequency;
+pub use email_event::{EmailEvent, EmailEventKind};
+pub use email_message::{
+    Attachment, EmailMessage, EmailMessageBuilder, Mailbox, SenderIdentity, MAX_ATTACHMENTS_SIZE,
+    MAX_RECIPIENTS,
+};
+pub use feed_source::{FeedItem, FeedSourceMode, NewsletterTemplate};
+pub use issue_slug::IssueSlug;
+pub use issue_visibility::IssueVisibility;
+pub use list_slug::ListSlug;
+pub use locale::Locale;
 pub use new_subscriber::NewSubscriber;
+pub use newsletter_content::NewsletterContent;
+pub use segment::{Comparison, Literal, SegmentExpression};
+pub use sequence::{SequenceStep, SequenceSteps};
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use subscriber_tag::SubscriberTag;
+pub use subscription_status::SubscriptionStatus;
+pub use system_email::SystemEmail;
@@ -1,7 +1,13 @@
+use crate::domain::custom_field::CustomFieldKey;
+use crate::domain::list_slug::ListSlug;
+use crate::domain::locale::Locale;
 use crate::domain::subscriber_email::SubscriberEmail;
 use crate::domain::subscriber_name::SubscriberName;
 
 pub struct NewSubscriber {
     pub email: SubscriberEmail,
     pub name: SubscriberName,
+    pub lists: Vec<ListSlug>,
+    pub locale: Locale,
+    pub custom_fields: Vec<(CustomFieldKey, String)>,
 }
@@ -0,0 +1,346 @@
+use std::{borrow::Cow, cell::RefCell};
+
+use lol_html::{
+    element, html_content::Element, rewrite_str, text, ElementContentHandlers, RewriteStrSettings,
+    Selector,
+};
+
+/// Width of the generated plain text part.
+const TEXT_WIDTH: usize = 80;
+
+/// CSS properties kept in `style` attributes. Anything that can load a URL,
+/// like `background` or `list-style-image`, is left out.
+const ALLOWED_STYLE_PROPERTIES: [&str; 40] = [
+    "background-color",
+    "border",
+    "border-bottom",
+    "border-collapse",
+    "border-color",
+    "border-left",
+    "border-radius",
+    "border-right",
+    "border-spacing",
+    "border-style",
+    "border-top",
+    "border-width",
+    "color",
+    "display",
+    "font",
+    "font-family",
+    "font-size",
+    "font-style",
+    "font-weight",
+    "height",
+    "letter-spacing",
+    "line-height",
+    "list-style-type",
+    "margin",
+    "margin-bottom",
+    "margin-left",
+    "margin-right",
+    "margin-top",
+    "max-width",
+    "padding",
+    "padding-bottom",
+    "padding-left",
+    "padding-right",
+    "padding-top",
+    "text-align",
+    "text-decoration",
+    "text-transform",
+    "vertical-align",
+    "white-space",
+    "width",
+];
+
+/// The HTML and plain text parts of an issue, ready to be sent: stylesheets are
+/// inlined, the HTML is sanitised and the text is generated from it when missing.
+#[derive(Debug)]
+pub struct NewsletterContent {
+    html: String,
+    text: String,
+}
+
+impl NewsletterContent {
+    pub fn parse(html: &str, text: Option<&str>) -> Result<NewsletterContent, String> {
+        let fragment = sanitise(&inline_css(html)?);
+        if fragment.trim().is_empty() {
+            return Err("The newsletter's HTML has no content left once sanitised.".into());
+        }
+        // Documents keep a bare wrapper, their head is dropped.
+        let html = if html.to_ascii_lowercase().contains("<body") {
+            format!("<html><body>{}</body></html>", fragment.trim())
+        } else {
+            fragment
+        };
+        let text = match text {
+            Some(text) if !text.trim().is_empty() => text.to_string(),
+            _ => html2text::from_read(html.as_bytes(), TEXT_WIDTH).map_err(|e| {
+                format!(
+                    "A plain text version could not be generated from the HTML: {}",
+                    e
+                )
+            })?,
+        };
+        Ok(NewsletterContent { html, text })
+    }
+
+    pub fn html(&self) -> &str {
+        &self.html
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+/// Drops scripts, event handlers, and URLs with schemes other than web, mail,
+/// phone and inline attachment ones. Returns a fragment.
+fn sanitise(html: &str) -> String {
+    ammonia::Builder::default()
+        .add_generic_attributes(["style"])
+        .filter_style_properties(ALLOWED_STYLE_PROPERTIES.into_iter().collect())
+        .add_clean_content_tags(["head", "title"])
+        .add_url_schemes(["cid"])
+        .link_rel(None)
+        .clean(html)
+        .to_string()
+}
+
+/// Moves the rules of `<style>` elements into the `style` attributes of the
+/// elements they match, since many email clients ignore stylesheets. Rules
+/// that cannot be inlined, like at-rules and pseudo-classes, are dropped.
+/// Later rules override earlier ones and inline styles override both;
+/// selector specificity is not taken into account.
+fn inline_css(html: &str) -> Result<String, String> {
+    let stylesheet = RefCell::new(String::new());
+    let html = rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                text!("style", |chunk| {
+                    stylesheet.borrow_mut().push_str(chunk.as_str());
+                    Ok(())
+                }),
+                element!("style", |el| {
+                    el.remove();
+                    Ok(())
+                }),
+            ],
+            ..RewriteStrSettings::new()
+        },
+    )
+    .map_err(|e| format!("The newsletter's HTML could not be parsed: {}", e))?;
+    let rules = parse_stylesheet(&stylesheet.into_inner())?;
+    if rules.is_empty() {
+        return Ok(html);
+    }
+
+    // Each handler puts its declarations before the current ones, so running
+    // them in reverse leaves the declarations in source order.
+    let element_content_handlers = rules
+        .into_iter()
+        .rev()
+        .map(|(selector, declarations)| {
+            let handler = ElementContentHandlers::default().element(move |el: &mut Element| {
+                let style = match el.get_attribute("style") {
+                    Some(current) => format!("{} {}", declarations, current),
+                    None => declarations.clone(),
+                };
+                el.set_attribute("style", &style)?;
+                Ok(())
+            });
+            (Cow::Owned(selector), handler)
+        })
+        .collect();
+    rewrite_str(
+        &html,
+        RewriteStrSettings {
+            element_content_handlers,
+            ..RewriteStrSettings::new()
+        },
+    )
+    .map_err(|e| format!("The newsletter's stylesheet could not be inlined: {}", e))
+}
+
+/// The inlinable rules of a stylesheet, one per selector, with their
+/// declarations normalised to end with a `;`.
+fn parse_stylesheet(css: &str) -> Result<Vec<(Selector, String)>, String> {
+    let css = strip_comments(css);
+    let unbalanced = || "The newsletter's stylesheet has unbalanced braces.".to_string();
+    let mut rules = Vec::new();
+    let mut rest = css.trim_start();
+    while !rest.is_empty() {
+        let open = rest.find('{').ok_or_else(unbalanced)?;
+        let prelude = rest[..open].trim();
+        let close = matching_brace(rest, open).ok_or_else(unbalanced)?;
+        let block = &rest[open + 1..close];
+        rest = rest[close + 1..].trim_start();
+        if prelude.starts_with('@') {
+            continue;
+        }
+        if block.contains('{') {
+            return Err(unbalanced());
+        }
+        let declarations = block
+            .split(';')
+            .map(str::trim)
+            .filter(|d| !d.is_empty())
+            .map(|d| format!("{};", d))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if declarations.is_empty() {
+            continue;
+        }
+        for selector in prelude.split(',').map(str::trim) {
+            if selector.contains(':') {
+                continue;
+            }
+            let parsed = selector
+                .parse::<Selector>()
+                .map_err(|e| format!("{} is not a supported CSS selector: {}", selector, e))?;
+            rules.push((parsed, declarations.clone()));
+        }
+    }
+    Ok(rules)
+}
+
+fn strip_comments(css: &str) -> String {
+    let mut stripped = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = rest.find("/*") {
+        stripped.push_str(&rest[..start]);
+        rest = match rest[start + 2..].find("*/") {
+            Some(end) => &rest[start + 2 + end + 2..],
+            None => "",
+        };
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+/// The index of the `}` closing the `{` at `open`.
+fn matching_brace(css: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (index, c) in css[open..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + index);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use crate::domain::NewsletterContent;
+
+    #[test]
+    fn scripts_event_handlers_and_dangerous_urls_are_removed() {
+        let content = NewsletterContent::parse(
+            r#"<p onclick="steal()">Hi<script>steal()</script></p>
+               <a href="javascript:steal()">Click</a>
+               <a href="https://example.com">Read</a>
+               <img src="cid:logo" alt="Logo">"#,
+            Some("Hi"),
+        )
+        .unwrap();
+
+        let html = content.html();
+        assert!(!html.contains("onclick"), "{}", html);
+        assert!(!html.contains("script"), "{}", html);
+        assert!(!html.contains("javascript"), "{}", html);
+        assert!(
+            html.contains(r#"<a href="https://example.com">Read</a>"#),
+            "{}",
+            html
+        );
+        assert!(html.contains(r#"src="cid:logo""#), "{}", html);
+    }
+
+    #[test]
+    fn stylesheets_are_inlined_in_source_order_under_inline_styles() {
+        let content = NewsletterContent::parse(
+            r#"<html><head><title>Issue</title><style>
+                 /* Brand colours */
+                 p { color: red; margin: 0 }
+                 .lead, h1 { color: blue; }
+                 a:hover { color: green; }
+                 @media (max-width: 600px) { p { font-size: 18px; } }
+               </style></head>
+               <body><h1>Title</h1><p class="lead" style="font-weight: bold">Hi</p></body></html>"#,
+            Some("Hi"),
+        )
+        .unwrap();
+
+        let html = content.html();
+        assert!(html.starts_with("<html><body>"), "{}", html);
+        assert!(html.ends_with("</body></html>"), "{}", html);
+        assert!(!html.contains("<style"), "{}", html);
+        assert!(!html.contains("Issue"), "{}", html);
+        assert!(html.contains(r#"<h1 style="color:blue">"#), "{}", html);
+        assert!(
+            html.contains(r#"<p style="color:red;margin:0;color:blue;font-weight:bold">"#),
+            "{}",
+            html
+        );
+        assert!(
+            !html.contains("green") && !html.contains("18px"),
+            "{}",
+            html
+        );
+    }
+
+    #[test]
+    fn style_properties_that_can_load_urls_are_dropped() {
+        let content = NewsletterContent::parse(
+            r#"<p style="background: url(https://tracker.example.com); color: red">Hi</p>"#,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(content.html(), r#"<p style="color:red">Hi</p>"#);
+    }
+
+    #[test]
+    fn the_text_part_is_generated_when_missing_or_blank() {
+        for text in [None, Some("  ")] {
+            let content = NewsletterContent::parse(
+                r#"<h1>Issue #1</h1><p>Read <a href="https://example.com">this</a>.</p>"#,
+                text,
+            )
+            .unwrap();
+
+            assert!(content.text().contains("Issue #1"), "{}", content.text());
+            assert!(
+                content.text().contains("https://example.com"),
+                "{}",
+                content.text()
+            );
+        }
+        let content = NewsletterContent::parse("<p>Hi</p>", Some("Hello")).unwrap();
+        assert_eq!(content.text(), "Hello");
+    }
+
+    #[test]
+    fn content_that_cannot_be_processed_is_rejected() {
+        assert_err!(NewsletterContent::parse("<script>steal()</script>", None));
+        assert_err!(NewsletterContent::parse(
+            "<style>p { color: red</style><p>Hi</p>",
+            None
+        ));
+        assert_err!(NewsletterContent::parse(
+            "<style>p > > a { color: red }</style><p>Hi</p>",
+            None
+        ));
+        assert_ok!(NewsletterContent::parse("<style></style><p>Hi</p>", None));
+    }
+}
@@ -0,0 +1,456 @@
+use chrono::NaiveDate;
+
+use crate::domain::{CustomFieldKey, SubscriberTag, SubscriptionStatus};
+
+/// A parsed segment filter, e.g.
+/// `status = "confirmed" AND (tag = "vip" OR field.seats >= 10) AND NOT engaged_within 30`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SegmentExpression {
+    And(Box<SegmentExpression>, Box<SegmentExpression>),
+    Or(Box<SegmentExpression>, Box<SegmentExpression>),
+    Not(Box<SegmentExpression>),
+    Status(Comparison, SubscriptionStatus),
+    Tag(Comparison, SubscriberTag),
+    Field(CustomFieldKey, Comparison, Literal),
+    SubscribedAt(Comparison, NaiveDate),
+    LastEngagedAt(Comparison, NaiveDate),
+    /// Subscribers that opened or clicked something in the last N days.
+    EngagedWithin(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            Comparison::Eq => "=",
+            Comparison::Ne => "<>",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        }
+    }
+
+    pub fn is_equality(&self) -> bool {
+        matches!(self, Comparison::Eq | Comparison::Ne)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    String(String),
+    Number(f64),
+    Boolean(bool),
+}
+
+/// How deeply parentheses and `not` can be nested, so a hostile filter cannot
+/// exhaust the stack while being parsed or turned into SQL.
+const MAX_NESTING_DEPTH: usize = 32;
+/// How many predicates a single filter can combine, which bounds `and`/`or` chains.
+const MAX_PREDICATES: usize = 256;
+
+impl SegmentExpression {
+    pub fn parse(s: &str) -> Result<SegmentExpression, String> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+            depth: 0,
+            predicates: 0,
+        };
+        let expression = parser.parse_or()?;
+
+        match parser.peek() {
+            None => Ok(expression),
+            Some(token) => Err(format!("Unexpected {} in segment expression.", token)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Identifier(String),
+    String(String),
+    Number(f64),
+    Operator(Comparison),
+    OpenParen,
+    CloseParen,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Identifier(i) => write!(f, "'{}'", i),
+            Token::String(s) => write!(f, "\"{}\"", s),
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Operator(c) => write!(f, "'{}'", c.as_sql()),
+            Token::OpenParen => write!(f, "'('"),
+            Token::CloseParen => write!(f, "')'"),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::OpenParen);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::CloseParen);
+            }
+            '=' => {
+                chars.next();
+                tokens.push(Token::Operator(Comparison::Eq));
+            }
+            '!' => {
+                chars.next();
+                if chars.next() != Some('=') {
+                    return Err("Expected '=' after '!'.".to_string());
+                }
+                tokens.push(Token::Operator(Comparison::Ne));
+            }
+            '<' | '>' => {
+                chars.next();
+                let or_equal = chars.next_if_eq(&'=').is_some();
+                tokens.push(Token::Operator(match (c, or_equal) {
+                    ('<', false) => Comparison::Lt,
+                    ('<', true) => Comparison::Le,
+                    ('>', false) => Comparison::Gt,
+                    _ => Comparison::Ge,
+                }));
+            }
+            '"' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(escaped) => value.push(escaped),
+                            None => return Err("Unterminated string.".to_string()),
+                        },
+                        Some(c) => value.push(c),
+                        None => return Err("Unterminated string.".to_string()),
+                    }
+                }
+                tokens.push(Token::String(value));
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                let mut number = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_digit() || *c == '.' || *c == '-')
+                {
+                    number.push(c);
+                }
+                let number = number
+                    .parse::<f64>()
+                    .map_err(|_| format!("{} is not a valid number.", number))?;
+                tokens.push(Token::Number(number));
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut identifier = String::new();
+                while let Some(c) =
+                    chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '.')
+                {
+                    identifier.push(c);
+                }
+                tokens.push(Token::Identifier(identifier));
+            }
+            other => return Err(format!("Unexpected character '{}'.", other)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    depth: usize,
+    predicates: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn next_is_keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Identifier(i)) if i.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<SegmentExpression, String> {
+        let mut expression = self.parse_and()?;
+        while self.next_is_keyword("or") {
+            expression = SegmentExpression::Or(Box::new(expression), Box::new(self.parse_and()?));
+        }
+        Ok(expression)
+    }
+
+    fn parse_and(&mut self) -> Result<SegmentExpression, String> {
+        let mut expression = self.parse_unary()?;
+        while self.next_is_keyword("and") {
+            expression =
+                SegmentExpression::And(Box::new(expression), Box::new(self.parse_unary()?));
+        }
+        Ok(expression)
+    }
+
+    fn enter(&mut self) -> Result<(), String> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING_DEPTH {
+            return Err(format!(
+                "Segment expressions cannot be nested more than {} levels deep.",
+                MAX_NESTING_DEPTH
+            ));
+        }
+        Ok(())
+    }
+
+    fn parse_unary(&mut self) -> Result<SegmentExpression, String> {
+        if self.next_is_keyword("not") {
+            self.enter()?;
+            let expression = SegmentExpression::Not(Box::new(self.parse_unary()?));
+            self.depth -= 1;
+            return Ok(expression);
+        }
+
+        match self.next() {
+            Some(Token::OpenParen) => {
+                self.enter()?;
+                let expression = self.parse_or()?;
+                self.depth -= 1;
+                match self.next() {
+                    Some(Token::CloseParen) => Ok(expression),
+                    _ => Err("Expected ')' in segment expression.".to_string()),
+                }
+            }
+            Some(Token::Identifier(identifier)) => {
+                self.predicates += 1;
+                if self.predicates > MAX_PREDICATES {
+                    return Err(format!(
+                        "Segment expressions cannot combine more than {} conditions.",
+                        MAX_PREDICATES
+                    ));
+                }
+                self.parse_predicate(identifier)
+            }
+            Some(token) => Err(format!("Unexpected {} in segment expression.", token)),
+            None => Err("Unexpected end of segment expression.".to_string()),
+        }
+    }
+
+    fn parse_predicate(&mut self, identifier: String) -> Result<SegmentExpression, String> {
+        if identifier == "engaged_within" {
+            return match self.next() {
+                Some(Token::Number(days)) if days >= 0.0 && days.fract() == 0.0 => {
+                    Ok(SegmentExpression::EngagedWithin(days as u32))
+                }
+                _ => Err("engaged_within expects a whole number of days.".to_string()),
+            };
+        }
+
+        let comparison = match self.next() {
+            Some(Token::Operator(comparison)) => comparison,
+            _ => return Err(format!("Expected an operator after '{}'.", identifier)),
+        };
+        let literal = match self.next() {
+            Some(Token::String(s)) => Literal::String(s),
+            Some(Token::Number(n)) => Literal::Number(n),
+            Some(Token::Identifier(i)) if i == "true" => Literal::Boolean(true),
+            Some(Token::Identifier(i)) if i == "false" => Literal::Boolean(false),
+            _ => return Err(format!("Expected a value after '{}'.", identifier)),
+        };
+
+        match (identifier.as_str(), literal) {
+            ("status", Literal::String(status)) if comparison.is_equality() => Ok(
+                SegmentExpression::Status(comparison, SubscriptionStatus::parse(&status)?),
+            ),
+            ("tag", Literal::String(tag)) if comparison.is_equality() => Ok(
+                SegmentExpression::Tag(comparison, SubscriberTag::parse(tag)?),
+            ),
+            ("subscribed_at", Literal::String(date)) => Ok(SegmentExpression::SubscribedAt(
+                comparison,
+                parse_date(&date)?,
+            )),
+            ("last_engaged_at", Literal::String(date)) => Ok(SegmentExpression::LastEngagedAt(
+                comparison,
+                parse_date(&date)?,
+            )),
+            (identifier, literal) => match identifier.strip_prefix("field.") {
+                Some(key) => Ok(SegmentExpression::Field(
+                    CustomFieldKey::parse(key.to_string())?,
+                    comparison,
+                    literal,
+                )),
+                None => Err(format!(
+                    "'{}' cannot be compared with {:?}.",
+                    identifier, literal
+                )),
+            },
+        }
+    }
+}
+
+fn parse_date(s: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map_err(|_| format!("{} is not a valid date, expected YYYY-MM-DD.", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::segment::{Comparison, Literal, SegmentExpression};
+    use crate::domain::{CustomFieldKey, SubscriberTag, SubscriptionStatus};
+    use claims::{assert_err, assert_ok};
+
+    fn tag(s: &str) -> SegmentExpression {
+        SegmentExpression::Tag(Comparison::Eq, SubscriberTag::parse(s.to_string()).unwrap())
+    }
+
+    #[test]
+    fn a_single_predicate_is_parsed() {
+        assert_eq!(SegmentExpression::parse(r#"tag = "vip""#), Ok(tag("vip")));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let parsed = SegmentExpression::parse(r#"tag = "a" OR tag = "b" AND tag = "c""#).unwrap();
+
+        assert_eq!(
+            parsed,
+            SegmentExpression::Or(
+                Box::new(tag("a")),
+                Box::new(SegmentExpression::And(
+                    Box::new(tag("b")),
+                    Box::new(tag("c"))
+                ))
+            )
+        );
+    }
+
+    #[test]
+    fn parentheses_and_not_are_supported() {
+        let parsed =
+            SegmentExpression::parse(r#"not (tag = "a" or tag = "b") and engaged_within 30"#)
+                .unwrap();
+
+        assert_eq!(
+            parsed,
+            SegmentExpression::And(
+                Box::new(SegmentExpression::Not(Box::new(SegmentExpression::Or(
+                    Box::new(tag("a")),
+                    Box::new(tag("b"))
+                )))),
+                Box::new(SegmentExpression::EngagedWithin(30))
+            )
+        );
+    }
+
+    #[test]
+    fn custom_field_comparisons_are_parsed() {
+        let parsed = SegmentExpression::parse("field.seats >= 10").unwrap();
+
+        assert_eq!(
+            parsed,
+            SegmentExpression::Field(
+                CustomFieldKey::parse("seats".to_string()).unwrap(),
+                Comparison::Ge,
+                Literal::Number(10.0)
+            )
+        );
+        assert_ok!(SegmentExpression::parse("field.trial != false"));
+        assert_ok!(SegmentExpression::parse(
+            r#"field.company = "Acme \"Inc\"""#
+        ));
+    }
+
+    #[test]
+    fn statuses_are_parsed_into_subscription_statuses() {
+        assert_eq!(
+            SegmentExpression::parse(r#"status != "bounced""#),
+            Ok(SegmentExpression::Status(
+                Comparison::Ne,
+                SubscriptionStatus::Bounced
+            ))
+        );
+    }
+
+    #[test]
+    fn dates_are_validated() {
+        assert_ok!(SegmentExpression::parse(r#"subscribed_at >= "2024-01-01""#));
+        assert_err!(SegmentExpression::parse(r#"subscribed_at >= "yesterday""#));
+        assert_err!(SegmentExpression::parse(r#"last_engaged_at < 5"#));
+    }
+
+    #[test]
+    fn deeply_nested_expressions_are_rejected() {
+        let nested = format!("{}tag = \"vip\"{}", "(".repeat(10_000), ")".repeat(10_000));
+        assert_err!(SegmentExpression::parse(&nested));
+        assert_err!(SegmentExpression::parse(&format!(
+            "{}tag = \"vip\"",
+            "not ".repeat(10_000)
+        )));
+
+        let shallow = format!("{}tag = \"vip\"{}", "(".repeat(32), ")".repeat(32));
+        assert_ok!(SegmentExpression::parse(&shallow));
+    }
+
+    #[test]
+    fn expressions_with_too_many_conditions_are_rejected() {
+        let chain = vec![r#"tag = "vip""#; 10_000].join(" or ");
+        assert_err!(SegmentExpression::parse(&chain));
+    }
+
+    #[test]
+    fn malformed_expressions_are_rejected() {
+        for expression in [
+            "",
+            "tag",
+            r#"tag = "vip" and"#,
+            r#"(tag = "vip""#,
+            r#"tag > "vip""#,
+            r#"status < "confirmed""#,
+            r#"status = "sleeping""#,
+            r#"colour = "blue""#,
+            r#"tag = "vip" tag = "beta""#,
+            "engaged_within -1",
+            "engaged_within 1.5",
+            r#"tag = "unterminated"#,
+        ] {
+            assert_err!(
+                SegmentExpression::parse(expression),
+                "{} should be rejected",
+                expression
+            );
+        }
+    }
+}
@@ -0,0 +1,118 @@
+/// One email of an automation sequence, sent `delay_minutes` after enrolment.
+#[derive(Debug, Clone)]
+pub struct SequenceStep {
+    pub delay_minutes: u32,
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+/// The ordered, validated steps of an automation sequence.
+#[derive(Debug, Clone)]
+pub struct SequenceSteps(Vec<SequenceStep>);
+
+impl SequenceSteps {
+    pub const MAX_STEPS: usize = 20;
+    pub const MAX_DELAY_MINUTES: u32 = 365 * 24 * 60;
+
+    pub fn parse(steps: Vec<SequenceStep>) -> Result<SequenceSteps, String> {
+        if steps.is_empty() || steps.len() > Self::MAX_STEPS {
+            return Err(format!(
+                "A sequence needs between 1 and {} steps.",
+                Self::MAX_STEPS
+            ));
+        }
+        for (position, step) in steps.iter().enumerate() {
+            if step.subject.trim().is_empty() {
+                return Err(format!("Step {} has no subject.", position + 1));
+            }
+            if step.html.trim().is_empty() || step.text.trim().is_empty() {
+                return Err(format!(
+                    "Step {} needs both an HTML and a text body.",
+                    position + 1
+                ));
+            }
+            if step.delay_minutes > Self::MAX_DELAY_MINUTES {
+                return Err(format!(
+                    "Step {} is delayed by more than a year.",
+                    position + 1
+                ));
+            }
+        }
+        if steps
+            .windows(2)
+            .any(|pair| pair[1].delay_minutes < pair[0].delay_minutes)
+        {
+            return Err("Step delays are counted from enrolment and cannot decrease.".to_string());
+        }
+
+        Ok(SequenceSteps(steps))
+    }
+}
+
+impl AsRef<[SequenceStep]> for SequenceSteps {
+    fn as_ref(&self) -> &[SequenceStep] {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::{SequenceStep, SequenceSteps};
+    use claims::{assert_err, assert_ok};
+
+    fn step(delay_minutes: u32) -> SequenceStep {
+        SequenceStep {
+            delay_minutes,
+            subject: "Welcome".to_string(),
+            html: "<p>Hi!</p>".to_string(),
+            text: "Hi!".to_string(),
+        }
+    }
+
+    #[test]
+    fn a_welcome_tips_and_survey_sequence_is_valid() {
+        assert_ok!(SequenceSteps::parse(vec![
+            step(0),
+            step(3 * 24 * 60),
+            step(14 * 24 * 60)
+        ]));
+    }
+
+    #[test]
+    fn steps_may_share_a_delay() {
+        assert_ok!(SequenceSteps::parse(vec![step(0), step(0)]));
+    }
+
+    #[test]
+    fn empty_and_oversized_sequences_are_rejected() {
+        assert_err!(SequenceSteps::parse(vec![]));
+        assert_err!(SequenceSteps::parse(vec![
+            step(0);
+            SequenceSteps::MAX_STEPS + 1
+        ]));
+    }
+
+    #[test]
+    fn decreasing_delays_are_rejected() {
+        assert_err!(SequenceSteps::parse(vec![step(60), step(0)]));
+    }
+
+    #[test]
+    fn steps_without_a_subject_or_body_are_rejected() {
+        let mut no_subject = step(0);
+        no_subject.subject = " ".to_string();
+        let mut no_text = step(0);
+        no_text.text = String::new();
+
+        assert_err!(SequenceSteps::parse(vec![no_subject]));
+        assert_err!(SequenceSteps::parse(vec![no_text]));
+    }
+
+    #[test]
+    fn delays_over_a_year_are_rejected() {
+        assert_err!(SequenceSteps::parse(vec![step(
+            SequenceSteps::MAX_DELAY_MINUTES + 1
+        )]));
+    }
+}
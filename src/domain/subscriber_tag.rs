@@ -0,0 +1,63 @@
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscriberTag(String);
+
+impl SubscriberTag {
+    pub fn parse(s: String) -> Result<SubscriberTag, String> {
+        let tag = s.trim().to_lowercase();
+        let is_empty = tag.is_empty();
+        let is_too_long = tag.chars().count() > 64;
+        let contains_forbidden_characters =
+            tag.chars().any(|c| c.is_control() || c == ',' || c == ';');
+
+        if !(is_empty || is_too_long || contains_forbidden_characters) {
+            Ok(SubscriberTag(tag))
+        } else {
+            Err(format!("{} is not a valid tag.", s))
+        }
+    }
+}
+
+impl std::fmt::Display for SubscriberTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl AsRef<str> for SubscriberTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberTag;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn empty_tags_are_rejected() {
+        assert_err!(SubscriberTag::parse("  ".to_string()));
+    }
+
+    #[test]
+    fn tags_longer_than_64_characters_are_rejected() {
+        assert_err!(SubscriberTag::parse("a".repeat(65)));
+    }
+
+    #[test]
+    fn tags_containing_separators_are_rejected() {
+        assert_err!(SubscriberTag::parse("vip,beta".to_string()));
+        assert_err!(SubscriberTag::parse("vip;beta".to_string()));
+    }
+
+    #[test]
+    fn tags_are_trimmed_and_lowercased() {
+        let tag = SubscriberTag::parse(" Early Adopter ".to_string()).unwrap();
+        assert_eq!(tag.as_ref(), "early adopter");
+    }
+
+    #[test]
+    fn a_valid_tag_is_parsed_successfully() {
+        assert_ok!(SubscriberTag::parse("plan:pro".to_string()));
+    }
+}
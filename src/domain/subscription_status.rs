@@ -0,0 +1,122 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriptionStatus {
+    PendingConfirmation,
+    Confirmed,
+    Unsubscribed,
+    Bounced,
+    Complained,
+}
+
+impl SubscriptionStatus {
+    pub const ALL: [SubscriptionStatus; 5] = [
+        SubscriptionStatus::PendingConfirmation,
+        SubscriptionStatus::Confirmed,
+        SubscriptionStatus::Unsubscribed,
+        SubscriptionStatus::Bounced,
+        SubscriptionStatus::Complained,
+    ];
+
+    pub fn parse(s: &str) -> Result<SubscriptionStatus, String> {
+        match s {
+            "pending_confirmation" => Ok(SubscriptionStatus::PendingConfirmation),
+            "confirmed" => Ok(SubscriptionStatus::Confirmed),
+            "unsubscribed" => Ok(SubscriptionStatus::Unsubscribed),
+            "bounced" => Ok(SubscriptionStatus::Bounced),
+            "complained" => Ok(SubscriptionStatus::Complained),
+            other => Err(format!("{} is not a valid subscription status.", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriptionStatus::PendingConfirmation => "pending_confirmation",
+            SubscriptionStatus::Confirmed => "confirmed",
+            SubscriptionStatus::Unsubscribed => "unsubscribed",
+            SubscriptionStatus::Bounced => "bounced",
+            SubscriptionStatus::Complained => "complained",
+        }
+    }
+
+    /// The statuses a subscriber can move to from this one. Leaving `Bounced` or
+    /// `Complained` always requires a fresh, confirmed opt-in.
+    pub fn allowed_transitions(&self) -> &'static [SubscriptionStatus] {
+        use SubscriptionStatus::*;
+
+        match self {
+            PendingConfirmation => &[Confirmed, Unsubscribed, Bounced, Complained],
+            Confirmed => &[Unsubscribed, Bounced, Complained],
+            Unsubscribed => &[PendingConfirmation, Bounced, Complained],
+            Bounced => &[PendingConfirmation],
+            Complained => &[PendingConfirmation],
+        }
+    }
+
+    pub fn can_transition_to(&self, next: SubscriptionStatus) -> bool {
+        self.allowed_transitions().contains(&next)
+    }
+
+    pub fn transition_to(self, next: SubscriptionStatus) -> Result<SubscriptionStatus, String> {
+        if self.can_transition_to(next) {
+            Ok(next)
+        } else {
+            Err(format!(
+                "A subscription cannot move from {} to {}.",
+                self, next
+            ))
+        }
+    }
+}
+
+impl std::fmt::Display for SubscriptionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriptionStatus::{self, *};
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn every_status_round_trips_through_its_string_form() {
+        for status in SubscriptionStatus::ALL {
+            assert_eq!(SubscriptionStatus::parse(status.as_str()), Ok(status));
+        }
+        assert_err!(SubscriptionStatus::parse("sleeping"));
+    }
+
+    #[test]
+    fn the_happy_path_is_allowed() {
+        assert_ok!(PendingConfirmation.transition_to(Confirmed));
+        assert_ok!(Confirmed.transition_to(Unsubscribed));
+        assert_ok!(Unsubscribed.transition_to(PendingConfirmation));
+    }
+
+    #[test]
+    fn delivery_problems_can_happen_from_any_active_status() {
+        for status in [PendingConfirmation, Confirmed, Unsubscribed] {
+            assert_ok!(status.transition_to(Bounced));
+            assert_ok!(status.transition_to(Complained));
+        }
+    }
+
+    #[test]
+    fn confirmation_cannot_be_skipped() {
+        for status in [Unsubscribed, Bounced, Complained] {
+            assert_err!(status.transition_to(Confirmed));
+        }
+    }
+
+    #[test]
+    fn confirmed_subscribers_cannot_go_back_to_pending() {
+        assert_err!(Confirmed.transition_to(PendingConfirmation));
+    }
+
+    #[test]
+    fn statuses_do_not_transition_to_themselves() {
+        for status in SubscriptionStatus::ALL {
+            assert_err!(status.transition_to(status));
+        }
+    }
+}
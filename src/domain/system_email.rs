@@ -0,0 +1,186 @@
+use crate::domain::{EmailMessage, Locale, Mailbox};
+
+/// An email sent on the application's behalf rather than as part of a newsletter.
+#[derive(Debug, Clone, Copy)]
+pub enum SystemEmail<'a> {
+    /// Asks a new subscriber to confirm their address.
+    Confirmation { confirmation_link: &'a str },
+    /// Sent instead of a confirmation to subscribers with nothing left to confirm.
+    AlreadySubscribed { preferences_link: &'a str },
+    /// Acknowledges that a subscriber left every list.
+    UnsubscribeConfirmation,
+}
+
+/// One entry of a message catalog. `{link}` is replaced by the email's link, if any.
+struct Message {
+    subject: &'static str,
+    html: &'static str,
+    text: &'static str,
+}
+
+impl SystemEmail<'_> {
+    /// The email in the recipient's language, or in English when it has no
+    /// translation.
+    pub fn message(&self, to: Mailbox, locale: Locale) -> EmailMessage {
+        let message = translate(catalog, locale, self);
+        let link = match self {
+            SystemEmail::Confirmation { confirmation_link } => confirmation_link,
+            SystemEmail::AlreadySubscribed { preferences_link } => preferences_link,
+            SystemEmail::UnsubscribeConfirmation => "",
+        };
+        EmailMessage::new(
+            to,
+            message.subject,
+            &message.html.replace("{link}", link),
+            &message.text.replace("{link}", link),
+        )
+    }
+}
+
+/// Looks `email` up in a message catalog, falling back to English.
+fn translate(
+    catalog: fn(Locale, &SystemEmail<'_>) -> Option<Message>,
+    locale: Locale,
+    email: &SystemEmail<'_>,
+) -> Message {
+    catalog(locale, email)
+        .or_else(|| catalog(Locale::English, email))
+        .expect("Every system email has an English version.")
+}
+
+fn catalog(locale: Locale, email: &SystemEmail<'_>) -> Option<Message> {
+    let message = match (locale, email) {
+        (Locale::English, SystemEmail::Confirmation { .. }) => Message {
+            subject: "Welcome!",
+            html: "Welcome to our newsletter! Visit <a href=\"{link}\">here</a> to confirm your subscription.",
+            text: "Welcome to our newsletter!\nVisit {link} to confirm your subscription.",
+        },
+        (Locale::English, SystemEmail::AlreadySubscribed { .. }) => Message {
+            subject: "You are already subscribed",
+            html: "You are already subscribed to our newsletter. Visit <a href=\"{link}\">your preferences</a> to change what you receive.",
+            text: "You are already subscribed to our newsletter.\nVisit {link} to change what you receive.",
+        },
+        (Locale::English, SystemEmail::UnsubscribeConfirmation) => Message {
+            subject: "You have been unsubscribed",
+            html: "You have been unsubscribed and will not receive our newsletter anymore. Sorry to see you go!",
+            text: "You have been unsubscribed and will not receive our newsletter anymore.\nSorry to see you go!",
+        },
+        (Locale::French, SystemEmail::Confirmation { .. }) => Message {
+            subject: "Bienvenue !",
+            html: "Bienvenue dans notre newsletter ! Cliquez <a href=\"{link}\">ici</a> pour confirmer votre abonnement.",
+            text: "Bienvenue dans notre newsletter !\nRendez-vous sur {link} pour confirmer votre abonnement.",
+        },
+        (Locale::French, SystemEmail::AlreadySubscribed { .. }) => Message {
+            subject: "Vous êtes déjà abonné",
+            html: "Vous êtes déjà abonné à notre newsletter. Rendez-vous sur <a href=\"{link}\">vos préférences</a> pour choisir ce que vous recevez.",
+            text: "Vous êtes déjà abonné à notre newsletter.\nRendez-vous sur {link} pour choisir ce que vous recevez.",
+        },
+        (Locale::French, SystemEmail::UnsubscribeConfirmation) => Message {
+            subject: "Votre désabonnement est confirmé",
+            html: "Votre désabonnement est confirmé, vous ne recevrez plus notre newsletter. Au revoir !",
+            text: "Votre désabonnement est confirmé, vous ne recevrez plus notre newsletter.\nAu revoir !",
+        },
+        (Locale::German, SystemEmail::Confirmation { .. }) => Message {
+            subject: "Willkommen!",
+            html: "Willkommen bei unserem Newsletter! Klicken Sie <a href=\"{link}\">hier</a>, um Ihr Abonnement zu bestätigen.",
+            text: "Willkommen bei unserem Newsletter!\nBesuchen Sie {link}, um Ihr Abonnement zu bestätigen.",
+        },
+        (Locale::German, SystemEmail::AlreadySubscribed { .. }) => Message {
+            subject: "Sie sind bereits angemeldet",
+            html: "Sie haben unseren Newsletter bereits abonniert. In <a href=\"{link}\">Ihren Einstellungen</a> können Sie festlegen, was Sie erhalten.",
+            text: "Sie haben unseren Newsletter bereits abonniert.\nUnter {link} können Sie festlegen, was Sie erhalten.",
+        },
+        (Locale::German, SystemEmail::UnsubscribeConfirmation) => Message {
+            subject: "Sie wurden abgemeldet",
+            html: "Sie wurden abgemeldet und erhalten unseren Newsletter nicht mehr. Schade, dass Sie gehen!",
+            text: "Sie wurden abgemeldet und erhalten unseren Newsletter nicht mehr.\nSchade, dass Sie gehen!",
+        },
+        (Locale::Spanish, SystemEmail::Confirmation { .. }) => Message {
+            subject: "¡Bienvenido!",
+            html: "¡Bienvenido a nuestro boletín! Haz clic <a href=\"{link}\">aquí</a> para confirmar tu suscripción.",
+            text: "¡Bienvenido a nuestro boletín!\nVisita {link} para confirmar tu suscripción.",
+        },
+        (Locale::Spanish, SystemEmail::AlreadySubscribed { .. }) => Message {
+            subject: "Ya estás suscrito",
+            html: "Ya estás suscrito a nuestro boletín. Visita <a href=\"{link}\">tus preferencias</a> para elegir lo que recibes.",
+            text: "Ya estás suscrito a nuestro boletín.\nVisita {link} para elegir lo que recibes.",
+        },
+        (Locale::Spanish, SystemEmail::UnsubscribeConfirmation) => Message {
+            subject: "Te has dado de baja",
+            html: "Te has dado de baja y ya no recibirás nuestro boletín. ¡Sentimos que te vayas!",
+            text: "Te has dado de baja y ya no recibirás nuestro boletín.\n¡Sentimos que te vayas!",
+        },
+    };
+    Some(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{catalog, translate, Message};
+    use crate::domain::{Locale, Mailbox, SubscriberEmail, SystemEmail};
+
+    fn recipient() -> Mailbox {
+        Mailbox::new(SubscriberEmail::parse("ursula@domain.com".to_string()).unwrap())
+    }
+
+    #[test]
+    fn system_emails_are_written_in_the_recipients_language() {
+        let email = SystemEmail::Confirmation {
+            confirmation_link: "https://example.com/confirm",
+        };
+
+        let message = email.message(recipient(), Locale::French);
+
+        assert_eq!(message.subject(), "Bienvenue !");
+        assert!(message
+            .html_part()
+            .contains(r#"<a href="https://example.com/confirm">ici</a>"#));
+        assert!(message.text_part().contains("https://example.com/confirm"));
+    }
+
+    #[test]
+    fn missing_translations_fall_back_to_english() {
+        fn english_only(locale: Locale, email: &SystemEmail<'_>) -> Option<Message> {
+            match locale {
+                Locale::English => catalog(locale, email),
+                _ => None,
+            }
+        }
+
+        let message = translate(
+            english_only,
+            Locale::Spanish,
+            &SystemEmail::UnsubscribeConfirmation,
+        );
+
+        assert_eq!(message.subject, "You have been unsubscribed");
+    }
+
+    #[test]
+    fn every_system_email_is_translated() {
+        let emails = [
+            SystemEmail::Confirmation {
+                confirmation_link: "https://example.com/confirm",
+            },
+            SystemEmail::AlreadySubscribed {
+                preferences_link: "https://example.com/preferences",
+            },
+            SystemEmail::UnsubscribeConfirmation,
+        ];
+        for locale in [
+            Locale::English,
+            Locale::French,
+            Locale::German,
+            Locale::Spanish,
+        ] {
+            for email in &emails {
+                assert!(
+                    catalog(locale, email).is_some(),
+                    "{:?} in {:?}",
+                    email,
+                    locale
+                );
+            }
+        }
+    }
+}
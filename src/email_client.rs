@@ -1,106 +1,841 @@
+use std::{collections::BTreeMap, time::Duration};
+
 use base64::{
     engine::general_purpose::{self},
     Engine,
 };
-use reqwest::Client;
+use reqwest::{header::HeaderMap, Client, Response, StatusCode};
 use secrecy::{ExposeSecret, Secret};
 
-use crate::domain::SubscriberEmail;
+use crate::{
+    circuit_breaker::{CircuitBreaker, CircuitState},
+    domain::{Attachment, EmailMessage, Mailbox, SenderIdentity, SubscriberEmail},
+    rate_limiter::{RateLimiter, RequestPermit},
+    smtp::SmtpRelay,
+};
 
 pub struct EmailClient {
     http_client: Client,
+    senders: SenderIdentities,
+    providers: Vec<EmailProvider>,
+}
+
+/// The configured sender identities, and which ones are used when a message
+/// does not pick its own.
+pub struct SenderIdentities {
+    identities: Vec<SenderIdentity>,
+    transactional: usize,
+    newsletter: usize,
+}
+
+impl SenderIdentities {
+    pub fn new(
+        identities: Vec<SenderIdentity>,
+        transactional: &str,
+        newsletter: &str,
+    ) -> Result<Self, String> {
+        for (i, identity) in identities.iter().enumerate() {
+            if identities[..i].iter().any(|other| other.id == identity.id) {
+                return Err(format!(
+                    "The {} sender identity is defined twice.",
+                    identity.id
+                ));
+            }
+        }
+        let position = |id: &str| {
+            identities
+                .iter()
+                .position(|identity| identity.id == id)
+                .ok_or_else(|| format!("{} is not a known sender identity.", id))
+        };
+
+        Ok(Self {
+            transactional: position(transactional)?,
+            newsletter: position(newsletter)?,
+            identities,
+        })
+    }
+
+    pub fn get(&self, id: &str) -> Option<&SenderIdentity> {
+        self.identities.iter().find(|identity| identity.id == id)
+    }
+
+    /// Sends confirmations and other mail a subscriber triggers.
+    pub fn transactional(&self) -> &SenderIdentity {
+        &self.identities[self.transactional]
+    }
+
+    /// Sends issues and sequences unless they pick another identity.
+    pub fn newsletter(&self) -> &SenderIdentity {
+        &self.identities[self.newsletter]
+    }
+}
+
+/// A Mailjet-compatible send API or an SMTP relay, tried in the order providers
+/// are configured.
+pub struct EmailProvider {
+    name: String,
+    transport: Transport,
+    rate_limiter: RateLimiter,
+    circuit_breaker: CircuitBreaker,
+}
+
+enum Transport {
+    Api(SendApi),
+    Smtp(SmtpRelay),
+}
+
+struct SendApi {
     base_url: String,
-    sender: SubscriberEmail,
     api_key_public: Secret<String>,
     api_key_private: Secret<String>,
 }
 
-impl EmailClient {
+impl EmailProvider {
     pub fn new(
+        name: String,
         base_url: String,
-        sender: SubscriberEmail,
         api_key_public: Secret<String>,
         api_key_private: Secret<String>,
+        rate_limiter: RateLimiter,
+        circuit_breaker: CircuitBreaker,
+    ) -> Self {
+        Self {
+            name,
+            transport: Transport::Api(SendApi {
+                base_url,
+                api_key_public,
+                api_key_private,
+            }),
+            rate_limiter,
+            circuit_breaker,
+        }
+    }
+
+    pub fn smtp(
+        name: String,
+        relay: SmtpRelay,
+        rate_limiter: RateLimiter,
+        circuit_breaker: CircuitBreaker,
+    ) -> Self {
+        Self {
+            name,
+            transport: Transport::Smtp(relay),
+            rate_limiter,
+            circuit_breaker,
+        }
+    }
+}
+
+impl SendApi {
+    fn authorization(&self) -> String {
+        let credentials: String = general_purpose::STANDARD.encode(format!(
+            "{}:{}",
+            self.api_key_public.expose_secret(),
+            self.api_key_private.expose_secret()
+        ));
+
+        format!("Basic {}", credentials)
+    }
+}
+
+/// A message the provider accepted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SentEmail {
+    /// The name of the provider that accepted it.
+    pub provider: String,
+    /// The provider's id for the message, which delivery events refer to, when the
+    /// provider returned one.
+    pub message_id: Option<String>,
+}
+
+impl EmailClient {
+    /// Sends through the first of `providers`, falling back to the next one on
+    /// retryable failures.
+    pub fn new(
+        senders: SenderIdentities,
         timeout: std::time::Duration,
+        providers: Vec<EmailProvider>,
     ) -> Self {
+        assert!(
+            !providers.is_empty(),
+            "An email client needs at least one provider."
+        );
         Self {
             http_client: Client::builder().timeout(timeout).build().unwrap(),
-            base_url,
-            sender,
-            api_key_public,
-            api_key_private,
+            senders,
+            providers,
         }
     }
 
+    pub fn senders(&self) -> &SenderIdentities {
+        &self.senders
+    }
+
+    /// Whether requests to each provider currently go through, in failover order.
+    pub fn circuit_states(&self) -> Vec<(&str, CircuitState)> {
+        self.providers
+            .iter()
+            .map(|provider| (provider.name.as_str(), provider.circuit_breaker.state()))
+            .collect()
+    }
+
+    #[tracing::instrument(
+        name = "Sending an email",
+        skip_all,
+        fields(
+            email.provider = tracing::field::Empty,
+            email.throughput = tracing::field::Empty,
+            email.in_flight = tracing::field::Empty
+        )
+    )]
+    pub async fn send(&self, message: &EmailMessage) -> Result<SentEmail, EmailClientError> {
+        let request_body = &SendEmailRequest::new(self.senders.transactional(), message);
+
+        let (provider, message_id) = self
+            .with_failover(|provider| async move {
+                match &provider.transport {
+                    Transport::Api(api) => self.send_email_with(provider, api, request_body).await,
+                    Transport::Smtp(relay) => self
+                        .relay_with(provider, relay, std::slice::from_ref(message))
+                        .await?
+                        .pop()
+                        .expect("The relay answers for every message."),
+                }
+            })
+            .await?;
+
+        Ok(SentEmail {
+            provider: provider.name.clone(),
+            message_id,
+        })
+    }
+
+    /// Sends a message with no more than a recipient, a subject and a body.
     pub async fn send_email(
         &self,
         recipient: &SubscriberEmail,
         subject: &str,
         html_part: &str,
         text_part: &str,
-    ) -> Result<(), reqwest::Error> {
-        let url = format!("{}/v3.1/send", self.base_url);
-        let request_body = SendEmailRequest {
-            from: SendEmailFrom {
-                email: self.sender.as_ref(),
-                name: self.sender.as_ref(),
-            },
-            to: vec![SendEmailTo {
-                email: recipient.as_ref(),
-                name: recipient.as_ref(),
-            }],
+    ) -> Result<SentEmail, EmailClientError> {
+        let message = EmailMessage::new(
+            Mailbox::new(recipient.clone()),
             subject,
-            text_part,
             html_part,
+            text_part,
+        );
+        self.send(&message).await
+    }
+
+    /// Sends through each provider in turn until one succeeds or fails in a way
+    /// that does not warrant failing over.
+    async fn with_failover<'a, T, F, Fut>(
+        &'a self,
+        mut send: F,
+    ) -> Result<(&'a EmailProvider, T), EmailClientError>
+    where
+        F: FnMut(&'a EmailProvider) -> Fut,
+        Fut: std::future::Future<Output = Result<T, EmailClientError>>,
+    {
+        let mut providers = self.providers.iter().peekable();
+        loop {
+            let provider = providers
+                .next()
+                .expect("An email client has at least one provider.");
+            match send(provider).await {
+                Err(error) if error.warrants_failover() && providers.peek().is_some() => {
+                    tracing::warn!(
+                        provider = provider.name,
+                        error.cause_chain = ?error,
+                        "Failing over to the next email provider."
+                    );
+                }
+                outcome => return outcome.map(|value| (provider, value)),
+            }
+        }
+    }
+
+    async fn send_email_with(
+        &self,
+        provider: &EmailProvider,
+        api: &SendApi,
+        request_body: &SendEmailRequest<'_>,
+    ) -> Result<Option<String>, EmailClientError> {
+        let response = self.post(provider, api, request_body, 1).await?;
+        let body = error_for_status(response).await?.bytes().await?;
+        // The message was accepted either way, an answer we cannot read only costs us
+        // the id that delivery events are matched on.
+        let message_id = serde_json::from_slice::<SendEmailResponse>(&body)
+            .ok()
+            .and_then(|response| response.first_message_id());
+        if message_id.is_none() {
+            tracing::warn!(
+                provider = provider.name,
+                "The email provider did not return a message id."
+            );
+        }
+
+        Ok(message_id)
+    }
+
+    /// Sends every email, packing up to `MAX_BATCH_SIZE` of them per request.
+    /// Returns one outcome per email, in order: the sent message, or why that
+    /// email was not sent. A failed request fails every email it carried.
+    pub async fn send_emails(
+        &self,
+        emails: &[EmailMessage],
+    ) -> Vec<Result<SentEmail, EmailClientError>> {
+        let mut outcomes = Vec::with_capacity(emails.len());
+        for batch in emails.chunks(MAX_BATCH_SIZE) {
+            match self.send_batch(batch).await {
+                Ok(results) => outcomes.extend(results),
+                Err(error) => {
+                    tracing::warn!(error.cause_chain = ?error, "Failed to send a batch of emails.");
+                    outcomes.extend(batch.iter().map(|_| Err(error.clone())));
+                }
+            }
+        }
+        outcomes
+    }
+
+    /// A batch fails over as a whole, messages the provider rejected are not
+    /// sent elsewhere.
+    #[tracing::instrument(
+        name = "Sending a batch of emails",
+        skip_all,
+        fields(
+            email.batch_size = batch.len(),
+            email.provider = tracing::field::Empty,
+            email.throughput = tracing::field::Empty,
+            email.in_flight = tracing::field::Empty
+        )
+    )]
+    async fn send_batch(
+        &self,
+        batch: &[EmailMessage],
+    ) -> Result<Vec<Result<SentEmail, EmailClientError>>, EmailClientError> {
+        let request_body = &SendBatchRequest {
+            messages: batch
+                .iter()
+                .map(|message| SendEmailRequest::new(self.senders.transactional(), message))
+                .collect(),
         };
 
-        let credentials: String = general_purpose::STANDARD.encode(format!(
-            "{}:{}",
-            self.api_key_public.expose_secret(),
-            self.api_key_private.expose_secret()
-        ));
+        let (provider, results) = self
+            .with_failover(|provider| async move {
+                match &provider.transport {
+                    Transport::Api(api) => self.send_batch_with(provider, api, request_body).await,
+                    Transport::Smtp(relay) => self.relay_with(provider, relay, batch).await,
+                }
+            })
+            .await?;
+
+        Ok(results
+            .into_iter()
+            .map(|result| {
+                result.map(|message_id| SentEmail {
+                    provider: provider.name.clone(),
+                    message_id,
+                })
+            })
+            .collect())
+    }
+
+    async fn send_batch_with(
+        &self,
+        provider: &EmailProvider,
+        api: &SendApi,
+        request_body: &SendBatchRequest<'_>,
+    ) -> Result<Vec<Result<Option<String>, EmailClientError>>, EmailClientError> {
+        let batch_size = request_body.messages.len();
+        let response = self
+            .post(provider, api, request_body, batch_size as u32)
+            .await?;
+        let status = response.status();
+        // Mailjet answers 400 when any message is rejected, with a result for each.
+        let response: SendBatchResponse = if status == StatusCode::BAD_REQUEST {
+            let headers = response.headers().clone();
+            let body = response.bytes().await?;
+            serde_json::from_slice(&body)
+                .map_err(|_| EmailClientError::from_response(status, &headers, &body))?
+        } else {
+            error_for_status(response).await?.json().await?
+        };
+        if response.messages.len() != batch_size {
+            return Err(EmailClientError::InvalidResponse(format!(
+                "{} answered with {} results for {} messages",
+                provider.name,
+                response.messages.len(),
+                batch_size
+            )));
+        }
+
+        Ok(response
+            .messages
+            .into_iter()
+            .map(|message| match message.status.as_str() {
+                "success" => Ok(message.to.first().and_then(SentRecipient::message_id)),
+                _ => Err(EmailClientError::rejected(ProviderErrorBody {
+                    errors: message.errors,
+                })),
+            })
+            .collect())
+    }
 
-        let auth_header_value = format!("Basic {}", credentials);
+    /// Posts to the provider's send endpoint once its rate limiter lets `messages`
+    /// through. A `429` pauses the limiter for as long as the provider asks. Fails
+    /// fast while the provider's circuit is open; timeouts, connection failures and
+    /// server errors count towards opening it.
+    async fn post(
+        &self,
+        provider: &EmailProvider,
+        api: &SendApi,
+        body: &impl serde::Serialize,
+        messages: u32,
+    ) -> Result<Response, EmailClientError> {
+        let _permit = self.admit(provider, messages).await?;
 
-        self.http_client
-            .post(&url)
-            .header("Authorization", auth_header_value)
-            .json(&request_body)
+        let response = self
+            .http_client
+            .post(format!("{}/v3.1/send", api.base_url))
+            .header("Authorization", api.authorization())
+            .json(body)
             .send()
-            .await?
-            .error_for_status()?;
+            .await;
+        match &response {
+            Ok(response) if !response.status().is_server_error() => {
+                provider.circuit_breaker.record_success()
+            }
+            _ => provider.circuit_breaker.record_failure(),
+        }
+        let response = response?;
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_retry_after);
+            tracing::warn!(
+                provider = provider.name,
+                ?retry_after,
+                "The email provider is rate limiting us."
+            );
+            provider
+                .rate_limiter
+                .pause(retry_after.unwrap_or(DEFAULT_RETRY_AFTER));
+        }
+
+        Ok(response)
+    }
+
+    /// Relays `batch` over one SMTP connection, under the same rate limiter and
+    /// circuit breaker rules as `post`.
+    async fn relay_with(
+        &self,
+        provider: &EmailProvider,
+        relay: &SmtpRelay,
+        batch: &[EmailMessage],
+    ) -> Result<Vec<Result<Option<String>, EmailClientError>>, EmailClientError> {
+        let _permit = self.admit(provider, batch.len() as u32).await?;
+
+        let messages: Vec<_> = batch
+            .iter()
+            .map(|message| {
+                let sender = message.sender().unwrap_or(self.senders.transactional());
+                (sender, message)
+            })
+            .collect();
+        let outcomes = relay.send_all(&messages).await;
+        match &outcomes {
+            Err(error) if error.is_retryable() => provider.circuit_breaker.record_failure(),
+            _ => provider.circuit_breaker.record_success(),
+        }
+
+        Ok(outcomes?
+            .into_iter()
+            .map(|outcome| outcome.map(Some))
+            .collect())
+    }
+
+    /// Waits until the provider's rate limiter lets `messages` through, failing
+    /// fast while its circuit is open.
+    async fn admit<'p>(
+        &self,
+        provider: &'p EmailProvider,
+        messages: u32,
+    ) -> Result<RequestPermit<'p>, EmailClientError> {
+        tracing::Span::current().record("email.provider", provider.name.as_str());
+        provider
+            .circuit_breaker
+            .try_acquire()
+            .map_err(|retry_after| EmailClientError::CircuitOpen { retry_after })?;
+        let permit = provider.rate_limiter.acquire(messages).await;
+        tracing::Span::current()
+            .record("email.throughput", provider.rate_limiter.throughput())
+            .record("email.in_flight", provider.rate_limiter.in_flight());
+
+        Ok(permit)
+    }
+}
+
+/// Mailjet accepts at most this many messages per send request.
+pub const MAX_BATCH_SIZE: usize = 50;
+
+/// How long to wait before sending again when the provider did not say.
+pub const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum EmailClientError {
+    #[error("The email provider did not answer in time.")]
+    Timeout,
+    #[error("Failed to reach the email provider: {0}")]
+    Connection(String),
+    #[error("The email provider rejected our credentials: {0}")]
+    Authentication(ProviderErrorBody),
+    #[error("The email provider is rate limiting us: {body}")]
+    RateLimited {
+        retry_after: Option<Duration>,
+        body: ProviderErrorBody,
+    },
+    #[error("The email provider rejected the recipient: {0}")]
+    InvalidRecipient(ProviderErrorBody),
+    #[error("The email provider answered {status}: {body}")]
+    Provider {
+        status: StatusCode,
+        body: ProviderErrorBody,
+    },
+    #[error("The email provider's answer could not be understood: {0}")]
+    InvalidResponse(String),
+    #[error("The email provider has been failing, not sending for now.")]
+    CircuitOpen { retry_after: Duration },
+    #[error("The SMTP relay answered {code}: {message}")]
+    Relay { code: u16, message: String },
+    #[error("The message could not be DKIM signed: {0}")]
+    Signing(String),
+}
+
+impl EmailClientError {
+    /// Whether the same email may go through if sent again later.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Timeout
+            | Self::Connection(_)
+            | Self::RateLimited { .. }
+            | Self::CircuitOpen { .. } => true,
+            // SMTP's transient failures.
+            Self::Relay { code, .. } => (400..500).contains(code),
+            _ => false,
+        }
+    }
+
+    /// Whether another provider may send what this one failed to: anything
+    /// retryable, and server errors on the provider's side. Not a timeout, the
+    /// provider may have sent the message without us hearing back.
+    fn warrants_failover(&self) -> bool {
+        match self {
+            Self::Provider { status, .. } => status.is_server_error(),
+            Self::Timeout => false,
+            error => error.is_retryable(),
+        }
+    }
+
+    /// How long the provider asked us to wait before sending again.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::RateLimited { retry_after, .. } => *retry_after,
+            Self::CircuitOpen { retry_after } => Some(*retry_after),
+            _ => None,
+        }
+    }
+
+    fn from_response(status: StatusCode, headers: &HeaderMap, body: &[u8]) -> Self {
+        let body = ProviderErrorBody::parse(body);
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Self::Authentication(body),
+            StatusCode::TOO_MANY_REQUESTS => Self::RateLimited {
+                retry_after: headers
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(parse_retry_after),
+                body,
+            },
+            StatusCode::BAD_REQUEST => Self::rejected(body),
+            status => Self::Provider { status, body },
+        }
+    }
 
-        Ok(())
+    fn rejected(body: ProviderErrorBody) -> Self {
+        if body.is_about_recipient() {
+            Self::InvalidRecipient(body)
+        } else {
+            Self::Provider {
+                status: StatusCode::BAD_REQUEST,
+                body,
+            }
+        }
+    }
+}
+
+impl From<reqwest::Error> for EmailClientError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            Self::Timeout
+        } else if error.is_decode() {
+            Self::InvalidResponse(format!("{:#}", anyhow::Error::from(error)))
+        } else {
+            Self::Connection(format!("{:#}", anyhow::Error::from(error)))
+        }
+    }
+}
+
+async fn error_for_status(response: Response) -> Result<Response, EmailClientError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let headers = response.headers().clone();
+    let body = response.bytes().await?;
+
+    Err(EmailClientError::from_response(status, &headers, &body))
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    let wait = date.with_timezone(&chrono::Utc) - chrono::Utc::now();
+    Some(wait.to_std().unwrap_or(Duration::ZERO))
+}
+
+/// The error details from the provider's answer, empty if it sent none.
+#[derive(Debug, Clone, Default)]
+pub struct ProviderErrorBody {
+    pub errors: Vec<ProviderError>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ProviderError {
+    #[serde(default)]
+    pub error_code: Option<String>,
+    pub error_message: String,
+    /// The request fields the error is about, e.g. `To[0].Email`.
+    #[serde(default)]
+    pub error_related_to: Vec<String>,
+}
+
+impl ProviderErrorBody {
+    /// Mailjet reports request-wide errors at the top level and message errors
+    /// under `Messages`.
+    fn parse(body: &[u8]) -> Self {
+        #[derive(serde::Deserialize)]
+        #[serde(untagged)]
+        enum ErrorResponse {
+            Messages {
+                #[serde(rename = "Messages")]
+                messages: Vec<BatchMessageResult>,
+            },
+            Error(ProviderError),
+        }
+
+        let errors = match serde_json::from_slice(body) {
+            Ok(ErrorResponse::Messages { messages }) => {
+                messages.into_iter().flat_map(|m| m.errors).collect()
+            }
+            Ok(ErrorResponse::Error(error)) => vec![error],
+            Err(_) => Vec::new(),
+        };
+        Self { errors }
+    }
+
+    fn is_about_recipient(&self) -> bool {
+        self.errors.iter().any(|error| {
+            error
+                .error_related_to
+                .iter()
+                .any(|field| field.starts_with("To"))
+        })
+    }
+}
+
+impl std::fmt::Display for ProviderErrorBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.errors.is_empty() {
+            return write!(f, "no error details");
+        }
+        let messages: Vec<_> = self
+            .errors
+            .iter()
+            .map(|error| match &error.error_code {
+                Some(code) => format!("{} ({})", error.error_message, code),
+                None => error.error_message.clone(),
+            })
+            .collect();
+        write!(f, "{}", messages.join("; "))
     }
 }
 
 #[derive(serde::Serialize)]
 #[serde(rename_all = "PascalCase")]
 struct SendEmailRequest<'a> {
-    from: SendEmailFrom<'a>,
-    to: Vec<SendEmailTo<'a>>,
+    from: SendEmailAddress<'a>,
+    to: Vec<SendEmailAddress<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    cc: Vec<SendEmailAddress<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    bcc: Vec<SendEmailAddress<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_to: Option<SendEmailAddress<'a>>,
     subject: &'a str,
     text_part: &'a str,
     html_part: &'a str,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    headers: BTreeMap<&'a str, &'a str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<SendEmailAttachment<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    inlined_attachments: Vec<SendEmailAttachment<'a>>,
+}
+
+impl<'a> SendEmailRequest<'a> {
+    /// `default_sender` is used unless the message has its own.
+    fn new(default_sender: &'a SenderIdentity, message: &'a EmailMessage) -> Self {
+        let sender = message.sender().unwrap_or(default_sender);
+        let (inlined, attachments): (Vec<_>, Vec<_>) = message
+            .attachments()
+            .iter()
+            .partition(|attachment| attachment.content_id().is_some());
+        Self {
+            from: (&sender.from).into(),
+            to: vec![message.to().into()],
+            cc: message.cc().iter().map(Into::into).collect(),
+            bcc: message.bcc().iter().map(Into::into).collect(),
+            reply_to: message
+                .reply_to()
+                .or(sender.reply_to.as_ref())
+                .map(Into::into),
+            subject: message.subject(),
+            text_part: message.text_part(),
+            html_part: message.html_part(),
+            headers: message
+                .headers()
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .collect(),
+            attachments: attachments.into_iter().map(Into::into).collect(),
+            inlined_attachments: inlined.into_iter().map(Into::into).collect(),
+        }
+    }
 }
 
 #[derive(serde::Serialize)]
 #[serde(rename_all = "PascalCase")]
-struct SendEmailFrom<'a> {
+struct SendEmailAddress<'a> {
     email: &'a str,
-    name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'a str>,
+}
+
+impl<'a> From<&'a Mailbox> for SendEmailAddress<'a> {
+    fn from(mailbox: &'a Mailbox) -> Self {
+        Self {
+            email: mailbox.email.as_ref(),
+            name: mailbox.name.as_ref().map(AsRef::as_ref),
+        }
+    }
 }
 
 #[derive(serde::Serialize)]
 #[serde(rename_all = "PascalCase")]
-struct SendEmailTo<'a> {
-    email: &'a str,
-    name: &'a str,
+struct SendEmailAttachment<'a> {
+    content_type: &'a str,
+    filename: &'a str,
+    #[serde(rename = "ContentID", skip_serializing_if = "Option::is_none")]
+    content_id: Option<&'a str>,
+    base64_content: String,
+}
+
+impl<'a> From<&'a Attachment> for SendEmailAttachment<'a> {
+    fn from(attachment: &'a Attachment) -> Self {
+        Self {
+            content_type: attachment.content_type(),
+            filename: attachment.filename(),
+            content_id: attachment.content_id(),
+            base64_content: general_purpose::STANDARD.encode(attachment.content()),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendBatchRequest<'a> {
+    messages: Vec<SendEmailRequest<'a>>,
+}
+
+/// Mailjet answers with one message per request and one entry per recipient.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailResponse {
+    #[serde(default)]
+    messages: Vec<SentMessage>,
+}
+
+impl SendEmailResponse {
+    fn first_message_id(&self) -> Option<String> {
+        self.messages
+            .iter()
+            .flat_map(|message| &message.to)
+            .find_map(SentRecipient::message_id)
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SentMessage {
+    #[serde(default)]
+    to: Vec<SentRecipient>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendBatchResponse {
+    messages: Vec<BatchMessageResult>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BatchMessageResult {
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    to: Vec<SentRecipient>,
+    #[serde(default)]
+    errors: Vec<ProviderError>,
+}
+
+#[derive(serde::Deserialize)]
+struct SentRecipient {
+    #[serde(rename = "MessageID", default)]
+    message_id: Option<serde_json::Value>,
+}
+
+impl SentRecipient {
+    /// Mailjet sends a number, which is kept as text so other shapes still fit.
+    fn message_id(&self) -> Option<String> {
+        match self.message_id.as_ref()? {
+            serde_json::Value::Number(n) => Some(n.to_string()),
+            serde_json::Value::String(s) if !s.is_empty() => Some(s.clone()),
+            _ => None,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use claims::{assert_err, assert_ok};
+    use claims::{assert_err, assert_ok, assert_ok_eq};
     use fake::{
         faker::{
             internet::en::SafeEmail,
@@ -114,7 +849,18 @@ mod tests {
         Mock, MockServer, Request, ResponseTemplate,
     };
 
-    use crate::{domain::SubscriberEmail, email_client::EmailClient};
+    use crate::{
+        circuit_breaker::{CircuitBreaker, CircuitState},
+        domain::{
+            Attachment, EmailMessage, Mailbox, SenderIdentity, SubscriberEmail, SubscriberName,
+        },
+        email_client::{
+            EmailClient, EmailClientError, EmailProvider, SenderIdentities, SentEmail,
+            MAX_BATCH_SIZE,
+        },
+        rate_limiter::RateLimiter,
+        smtp::tests::fake_relay,
+    };
 
     struct SendEmailBodyMatcher;
     impl wiremock::Match for SendEmailBodyMatcher {
@@ -131,9 +877,6 @@ mod tests {
                     && body.get("To").unwrap().as_array().unwrap()[0]
                         .get("Email")
                         .is_some()
-                    && body.get("To").unwrap().as_array().unwrap()[0]
-                        .get("Name")
-                        .is_some()
                     && body.get("From").unwrap().get("Email").is_some()
                     && body.get("From").unwrap().get("Name").is_some()
             } else {
@@ -154,13 +897,71 @@ mod tests {
         SubscriberEmail::parse(SafeEmail().fake()).unwrap()
     }
 
-    fn email_client(base_url: String) -> EmailClient {
-        EmailClient::new(
+    fn sent_response(message_id: u64) -> ResponseTemplate {
+        ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Messages": [{
+                "Status": "success",
+                "To": [{
+                    "Email": "recipient@example.com",
+                    "MessageUUID": "123e4567-e89b-12d3-a456-426614174000",
+                    "MessageID": message_id,
+                    "MessageHref": format!("https://api.mailjet.com/v3/message/{}", message_id)
+                }]
+            }]
+        }))
+    }
+
+    fn provider(name: &str, base_url: String) -> EmailProvider {
+        EmailProvider::new(
+            name.into(),
             base_url,
-            email(),
             Secret::new(Faker.fake()),
             Secret::new(Faker.fake()),
+            RateLimiter::new(100, 10),
+            CircuitBreaker::new(3, std::time::Duration::from_secs(30)),
+        )
+    }
+
+    fn message(recipient: &SubscriberEmail) -> EmailMessage {
+        EmailMessage::new(
+            Mailbox::new(recipient.clone()),
+            "Subject",
+            "<p>Body</p>",
+            "Body",
+        )
+    }
+
+    fn senders() -> SenderIdentities {
+        let identity = |id: &str, name: &str| {
+            SenderIdentity::parse(id.into(), SafeEmail().fake(), name.into(), None).unwrap()
+        };
+        SenderIdentities::new(
+            vec![
+                identity("noreply", "Newsletter"),
+                identity("editor", "The Editor"),
+            ],
+            "noreply",
+            "editor",
+        )
+        .unwrap()
+    }
+
+    fn email_client(base_url: String) -> EmailClient {
+        EmailClient::new(
+            senders(),
+            std::time::Duration::from_millis(200),
+            vec![provider("primary", base_url)],
+        )
+    }
+
+    fn email_client_with_fallback(primary_url: String, secondary_url: String) -> EmailClient {
+        EmailClient::new(
+            senders(),
             std::time::Duration::from_millis(200),
+            vec![
+                provider("primary", primary_url),
+                provider("secondary", secondary_url),
+            ],
         )
     }
 
@@ -170,7 +971,7 @@ mod tests {
         let email_client = email_client(mock_server.uri());
 
         Mock::given(any())
-            .respond_with(ResponseTemplate::new(200))
+            .respond_with(sent_response(1))
             .expect(1)
             .mount(&mock_server)
             .await;
@@ -183,12 +984,12 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn send_email_fails_if_the_server_returns_a_500() {
+    async fn send_email_returns_the_provider_message_id() {
         let mock_server = MockServer::start().await;
         let email_client = email_client(mock_server.uri());
 
         Mock::given(any())
-            .respond_with(ResponseTemplate::new(500))
+            .respond_with(sent_response(288230376151711744))
             .expect(1)
             .mount(&mock_server)
             .await;
@@ -197,18 +998,22 @@ mod tests {
             .send_email(&email(), &subject(), &content(), &content())
             .await;
 
-        assert_err!(outcome);
+        assert_ok_eq!(
+            outcome,
+            SentEmail {
+                provider: "primary".into(),
+                message_id: Some("288230376151711744".into()),
+            }
+        );
     }
 
     #[tokio::test]
-    async fn send_email_times_out_if_the_server_takes_too_long() {
+    async fn send_email_succeeds_without_an_id_if_the_response_has_none() {
         let mock_server = MockServer::start().await;
         let email_client = email_client(mock_server.uri());
 
-        let response = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180));
-
         Mock::given(any())
-            .respond_with(response)
+            .respond_with(ResponseTemplate::new(200))
             .expect(1)
             .mount(&mock_server)
             .await;
@@ -217,30 +1022,732 @@ mod tests {
             .send_email(&email(), &subject(), &content(), &content())
             .await;
 
-        assert_err!(outcome);
+        assert_eq!(assert_ok!(outcome).message_id, None);
     }
 
     #[tokio::test]
-    async fn send_email_sends_the_expected_request() {
-        // ARRANGE
+    async fn send_email_takes_the_first_message_id_from_a_longer_response() {
         let mock_server = MockServer::start().await;
         let email_client = email_client(mock_server.uri());
 
-        Mock::given(header_exists("Authorization"))
-            .and(header("Content-Type", "application/json"))
-            .and(path("/v3.1/send"))
-            .and(method("POST"))
-            .and(SendEmailBodyMatcher)
-            .respond_with(ResponseTemplate::new(200))
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "Messages": [
+                    {"Status": "success", "To": [], "Cc": []},
+                    {"Status": "success", "To": [{"MessageID": 7}, {"MessageID": 8}]}
+                ]
+            })))
             .expect(1)
             .mount(&mock_server)
             .await;
 
-        // ACT
-        let _ = email_client
+        let outcome = email_client
             .send_email(&email(), &subject(), &content(), &content())
             .await;
 
-        // ASSERT (the Mock::given will assert at the end)
+        assert_eq!(assert_ok!(outcome).message_id.as_deref(), Some("7"));
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_returns_a_500() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_the_server_takes_too_long() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        let response = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180));
+
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_sends_the_expected_request() {
+        // ARRANGE
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("Authorization"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/v3.1/send"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(sent_response(1))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // ACT
+        let _ = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // ASSERT (the Mock::given will assert at the end)
+    }
+
+    /// Answers every batch with a success per message, numbered from 1.
+    struct BatchResponder;
+    impl wiremock::Respond for BatchResponder {
+        fn respond(&self, request: &Request) -> ResponseTemplate {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            let messages: Vec<_> = (1..=body["Messages"].as_array().unwrap().len())
+                .map(|id| serde_json::json!({ "Status": "success", "To": [{ "MessageID": id }] }))
+                .collect();
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({ "Messages": messages }))
+        }
+    }
+
+    #[tokio::test]
+    async fn send_emails_packs_up_to_the_batch_size_per_request() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipients: Vec<_> = (0..MAX_BATCH_SIZE + 1).map(|_| email()).collect();
+        let emails: Vec<_> = recipients.iter().map(message).collect();
+
+        Mock::given(path("/v3.1/send"))
+            .and(method("POST"))
+            .respond_with(BatchResponder)
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let outcomes = email_client.send_emails(&emails).await;
+
+        assert_eq!(outcomes.len(), MAX_BATCH_SIZE + 1);
+        assert!(outcomes.iter().all(|outcome| outcome.is_ok()));
+        let requests = mock_server.received_requests().await.unwrap();
+        let sizes: Vec<_> = requests
+            .iter()
+            .map(|request| {
+                let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+                body["Messages"].as_array().unwrap().len()
+            })
+            .collect();
+        assert_eq!(sizes, vec![MAX_BATCH_SIZE, 1]);
+    }
+
+    #[tokio::test]
+    async fn send_emails_reports_each_message_outcome() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let (accepted, rejected) = (email(), email());
+        let emails: Vec<_> = [&accepted, &rejected].into_iter().map(message).collect();
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "Messages": [
+                    { "Status": "success", "To": [{ "MessageID": 42 }] },
+                    {
+                        "Status": "error",
+                        "Errors": [{
+                            "ErrorCode": "send-0003",
+                            "ErrorMessage": "Invalid email address.",
+                            "ErrorRelatedTo": ["To[0].Email"],
+                        }],
+                    },
+                ]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcomes = email_client.send_emails(&emails).await;
+
+        assert_eq!(
+            outcomes[0].as_ref().unwrap().message_id.as_deref(),
+            Some("42")
+        );
+        assert!(matches!(
+            &outcomes[1],
+            Err(EmailClientError::InvalidRecipient(body))
+                if body.errors[0].error_message == "Invalid email address."
+        ));
+    }
+
+    #[tokio::test]
+    async fn send_emails_fails_every_message_of_a_failed_request() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipients = [email(), email()];
+        let emails: Vec<_> = recipients.iter().map(message).collect();
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcomes = email_client.send_emails(&emails).await;
+
+        assert_eq!(outcomes.len(), 2);
+        assert!(outcomes
+            .iter()
+            .all(|outcome| matches!(outcome, Err(EmailClientError::Provider { .. }))));
+    }
+
+    async fn send_email_error(response: ResponseTemplate) -> EmailClientError {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await
+            .unwrap_err()
+    }
+
+    #[tokio::test]
+    async fn send_email_reports_a_timeout() {
+        let error = send_email_error(
+            ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180)),
+        )
+        .await;
+
+        assert!(matches!(error, EmailClientError::Timeout));
+        assert!(error.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn send_email_reports_a_connection_failure() {
+        let email_client = email_client("http://127.0.0.1:1".to_string());
+
+        let error = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, EmailClientError::Connection(_)));
+        assert!(error.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn send_email_reports_an_authentication_failure_with_its_details() {
+        let error = send_email_error(ResponseTemplate::new(401).set_body_json(serde_json::json!({
+            "ErrorIdentifier": "d8f5c4a3-0000-0000-0000-000000000000",
+            "StatusCode": 401,
+            "ErrorMessage": "API key authentication/authorization failure.",
+        })))
+        .await;
+
+        let EmailClientError::Authentication(body) = &error else {
+            panic!("Expected an authentication error, got {:?}", error);
+        };
+        assert_eq!(
+            body.errors[0].error_message,
+            "API key authentication/authorization failure."
+        );
+        assert!(!error.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn send_email_reports_rate_limiting_with_the_retry_delay() {
+        let error =
+            send_email_error(ResponseTemplate::new(429).insert_header("Retry-After", "120")).await;
+
+        assert!(matches!(error, EmailClientError::RateLimited { .. }));
+        assert_eq!(
+            error.retry_after(),
+            Some(std::time::Duration::from_secs(120))
+        );
+        assert!(error.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn send_email_reports_an_invalid_recipient() {
+        let error = send_email_error(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+            "Messages": [{
+                "Status": "error",
+                "Errors": [{
+                    "ErrorCode": "send-0003",
+                    "StatusCode": 400,
+                    "ErrorMessage": "\"nobody@\" is an invalid email address.",
+                    "ErrorRelatedTo": ["To[0].Email"],
+                }],
+            }]
+        })))
+        .await;
+
+        assert!(matches!(error, EmailClientError::InvalidRecipient(_)));
+        assert!(!error.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn send_email_reports_other_provider_errors_with_their_status() {
+        let error = send_email_error(ResponseTemplate::new(503)).await;
+
+        assert!(matches!(
+            error,
+            EmailClientError::Provider { status, .. } if status.as_u16() == 503
+        ));
+    }
+
+    #[tokio::test]
+    async fn a_429_pauses_sending_for_the_retry_after_delay() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(sent_response(1))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let first = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+        let start = std::time::Instant::now();
+        let second = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_err!(first);
+        assert_ok!(second);
+        assert!(start.elapsed() >= std::time::Duration::from_millis(900));
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_fast_once_the_circuit_is_open() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(3)
+            .mount(&mock_server)
+            .await;
+
+        for _ in 0..3 {
+            assert_err!(
+                email_client
+                    .send_email(&email(), &subject(), &content(), &content())
+                    .await
+            );
+        }
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert!(matches!(outcome, Err(EmailClientError::CircuitOpen { .. })));
+        assert_eq!(
+            email_client.circuit_states(),
+            vec![("primary", CircuitState::Open)]
+        );
+    }
+
+    #[tokio::test]
+    async fn client_errors_do_not_open_the_circuit() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(401))
+            .expect(4)
+            .mount(&mock_server)
+            .await;
+
+        for _ in 0..4 {
+            let outcome = email_client
+                .send_email(&email(), &subject(), &content(), &content())
+                .await;
+            assert!(matches!(outcome, Err(EmailClientError::Authentication(_))));
+        }
+
+        assert_eq!(
+            email_client.circuit_states(),
+            vec![("primary", CircuitState::Closed)]
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_over_to_the_next_provider_on_retryable_failures() {
+        let primary = MockServer::start().await;
+        let secondary = MockServer::start().await;
+        let email_client = email_client_with_fallback(primary.uri(), secondary.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(sent_response(7))
+            .expect(1)
+            .mount(&secondary)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_ok_eq!(
+            outcome,
+            SentEmail {
+                provider: "secondary".into(),
+                message_id: Some("7".into()),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_over_to_an_smtp_relay() {
+        let primary = MockServer::start().await;
+        let (relay, transcript) = fake_relay().await;
+        let email_client = EmailClient::new(
+            senders(),
+            std::time::Duration::from_millis(200),
+            vec![
+                provider("primary", primary.uri()),
+                EmailProvider::smtp(
+                    "relay".into(),
+                    relay,
+                    RateLimiter::new(100, 10),
+                    CircuitBreaker::new(3, std::time::Duration::from_secs(30)),
+                ),
+            ],
+        );
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        let recipient = email();
+
+        let sent = email_client
+            .send_email(&recipient, "Subject", "<p>Body</p>", "Body")
+            .await
+            .unwrap();
+
+        assert_eq!(sent.provider, "relay");
+        assert!(sent.message_id.is_some());
+        let transcript = transcript.await.unwrap();
+        assert!(transcript.contains(&format!("RCPT TO:<{}>", recipient.as_ref())));
+    }
+
+    #[tokio::test]
+    async fn send_email_does_not_fail_over_on_a_timeout() {
+        let primary = MockServer::start().await;
+        let secondary = MockServer::start().await;
+        let email_client = email_client_with_fallback(primary.uri(), secondary.uri());
+        Mock::given(any())
+            .respond_with(sent_response(7).set_delay(std::time::Duration::from_secs(180)))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(sent_response(8))
+            .expect(0)
+            .mount(&secondary)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert!(matches!(outcome, Err(EmailClientError::Timeout)));
+    }
+
+    #[tokio::test]
+    async fn send_email_does_not_fail_over_when_the_recipient_is_rejected() {
+        let primary = MockServer::start().await;
+        let secondary = MockServer::start().await;
+        let email_client = email_client_with_fallback(primary.uri(), secondary.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "ErrorMessage": "Invalid email address",
+                "ErrorRelatedTo": ["To[0].Email"]
+            })))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(sent_response(7))
+            .expect(0)
+            .mount(&secondary)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert!(matches!(
+            outcome,
+            Err(EmailClientError::InvalidRecipient(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn send_email_reports_the_last_failure_when_every_provider_fails() {
+        let primary = MockServer::start().await;
+        let secondary = MockServer::start().await;
+        let email_client = email_client_with_fallback(primary.uri(), secondary.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "30"))
+            .expect(1)
+            .mount(&secondary)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert!(matches!(outcome, Err(EmailClientError::RateLimited { .. })));
+    }
+
+    #[tokio::test]
+    async fn an_open_circuit_sends_straight_to_the_next_provider() {
+        let primary = MockServer::start().await;
+        let secondary = MockServer::start().await;
+        let email_client = email_client_with_fallback(primary.uri(), secondary.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(3)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(sent_response(7))
+            .expect(5)
+            .mount(&secondary)
+            .await;
+
+        for _ in 0..5 {
+            let sent = email_client
+                .send_email(&email(), &subject(), &content(), &content())
+                .await
+                .unwrap();
+            assert_eq!(sent.provider, "secondary");
+        }
+
+        assert_eq!(
+            email_client.circuit_states(),
+            vec![
+                ("primary", CircuitState::Open),
+                ("secondary", CircuitState::Closed)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn send_emails_fails_a_whole_batch_over_to_the_next_provider() {
+        let primary = MockServer::start().await;
+        let secondary = MockServer::start().await;
+        let email_client = email_client_with_fallback(primary.uri(), secondary.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(502))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(BatchResponder)
+            .expect(1)
+            .mount(&secondary)
+            .await;
+        let recipients: Vec<_> = (0..3).map(|_| email()).collect();
+        let emails: Vec<_> = recipients.iter().map(message).collect();
+
+        let outcomes = email_client.send_emails(&emails).await;
+
+        assert!(outcomes
+            .iter()
+            .all(|outcome| outcome.as_ref().unwrap().provider == "secondary"));
+    }
+
+    #[tokio::test]
+    async fn send_serialises_every_part_of_the_message() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let named = |email: &str, name: &str| {
+            Mailbox::named(
+                SubscriberEmail::parse(email.into()).unwrap(),
+                SubscriberName::parse(name.into()).unwrap(),
+            )
+        };
+        let message = EmailMessage::builder(
+            named("ursula@example.com", "Ursula Le Guin"),
+            "Subject",
+            "<p>Body <img src=\"cid:logo\"></p>",
+            "Body",
+        )
+        .cc(Mailbox::new(
+            SubscriberEmail::parse("editor@example.com".into()).unwrap(),
+        ))
+        .bcc(named("archive@example.com", "Archive"))
+        .reply_to(named("editor@example.com", "The editor"))
+        .header("X-Campaign", "spring")
+        .attachment(
+            Attachment::file("notes.txt".into(), "text/plain".into(), b"notes".to_vec()).unwrap(),
+        )
+        .attachment(
+            Attachment::inline_image(
+                "logo".into(),
+                "logo.png".into(),
+                "image/png".into(),
+                b"png".to_vec(),
+            )
+            .unwrap(),
+        )
+        .build()
+        .unwrap();
+        Mock::given(any())
+            .respond_with(sent_response(1))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        assert_ok!(email_client.send(&message).await);
+
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["From"]["Name"], "Newsletter");
+        assert_eq!(
+            body["To"],
+            serde_json::json!([{ "Email": "ursula@example.com", "Name": "Ursula Le Guin" }])
+        );
+        assert_eq!(
+            body["Cc"],
+            serde_json::json!([{ "Email": "editor@example.com" }])
+        );
+        assert_eq!(body["Bcc"][0]["Name"], "Archive");
+        assert_eq!(body["ReplyTo"]["Email"], "editor@example.com");
+        assert_eq!(
+            body["Headers"],
+            serde_json::json!({ "X-Campaign": "spring" })
+        );
+        assert_eq!(
+            body["Attachments"],
+            serde_json::json!([{
+                "ContentType": "text/plain",
+                "Filename": "notes.txt",
+                "Base64Content": "bm90ZXM=",
+            }])
+        );
+        assert_eq!(
+            body["InlinedAttachments"],
+            serde_json::json!([{
+                "ContentType": "image/png",
+                "Filename": "logo.png",
+                "ContentID": "logo",
+                "Base64Content": "cG5n",
+            }])
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_leaves_out_what_the_message_does_not_have() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(any())
+            .respond_with(sent_response(1))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        assert_ok!(
+            email_client
+                .send_email(&email(), &subject(), &content(), &content())
+                .await
+        );
+
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        for key in [
+            "Cc",
+            "Bcc",
+            "ReplyTo",
+            "Headers",
+            "Attachments",
+            "InlinedAttachments",
+        ] {
+            assert!(body.get(key).is_none(), "{} should be left out", key);
+        }
+        assert!(body["To"][0].get("Name").is_none());
+    }
+
+    #[tokio::test]
+    async fn a_message_can_be_sent_from_another_identity() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let editor = SenderIdentity::parse(
+            "editor".into(),
+            "editor@example.com".into(),
+            "The Editor".into(),
+            Some("letters@example.com".into()),
+        )
+        .unwrap();
+        Mock::given(any())
+            .respond_with(sent_response(1))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let message = EmailMessage::new(Mailbox::new(email()), "Subject", "<p>Body</p>", "Body");
+        assert_ok!(
+            email_client
+                .send(&message.clone().with_sender(editor))
+                .await
+        );
+        assert_ok!(email_client.send(&message).await);
+
+        let requests = mock_server.received_requests().await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert_eq!(
+            body["From"],
+            serde_json::json!({ "Email": "editor@example.com", "Name": "The Editor" })
+        );
+        assert_eq!(body["ReplyTo"]["Email"], "letters@example.com");
+        let body: serde_json::Value = serde_json::from_slice(&requests[1].body).unwrap();
+        assert_eq!(body["From"]["Name"], "Newsletter");
+        assert!(body.get("ReplyTo").is_none());
+    }
+
+    #[test]
+    fn sender_identities_need_unique_ids_and_known_defaults() {
+        let identity = |id: &str| {
+            SenderIdentity::parse(id.into(), SafeEmail().fake(), "Newsletter".into(), None).unwrap()
+        };
+
+        assert!(SenderIdentities::new(vec![identity("noreply")], "noreply", "noreply").is_ok());
+        assert!(SenderIdentities::new(vec![identity("noreply")], "noreply", "editor").is_err());
+        assert!(SenderIdentities::new(
+            vec![identity("noreply"), identity("noreply")],
+            "noreply",
+            "noreply"
+        )
+        .is_err());
     }
 }
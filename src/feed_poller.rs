@@ -0,0 +1,339 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Context;
+use reqwest::{header, StatusCode};
+use secrecy::Secret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    configuration::Settings,
+    domain::{FeedItem, FeedSourceMode, IssueVisibility, ListSlug, NewsletterTemplate},
+    email_client::EmailClient,
+    issue_delivery_worker::{ExecutionOutcome, IssueSender},
+    routes::{escape_html, publish_issue, Delivery, NewIssue},
+    startup::get_connection_pool,
+};
+
+struct DueFeedSource {
+    id: Uuid,
+    url: String,
+    mode: String,
+    list_id: Option<Uuid>,
+    list_slug: Option<String>,
+    subject_template: String,
+    html_template: String,
+    text_template: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    first_poll: bool,
+}
+
+enum Fetched {
+    NotModified,
+    Feed {
+        items: Vec<FeedItem>,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    },
+}
+
+/// Polls one feed source that is due and turns its new items into drafts or issues.
+/// The first successful fetch of a source only records the items it already has.
+///
+/// The source is claimed by moving its `last_polled_at` forward before it is fetched,
+/// so no lock is held while waiting on the feed or publishing its items.
+#[tracing::instrument(skip_all, fields(feed_url = tracing::field::Empty), err)]
+pub async fn try_poll_feed_source(
+    sender: &IssueSender<'_>,
+    http_client: &reqwest::Client,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = sender.pool.begin().await?;
+    let Some(source) = sqlx::query_as!(
+        DueFeedSource,
+        r#"SELECT f.id, f.url, f.mode, f.list_id, l.slug AS "list_slug?", f.subject_template,
+               f.html_template, f.text_template, f.etag, f.last_modified,
+               f.last_fetched_at IS NULL AS "first_poll!"
+           FROM feed_sources f
+           LEFT JOIN lists l ON l.id = f.list_id
+           WHERE f.last_polled_at IS NULL
+               OR f.last_polled_at + make_interval(mins => f.poll_interval_minutes) <= now()
+           ORDER BY f.last_polled_at NULLS FIRST
+           FOR UPDATE OF f
+           SKIP LOCKED
+           LIMIT 1"#,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    sqlx::query!(
+        r#"UPDATE feed_sources SET last_polled_at = now() WHERE id = $1"#,
+        source.id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+    tracing::Span::current().record("feed_url", tracing::field::display(&source.url));
+
+    let error = match fetch_feed(http_client, &source).await {
+        Ok(Fetched::NotModified) => {
+            sqlx::query!(
+                r#"UPDATE feed_sources SET last_fetched_at = now() WHERE id = $1"#,
+                source.id
+            )
+            .execute(sender.pool)
+            .await?;
+            None
+        }
+        Ok(Fetched::Feed {
+            items,
+            etag,
+            last_modified,
+        }) => {
+            let error = process_items(sender, &source, items).await?;
+            sqlx::query!(
+                r#"UPDATE feed_sources
+                   SET last_fetched_at = now(), etag = $1, last_modified = $2
+                   WHERE id = $3"#,
+                etag,
+                last_modified,
+                source.id,
+            )
+            .execute(sender.pool)
+            .await?;
+            error
+        }
+        Err(error) => {
+            tracing::warn!(error.cause_chain = ?error, "Failed to fetch a feed.");
+            Some(format!("{:#}", error))
+        }
+    };
+    sqlx::query!(
+        r#"UPDATE feed_sources SET last_error = $1 WHERE id = $2"#,
+        error,
+        source.id,
+    )
+    .execute(sender.pool)
+    .await?;
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+async fn fetch_feed(
+    http_client: &reqwest::Client,
+    source: &DueFeedSource,
+) -> Result<Fetched, anyhow::Error> {
+    let mut request = http_client.get(&source.url);
+    if let Some(etag) = &source.etag {
+        request = request.header(header::IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = &source.last_modified {
+        request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+    }
+    let response = request.send().await?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(Fetched::NotModified);
+    }
+    let response = response.error_for_status()?;
+
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value: &header::HeaderValue| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let etag = header(header::ETAG);
+    let last_modified = header(header::LAST_MODIFIED);
+    let body = response.bytes().await?;
+    let feed = feed_rs::parser::parse(body.as_ref()).context("The feed could not be parsed")?;
+
+    let mut items: Vec<FeedItem> = feed
+        .entries
+        .into_iter()
+        .map(|entry| {
+            let link = entry
+                .links
+                .iter()
+                .find(|link| link.rel.as_deref().unwrap_or("alternate") == "alternate")
+                .or(entry.links.first())
+                .map(|link| link.href.clone())
+                .unwrap_or_default();
+            let summary = entry.summary.map(|text| text.content).unwrap_or_default();
+            FeedItem {
+                guid: entry.id,
+                title: entry
+                    .title
+                    .map(|text| text.content.trim().to_string())
+                    .filter(|title| !title.is_empty())
+                    .unwrap_or_else(|| "New post".to_string()),
+                link,
+                content: entry
+                    .content
+                    .and_then(|content| content.body)
+                    .unwrap_or_else(|| summary.clone()),
+                summary,
+                published_at: entry.published.or(entry.updated),
+            }
+        })
+        .collect();
+    // Oldest first, so several new items go out in the order they were written.
+    items.sort_by_key(|item| item.published_at);
+
+    Ok(Fetched::Feed {
+        items,
+        etag,
+        last_modified,
+    })
+}
+
+/// Records every unseen item and, after the first fetch, drafts or sends it. Returns
+/// the last error of an item that could not be published. An item is recorded as seen
+/// before it is published and never retried, so it cannot go out twice.
+async fn process_items(
+    sender: &IssueSender<'_>,
+    source: &DueFeedSource,
+    items: Vec<FeedItem>,
+) -> Result<Option<String>, anyhow::Error> {
+    let mode = FeedSourceMode::parse(&source.mode).map_err(anyhow::Error::msg)?;
+    let template = |template: &str| {
+        NewsletterTemplate::parse(template.to_string()).map_err(anyhow::Error::msg)
+    };
+    let subject_template = template(&source.subject_template)?;
+    let html_template = template(&source.html_template)?;
+    let text_template = template(&source.text_template)?;
+    let mut last_error = None;
+
+    for item in items {
+        let mut transaction = sender.pool.begin().await?;
+        let inserted = sqlx::query!(
+            r#"INSERT INTO feed_source_items (feed_source_id, guid, seen_at)
+               VALUES ($1, $2, now())
+               ON CONFLICT DO NOTHING"#,
+            source.id,
+            item.guid,
+        )
+        .execute(&mut *transaction)
+        .await?;
+        if inserted.rows_affected() == 0 || source.first_poll {
+            transaction.commit().await?;
+            continue;
+        }
+
+        let title = subject_template.render_text(&item);
+        let html = html_template.render_html(&item, escape_html);
+        let text = text_template.render_text(&item);
+        match mode {
+            FeedSourceMode::Draft => {
+                sqlx::query!(
+                    r#"INSERT INTO newsletter_drafts
+                       (id, title, text_content, html_content, list_id, feed_source_id, created_at)
+                       VALUES ($1, $2, $3, $4, $5, $6, now())"#,
+                    Uuid::new_v4(),
+                    title,
+                    text,
+                    html,
+                    source.list_id,
+                    source.id,
+                )
+                .execute(&mut *transaction)
+                .await?;
+                transaction.commit().await?;
+            }
+            FeedSourceMode::Send => {
+                transaction.commit().await?;
+                let list = source
+                    .list_slug
+                    .clone()
+                    .map(ListSlug::parse)
+                    .transpose()
+                    .map_err(anyhow::Error::msg)?;
+                let published = publish_issue(
+                    sender,
+                    NewIssue {
+                        title,
+                        html,
+                        text: Some(text),
+                        list,
+                        segment_id: None,
+                        ab_test: None,
+                        visibility: IssueVisibility::default_for(source.list_slug.is_some()),
+                        sender: None,
+                        translations: Vec::new(),
+                    },
+                    Delivery::Queued,
+                )
+                .await;
+                match published {
+                    Ok(issue_id) => {
+                        sqlx::query!(
+                            r#"UPDATE feed_source_items SET issue_id = $1
+                               WHERE feed_source_id = $2 AND guid = $3"#,
+                            issue_id,
+                            source.id,
+                            item.guid,
+                        )
+                        .execute(sender.pool)
+                        .await?;
+                    }
+                    Err(error) => {
+                        tracing::error!(
+                            error.cause_chain = ?error,
+                            feed_item = %item.guid,
+                            "Failed to publish a feed item."
+                        );
+                        last_error = Some(error.to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(last_error)
+}
+
+async fn poller_loop(
+    pool: PgPool,
+    email_client: Arc<EmailClient>,
+    base_url: String,
+    hmac_secret: Secret<String>,
+) -> Result<(), anyhow::Error> {
+    let sender = IssueSender {
+        pool: &pool,
+        email_client: &email_client,
+        base_url: &base_url,
+        hmac_secret: &hmac_secret,
+    };
+    let http_client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()?;
+
+    loop {
+        match try_poll_feed_source(&sender, &http_client).await {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+            }
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+pub async fn run_feed_poller_until_stopped(
+    configuration: Settings,
+    email_client: Arc<EmailClient>,
+) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+
+    poller_loop(
+        connection_pool,
+        email_client,
+        configuration.application.base_url,
+        configuration.application.hmac_secret,
+    )
+    .await
+}
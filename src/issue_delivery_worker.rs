@@ -0,0 +1,771 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Context;
+use secrecy::Secret;
+use sqlx::{postgres::types::PgInterval, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    configuration::Settings,
+    domain::{
+        pick_ab_test_winner, AbTestMetric, ClickToken, DeliveryStatus, EmailMessage, Locale,
+        Mailbox, SubscriberEmail, SubscriptionStatus,
+    },
+    email_client::{EmailClient, DEFAULT_RETRY_AFTER, MAX_BATCH_SIZE},
+    routes::{
+        escape_html, generate_tracking_token, get_suppressed_emails, is_suppressed,
+        record_delivery, with_open_pixel, with_tracked_links, NewDelivery,
+    },
+    startup::get_connection_pool,
+};
+
+/// A stored newsletter issue, ready to be sent.
+pub struct Issue {
+    pub id: Uuid,
+    pub title: String,
+    pub html: String,
+    pub text: String,
+    pub open_tracking: bool,
+    /// Id of the sender identity the issue goes out from, the newsletter one if unset.
+    pub sender: Option<String>,
+    /// Ids of the trackable links of the issue and its translations, by URL.
+    pub link_ids: HashMap<String, Uuid>,
+    /// Sent instead of the issue to subscribers with a matching locale.
+    pub translations: HashMap<Locale, Translation>,
+}
+
+/// A version of an issue in another language.
+pub struct Translation {
+    pub title: String,
+    pub html: String,
+    pub text: String,
+}
+
+pub struct Recipient {
+    pub id: Uuid,
+    pub email: SubscriberEmail,
+    pub locale: Locale,
+}
+
+/// One of the subjects tried by an A/B test.
+pub struct Variant {
+    pub id: Uuid,
+    pub subject: String,
+}
+
+/// An issue to send to one recipient, with the variant's subject if given.
+pub struct OutgoingIssue<'a> {
+    pub issue: &'a Issue,
+    pub recipient: &'a Recipient,
+    pub variant: Option<&'a Variant>,
+    /// Whether a retryable failure is returned rather than recorded.
+    pub may_retry: bool,
+}
+
+/// What became of one attempt to send an issue.
+pub enum SendOutcome {
+    /// The delivery was recorded, as sent or as failed.
+    Recorded,
+    /// The provider failed in a way that may pass, nothing was recorded.
+    RetryLater { retry_after: Option<Duration> },
+}
+
+/// Deliveries that keep failing with retryable errors are recorded as failed
+/// after this many retries.
+const MAX_RETRIES: i32 = 5;
+
+/// Everything needed to personalise and send an issue to one recipient.
+pub struct IssueSender<'a> {
+    pub pool: &'a PgPool,
+    pub email_client: &'a EmailClient,
+    pub base_url: &'a str,
+    pub hmac_secret: &'a Secret<String>,
+}
+
+impl IssueSender<'_> {
+    /// Sends each issue, in the recipient's language when translated and with the
+    /// variant's subject if given, and records the deliveries. The emails go to the
+    /// provider in batches; the outcomes come back one per issue, in order.
+    /// A provider failure is recorded as a failed delivery, not returned, unless
+    /// `may_retry` is set and the failure is retryable.
+    #[tracing::instrument(name = "Sending issues", skip_all, fields(n_emails = outgoing.len()))]
+    pub async fn send_all(
+        &self,
+        outgoing: &[OutgoingIssue<'_>],
+    ) -> Result<Vec<SendOutcome>, sqlx::Error> {
+        let mut messages = Vec::with_capacity(outgoing.len());
+        let mut tracking = Vec::with_capacity(outgoing.len());
+        for issue in outgoing {
+            let delivery_id = Uuid::new_v4();
+            let tracking_token = issue.issue.open_tracking.then(generate_tracking_token);
+            messages.push(self.message(issue, delivery_id, tracking_token.as_deref()));
+            tracking.push((delivery_id, tracking_token));
+        }
+
+        let results = self.email_client.send_emails(&messages).await;
+        let mut outcomes = Vec::with_capacity(outgoing.len());
+        for ((outgoing, (delivery_id, tracking_token)), result) in
+            outgoing.iter().zip(tracking).zip(results)
+        {
+            let (status, sent, error) = match result {
+                Ok(sent) => (DeliveryStatus::Sent, Some(sent), None),
+                Err(error) if outgoing.may_retry && error.is_retryable() => {
+                    tracing::info!(
+                        error.cause_chain = ?error,
+                        issue_id = %outgoing.issue.id,
+                        subscriber_email = %outgoing.recipient.email,
+                        "Sending a newsletter issue will be retried."
+                    );
+                    outcomes.push(SendOutcome::RetryLater {
+                        retry_after: error.retry_after(),
+                    });
+                    continue;
+                }
+                Err(error) => {
+                    tracing::warn!(
+                        error.cause_chain = ?error,
+                        issue_id = %outgoing.issue.id,
+                        subscriber_email = %outgoing.recipient.email,
+                        "Failed to send a newsletter issue."
+                    );
+                    (DeliveryStatus::Failed, None, Some(error.to_string()))
+                }
+            };
+
+            record_delivery(
+                self.pool,
+                NewDelivery {
+                    id: delivery_id,
+                    issue_id: outgoing.issue.id,
+                    subscriber_id: outgoing.recipient.id,
+                    status,
+                    provider: sent.as_ref().map(|sent| sent.provider.as_str()),
+                    provider_message_id: sent.as_ref().and_then(|sent| sent.message_id.as_deref()),
+                    error: error.as_deref(),
+                    tracking_token: tracking_token.as_deref(),
+                    variant_id: outgoing.variant.map(|v| v.id),
+                },
+            )
+            .await?;
+            outcomes.push(SendOutcome::Recorded);
+        }
+
+        Ok(outcomes)
+    }
+
+    /// Personalises the issue for its recipient, with tracked links pointing at
+    /// `delivery_id` and an open pixel when the issue tracks opens.
+    fn message(
+        &self,
+        outgoing: &OutgoingIssue<'_>,
+        delivery_id: Uuid,
+        tracking_token: Option<&str>,
+    ) -> EmailMessage {
+        let OutgoingIssue {
+            issue,
+            recipient,
+            variant,
+            ..
+        } = outgoing;
+        let (title, html, text) = match issue.translations.get(&recipient.locale) {
+            Some(translation) => (&translation.title, &translation.html, &translation.text),
+            None => (&issue.title, &issue.html, &issue.text),
+        };
+        let mut html = with_tracked_links(html, |url| {
+            let token = ClickToken {
+                link_id: *issue.link_ids.get(url)?,
+                delivery_id,
+            };
+            Some(format!(
+                "{}/t/c/{}",
+                self.base_url,
+                token.sign(self.hmac_secret)
+            ))
+        });
+        if let Some(token) = tracking_token {
+            html = with_open_pixel(&html, self.base_url, token);
+        }
+        let subject = variant.map_or(title.as_str(), |v| v.subject.as_str());
+        let senders = self.email_client.senders();
+        let sender = match issue.sender.as_deref() {
+            Some(id) => senders.get(id).unwrap_or_else(|| {
+                tracing::warn!(
+                    sender_identity = id,
+                    "The issue's sender identity is no longer configured, sending from the newsletter one."
+                );
+                senders.newsletter()
+            }),
+            None => senders.newsletter(),
+        };
+
+        EmailMessage::new(Mailbox::new(recipient.email.clone()), subject, &html, text)
+            .with_sender(sender.clone())
+    }
+
+    /// Sends the issues as one email, each in the recipient's language when
+    /// translated, and records a delivery of each. Digests are not tracked.
+    /// Retryable failures are returned if `may_retry` is set, other failures are
+    /// recorded.
+    #[tracing::instrument(
+        name = "Sending a digest to a recipient",
+        skip_all,
+        fields(n_issues = issues.len(), subscriber_email = %recipient.email)
+    )]
+    pub async fn send_digest(
+        &self,
+        issues: &[Issue],
+        recipient: &Recipient,
+        may_retry: bool,
+    ) -> Result<SendOutcome, sqlx::Error> {
+        let parts: Vec<_> = issues
+            .iter()
+            .map(|issue| match issue.translations.get(&recipient.locale) {
+                Some(translation) => (&translation.title, &translation.html, &translation.text),
+                None => (&issue.title, &issue.html, &issue.text),
+            })
+            .collect();
+        let subject = parts
+            .iter()
+            .map(|(title, _, _)| title.as_str())
+            .collect::<Vec<_>>()
+            .join(" | ");
+        let html = parts
+            .iter()
+            .map(|(title, html, _)| {
+                // Issues written as documents are unwrapped to sit side by side.
+                let body = html
+                    .strip_prefix("<html><body>")
+                    .and_then(|html| html.strip_suffix("</body></html>"))
+                    .unwrap_or(html);
+                format!("<h1>{}</h1>\n{}", escape_html(title), body)
+            })
+            .collect::<Vec<_>>()
+            .join("\n<hr>\n");
+        let text = parts
+            .iter()
+            .map(|(title, _, text)| format!("{}\n\n{}", title, text))
+            .collect::<Vec<_>>()
+            .join("\n\n---\n\n");
+        let message = EmailMessage::new(
+            Mailbox::new(recipient.email.clone()),
+            &subject,
+            &html,
+            &text,
+        )
+        .with_sender(self.email_client.senders().newsletter().clone());
+
+        let (status, sent, error) = match self.email_client.send(&message).await {
+            Ok(sent) => (DeliveryStatus::Sent, Some(sent), None),
+            Err(error) if may_retry && error.is_retryable() => {
+                tracing::info!(error.cause_chain = ?error, "Sending a digest will be retried.");
+                return Ok(SendOutcome::RetryLater {
+                    retry_after: error.retry_after(),
+                });
+            }
+            Err(error) => {
+                tracing::warn!(error.cause_chain = ?error, "Failed to send a digest.");
+                (DeliveryStatus::Failed, None, Some(error.to_string()))
+            }
+        };
+        for issue in issues {
+            record_delivery(
+                self.pool,
+                NewDelivery {
+                    id: Uuid::new_v4(),
+                    issue_id: issue.id,
+                    subscriber_id: recipient.id,
+                    status,
+                    provider: sent.as_ref().map(|sent| sent.provider.as_str()),
+                    provider_message_id: sent.as_ref().and_then(|sent| sent.message_id.as_deref()),
+                    error: error.as_deref(),
+                    tracking_token: None,
+                    variant_id: None,
+                },
+            )
+            .await?;
+        }
+
+        Ok(SendOutcome::Recorded)
+    }
+
+    /// Sends the issues, leaving retryable failures to the background worker.
+    pub async fn send_or_queue(&self, outgoing: &[OutgoingIssue<'_>]) -> Result<(), sqlx::Error> {
+        let outcomes = self.send_all(outgoing).await?;
+        for (outgoing, outcome) in outgoing.iter().zip(outcomes) {
+            if let SendOutcome::RetryLater { retry_after } = outcome {
+                sqlx::query!(
+                    r#"INSERT INTO issue_delivery_queue
+                       (issue_id, subscriber_id, variant_id, enqueued_at, execute_after)
+                       VALUES ($1, $2, $3, now(), now() + $4)"#,
+                    outgoing.issue.id,
+                    outgoing.recipient.id,
+                    outgoing.variant.map(|v| v.id),
+                    retry_delay(retry_after, 0),
+                )
+                .execute(self.pool)
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The provider's `Retry-After` if it sent one, an exponential backoff otherwise.
+fn retry_delay(retry_after: Option<Duration>, n_retries: i32) -> PgInterval {
+    let delay = retry_after.unwrap_or(DEFAULT_RETRY_AFTER * 2u32.pow(n_retries as u32));
+    PgInterval {
+        months: 0,
+        days: 0,
+        microseconds: delay.as_micros() as i64,
+    }
+}
+
+#[tracing::instrument(name = "Loading a newsletter issue", skip(pool))]
+pub async fn load_issue(pool: &PgPool, issue_id: Uuid) -> Result<Issue, sqlx::Error> {
+    let issue = sqlx::query!(
+        r#"SELECT i.title, i.html_content, i.text_content, i.sender_identity,
+               COALESCE(l.open_tracking, TRUE) AS "open_tracking!"
+           FROM newsletter_issues i
+           LEFT JOIN lists l ON l.id = i.list_id
+           WHERE i.id = $1"#,
+        issue_id
+    )
+    .fetch_one(pool)
+    .await?;
+    let link_ids = sqlx::query!(
+        r#"SELECT id, url FROM issue_links WHERE issue_id = $1"#,
+        issue_id
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| (row.url, row.id))
+    .collect();
+    let translations = sqlx::query!(
+        r#"SELECT locale, title, html_content, text_content
+           FROM issue_translations WHERE issue_id = $1"#,
+        issue_id
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .filter_map(|row| {
+        let locale = Locale::parse(&row.locale).ok()?;
+        let translation = Translation {
+            title: row.title,
+            html: row.html_content,
+            text: row.text_content,
+        };
+        Some((locale, translation))
+    })
+    .collect();
+
+    Ok(Issue {
+        id: issue_id,
+        title: issue.title,
+        html: issue.html_content,
+        text: issue.text_content,
+        open_tracking: issue.open_tracking,
+        sender: issue.sender_identity,
+        link_ids,
+        translations,
+    })
+}
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+/// Sends a batch of queued issue deliveries in as few requests as the provider
+/// allows. Recipients that stopped being confirmed or got suppressed while the
+/// delivery was queued are skipped.
+#[tracing::instrument(skip_all, fields(n_tasks = tracing::field::Empty), err)]
+pub async fn try_execute_task(sender: &IssueSender<'_>) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = sender.pool.begin().await?;
+    let tasks = sqlx::query!(
+        r#"SELECT issue_id, subscriber_id, variant_id, n_retries
+           FROM issue_delivery_queue
+           WHERE execute_after <= now()
+           ORDER BY issue_id
+           FOR UPDATE
+           SKIP LOCKED
+           LIMIT $1"#,
+        MAX_BATCH_SIZE as i64,
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+    if tasks.is_empty() {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    }
+    tracing::Span::current().record("n_tasks", tasks.len());
+
+    let mut issues = HashMap::new();
+    let mut variants = HashMap::new();
+    let mut deliverable = Vec::with_capacity(tasks.len());
+    for task in &tasks {
+        let Some(recipient) =
+            get_deliverable_recipient(sender.pool, task.subscriber_id, Some(task.issue_id)).await?
+        else {
+            continue;
+        };
+        if let Entry::Vacant(entry) = issues.entry(task.issue_id) {
+            let issue = load_issue(sender.pool, task.issue_id)
+                .await
+                .context("Failed to load the queued issue")?;
+            entry.insert(issue);
+        }
+        if let Some(variant_id) = task.variant_id.filter(|id| !variants.contains_key(id)) {
+            let variant = sqlx::query_as!(
+                Variant,
+                r#"SELECT id, subject FROM ab_test_variants WHERE id = $1"#,
+                variant_id
+            )
+            .fetch_one(sender.pool)
+            .await?;
+            variants.insert(variant_id, variant);
+        }
+        deliverable.push((task, recipient));
+    }
+
+    let outgoing: Vec<_> = deliverable
+        .iter()
+        .map(|(task, recipient)| OutgoingIssue {
+            issue: &issues[&task.issue_id],
+            recipient,
+            variant: task.variant_id.map(|id| &variants[&id]),
+            may_retry: task.n_retries < MAX_RETRIES,
+        })
+        .collect();
+    let outcomes = sender
+        .send_all(&outgoing)
+        .await
+        .context("Failed to record a delivery")?;
+    let mut retried = HashMap::new();
+    for ((task, _), outcome) in deliverable.iter().zip(outcomes) {
+        if let SendOutcome::RetryLater { retry_after } = outcome {
+            retried.insert((task.issue_id, task.subscriber_id), retry_after);
+        }
+    }
+
+    for task in &tasks {
+        match retried.get(&(task.issue_id, task.subscriber_id)) {
+            Some(retry_after) => {
+                sqlx::query!(
+                    r#"UPDATE issue_delivery_queue
+                       SET n_retries = n_retries + 1, execute_after = now() + $3
+                       WHERE issue_id = $1 AND subscriber_id = $2"#,
+                    task.issue_id,
+                    task.subscriber_id,
+                    retry_delay(*retry_after, task.n_retries),
+                )
+                .execute(&mut *transaction)
+                .await?;
+            }
+            None => delete_task(&mut transaction, task.issue_id, task.subscriber_id).await?,
+        }
+    }
+    transaction.commit().await?;
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// Checks the subscriber can still be sent to and, when given the `issue_id` of an
+/// issue sent to a list, that they are still confirmed on that list.
+async fn get_deliverable_recipient(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    issue_id: Option<Uuid>,
+) -> Result<Option<Recipient>, anyhow::Error> {
+    let subscriber = sqlx::query!(
+        r#"SELECT s.email, s.status, s.locale, i.list_id AS "list_id?",
+                  m.status AS "list_status?"
+           FROM subscriptions s
+           LEFT JOIN newsletter_issues i ON i.id = $2
+           LEFT JOIN list_memberships m
+               ON m.subscriber_id = s.id AND m.list_id = i.list_id
+           WHERE s.id = $1"#,
+        subscriber_id,
+        issue_id,
+    )
+    .fetch_one(pool)
+    .await?;
+
+    if subscriber.status != SubscriptionStatus::Confirmed.as_str() {
+        tracing::info!("Skipping a queued delivery. The subscriber is no longer confirmed.");
+        return Ok(None);
+    }
+    if subscriber.list_id.is_some() && subscriber.list_status.as_deref() != Some("confirmed") {
+        tracing::info!("Skipping a queued delivery. The subscriber left the issue's list.");
+        return Ok(None);
+    }
+    let email = match SubscriberEmail::parse(subscriber.email) {
+        Ok(email) => email,
+        Err(error) => {
+            tracing::warn!(
+                error,
+                "Skipping a queued delivery. The stored email is invalid."
+            );
+            return Ok(None);
+        }
+    };
+    let suppressed = get_suppressed_emails(pool, &[&email]).await?;
+    if is_suppressed(&suppressed, &email) {
+        tracing::info!("Skipping a queued delivery. The address is suppressed.");
+        return Ok(None);
+    }
+
+    Ok(Some(Recipient {
+        id: subscriber_id,
+        email,
+        locale: Locale::parse(&subscriber.locale).unwrap_or_default(),
+    }))
+}
+
+async fn delete_task(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM issue_delivery_queue WHERE issue_id = $1 AND subscriber_id = $2"#,
+        issue_id,
+        subscriber_id,
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
+
+/// Picks the winner of one A/B test whose wait window is over and queues the
+/// held-back recipients with the winning subject.
+#[tracing::instrument(skip_all, fields(issue_id = tracing::field::Empty), err)]
+pub async fn try_decide_ab_test(pool: &PgPool) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let Some(test) = sqlx::query!(
+        r#"SELECT issue_id, metric
+           FROM ab_tests
+           WHERE winning_variant_id IS NULL AND decide_at <= now()
+           ORDER BY decide_at
+           FOR UPDATE
+           SKIP LOCKED
+           LIMIT 1"#,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    tracing::Span::current().record("issue_id", tracing::field::display(test.issue_id));
+    let metric = AbTestMetric::parse(&test.metric).map_err(anyhow::Error::msg)?;
+
+    let variants = sqlx::query!(
+        r#"SELECT
+               v.id,
+               COUNT(d.id) AS "sent!",
+               COUNT(d.id) FILTER (WHERE d.first_opened_at IS NOT NULL) AS "opened!",
+               COUNT(d.id) FILTER (
+                   WHERE EXISTS (SELECT 1 FROM link_clicks c WHERE c.delivery_id = d.id)
+               ) AS "clicked!"
+           FROM ab_test_variants v
+           LEFT JOIN deliveries d ON d.variant_id = v.id AND d.status <> $2
+           WHERE v.issue_id = $1
+           GROUP BY v.id, v.position
+           ORDER BY v.position"#,
+        test.issue_id,
+        DeliveryStatus::Failed.as_str(),
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+    let results: Vec<(i64, i64)> = variants
+        .iter()
+        .map(|v| match metric {
+            AbTestMetric::Opens => (v.opened, v.sent),
+            AbTestMetric::Clicks => (v.clicked, v.sent),
+        })
+        .collect();
+    let winner = variants[pick_ab_test_winner(&results)].id;
+
+    sqlx::query!(
+        r#"UPDATE ab_tests SET winning_variant_id = $1, decided_at = now() WHERE issue_id = $2"#,
+        winner,
+        test.issue_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"INSERT INTO issue_delivery_queue (issue_id, subscriber_id, variant_id, enqueued_at)
+           SELECT issue_id, subscriber_id, $2, now()
+           FROM ab_test_holdouts
+           WHERE issue_id = $1"#,
+        test.issue_id,
+        winner,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM ab_test_holdouts WHERE issue_id = $1"#,
+        test.issue_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// Sends one subscriber whose digest is due the issues held for them, as a single
+/// email. A digest is due once its oldest issue has waited for the subscriber's
+/// digest frequency, straight away if they went back to immediate delivery.
+/// Issues of lists the subscriber has left since are dropped from it. A digest
+/// that cannot be sent yet waits like a queued delivery, oldest digests go first.
+#[tracing::instrument(skip_all, fields(subscriber_id = tracing::field::Empty), err)]
+pub async fn try_send_digest(sender: &IssueSender<'_>) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = sender.pool.begin().await?;
+    let Some(subscriber_id) = sqlx::query_scalar!(
+        r#"SELECT s.id
+           FROM subscriptions s
+           WHERE EXISTS (
+               SELECT 1 FROM digest_items d
+               WHERE d.subscriber_id = s.id
+                 AND d.enqueued_at + CASE s.digest_frequency
+                     WHEN 'daily' THEN interval '1 day'
+                     WHEN 'weekly' THEN interval '1 week'
+                     WHEN 'monthly' THEN interval '1 month'
+                     ELSE interval '0'
+                 END <= now()
+           )
+           AND NOT EXISTS (
+               SELECT 1 FROM digest_items d
+               WHERE d.subscriber_id = s.id AND d.execute_after > now()
+           )
+           ORDER BY (SELECT MIN(d.enqueued_at) FROM digest_items d WHERE d.subscriber_id = s.id)
+           -- Weaker than FOR UPDATE, which would block recording the deliveries.
+           FOR NO KEY UPDATE OF s
+           SKIP LOCKED
+           LIMIT 1"#,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    tracing::Span::current().record("subscriber_id", tracing::field::display(subscriber_id));
+
+    let items = sqlx::query!(
+        r#"SELECT d.issue_id, d.n_retries,
+               (i.list_id IS NULL OR EXISTS (
+                   SELECT 1 FROM list_memberships m
+                   WHERE m.list_id = i.list_id
+                     AND m.subscriber_id = d.subscriber_id
+                     AND m.status = 'confirmed'
+               )) AS "deliverable!"
+           FROM digest_items d
+           JOIN newsletter_issues i ON i.id = d.issue_id
+           WHERE d.subscriber_id = $1
+           ORDER BY i.published_at"#,
+        subscriber_id,
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+    let issue_ids: Vec<Uuid> = items.iter().map(|item| item.issue_id).collect();
+    let n_retries = items.iter().map(|item| item.n_retries).max().unwrap_or_default();
+
+    let recipient = get_deliverable_recipient(sender.pool, subscriber_id, None).await?;
+    let mut issues = Vec::new();
+    for item in items.iter().filter(|item| item.deliverable) {
+        issues.push(
+            load_issue(sender.pool, item.issue_id)
+                .await
+                .context("Failed to load a held issue")?,
+        );
+    }
+    match recipient {
+        Some(recipient) if !issues.is_empty() => {
+            let outcome = sender
+                .send_digest(&issues, &recipient, n_retries < MAX_RETRIES)
+                .await
+                .context("Failed to record a delivery")?;
+            if let SendOutcome::RetryLater { retry_after } = outcome {
+                sqlx::query!(
+                    r#"UPDATE digest_items
+                       SET n_retries = n_retries + 1, execute_after = now() + $3
+                       WHERE subscriber_id = $1 AND issue_id = ANY($2)"#,
+                    subscriber_id,
+                    &issue_ids,
+                    retry_delay(retry_after, n_retries),
+                )
+                .execute(&mut *transaction)
+                .await?;
+                transaction.commit().await?;
+                return Ok(ExecutionOutcome::TaskCompleted);
+            }
+        }
+        Some(_) => tracing::info!("Dropping a digest. The subscriber left every list it was for."),
+        None => {}
+    }
+
+    sqlx::query!(
+        r#"DELETE FROM digest_items WHERE subscriber_id = $1 AND issue_id = ANY($2)"#,
+        subscriber_id,
+        &issue_ids,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+async fn worker_loop(
+    pool: PgPool,
+    email_client: Arc<EmailClient>,
+    base_url: String,
+    hmac_secret: Secret<String>,
+) -> Result<(), anyhow::Error> {
+    let sender = IssueSender {
+        pool: &pool,
+        email_client: &email_client,
+        base_url: &base_url,
+        hmac_secret: &hmac_secret,
+    };
+
+    loop {
+        if try_decide_ab_test(&pool).await.is_err() {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        if try_send_digest(&sender).await.is_err() {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        match try_execute_task(&sender).await {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    email_client: Arc<EmailClient>,
+) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+
+    worker_loop(
+        connection_pool,
+        email_client,
+        configuration.application.base_url,
+        configuration.application.hmac_secret,
+    )
+    .await
+}
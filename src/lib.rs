@@ -1,6 +1,13 @@
+pub mod circuit_breaker;
 pub mod configuration;
+pub mod dkim;
 pub mod domain;
 pub mod email_client;
+pub mod feed_poller;
+pub mod issue_delivery_worker;
+pub mod rate_limiter;
 pub mod routes;
+pub mod sequence_worker;
+pub mod smtp;
 pub mod startup;
 pub mod telemetry;
@@ -1,16 +1,70 @@
+use std::{
+    fmt::{Debug, Display},
+    sync::Arc,
+};
+
+use tokio::task::JoinError;
 use zero2prod::configuration::get_configuration;
+use zero2prod::feed_poller::run_feed_poller_until_stopped;
+use zero2prod::issue_delivery_worker::run_worker_until_stopped;
+use zero2prod::sequence_worker::run_sequence_worker_until_stopped;
 use zero2prod::startup::Application;
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
 #[tokio::main]
-async fn main() -> Result<(), std::io::Error> {
+async fn main() -> anyhow::Result<()> {
     let subscriber = get_subscriber("zero2prod".into(), "info".into(), std::io::stdout);
     init_subscriber(subscriber);
 
     let configuration = get_configuration().expect("Failed to read configuration.");
-    let server = Application::build(configuration).await?;
+    // One client for every task, so they share the providers' rate limits and
+    // circuit breakers.
+    let email_client = Arc::new(configuration.email_client.clone().client());
+    let application = Application::build(configuration.clone(), email_client.clone()).await?;
+    let application_task = tokio::spawn(application.run_until_stopped());
+    let worker_task = tokio::spawn(run_worker_until_stopped(
+        configuration.clone(),
+        email_client.clone(),
+    ));
+    let feed_poller_task = tokio::spawn(run_feed_poller_until_stopped(
+        configuration.clone(),
+        email_client.clone(),
+    ));
+    let sequence_worker_task = tokio::spawn(run_sequence_worker_until_stopped(
+        configuration,
+        email_client,
+    ));
 
-    server.run_until_stopped().await?;
+    tokio::select! {
+        o = application_task => report_exit("API", o),
+        o = worker_task => report_exit("Background worker", o),
+        o = feed_poller_task => report_exit("Feed poller", o),
+        o = sequence_worker_task => report_exit("Sequence worker", o),
+    };
 
     Ok(())
 }
+
+fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>, JoinError>) {
+    match outcome {
+        Ok(Ok(())) => {
+            tracing::info!("{} has exited", task_name)
+        }
+        Ok(Err(e)) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{} failed",
+                task_name
+            )
+        }
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{}' task failed to complete",
+                task_name
+            )
+        }
+    }
+}
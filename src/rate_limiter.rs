@@ -0,0 +1,186 @@
+use std::{collections::VecDeque, sync::Mutex, time::Duration};
+
+use tokio::{
+    sync::{Semaphore, SemaphorePermit},
+    time::Instant,
+};
+
+/// A token bucket refilled at `messages_per_second`, holding at most one second
+/// worth of tokens, with a cap on concurrent requests.
+pub struct RateLimiter {
+    messages_per_second: f64,
+    max_concurrent_requests: usize,
+    requests: Semaphore,
+    state: Mutex<BucketState>,
+}
+
+struct BucketState {
+    tokens: f64,
+    refilled_at: Instant,
+    paused_until: Option<Instant>,
+    /// When messages were let through in the last second, and how many.
+    recent: VecDeque<(Instant, u32)>,
+}
+
+/// Allows one request, for as long as it is held.
+pub struct RequestPermit<'a> {
+    _permit: SemaphorePermit<'a>,
+}
+
+impl RateLimiter {
+    pub fn new(messages_per_second: u32, max_concurrent_requests: usize) -> Self {
+        let messages_per_second = f64::from(messages_per_second.max(1));
+        let max_concurrent_requests = max_concurrent_requests.max(1);
+        Self {
+            messages_per_second,
+            max_concurrent_requests,
+            requests: Semaphore::new(max_concurrent_requests),
+            state: Mutex::new(BucketState {
+                tokens: messages_per_second,
+                refilled_at: Instant::now(),
+                paused_until: None,
+                recent: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Waits for a request slot and for enough tokens to send `messages`.
+    /// Batches larger than the bucket wait for a full bucket and leave it in debt.
+    pub async fn acquire(&self, messages: u32) -> RequestPermit<'_> {
+        let permit = self
+            .requests
+            .acquire()
+            .await
+            .expect("The rate limiter's semaphore is never closed.");
+        let needed = f64::from(messages).min(self.messages_per_second);
+
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                let now = Instant::now();
+                self.refill(&mut state, now);
+                match state.paused_until {
+                    Some(until) if until > now => until - now,
+                    _ if state.tokens >= needed => {
+                        state.tokens -= f64::from(messages);
+                        state.recent.push_back((now, messages));
+                        break;
+                    }
+                    _ => {
+                        Duration::from_secs_f64((needed - state.tokens) / self.messages_per_second)
+                    }
+                }
+            };
+            tokio::time::sleep(wait).await;
+        }
+
+        RequestPermit { _permit: permit }
+    }
+
+    /// Lets nothing through for `duration`, e.g. when the provider asks us to back off.
+    pub fn pause(&self, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        let until = Instant::now() + duration;
+        if state.paused_until.is_none_or(|current| current < until) {
+            state.paused_until = Some(until);
+        }
+    }
+
+    /// Messages let through over the last second.
+    pub fn throughput(&self) -> u32 {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        while let Some((at, _)) = state.recent.front() {
+            if now - *at < Duration::from_secs(1) {
+                break;
+            }
+            state.recent.pop_front();
+        }
+        state.recent.iter().map(|(_, messages)| messages).sum()
+    }
+
+    /// Requests currently holding a permit.
+    pub fn in_flight(&self) -> usize {
+        self.max_concurrent_requests - self.requests.available_permits()
+    }
+
+    fn refill(&self, state: &mut BucketState, now: Instant) {
+        let elapsed = (now - state.refilled_at).as_secs_f64();
+        state.tokens =
+            (state.tokens + elapsed * self.messages_per_second).min(self.messages_per_second);
+        state.refilled_at = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use crate::rate_limiter::RateLimiter;
+
+    #[tokio::test(start_paused = true)]
+    async fn a_full_bucket_lets_a_burst_through() {
+        let limiter = RateLimiter::new(10, 10);
+        let start = Instant::now();
+
+        for _ in 0..10 {
+            limiter.acquire(1).await;
+        }
+
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        assert_eq!(limiter.throughput(), 10);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn messages_beyond_the_burst_are_spaced_out() {
+        let limiter = RateLimiter::new(10, 10);
+        let start = Instant::now();
+
+        for _ in 0..20 {
+            limiter.acquire(1).await;
+        }
+
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(990), "{:?}", elapsed);
+        assert!(elapsed <= Duration::from_millis(1100), "{:?}", elapsed);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn batches_larger_than_the_bucket_leave_it_in_debt() {
+        let limiter = RateLimiter::new(10, 10);
+        let start = Instant::now();
+
+        limiter.acquire(30).await;
+        limiter.acquire(1).await;
+
+        assert!(start.elapsed() >= Duration::from_secs(2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pausing_holds_back_every_request() {
+        let limiter = RateLimiter::new(10, 10);
+        let start = Instant::now();
+
+        limiter.pause(Duration::from_secs(30));
+        limiter.acquire(1).await;
+
+        assert!(start.elapsed() >= Duration::from_secs(30));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn concurrent_requests_are_capped() {
+        let limiter = RateLimiter::new(100, 2);
+
+        let first = limiter.acquire(1).await;
+        let _second = limiter.acquire(1).await;
+        assert_eq!(limiter.in_flight(), 2);
+        let third = tokio::time::timeout(Duration::from_secs(1), limiter.acquire(1)).await;
+        assert!(third.is_err());
+
+        drop(first);
+        assert_eq!(limiter.in_flight(), 1);
+        limiter.acquire(1).await;
+    }
+}
@@ -0,0 +1,275 @@
+use actix_web::{http::header::ContentType, web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::{
+    domain::{IssueSlug, IssueVisibility, SubscriptionStatus},
+    routes::{error_chain_fmt, escape_html, href_values, unescape_href},
+};
+
+const ARCHIVE_PAGE_SIZE: i64 = 20;
+
+#[derive(thiserror::Error)]
+pub enum ArchiveError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("{0}")]
+    MissingIssueError(String),
+    #[error("{0}")]
+    SubscribersOnlyError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ArchiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ArchiveError {
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        match self {
+            Self::ValidationError(_) => actix_web::http::StatusCode::BAD_REQUEST,
+            Self::MissingIssueError(_) => actix_web::http::StatusCode::NOT_FOUND,
+            Self::SubscribersOnlyError(_) => actix_web::http::StatusCode::FORBIDDEN,
+            Self::UnexpectedError(_) => actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct ArchiveParameters {
+    page: Option<i64>,
+}
+
+struct ArchivedIssue {
+    slug: String,
+    title: String,
+    published_at: DateTime<Utc>,
+    visibility: String,
+}
+
+/// Lists every issue that is not hidden, newest first.
+#[tracing::instrument(name = "Listing archived issues", skip(parameters, pool))]
+pub async fn archive(
+    parameters: web::Query<ArchiveParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ArchiveError> {
+    let page = parameters.page.unwrap_or(1);
+    if page < 1 {
+        return Err(ArchiveError::ValidationError(
+            "Pages are numbered from 1.".to_string(),
+        ));
+    }
+
+    // One extra row tells whether there is an older page.
+    let mut issues = sqlx::query_as!(
+        ArchivedIssue,
+        r#"SELECT slug, title, published_at, visibility
+           FROM newsletter_issues
+           WHERE visibility <> $1
+           ORDER BY published_at DESC, id DESC
+           LIMIT $2 OFFSET $3"#,
+        IssueVisibility::Hidden.as_str(),
+        ARCHIVE_PAGE_SIZE + 1,
+        (page - 1) * ARCHIVE_PAGE_SIZE,
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve archived issues")?;
+    let has_older_page = issues.len() as i64 > ARCHIVE_PAGE_SIZE;
+    issues.truncate(ARCHIVE_PAGE_SIZE as usize);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(render_page(
+            "Newsletter archive",
+            &render_issue_list(&issues, page, has_older_page),
+        )))
+}
+
+#[derive(serde::Deserialize)]
+pub struct ArchivedIssueParameters {
+    subscription_token: Option<String>,
+}
+
+/// Renders an issue's HTML body. Subscribers-only issues need the token of a
+/// confirmed subscriber.
+#[tracing::instrument(name = "Showing an archived issue", skip(parameters, pool))]
+pub async fn archived_issue(
+    slug: web::Path<String>,
+    parameters: web::Query<ArchivedIssueParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ArchiveError> {
+    let missing_issue = || ArchiveError::MissingIssueError("No such issue.".to_string());
+    let slug = IssueSlug::parse(slug.into_inner()).map_err(|_| missing_issue())?;
+    let issue = sqlx::query!(
+        r#"SELECT title, html_content, published_at, visibility
+           FROM newsletter_issues
+           WHERE slug = $1"#,
+        slug.as_ref(),
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the newsletter issue")?
+    .ok_or_else(missing_issue)?;
+
+    match IssueVisibility::parse(&issue.visibility).map_err(anyhow::Error::msg)? {
+        IssueVisibility::Public => {}
+        IssueVisibility::SubscribersOnly => {
+            let is_subscriber = match &parameters.subscription_token {
+                Some(token) => is_confirmed_subscriber(&pool, token)
+                    .await
+                    .context("Failed to check the subscription token")?,
+                None => false,
+            };
+            if !is_subscriber {
+                return Err(ArchiveError::SubscribersOnlyError(
+                    "This issue is only available to subscribers.".to_string(),
+                ));
+            }
+        }
+        IssueVisibility::Hidden => return Err(missing_issue()),
+    }
+
+    let content = format!(
+        "<article>\n<h1>{}</h1>\n<p><time datetime=\"{}\">{}</time></p>\n{}\n</article>",
+        escape_html(&issue.title),
+        issue.published_at.to_rfc3339(),
+        issue.published_at.format("%B %-d, %Y"),
+        without_personal_links(body_of(&issue.html_content)),
+    );
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(render_page(&issue.title, &content)))
+}
+
+#[tracing::instrument(name = "Checking a subscription token", skip(pool, subscription_token))]
+async fn is_confirmed_subscriber(
+    pool: &PgPool,
+    subscription_token: &str,
+) -> Result<bool, sqlx::Error> {
+    let status = sqlx::query!(
+        r#"SELECT s.status
+           FROM subscription_tokens t
+           JOIN subscriptions s ON s.id = t.subscriber_id
+           WHERE t.subscription_token = $1"#,
+        subscription_token,
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(status.is_some_and(|row| row.status == SubscriptionStatus::Confirmed.as_str()))
+}
+
+/// The inside of the `<body>` element, or the whole document if it has none.
+pub(crate) fn body_of(html: &str) -> &str {
+    let lowercase = html.to_ascii_lowercase();
+    let Some(opening) = lowercase.find("<body") else {
+        return html;
+    };
+    let Some(start) = lowercase[opening..].find('>').map(|i| opening + i + 1) else {
+        return html;
+    };
+    let end = lowercase[start..]
+        .find("</body>")
+        .map_or(html.len(), |i| start + i);
+    &html[start..end]
+}
+
+/// Removes the links that only make sense for the recipient of the email, such as
+/// unsubscribe and preference links carrying their subscription token.
+pub(crate) fn without_personal_links(html: &str) -> String {
+    let lowercase = html.to_ascii_lowercase();
+    let mut stripped = String::with_capacity(html.len());
+    let mut copied_up_to = 0;
+    for (start, end) in href_values(html) {
+        if start < copied_up_to || !is_personal_link(&unescape_href(&html[start..end])) {
+            continue;
+        }
+        let Some(anchor_start) = lowercase[copied_up_to..start]
+            .rfind("<a")
+            .map(|i| copied_up_to + i)
+        else {
+            continue;
+        };
+        let anchor_end = lowercase[end..]
+            .find("</a>")
+            .map_or(html.len(), |i| end + i + "</a>".len());
+        stripped.push_str(&html[copied_up_to..anchor_start]);
+        copied_up_to = anchor_end;
+    }
+    stripped.push_str(&html[copied_up_to..]);
+    stripped
+}
+
+fn is_personal_link(url: &str) -> bool {
+    let url = url.to_ascii_lowercase();
+    [
+        "/subscriptions/unsubscribe",
+        "/preferences",
+        "subscription_token=",
+    ]
+    .iter()
+    .any(|marker| url.contains(marker))
+}
+
+fn render_issue_list(issues: &[ArchivedIssue], page: i64, has_older_page: bool) -> String {
+    let items: String = issues
+        .iter()
+        .map(|issue| {
+            format!(
+                "<li><a href=\"/archive/{}\">{}</a> <time datetime=\"{}\">{}</time>{}</li>\n",
+                escape_html(&issue.slug),
+                escape_html(&issue.title),
+                issue.published_at.to_rfc3339(),
+                issue.published_at.format("%B %-d, %Y"),
+                if issue.visibility == IssueVisibility::SubscribersOnly.as_str() {
+                    " <em>Subscribers only</em>"
+                } else {
+                    ""
+                },
+            )
+        })
+        .collect();
+
+    let mut pagination = String::new();
+    if page > 1 {
+        pagination.push_str(&format!(
+            r#"<a rel="prev" href="/archive?page={}">Newer issues</a> "#,
+            page - 1
+        ));
+    }
+    if has_older_page {
+        pagination.push_str(&format!(
+            r#"<a rel="next" href="/archive?page={}">Older issues</a>"#,
+            page + 1
+        ));
+    }
+
+    if items.is_empty() {
+        format!("<h1>Newsletter archive</h1>\n<p>Nothing here yet.</p>\n<nav>{pagination}</nav>")
+    } else {
+        format!("<h1>Newsletter archive</h1>\n<ul>\n{items}</ul>\n<nav>{pagination}</nav>")
+    }
+}
+
+fn render_page(title: &str, content: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><title>{title}</title></head>
+<body>
+<header><a href="/archive">Newsletter archive</a></header>
+<main>
+{content}
+</main>
+</body>
+</html>"#,
+        title = escape_html(title),
+        content = content,
+    )
+}
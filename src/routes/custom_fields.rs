@@ -0,0 +1,124 @@
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    domain::{CustomFieldKey, CustomFieldType},
+    routes::error_chain_fmt,
+};
+
+#[derive(thiserror::Error)]
+pub enum CustomFieldError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("{0}")]
+    ConflictError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for CustomFieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for CustomFieldError {
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        match self {
+            Self::ValidationError(_) => actix_web::http::StatusCode::BAD_REQUEST,
+            Self::ConflictError(_) => actix_web::http::StatusCode::CONFLICT,
+            Self::UnexpectedError(_) => actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct CustomFieldData {
+    key: String,
+    field_type: String,
+    #[serde(default)]
+    public: bool,
+}
+
+/// A custom field definition. Only `public` fields can be set from the subscribe form.
+#[derive(serde::Serialize)]
+pub struct CustomFieldDefinition {
+    pub id: Uuid,
+    pub key: String,
+    pub field_type: String,
+    pub public: bool,
+}
+
+impl CustomFieldDefinition {
+    pub fn field_type(&self) -> CustomFieldType {
+        // The column is constrained to valid types by the database.
+        CustomFieldType::parse(&self.field_type).expect("Invalid custom field type in database.")
+    }
+}
+
+#[tracing::instrument(name = "Creating a custom field definition", skip(body, pool), fields(field_key = %body.key))]
+pub async fn create_custom_field(
+    body: web::Json<CustomFieldData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, CustomFieldError> {
+    let key = CustomFieldKey::parse(body.key.clone()).map_err(CustomFieldError::ValidationError)?;
+    let field_type =
+        CustomFieldType::parse(&body.field_type).map_err(CustomFieldError::ValidationError)?;
+
+    let definition = CustomFieldDefinition {
+        id: Uuid::new_v4(),
+        key: key.to_string(),
+        field_type: field_type.as_str().to_string(),
+        public: body.public,
+    };
+    let inserted = sqlx::query!(
+        r#"INSERT INTO custom_field_definitions (id, key, field_type, public, created_at)
+           VALUES ($1, $2, $3, $4, $5)
+           ON CONFLICT (key) DO NOTHING"#,
+        definition.id,
+        definition.key,
+        definition.field_type,
+        definition.public,
+        Utc::now(),
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to insert a custom field definition in the database.")?;
+
+    if inserted.rows_affected() == 0 {
+        return Err(CustomFieldError::ConflictError(format!(
+            "A custom field with key {} already exists.",
+            key
+        )));
+    }
+
+    Ok(HttpResponse::Ok().json(definition))
+}
+
+#[tracing::instrument(name = "Listing custom field definitions", skip(pool))]
+pub async fn list_custom_fields(pool: web::Data<PgPool>) -> Result<HttpResponse, CustomFieldError> {
+    let mut connection = pool
+        .acquire()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let definitions = get_custom_field_definitions(&mut connection)
+        .await
+        .context("Failed to retrieve custom field definitions")?;
+
+    Ok(HttpResponse::Ok().json(definitions))
+}
+
+#[tracing::instrument(name = "Retrieving custom field definitions", skip(connection))]
+pub async fn get_custom_field_definitions(
+    connection: &mut PgConnection,
+) -> Result<Vec<CustomFieldDefinition>, sqlx::Error> {
+    sqlx::query_as!(
+        CustomFieldDefinition,
+        r#"SELECT id, key, field_type, public FROM custom_field_definitions ORDER BY key"#
+    )
+    .fetch_all(connection)
+    .await
+}
@@ -0,0 +1,112 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    domain::{IssueVisibility, ListSlug},
+    email_client::EmailClient,
+    issue_delivery_worker::IssueSender,
+    routes::{publish_issue, Delivery, NewIssue, PublishError, PublishedIssue},
+    startup::{ApplicationBaseUrl, HmacSecret},
+};
+
+#[derive(serde::Serialize)]
+pub struct Draft {
+    id: Uuid,
+    title: String,
+    html_content: String,
+    text_content: String,
+    list_id: Option<Uuid>,
+    feed_source_id: Option<Uuid>,
+    created_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Listing newsletter drafts", skip(pool))]
+pub async fn list_drafts(pool: web::Data<PgPool>) -> Result<HttpResponse, PublishError> {
+    let drafts = sqlx::query_as!(
+        Draft,
+        r#"SELECT id, title, html_content, text_content, list_id, feed_source_id, created_at
+           FROM newsletter_drafts
+           ORDER BY created_at"#
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve newsletter drafts")?;
+
+    Ok(HttpResponse::Ok().json(drafts))
+}
+
+/// Publishes a draft to its list, or to every confirmed subscriber, and deletes it.
+/// The deliveries are queued for the delivery worker rather than sent while the
+/// draft is locked.
+#[tracing::instrument(
+    name = "Publishing a newsletter draft",
+    skip(pool, email_client, base_url, hmac_secret)
+)]
+pub async fn publish_draft(
+    draft_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, PublishError> {
+    let draft_id = draft_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    // The lock stops a second request from publishing the same draft.
+    let draft = sqlx::query!(
+        r#"SELECT d.title, d.html_content, d.text_content, l.slug AS "list_slug?"
+           FROM newsletter_drafts d
+           LEFT JOIN lists l ON l.id = d.list_id
+           WHERE d.id = $1
+           FOR UPDATE OF d"#,
+        draft_id,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to retrieve the newsletter draft")?
+    .ok_or_else(|| PublishError::UnknownDraftError(format!("No draft with id {}", draft_id)))?;
+    let list = draft
+        .list_slug
+        .map(ListSlug::parse)
+        .transpose()
+        .map_err(anyhow::Error::msg)?;
+    let visibility = IssueVisibility::default_for(list.is_some());
+
+    let sender = IssueSender {
+        pool: &pool,
+        email_client: &email_client,
+        base_url: &base_url.0,
+        hmac_secret: &hmac_secret.0,
+    };
+    let issue_id = publish_issue(
+        &sender,
+        NewIssue {
+            title: draft.title,
+            html: draft.html_content,
+            text: Some(draft.text_content),
+            list,
+            segment_id: None,
+            ab_test: None,
+            visibility,
+            sender: None,
+            translations: Vec::new(),
+        },
+        Delivery::Queued,
+    )
+    .await?;
+    sqlx::query!(r#"DELETE FROM newsletter_drafts WHERE id = $1"#, draft_id)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete the newsletter draft")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to delete a published draft")?;
+
+    Ok(HttpResponse::Ok().json(PublishedIssue { issue_id }))
+}
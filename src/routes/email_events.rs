@@ -0,0 +1,212 @@
+use actix_web::{
+    http::header::{self, HeaderMap, HeaderValue},
+    web, HttpRequest, HttpResponse, ResponseError,
+};
+use anyhow::Context;
+use base64::Engine;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    domain::{EmailEvent, EmailEventKind, SubscriptionStatus},
+    routes::{
+        error_chain_fmt, mark_delivery_bounced, suppress_email, transition_subscription_status,
+        unsubscribe_from_all, StatusTransitionError,
+    },
+    startup::WebhookSecret,
+};
+
+#[derive(thiserror::Error)]
+pub enum EmailEventError {
+    #[error("Authentication failed.")]
+    AuthError(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for EmailEventError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for EmailEventError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            Self::AuthError(_) => {
+                let mut response = HttpResponse::new(self.status_code());
+                response.headers_mut().insert(
+                    header::WWW_AUTHENTICATE,
+                    HeaderValue::from_static(r#"Basic realm="email-events""#),
+                );
+                response
+            }
+            _ => HttpResponse::new(self.status_code()),
+        }
+    }
+
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        match self {
+            Self::AuthError(_) => actix_web::http::StatusCode::UNAUTHORIZED,
+            Self::UnexpectedError(_) => actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Mailjet posts either a single event or, when grouping is enabled, an array of events.
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+pub enum EmailEventsPayload {
+    Many(Vec<serde_json::Value>),
+    One(serde_json::Value),
+}
+
+/// Receives Mailjet event callbacks. The webhook URL is configured with HTTP basic
+/// credentials whose password is the shared secret, e.g.
+/// `https://mailjet:<secret>@example.com/webhooks/email-events`.
+///
+/// Events that cannot be parsed are logged and skipped, so one bad event does not make
+/// Mailjet retry the rest of its batch.
+#[tracing::instrument(name = "Receiving email events", skip(request, body, pool, secret))]
+pub async fn receive_email_events(
+    request: HttpRequest,
+    body: web::Json<EmailEventsPayload>,
+    pool: web::Data<PgPool>,
+    secret: web::Data<WebhookSecret>,
+) -> Result<HttpResponse, EmailEventError> {
+    verify_shared_secret(request.headers(), &secret.0).map_err(EmailEventError::AuthError)?;
+
+    let payloads = match body.into_inner() {
+        EmailEventsPayload::Many(payloads) => payloads,
+        EmailEventsPayload::One(payload) => vec![payload],
+    };
+    let events = payloads.into_iter().filter_map(|payload| {
+        match serde_json::from_value::<EmailEvent>(payload.clone()) {
+            Ok(event) => Some((event, payload)),
+            Err(e) => {
+                tracing::warn!(error = %e, %payload, "Skipping an invalid email event");
+                None
+            }
+        }
+    });
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    for (event, payload) in events {
+        if event.event == EmailEventKind::Other {
+            continue;
+        }
+        record_email_event(&mut transaction, &event, payload)
+            .await
+            .context("Failed to store an email event")?;
+    }
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store email events")?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+fn verify_shared_secret(headers: &HeaderMap, secret: &Secret<String>) -> Result<(), anyhow::Error> {
+    let header_value = headers
+        .get(header::AUTHORIZATION)
+        .context("The 'Authorization' header was missing")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF8 string.")?;
+    let base64encoded_segment = header_value
+        .strip_prefix("Basic ")
+        .context("The authorization scheme was not 'Basic'.")?;
+    let decoded_bytes = base64::engine::general_purpose::STANDARD
+        .decode(base64encoded_segment)
+        .context("Failed to base64-decode 'Basic' credentials.")?;
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .context("The decoded credential string is not valid UTF8.")?;
+    let (_, password) = decoded_credentials
+        .split_once(':')
+        .context("A password must be provided in 'Basic' auth.")?;
+
+    // Compare MACs rather than the strings so the check takes the same time however
+    // much of the password matches.
+    let mac = |value: &str| {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+            .expect("HMAC can take a key of any size");
+        mac.update(value.as_bytes());
+        mac
+    };
+    mac(password)
+        .verify(&mac(secret.expose_secret()).finalize().into_bytes())
+        .map_err(|_| anyhow::anyhow!("Invalid webhook secret."))?;
+
+    Ok(())
+}
+
+/// Stores the event and applies its status change. Events for unknown addresses are kept
+/// for auditing, and changes the subscriber's current status does not allow are skipped.
+/// Hard bounces and complaints also suppress the address, so that signing it up again
+/// does not email it.
+#[tracing::instrument(
+    name = "Recording an email event",
+    skip(transaction, event, payload),
+    fields(event_type = event.event.as_str())
+)]
+async fn record_email_event(
+    transaction: &mut Transaction<'_, Postgres>,
+    event: &EmailEvent,
+    payload: serde_json::Value,
+) -> Result<(), StatusTransitionError> {
+    let subscriber_id = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE email = $1"#,
+        event.email
+    )
+    .fetch_optional(&mut **transaction)
+    .await?
+    .map(|row| row.id);
+
+    sqlx::query!(
+        r#"INSERT INTO email_events
+           (id, subscriber_id, email, event_type, payload, occurred_at, received_at)
+           VALUES ($1, $2, $3, $4, $5, $6, $7)"#,
+        Uuid::new_v4(),
+        subscriber_id,
+        event.email,
+        event.event.as_str(),
+        payload,
+        event.occurred_at(),
+        Utc::now(),
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    if let (true, Some(message_id)) = (event.is_delivery_failure(), event.message_id) {
+        mark_delivery_bounced(transaction, &message_id.to_string(), event.error.as_deref()).await?;
+    }
+    if let Some(reason) = event.suppression_reason() {
+        suppress_email(transaction, &event.email, reason, "mailjet").await?;
+    }
+
+    let (Some(subscriber_id), Some(next)) = (subscriber_id, event.resulting_status()) else {
+        return Ok(());
+    };
+    let result = match next {
+        SubscriptionStatus::Unsubscribed => unsubscribe_from_all(transaction, subscriber_id).await,
+        next => transition_subscription_status(transaction, subscriber_id, next)
+            .await
+            .map(|_| ()),
+    };
+    match result {
+        Err(StatusTransitionError::IllegalTransition(e)) => {
+            tracing::warn!(%subscriber_id, "Ignoring an email event: {}", e);
+            Ok(())
+        }
+        result => result,
+    }
+}
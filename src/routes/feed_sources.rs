@@ -0,0 +1,180 @@
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    domain::{FeedSourceMode, ListSlug, NewsletterTemplate},
+    routes::{error_chain_fmt, get_list_id},
+};
+
+const DEFAULT_SUBJECT_TEMPLATE: &str = "{{title}}";
+const DEFAULT_HTML_TEMPLATE: &str =
+    "<h1>{{title}}</h1>\n{{summary}}\n<p><a href=\"{{link}}\">Read the full post</a></p>";
+const DEFAULT_TEXT_TEMPLATE: &str = "{{title}}\n\n{{summary}}\n\nRead the full post: {{link}}";
+const DEFAULT_POLL_INTERVAL_MINUTES: u32 = 60;
+const MIN_POLL_INTERVAL_MINUTES: u32 = 5;
+const MAX_POLL_INTERVAL_MINUTES: u32 = 7 * 24 * 60;
+
+#[derive(thiserror::Error)]
+pub enum FeedSourceError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("{0}")]
+    ConflictError(String),
+    #[error("{0}")]
+    MissingListError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for FeedSourceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for FeedSourceError {
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        match self {
+            Self::ValidationError(_) => actix_web::http::StatusCode::BAD_REQUEST,
+            Self::ConflictError(_) => actix_web::http::StatusCode::CONFLICT,
+            Self::MissingListError(_) => actix_web::http::StatusCode::NOT_FOUND,
+            Self::UnexpectedError(_) => actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Templates default to the item's title, summary and a link to the full post.
+#[derive(serde::Deserialize)]
+pub struct FeedSourceData {
+    url: String,
+    mode: String,
+    list: Option<String>,
+    subject_template: Option<String>,
+    html_template: Option<String>,
+    text_template: Option<String>,
+    poll_interval_minutes: Option<u32>,
+}
+
+#[derive(serde::Serialize)]
+pub struct FeedSource {
+    id: Uuid,
+    url: String,
+    mode: String,
+    list_id: Option<Uuid>,
+    subject_template: String,
+    html_template: String,
+    text_template: String,
+    poll_interval_minutes: i32,
+    last_polled_at: Option<DateTime<Utc>>,
+    /// Why the last poll failed, cleared by the next successful one.
+    last_error: Option<String>,
+}
+
+#[tracing::instrument(name = "Creating a feed source", skip(body, pool), fields(feed_url = %body.url))]
+pub async fn create_feed_source(
+    body: web::Json<FeedSourceData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, FeedSourceError> {
+    let body = body.into_inner();
+    let url = reqwest::Url::parse(body.url.trim())
+        .ok()
+        .filter(|url| url.scheme() == "http" || url.scheme() == "https")
+        .ok_or_else(|| {
+            FeedSourceError::ValidationError(format!("{} is not a valid feed URL.", body.url))
+        })?;
+    let mode = FeedSourceMode::parse(&body.mode).map_err(FeedSourceError::ValidationError)?;
+    let template = |template: Option<String>, default: &str| {
+        NewsletterTemplate::parse(template.unwrap_or_else(|| default.to_string()))
+            .map_err(FeedSourceError::ValidationError)
+    };
+    let subject_template = template(body.subject_template, DEFAULT_SUBJECT_TEMPLATE)?;
+    let html_template = template(body.html_template, DEFAULT_HTML_TEMPLATE)?;
+    let text_template = template(body.text_template, DEFAULT_TEXT_TEMPLATE)?;
+    let poll_interval_minutes = body
+        .poll_interval_minutes
+        .unwrap_or(DEFAULT_POLL_INTERVAL_MINUTES);
+    if !(MIN_POLL_INTERVAL_MINUTES..=MAX_POLL_INTERVAL_MINUTES).contains(&poll_interval_minutes) {
+        return Err(FeedSourceError::ValidationError(format!(
+            "Feeds are polled every {} to {} minutes.",
+            MIN_POLL_INTERVAL_MINUTES, MAX_POLL_INTERVAL_MINUTES
+        )));
+    }
+
+    let mut connection = pool
+        .acquire()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let list_id = match body.list {
+        Some(slug) => {
+            let slug = ListSlug::parse(slug).map_err(FeedSourceError::ValidationError)?;
+            let list_id = get_list_id(&mut connection, &slug)
+                .await
+                .context("Failed to look up a mailing list.")?
+                .ok_or_else(|| {
+                    FeedSourceError::MissingListError(format!("{} is not a known list.", slug))
+                })?;
+            Some(list_id)
+        }
+        None => None,
+    };
+
+    let source = FeedSource {
+        id: Uuid::new_v4(),
+        url: url.to_string(),
+        mode: mode.as_str().to_string(),
+        list_id,
+        subject_template: subject_template.as_ref().to_string(),
+        html_template: html_template.as_ref().to_string(),
+        text_template: text_template.as_ref().to_string(),
+        poll_interval_minutes: poll_interval_minutes as i32,
+        last_polled_at: None,
+        last_error: None,
+    };
+    let inserted = sqlx::query!(
+        r#"INSERT INTO feed_sources
+           (id, url, mode, list_id, subject_template, html_template, text_template,
+            poll_interval_minutes, created_at)
+           VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+           ON CONFLICT (url) DO NOTHING"#,
+        source.id,
+        source.url,
+        source.mode,
+        source.list_id,
+        source.subject_template,
+        source.html_template,
+        source.text_template,
+        source.poll_interval_minutes,
+        Utc::now(),
+    )
+    .execute(&mut *connection)
+    .await
+    .context("Failed to insert a feed source in the database.")?;
+
+    if inserted.rows_affected() == 0 {
+        return Err(FeedSourceError::ConflictError(format!(
+            "The feed {} is already polled.",
+            source.url
+        )));
+    }
+
+    Ok(HttpResponse::Ok().json(source))
+}
+
+#[tracing::instrument(name = "Listing feed sources", skip(pool))]
+pub async fn list_feed_sources(pool: web::Data<PgPool>) -> Result<HttpResponse, FeedSourceError> {
+    let sources = sqlx::query_as!(
+        FeedSource,
+        r#"SELECT id, url, mode, list_id, subject_template, html_template, text_template,
+               poll_interval_minutes, last_polled_at, last_error
+           FROM feed_sources
+           ORDER BY created_at"#
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve feed sources")?;
+
+    Ok(HttpResponse::Ok().json(sources))
+}
@@ -0,0 +1,249 @@
+use std::time::SystemTime;
+
+use actix_web::{
+    http::header::{self, EntityTag, HttpDate},
+    web, HttpMessage, HttpRequest, HttpResponse,
+};
+use anyhow::Context;
+use chrono::{DateTime, SecondsFormat, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    domain::IssueVisibility,
+    routes::{body_of, escape_html, without_personal_links, ArchiveError},
+    startup::ApplicationBaseUrl,
+};
+
+const FEED_SIZE: i64 = 20;
+const FEED_TITLE: &str = "Newsletter archive";
+
+#[derive(Clone, Copy)]
+enum FeedFormat {
+    Rss,
+    Atom,
+}
+
+impl FeedFormat {
+    fn as_str(&self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "rss",
+            FeedFormat::Atom => "atom",
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "application/rss+xml; charset=utf-8",
+            FeedFormat::Atom => "application/atom+xml; charset=utf-8",
+        }
+    }
+}
+
+struct FeedIssue {
+    id: Uuid,
+    slug: String,
+    title: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Serving the RSS feed", skip_all)]
+pub async fn rss_feed(
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, ArchiveError> {
+    feed(&request, &pool, &base_url.0, FeedFormat::Rss).await
+}
+
+#[tracing::instrument(name = "Serving the Atom feed", skip_all)]
+pub async fn atom_feed(
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, ArchiveError> {
+    feed(&request, &pool, &base_url.0, FeedFormat::Atom).await
+}
+
+/// Answers conditional requests from a fingerprint of the feed's issues, so polling
+/// an unchanged feed never loads their content.
+async fn feed(
+    request: &HttpRequest,
+    pool: &PgPool,
+    base_url: &str,
+    format: FeedFormat,
+) -> Result<HttpResponse, ArchiveError> {
+    let fingerprint = sqlx::query!(
+        r#"SELECT
+               MAX(published_at) AS last_published_at,
+               md5(COALESCE(string_agg(id::text, ',' ORDER BY published_at DESC, id DESC), ''))
+                   AS "fingerprint!"
+           FROM (
+               SELECT id, published_at
+               FROM newsletter_issues
+               WHERE visibility = $1
+               ORDER BY published_at DESC, id DESC
+               LIMIT $2
+           ) recent"#,
+        IssueVisibility::Public.as_str(),
+        FEED_SIZE,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to fingerprint the feed")?;
+    let etag = EntityTag::new_strong(format!("{}-{}", format.as_str(), fingerprint.fingerprint));
+    let last_modified = fingerprint.last_published_at.map(http_date);
+
+    if is_not_modified(request, &etag, last_modified) {
+        let mut response = HttpResponse::NotModified();
+        response.insert_header(header::ETag(etag));
+        if let Some(last_modified) = last_modified {
+            response.insert_header(header::LastModified(last_modified));
+        }
+        return Ok(response.finish());
+    }
+
+    let issues = sqlx::query_as!(
+        FeedIssue,
+        r#"SELECT id, slug, title, html_content, published_at
+           FROM newsletter_issues
+           WHERE visibility = $1
+           ORDER BY published_at DESC, id DESC
+           LIMIT $2"#,
+        IssueVisibility::Public.as_str(),
+        FEED_SIZE,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the feed's issues")?;
+    let body = match format {
+        FeedFormat::Rss => render_rss(&issues, base_url),
+        FeedFormat::Atom => render_atom(&issues, base_url),
+    };
+
+    let mut response = HttpResponse::Ok();
+    response
+        .content_type(format.content_type())
+        .insert_header(header::ETag(etag));
+    if let Some(last_modified) = last_modified {
+        response.insert_header(header::LastModified(last_modified));
+    }
+    Ok(response.body(body))
+}
+
+/// `If-None-Match` takes precedence over `If-Modified-Since`, as in RFC 9110.
+fn is_not_modified(
+    request: &HttpRequest,
+    etag: &EntityTag,
+    last_modified: Option<HttpDate>,
+) -> bool {
+    if let Some(if_none_match) = request.get_header::<header::IfNoneMatch>() {
+        return match if_none_match {
+            header::IfNoneMatch::Any => true,
+            header::IfNoneMatch::Items(tags) => tags.iter().any(|tag| tag.weak_eq(etag)),
+        };
+    }
+    match (
+        request.get_header::<header::IfModifiedSince>(),
+        last_modified,
+    ) {
+        (Some(header::IfModifiedSince(since)), Some(last_modified)) => last_modified <= since,
+        _ => false,
+    }
+}
+
+/// HTTP dates have a one second resolution.
+fn http_date(at: DateTime<Utc>) -> HttpDate {
+    let whole_seconds = DateTime::from_timestamp(at.timestamp(), 0).unwrap_or(at);
+    SystemTime::from(whole_seconds).into()
+}
+
+fn issue_url(base_url: &str, issue: &FeedIssue) -> String {
+    format!("{}/archive/{}", base_url, issue.slug)
+}
+
+fn issue_content(issue: &FeedIssue) -> String {
+    escape_html(&without_personal_links(body_of(&issue.html_content)))
+}
+
+fn render_rss(issues: &[FeedIssue], base_url: &str) -> String {
+    let items: String = issues
+        .iter()
+        .map(|issue| {
+            format!(
+                "<item>\n<title>{}</title>\n<link>{}</link>\n<guid isPermaLink=\"false\">urn:uuid:{}</guid>\n<pubDate>{}</pubDate>\n<content:encoded>{}</content:encoded>\n</item>\n",
+                escape_html(&issue.title),
+                escape_html(&issue_url(base_url, issue)),
+                issue.id,
+                issue.published_at.to_rfc2822(),
+                issue_content(issue),
+            )
+        })
+        .collect();
+    let last_build_date = issues.first().map_or(String::new(), |issue| {
+        format!(
+            "<lastBuildDate>{}</lastBuildDate>\n",
+            issue.published_at.to_rfc2822()
+        )
+    });
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:content="http://purl.org/rss/1.0/modules/content/" xmlns:atom="http://www.w3.org/2005/Atom">
+<channel>
+<title>{title}</title>
+<link>{base_url}/archive</link>
+<description>Every public issue of the newsletter.</description>
+<atom:link href="{base_url}/feed.rss" rel="self" type="application/rss+xml"/>
+{last_build_date}{items}</channel>
+</rss>
+"#,
+        title = FEED_TITLE,
+        base_url = escape_html(base_url),
+        last_build_date = last_build_date,
+        items = items,
+    )
+}
+
+fn render_atom(issues: &[FeedIssue], base_url: &str) -> String {
+    let entries: String = issues
+        .iter()
+        .map(|issue| {
+            let published_at = issue
+                .published_at
+                .to_rfc3339_opts(SecondsFormat::Secs, true);
+            format!(
+                "<entry>\n<title>{}</title>\n<id>urn:uuid:{}</id>\n<link rel=\"alternate\" type=\"text/html\" href=\"{}\"/>\n<published>{}</published>\n<updated>{}</updated>\n<content type=\"html\">{}</content>\n</entry>\n",
+                escape_html(&issue.title),
+                issue.id,
+                escape_html(&issue_url(base_url, issue)),
+                published_at,
+                published_at,
+                issue_content(issue),
+            )
+        })
+        .collect();
+    // A feed without entries was last updated at the epoch as far as readers care.
+    let updated = issues
+        .first()
+        .map_or(DateTime::UNIX_EPOCH, |issue| issue.published_at)
+        .to_rfc3339_opts(SecondsFormat::Secs, true);
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+<title>{title}</title>
+<id>{base_url}/feed.atom</id>
+<link rel="self" type="application/atom+xml" href="{base_url}/feed.atom"/>
+<link rel="alternate" type="text/html" href="{base_url}/archive"/>
+<author><name>{title}</name></author>
+<updated>{updated}</updated>
+{entries}</feed>
+"#,
+        title = FEED_TITLE,
+        base_url = escape_html(base_url),
+        updated = updated,
+        entries = entries,
+    )
+}
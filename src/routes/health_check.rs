@@ -1,5 +1,15 @@
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 
-pub async fn health_check() -> HttpResponse {
-    HttpResponse::Ok().finish()
+use crate::email_client::EmailClient;
+
+/// Always healthy while the app serves requests, the email providers' circuits
+/// are reported for monitoring only. The background workers share the API's
+/// email client, so their failures show here too.
+pub async fn health_check(email_client: web::Data<EmailClient>) -> HttpResponse {
+    let providers: Vec<_> = email_client
+        .circuit_states()
+        .into_iter()
+        .map(|(name, state)| serde_json::json!({ "name": name, "circuit": state.as_str() }))
+        .collect();
+    HttpResponse::Ok().json(serde_json::json!({ "email_providers": providers }))
 }
@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    domain::{DeliveryStatus, IssueVisibility},
+    routes::error_chain_fmt,
+};
+
+#[derive(thiserror::Error)]
+pub enum IssueError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("{0}")]
+    MissingIssueError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for IssueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for IssueError {
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        match self {
+            Self::ValidationError(_) => actix_web::http::StatusCode::BAD_REQUEST,
+            Self::MissingIssueError(_) => actix_web::http::StatusCode::NOT_FOUND,
+            Self::UnexpectedError(_) => actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct IssueSettingsData {
+    visibility: String,
+}
+
+#[tracing::instrument(name = "Updating newsletter issue settings", skip(body, pool))]
+pub async fn update_issue(
+    issue_id: web::Path<Uuid>,
+    body: web::Json<IssueSettingsData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, IssueError> {
+    let issue_id = issue_id.into_inner();
+    let visibility =
+        IssueVisibility::parse(&body.visibility).map_err(IssueError::ValidationError)?;
+
+    let updated = sqlx::query!(
+        r#"UPDATE newsletter_issues SET visibility = $1 WHERE id = $2"#,
+        visibility.as_str(),
+        issue_id,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update a newsletter issue in the database.")?;
+
+    if updated.rows_affected() == 0 {
+        return Err(IssueError::MissingIssueError(format!(
+            "No issue with id {}",
+            issue_id
+        )));
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Each delivery is counted under its current status only, so a bounced delivery
+/// is not also counted as sent.
+#[derive(serde::Serialize)]
+pub struct IssueStats {
+    issue_id: Uuid,
+    title: String,
+    recipients: i64,
+    sent: i64,
+    failed: i64,
+    bounced: i64,
+    /// Deliveries opened at least once. Only tracked deliveries can count.
+    opened: i64,
+}
+
+#[tracing::instrument(name = "Computing issue statistics", skip(pool))]
+pub async fn get_issue_stats(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, IssueError> {
+    let issue_id = issue_id.into_inner();
+    let issue = sqlx::query!(
+        r#"SELECT title FROM newsletter_issues WHERE id = $1"#,
+        issue_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the newsletter issue")?
+    .ok_or_else(|| IssueError::MissingIssueError(format!("No issue with id {}", issue_id)))?;
+
+    let counts = sqlx::query!(
+        r#"SELECT
+               COUNT(*) AS "recipients!",
+               COUNT(*) FILTER (WHERE status = $2) AS "sent!",
+               COUNT(*) FILTER (WHERE status = $3) AS "failed!",
+               COUNT(*) FILTER (WHERE status = $4) AS "bounced!",
+               COUNT(*) FILTER (WHERE first_opened_at IS NOT NULL) AS "opened!"
+           FROM deliveries WHERE issue_id = $1"#,
+        issue_id,
+        DeliveryStatus::Sent.as_str(),
+        DeliveryStatus::Failed.as_str(),
+        DeliveryStatus::Bounced.as_str(),
+    )
+    .fetch_one(pool.get_ref())
+    .await
+    .context("Failed to count deliveries")?;
+
+    Ok(HttpResponse::Ok().json(IssueStats {
+        issue_id,
+        title: issue.title,
+        recipients: counts.recipients,
+        sent: counts.sent,
+        failed: counts.failed,
+        bounced: counts.bounced,
+        opened: counts.opened,
+    }))
+}
+
+#[derive(serde::Serialize)]
+pub struct LinkClicks {
+    url: String,
+    clicks: i64,
+    /// Distinct recipients that clicked the link.
+    unique_clicks: i64,
+}
+
+#[tracing::instrument(name = "Counting clicks per link", skip(pool))]
+pub async fn get_issue_links(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, IssueError> {
+    let issue_id = issue_id.into_inner();
+    sqlx::query!(
+        r#"SELECT id FROM newsletter_issues WHERE id = $1"#,
+        issue_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the newsletter issue")?
+    .ok_or_else(|| IssueError::MissingIssueError(format!("No issue with id {}", issue_id)))?;
+
+    let links = sqlx::query_as!(
+        LinkClicks,
+        r#"SELECT
+               l.url,
+               COUNT(c.id) AS "clicks!",
+               COUNT(DISTINCT c.delivery_id) AS "unique_clicks!"
+           FROM issue_links l
+           LEFT JOIN link_clicks c ON c.link_id = l.id
+           WHERE l.issue_id = $1
+           GROUP BY l.id, l.url, l.position
+           ORDER BY l.position"#,
+        issue_id
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to count link clicks")?;
+
+    Ok(HttpResponse::Ok().json(links))
+}
+
+/// Stores the links of an issue and returns their ids by URL.
+#[tracing::instrument(name = "Storing issue links", skip(transaction, urls))]
+pub async fn insert_issue_links(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    urls: &[String],
+) -> Result<HashMap<String, Uuid>, sqlx::Error> {
+    let mut link_ids = HashMap::with_capacity(urls.len());
+    for (position, url) in urls.iter().enumerate() {
+        let link_id = Uuid::new_v4();
+        sqlx::query!(
+            r#"INSERT INTO issue_links (id, issue_id, url, position) VALUES ($1, $2, $3, $4)"#,
+            link_id,
+            issue_id,
+            url,
+            position as i32,
+        )
+        .execute(&mut **transaction)
+        .await?;
+        link_ids.insert(url.clone(), link_id);
+    }
+
+    Ok(link_ids)
+}
+
+pub struct NewDelivery<'a> {
+    /// Generated before sending, since tracked links refer to it.
+    pub id: Uuid,
+    pub issue_id: Uuid,
+    pub subscriber_id: Uuid,
+    pub status: DeliveryStatus,
+    /// The provider that accepted the message.
+    pub provider: Option<&'a str>,
+    pub provider_message_id: Option<&'a str>,
+    pub error: Option<&'a str>,
+    pub tracking_token: Option<&'a str>,
+    pub variant_id: Option<Uuid>,
+}
+
+#[tracing::instrument(name = "Recording a delivery", skip(pool, delivery), fields(delivery_id = %delivery.id))]
+pub async fn record_delivery(pool: &PgPool, delivery: NewDelivery<'_>) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+    sqlx::query!(
+        r#"INSERT INTO deliveries
+           (id, issue_id, subscriber_id, status, provider, provider_message_id, error,
+            tracking_token, variant_id, attempted_at, updated_at)
+           VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)"#,
+        delivery.id,
+        delivery.issue_id,
+        delivery.subscriber_id,
+        delivery.status.as_str(),
+        delivery.provider,
+        delivery.provider_message_id,
+        delivery.error,
+        delivery.tracking_token,
+        delivery.variant_id,
+        now,
+        now,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Marks the delivery of a provider message as bounced, if we sent it.
+#[tracing::instrument(name = "Marking a delivery as bounced", skip(transaction, error))]
+pub async fn mark_delivery_bounced(
+    transaction: &mut Transaction<'_, Postgres>,
+    provider_message_id: &str,
+    error: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE deliveries SET status = $1, error = COALESCE($2, error), updated_at = $3
+           WHERE provider_message_id = $4"#,
+        DeliveryStatus::Bounced.as_str(),
+        error,
+        Utc::now(),
+        provider_message_id,
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,146 @@
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::{domain::ListSlug, routes::error_chain_fmt};
+
+#[derive(thiserror::Error)]
+pub enum ListError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("{0}")]
+    ConflictError(String),
+    #[error("{0}")]
+    MissingListError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ListError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ListError {
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        match self {
+            Self::ValidationError(_) => actix_web::http::StatusCode::BAD_REQUEST,
+            Self::ConflictError(_) => actix_web::http::StatusCode::CONFLICT,
+            Self::MissingListError(_) => actix_web::http::StatusCode::NOT_FOUND,
+            Self::UnexpectedError(_) => actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct ListData {
+    slug: String,
+    name: String,
+    /// Whether newsletters to this list embed an open tracking pixel. Defaults to true.
+    open_tracking: Option<bool>,
+}
+
+#[derive(serde::Serialize)]
+pub struct CreatedList {
+    id: Uuid,
+    slug: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ListSettingsData {
+    open_tracking: Option<bool>,
+}
+
+#[tracing::instrument(name = "Creating a new mailing list", skip(body, pool), fields(list_slug = %body.slug))]
+pub async fn create_list(
+    body: web::Json<ListData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ListError> {
+    let slug = ListSlug::parse(body.slug.clone()).map_err(ListError::ValidationError)?;
+    if body.name.trim().is_empty() {
+        return Err(ListError::ValidationError(
+            "A list must have a name.".to_string(),
+        ));
+    }
+
+    let list_id = Uuid::new_v4();
+    let inserted = sqlx::query!(
+        r#"INSERT INTO lists (id, slug, name, open_tracking, created_at)
+           VALUES ($1, $2, $3, $4, $5)
+           ON CONFLICT (slug) DO NOTHING"#,
+        list_id,
+        slug.as_ref(),
+        body.name.trim(),
+        body.open_tracking.unwrap_or(true),
+        Utc::now(),
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to insert a new list in the database.")?;
+
+    if inserted.rows_affected() == 0 {
+        return Err(ListError::ConflictError(format!(
+            "A list with slug {} already exists.",
+            slug
+        )));
+    }
+
+    Ok(HttpResponse::Ok().json(CreatedList {
+        id: list_id,
+        slug: slug.to_string(),
+    }))
+}
+
+#[tracing::instrument(name = "Updating mailing list settings", skip(body, pool))]
+pub async fn update_list(
+    slug: web::Path<String>,
+    body: web::Json<ListSettingsData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ListError> {
+    let slug = ListSlug::parse(slug.into_inner()).map_err(ListError::ValidationError)?;
+
+    let updated = sqlx::query!(
+        r#"UPDATE lists SET open_tracking = COALESCE($1, open_tracking) WHERE slug = $2"#,
+        body.open_tracking,
+        slug.as_ref(),
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update a list in the database.")?;
+
+    if updated.rows_affected() == 0 {
+        return Err(ListError::MissingListError(format!(
+            "{} is not a known list.",
+            slug
+        )));
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(name = "Checking whether a list tracks opens", skip(connection))]
+pub async fn list_tracks_opens(
+    connection: &mut PgConnection,
+    list_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(r#"SELECT open_tracking FROM lists WHERE id = $1"#, list_id)
+        .fetch_one(connection)
+        .await?;
+
+    Ok(result.open_tracking)
+}
+
+#[tracing::instrument(name = "Retrieving list ID by slug", skip(connection))]
+pub async fn get_list_id(
+    connection: &mut PgConnection,
+    slug: &ListSlug,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let result = sqlx::query!(r#"SELECT id FROM lists WHERE slug = $1"#, slug.as_ref())
+        .fetch_optional(connection)
+        .await?;
+
+    Ok(result.map(|r| r.id))
+}
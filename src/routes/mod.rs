@@ -1,11 +1,41 @@
+mod archive;
+mod custom_fields;
+mod drafts;
+mod email_events;
+mod feed_sources;
+mod feeds;
 mod health_check;
 mod helpers;
+mod issues;
+mod lists;
 mod newsletters;
+mod preferences;
+mod segments;
+mod sequences;
+mod subscribers;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
+mod suppressions;
+mod tracking;
 
+pub use archive::*;
+pub use custom_fields::*;
+pub use drafts::*;
+pub use email_events::*;
+pub use feed_sources::*;
+pub use feeds::*;
 pub use health_check::*;
 pub use helpers::*;
+pub use issues::*;
+pub use lists::*;
 pub use newsletters::*;
+pub use preferences::*;
+pub use segments::*;
+pub use sequences::*;
+pub use subscribers::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_unsubscribe::*;
+pub use suppressions::*;
+pub use tracking::*;
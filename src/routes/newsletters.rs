@@ -1,16 +1,44 @@
-use std::fmt::Debug;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Debug,
+};
 
 use actix_web::{web, HttpResponse, ResponseError};
 use anyhow::Context;
+use chrono::Utc;
+use rand::seq::SliceRandom;
 use reqwest::StatusCode;
-use sqlx::PgPool;
+use sqlx::{Acquire, PgPool, Postgres, QueryBuilder, Transaction};
+use uuid::Uuid;
 
-use crate::{domain::SubscriberEmail, email_client::EmailClient};
+use crate::{
+    domain::{
+        AbTest, AbTestMetric, DigestFrequency, IssueSlug, IssueVisibility, ListSlug, Locale,
+        NewsletterContent, SegmentExpression, SubscriberEmail,
+    },
+    email_client::EmailClient,
+    issue_delivery_worker::{Issue, IssueSender, OutgoingIssue, Recipient, Translation, Variant},
+    startup::{ApplicationBaseUrl, HmacSecret},
+};
 
-use super::error_chain_fmt;
+use super::{
+    error_chain_fmt, get_custom_field_definitions, get_list_id, get_segment_expression,
+    get_suppressed_emails, insert_issue_links, is_suppressed, list_tracks_opens,
+    push_segment_filter, trackable_links, CustomFieldDefinition,
+};
 
 #[derive(thiserror::Error)]
 pub enum PublishError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("{0}")]
+    UnknownListError(String),
+    #[error("{0}")]
+    UnknownSegmentError(String),
+    #[error("{0}")]
+    UnknownDraftError(String),
+    #[error("{0}")]
+    InvalidContentError(String),
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -24,6 +52,11 @@ impl Debug for PublishError {
 impl ResponseError for PublishError {
     fn status_code(&self) -> reqwest::StatusCode {
         match self {
+            PublishError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            PublishError::UnknownListError(_) => StatusCode::NOT_FOUND,
+            PublishError::UnknownSegmentError(_) => StatusCode::NOT_FOUND,
+            PublishError::UnknownDraftError(_) => StatusCode::NOT_FOUND,
+            PublishError::InvalidContentError(_) => StatusCode::BAD_REQUEST,
             PublishError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -33,38 +66,256 @@ impl ResponseError for PublishError {
 pub struct BodyData {
     title: String,
     content: Content,
+    list: Option<String>,
+    segment_id: Option<Uuid>,
+    ab_test: Option<AbTestData>,
+    /// Archive visibility. Unless given, `hidden` for issues sent to a list or a
+    /// segment and `public` otherwise.
+    visibility: Option<String>,
+    /// Id of the sender identity to send from, the newsletter one unless given.
+    sender: Option<String>,
+    /// Versions of the issue in other languages, by language tag.
+    translations: Option<BTreeMap<String, TranslationData>>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct TranslationData {
+    title: String,
+    content: Content,
+}
+
+#[derive(serde::Deserialize)]
+pub struct AbTestData {
+    subjects: Vec<String>,
+    sample_percentage: u8,
+    wait_minutes: u32,
+    metric: String,
 }
 
 #[derive(serde::Deserialize)]
 pub struct Content {
     html: String,
-    text: String,
+    /// Generated from the HTML when omitted.
+    text: Option<String>,
 }
 
-struct ConfirmedSubscriber {
-    email: SubscriberEmail,
+#[derive(serde::Serialize)]
+pub struct PublishedIssue {
+    pub issue_id: Uuid,
+}
+
+/// How `publish_issue` gets the issue to its recipients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// Sent before returning, leaving only retryable failures to the worker.
+    Immediate,
+    /// Handed to the delivery worker, for callers that must not wait on the provider.
+    Queued,
+}
+
+/// A validated issue, ready to be published.
+pub struct NewIssue {
+    pub title: String,
+    pub html: String,
+    /// Generated from the HTML when missing.
+    pub text: Option<String>,
+    pub list: Option<ListSlug>,
+    pub segment_id: Option<Uuid>,
+    pub ab_test: Option<AbTest>,
+    pub visibility: IssueVisibility,
+    /// Id of a configured sender identity.
+    pub sender: Option<String>,
+    /// Sent instead of the issue to subscribers with a matching locale.
+    pub translations: Vec<NewTranslation>,
+}
+
+/// A version of a new issue in another language.
+pub struct NewTranslation {
+    pub locale: Locale,
+    pub title: String,
+    pub html: String,
+    /// Generated from the HTML when missing.
+    pub text: Option<String>,
 }
 
 pub async fn publish_newsletter(
     body: web::Json<BodyData>,
     pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
 ) -> Result<HttpResponse, PublishError> {
-    let subscribers = get_confirmed_subscribers(&pool).await?;
+    let body = body.into_inner();
+    let visibility = match &body.visibility {
+        Some(visibility) => {
+            IssueVisibility::parse(visibility).map_err(PublishError::ValidationError)?
+        }
+        None => IssueVisibility::default_for(body.list.is_some() || body.segment_id.is_some()),
+    };
+    let ab_test = body
+        .ab_test
+        .as_ref()
+        .map(|t| {
+            AbTest::parse(
+                t.subjects.clone(),
+                t.sample_percentage,
+                t.wait_minutes,
+                &t.metric,
+            )
+        })
+        .transpose()
+        .map_err(PublishError::ValidationError)?;
+    let list = body
+        .list
+        .map(ListSlug::parse)
+        .transpose()
+        .map_err(PublishError::ValidationError)?;
+    let mut translations: Vec<NewTranslation> = Vec::new();
+    for (tag, translation) in body.translations.unwrap_or_default() {
+        let locale = Locale::parse(&tag).map_err(PublishError::ValidationError)?;
+        if translations.iter().any(|t| t.locale == locale) {
+            return Err(PublishError::ValidationError(format!(
+                "The issue is translated to {} more than once.",
+                locale
+            )));
+        }
+        translations.push(NewTranslation {
+            locale,
+            title: translation.title,
+            html: translation.content.html,
+            text: translation.content.text,
+        });
+    }
+    // A translated title would replace the subject being tested.
+    if ab_test.is_some() && !translations.is_empty() {
+        return Err(PublishError::ValidationError(
+            "A/B tests cannot be combined with translations.".to_string(),
+        ));
+    }
+    if let Some(id) = &body.sender {
+        if email_client.senders().get(id).is_none() {
+            return Err(PublishError::ValidationError(format!(
+                "{} is not a known sender identity.",
+                id
+            )));
+        }
+    }
+
+    let sender = IssueSender {
+        pool: &pool,
+        email_client: &email_client,
+        base_url: &base_url.0,
+        hmac_secret: &hmac_secret.0,
+    };
+    let issue_id = publish_issue(
+        &sender,
+        NewIssue {
+            title: body.title,
+            html: body.content.html,
+            text: body.content.text,
+            list,
+            segment_id: body.segment_id,
+            ab_test,
+            visibility,
+            sender: body.sender,
+            translations,
+        },
+        Delivery::Immediate,
+    )
+    .await?;
 
+    Ok(HttpResponse::Ok().json(PublishedIssue { issue_id }))
+}
+
+/// Sanitises the issue's content, stores the issue and sends or queues it for every
+/// confirmed, non-suppressed recipient, or for an A/B test sample. Recipients who get
+/// digests have it held for their next one instead.
+#[tracing::instrument(name = "Publishing a newsletter issue", skip_all, fields(title = %new_issue.title))]
+pub async fn publish_issue(
+    sender: &IssueSender<'_>,
+    new_issue: NewIssue,
+    delivery: Delivery,
+) -> Result<Uuid, PublishError> {
+    let content = NewsletterContent::parse(&new_issue.html, new_issue.text.as_deref())
+        .map_err(PublishError::InvalidContentError)?;
+    let translations = new_issue
+        .translations
+        .iter()
+        .map(|t| {
+            NewsletterContent::parse(&t.html, t.text.as_deref())
+                .map(|content| (t, content))
+                .map_err(|e| {
+                    PublishError::InvalidContentError(format!("{} translation: {}", t.locale, e))
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let pool = sender.pool;
+    let mut connection = pool
+        .acquire()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    let list_id = match &new_issue.list {
+        Some(slug) => {
+            let list_id = get_list_id(&mut connection, slug)
+                .await
+                .context("Failed to look up a mailing list.")?
+                .ok_or_else(|| {
+                    PublishError::UnknownListError(format!("{} is not a known list.", slug))
+                })?;
+            Some(list_id)
+        }
+        None => None,
+    };
+
+    let subscribers = match (new_issue.segment_id, list_id) {
+        (Some(segment_id), list_id) => {
+            let expression = get_segment_expression(&mut connection, segment_id)
+                .await
+                .context("Failed to retrieve segment")?
+                .ok_or_else(|| {
+                    PublishError::UnknownSegmentError(format!("No segment with id {}", segment_id))
+                })?
+                .map_err(PublishError::ValidationError)?;
+            let definitions = get_custom_field_definitions(&mut connection)
+                .await
+                .context("Failed to retrieve custom field definitions")?;
+
+            get_confirmed_segment_subscribers(pool, list_id, &expression, &definitions).await?
+        }
+        (None, Some(list_id)) => get_confirmed_list_subscribers(pool, list_id).await?,
+        (None, None) => get_confirmed_subscribers(pool).await?,
+    };
+    let open_tracking = match list_id {
+        Some(list_id) => list_tracks_opens(&mut connection, list_id)
+            .await
+            .context("Failed to retrieve the list's tracking settings")?,
+        None => true,
+    };
+    drop(connection);
+    if new_issue.ab_test.as_ref().map(|t| t.metric) == Some(AbTestMetric::Opens) && !open_tracking {
+        return Err(PublishError::ValidationError(
+            "Opens cannot decide an A/B test on a list without open tracking.".to_string(),
+        ));
+    }
+
+    let emails: Vec<_> = subscribers
+        .iter()
+        .filter_map(|s| s.as_ref().ok().map(|s| &s.email))
+        .collect();
+    let suppressed = get_suppressed_emails(pool, &emails)
+        .await
+        .context("Failed to check the suppression list")?;
+    let mut recipients = Vec::new();
     for subscriber in subscribers {
         match subscriber {
-            Ok(subscriber) => {
-                email_client
-                    .send_email(
-                        &subscriber.email,
-                        &body.title,
-                        &body.content.html,
-                        &body.content.text,
-                    )
-                    .await
-                    .with_context(|| format!("Failed to send email to {}", subscriber.email))?;
+            Ok(subscriber) if is_suppressed(&suppressed, &subscriber.email) => {
+                tracing::info!(
+                    subscriber_email = %subscriber.email,
+                    "Skipping a confirmed subscriber. Their address is suppressed."
+                );
             }
+            Ok(subscriber) => recipients.push(subscriber),
             Err(error) => {
                 tracing::warn!(error.cause_chain = ?error, "Skipping a confirmed subscriber. \
                     Their stored contact details are invalid.");
@@ -72,23 +323,377 @@ pub async fn publish_newsletter(
         }
     }
 
-    Ok(HttpResponse::Ok().finish())
+    // Nothing about the issue is visible until it is stored in full.
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let issue_id = insert_newsletter_issue(&mut transaction, &new_issue, &content, list_id)
+        .await
+        .context("Failed to store the newsletter issue")?;
+    insert_issue_translations(&mut transaction, issue_id, &translations)
+        .await
+        .context("Failed to store the newsletter issue's translations")?;
+    let mut urls = trackable_links(content.html());
+    for url in translations
+        .iter()
+        .flat_map(|(_, content)| trackable_links(content.html()))
+    {
+        if !urls.contains(&url) {
+            urls.push(url);
+        }
+    }
+    let link_ids = insert_issue_links(&mut transaction, issue_id, &urls)
+        .await
+        .context("Failed to store the newsletter issue's links")?;
+    let issue = Issue {
+        id: issue_id,
+        title: new_issue.title,
+        html: content.html().to_string(),
+        text: content.text().to_string(),
+        open_tracking,
+        sender: new_issue.sender,
+        link_ids,
+        translations: translations
+            .into_iter()
+            .map(|(t, content)| {
+                let translation = Translation {
+                    title: t.title.clone(),
+                    html: content.html().to_string(),
+                    text: content.text().to_string(),
+                };
+                (t.locale, translation)
+            })
+            .collect(),
+    };
+
+    let held = hold_for_digests(&mut transaction, issue_id, &recipients)
+        .await
+        .context("Failed to hold the issue for digest subscribers")?;
+    recipients.retain(|recipient| !held.contains(&recipient.id));
+
+    let variants;
+    let deliveries: Vec<(&Recipient, Option<&Variant>)> = match new_issue.ab_test {
+        Some(ab_test) => {
+            // The sample gets the variants round-robin, the rest waits for the winner.
+            recipients.shuffle(&mut rand::thread_rng());
+            let (sample, holdouts) = recipients.split_at(ab_test.sample_size(recipients.len()));
+            variants = insert_ab_test(&mut transaction, issue_id, &ab_test, holdouts)
+                .await
+                .context("Failed to store the A/B test")?;
+            sample
+                .iter()
+                .zip(variants.iter().map(Some).cycle())
+                .collect()
+        }
+        None => recipients
+            .iter()
+            .map(|recipient| (recipient, None))
+            .collect(),
+    };
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a newsletter issue")?;
+    match delivery {
+        Delivery::Immediate => {
+            let outgoing: Vec<_> = deliveries
+                .into_iter()
+                .map(|(recipient, variant)| OutgoingIssue {
+                    issue: &issue,
+                    recipient,
+                    variant,
+                    may_retry: true,
+                })
+                .collect();
+            sender
+                .send_or_queue(&outgoing)
+                .await
+                .context("Failed to record a delivery")?;
+        }
+        Delivery::Queued => enqueue_deliveries(pool, issue_id, &deliveries)
+            .await
+            .context("Failed to queue the issue's deliveries")?,
+    }
+
+    Ok(issue_id)
+}
+
+#[tracing::instrument(name = "Queue the deliveries of an issue", skip(pool, deliveries))]
+async fn enqueue_deliveries(
+    pool: &PgPool,
+    issue_id: Uuid,
+    deliveries: &[(&Recipient, Option<&Variant>)],
+) -> Result<(), sqlx::Error> {
+    let subscriber_ids: Vec<Uuid> = deliveries.iter().map(|(r, _)| r.id).collect();
+    let variant_ids: Vec<Option<Uuid>> = deliveries.iter().map(|(_, v)| v.map(|v| v.id)).collect();
+    sqlx::query!(
+        r#"INSERT INTO issue_delivery_queue (issue_id, subscriber_id, variant_id, enqueued_at)
+           SELECT $1, subscriber_id, variant_id, now()
+           FROM UNNEST($2::uuid[], $3::uuid[]) AS d(subscriber_id, variant_id)"#,
+        issue_id,
+        &subscriber_ids,
+        &variant_ids as &[Option<Uuid>],
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Holds the issue for the recipients who get their issues in a digest and returns
+/// their ids.
+#[tracing::instrument(name = "Hold an issue for digests", skip(transaction, recipients))]
+async fn hold_for_digests(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    recipients: &[Recipient],
+) -> Result<HashSet<Uuid>, sqlx::Error> {
+    let subscriber_ids: Vec<Uuid> = recipients.iter().map(|r| r.id).collect();
+    let held = sqlx::query!(
+        r#"INSERT INTO digest_items (subscriber_id, issue_id, enqueued_at)
+           SELECT id, $2, now() FROM subscriptions
+           WHERE id = ANY($1) AND digest_frequency <> $3
+           RETURNING subscriber_id"#,
+        &subscriber_ids,
+        issue_id,
+        DigestFrequency::Immediate.as_str(),
+    )
+    .fetch_all(&mut **transaction)
+    .await?
+    .into_iter()
+    .map(|row| row.subscriber_id)
+    .collect();
+
+    Ok(held)
+}
+
+#[tracing::instrument(name = "Store an A/B test", skip(transaction, ab_test, holdouts))]
+async fn insert_ab_test(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    ab_test: &AbTest,
+    holdouts: &[Recipient],
+) -> Result<Vec<Variant>, sqlx::Error> {
+    sqlx::query!(
+        r#"INSERT INTO ab_tests (issue_id, metric, sample_percentage, decide_at)
+           VALUES ($1, $2, $3, $4)"#,
+        issue_id,
+        ab_test.metric.as_str(),
+        i32::from(ab_test.sample_percentage),
+        Utc::now() + ab_test.wait,
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    let mut variants = Vec::with_capacity(ab_test.subjects.len());
+    for (position, subject) in ab_test.subjects.iter().enumerate() {
+        let variant = Variant {
+            id: Uuid::new_v4(),
+            subject: subject.clone(),
+        };
+        sqlx::query!(
+            r#"INSERT INTO ab_test_variants (id, issue_id, position, subject)
+               VALUES ($1, $2, $3, $4)"#,
+            variant.id,
+            issue_id,
+            position as i32,
+            variant.subject,
+        )
+        .execute(&mut **transaction)
+        .await?;
+        variants.push(variant);
+    }
+
+    let subscriber_ids: Vec<Uuid> = holdouts.iter().map(|r| r.id).collect();
+    sqlx::query!(
+        r#"INSERT INTO ab_test_holdouts (issue_id, subscriber_id)
+           SELECT $1, * FROM UNNEST($2::uuid[])"#,
+        issue_id,
+        &subscriber_ids,
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(variants)
+}
+
+#[tracing::instrument(name = "Store a newsletter issue", skip(transaction, new_issue))]
+async fn insert_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    new_issue: &NewIssue,
+    content: &NewsletterContent,
+    list_id: Option<Uuid>,
+) -> Result<Uuid, sqlx::Error> {
+    let issue_id = Uuid::new_v4();
+    let mut attempts = 1;
+    loop {
+        let slug =
+            available_issue_slug(transaction, IssueSlug::from_title(&new_issue.title)).await?;
+        // A savepoint, so a taken slug does not abort the whole transaction.
+        let mut savepoint = transaction.begin().await?;
+        let inserted = sqlx::query!(
+            r#"INSERT INTO newsletter_issues
+               (id, title, text_content, html_content, list_id, segment_id, published_at, slug,
+                visibility, sender_identity)
+               VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)"#,
+            issue_id,
+            new_issue.title,
+            content.text(),
+            content.html(),
+            list_id,
+            new_issue.segment_id,
+            Utc::now(),
+            slug.as_ref(),
+            new_issue.visibility.as_str(),
+            new_issue.sender,
+        )
+        .execute(&mut *savepoint)
+        .await;
+        match inserted {
+            // Another issue with the same title took the slug since we looked it up.
+            Err(sqlx::Error::Database(e))
+                if e.constraint() == Some("newsletter_issues_slug_key")
+                    && attempts < MAX_SLUG_ATTEMPTS =>
+            {
+                attempts += 1;
+            }
+            inserted => {
+                inserted?;
+                savepoint.commit().await?;
+                return Ok(issue_id);
+            }
+        }
+    }
+}
+
+const MAX_SLUG_ATTEMPTS: u32 = 5;
+
+#[tracing::instrument(name = "Saving newsletter issue translations", skip_all)]
+async fn insert_issue_translations(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    translations: &[(&NewTranslation, NewsletterContent)],
+) -> Result<(), sqlx::Error> {
+    for (translation, content) in translations {
+        sqlx::query!(
+            r#"INSERT INTO issue_translations (issue_id, locale, title, html_content, text_content)
+               VALUES ($1, $2, $3, $4, $5)"#,
+            issue_id,
+            translation.locale.as_str(),
+            translation.title,
+            content.html(),
+            content.text(),
+        )
+        .execute(&mut **transaction)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// The first of `slug`, `slug-2`, `slug-3`... not used by another issue.
+async fn available_issue_slug(
+    transaction: &mut Transaction<'_, Postgres>,
+    slug: IssueSlug,
+) -> Result<IssueSlug, sqlx::Error> {
+    let taken: Vec<String> = sqlx::query!(
+        r#"SELECT slug FROM newsletter_issues WHERE slug = $1 OR slug LIKE $1 || '-%'"#,
+        slug.as_ref(),
+    )
+    .fetch_all(&mut **transaction)
+    .await?
+    .into_iter()
+    .map(|row| row.slug)
+    .collect();
+
+    let mut candidate = slug.clone();
+    let mut suffix = 1;
+    while taken.iter().any(|t| t == candidate.as_ref()) {
+        suffix += 1;
+        candidate = slug.with_suffix(suffix);
+    }
+
+    Ok(candidate)
 }
 
 #[tracing::instrument(name = "Get confirmed subscribers", skip(pool))]
 async fn get_confirmed_subscribers(
     pool: &PgPool,
-) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, anyhow::Error> {
+) -> Result<Vec<Result<Recipient, anyhow::Error>>, anyhow::Error> {
     let confirmed_subscribers =
-        sqlx::query!(r#"SELECT email FROM subscriptions WHERE status = 'confirmed'"#)
+        sqlx::query!(r#"SELECT id, email, locale FROM subscriptions WHERE status = 'confirmed'"#)
             .fetch_all(pool)
             .await?
             .into_iter()
-            .map(|row| match SubscriberEmail::parse(row.email) {
-                Ok(email) => Ok(ConfirmedSubscriber { email }),
-                Err(error) => Err(anyhow::anyhow!(error)),
-            })
+            .map(|row| recipient(row.id, row.email, &row.locale))
             .collect();
 
     Ok(confirmed_subscribers)
 }
+
+#[tracing::instrument(name = "Get confirmed subscribers of a list", skip(pool))]
+async fn get_confirmed_list_subscribers(
+    pool: &PgPool,
+    list_id: Uuid,
+) -> Result<Vec<Result<Recipient, anyhow::Error>>, anyhow::Error> {
+    let confirmed_subscribers = sqlx::query!(
+        r#"SELECT s.id, s.email, s.locale
+           FROM subscriptions s
+           JOIN list_memberships m ON m.subscriber_id = s.id
+           WHERE m.list_id = $1 AND m.status = 'confirmed' AND s.status = 'confirmed'"#,
+        list_id,
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| recipient(row.id, row.email, &row.locale))
+    .collect();
+
+    Ok(confirmed_subscribers)
+}
+
+#[tracing::instrument(
+    name = "Get confirmed subscribers in a segment",
+    skip(pool, expression, definitions)
+)]
+async fn get_confirmed_segment_subscribers(
+    pool: &PgPool,
+    list_id: Option<Uuid>,
+    expression: &SegmentExpression,
+    definitions: &[CustomFieldDefinition],
+) -> Result<Vec<Result<Recipient, anyhow::Error>>, PublishError> {
+    let mut builder = QueryBuilder::new(
+        "SELECT s.id, s.email, s.locale FROM subscriptions s WHERE s.status = 'confirmed'",
+    );
+    if let Some(list_id) = list_id {
+        builder
+            .push(" AND EXISTS (SELECT 1 FROM list_memberships m WHERE m.subscriber_id = s.id AND m.status = 'confirmed' AND m.list_id = ")
+            .push_bind(list_id)
+            .push(")");
+    }
+    builder.push(" AND ");
+    push_segment_filter(&mut builder, expression, definitions)
+        .map_err(PublishError::ValidationError)?;
+
+    let confirmed_subscribers = builder
+        .build_query_as::<(Uuid, String, String)>()
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve segment members")?
+        .into_iter()
+        .map(|(id, email, locale)| recipient(id, email, &locale))
+        .collect();
+
+    Ok(confirmed_subscribers)
+}
+
+/// Unknown stored locales fall back to the default one.
+fn recipient(id: Uuid, email: String, locale: &str) -> Result<Recipient, anyhow::Error> {
+    let email = SubscriberEmail::parse(email).map_err(|error| anyhow::anyhow!(error))?;
+    Ok(Recipient {
+        id,
+        email,
+        locale: Locale::parse(locale).unwrap_or_default(),
+    })
+}
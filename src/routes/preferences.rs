@@ -0,0 +1,444 @@
+use actix_web::{http::header::ContentType, web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    domain::{DigestFrequency, ListSlug, SubscriberName, SubscriptionStatus},
+    routes::{
+        enrol_in_sequences, error_chain_fmt, escape_html, get_subscriber_id_from_token,
+        unsubscribe_from_all, unsubscribe_from_list, StatusTransitionError,
+    },
+};
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesParameters {
+    subscription_token: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum PreferencesError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("{0}")]
+    MissingSubscriberError(String),
+    #[error("{0}")]
+    ConflictError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PreferencesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PreferencesError {
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        match self {
+            Self::ValidationError(_) => actix_web::http::StatusCode::BAD_REQUEST,
+            Self::MissingSubscriberError(_) => actix_web::http::StatusCode::NOT_FOUND,
+            Self::ConflictError(_) => actix_web::http::StatusCode::CONFLICT,
+            Self::UnexpectedError(_) => actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+pub struct PreferencesUpdate {
+    subscription_token: String,
+    name: SubscriberName,
+    lists: Vec<ListSlug>,
+    digest_frequency: DigestFrequency,
+    unsubscribe: bool,
+}
+
+/// The preference form submits one `lists` pair per ticked checkbox, so it is read as raw pairs.
+impl TryFrom<Vec<(String, String)>> for PreferencesUpdate {
+    type Error = String;
+
+    fn try_from(pairs: Vec<(String, String)>) -> Result<Self, Self::Error> {
+        let mut subscription_token = None;
+        let mut name = None;
+        let mut lists = Vec::new();
+        let mut digest_frequency = None;
+        let mut unsubscribe = false;
+
+        for (key, value) in pairs {
+            match key.as_str() {
+                "subscription_token" => subscription_token = Some(value),
+                "name" => name = Some(SubscriberName::parse(value)?),
+                "lists" => {
+                    for slug in ListSlug::parse_many(&value)? {
+                        if !lists.contains(&slug) {
+                            lists.push(slug);
+                        }
+                    }
+                }
+                "digest_frequency" => digest_frequency = Some(DigestFrequency::parse(value)?),
+                "unsubscribe" => unsubscribe = value == "true" || value == "on",
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            subscription_token: subscription_token.ok_or("Missing subscription token.")?,
+            name: name.ok_or("Missing subscriber name.")?,
+            lists,
+            digest_frequency: digest_frequency.ok_or("Missing digest frequency.")?,
+            unsubscribe,
+        })
+    }
+}
+
+struct Preferences {
+    email: String,
+    name: String,
+    status: String,
+    digest_frequency: String,
+    lists: Vec<ListPreference>,
+}
+
+struct ListPreference {
+    id: Uuid,
+    slug: String,
+    name: String,
+    subscribed: bool,
+}
+
+#[tracing::instrument(name = "Show subscriber preferences", skip(parameters, pool))]
+pub async fn preferences_form(
+    parameters: web::Query<PreferencesParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PreferencesError> {
+    let subscriber_id = get_subscriber_id_from_token(&pool, &parameters.subscription_token)
+        .await
+        .context("Unable to retrieve subscriber id from token")?
+        .ok_or_else(|| {
+            PreferencesError::MissingSubscriberError("Invalid subscription token".to_string())
+        })?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let preferences = get_preferences(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to retrieve subscriber preferences")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to read subscriber preferences")?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(render_preferences(
+            &parameters.subscription_token,
+            &preferences,
+        )))
+}
+
+#[tracing::instrument(name = "Update subscriber preferences", skip(form, pool))]
+pub async fn update_preferences(
+    form: web::Form<Vec<(String, String)>>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PreferencesError> {
+    let update: PreferencesUpdate = form
+        .into_inner()
+        .try_into()
+        .map_err(PreferencesError::ValidationError)?;
+
+    let subscriber_id = get_subscriber_id_from_token(&pool, &update.subscription_token)
+        .await
+        .context("Unable to retrieve subscriber id from token")?
+        .ok_or_else(|| {
+            PreferencesError::MissingSubscriberError("Invalid subscription token".to_string())
+        })?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    let current = get_preferences(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to retrieve subscriber preferences")?;
+
+    if let Some(unknown) = update
+        .lists
+        .iter()
+        .find(|slug| !current.lists.iter().any(|l| l.slug == slug.as_ref()))
+    {
+        return Err(PreferencesError::ValidationError(format!(
+            "{} is not a known list.",
+            unknown
+        )));
+    }
+
+    let mut changed = false;
+
+    if current.name != update.name.as_ref() {
+        sqlx::query!(
+            r#"UPDATE subscriptions SET name = $1 WHERE id = $2"#,
+            update.name.as_ref(),
+            subscriber_id,
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to update subscriber name")?;
+        record_preference_change(
+            &mut transaction,
+            subscriber_id,
+            "name",
+            update.name.as_ref(),
+        )
+        .await
+        .context("Failed to record a preference change")?;
+        changed = true;
+    }
+
+    if current.digest_frequency != update.digest_frequency.as_str() {
+        sqlx::query!(
+            r#"UPDATE subscriptions SET digest_frequency = $1 WHERE id = $2"#,
+            update.digest_frequency.as_str(),
+            subscriber_id,
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to update digest frequency")?;
+        record_preference_change(
+            &mut transaction,
+            subscriber_id,
+            "digest_frequency",
+            update.digest_frequency.as_str(),
+        )
+        .await
+        .context("Failed to record a preference change")?;
+        changed = true;
+    }
+
+    if update.unsubscribe {
+        if current.status != SubscriptionStatus::Unsubscribed.as_str() {
+            unsubscribe_from_all(&mut transaction, subscriber_id)
+                .await
+                .map_err(|e| match e {
+                    StatusTransitionError::IllegalTransition(e) => {
+                        PreferencesError::ConflictError(e)
+                    }
+                    e => PreferencesError::UnexpectedError(
+                        anyhow::Error::new(e).context("Failed to unsubscribe from all lists"),
+                    ),
+                })?;
+            record_preference_change(
+                &mut transaction,
+                subscriber_id,
+                "status",
+                SubscriptionStatus::Unsubscribed.as_str(),
+            )
+            .await
+            .context("Failed to record a preference change")?;
+            changed = true;
+        }
+    } else {
+        for list in &current.lists {
+            let wanted = update.lists.iter().any(|slug| slug.as_ref() == list.slug);
+            if wanted == list.subscribed {
+                continue;
+            }
+
+            let status = if wanted {
+                // Only a confirmed address may join a list without a new opt-in.
+                if current.status != SubscriptionStatus::Confirmed.as_str() {
+                    return Err(PreferencesError::ConflictError(format!(
+                        "Confirm your subscription before joining {}.",
+                        list.slug
+                    )));
+                }
+                join_list(&mut transaction, subscriber_id, list.id)
+                    .await
+                    .context("Failed to update a list membership")?;
+                "confirmed"
+            } else {
+                unsubscribe_from_list(&mut transaction, subscriber_id, list.id)
+                    .await
+                    .context("Failed to update a list membership")?;
+                "unsubscribed"
+            };
+            record_preference_change(
+                &mut transaction,
+                subscriber_id,
+                &format!("list:{}", list.slug),
+                status,
+            )
+            .await
+            .context("Failed to record a preference change")?;
+            changed = true;
+        }
+    }
+
+    if changed {
+        sqlx::query!(
+            r#"UPDATE subscriptions SET preferences_updated_at = $1 WHERE id = $2"#,
+            Utc::now(),
+            subscriber_id,
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to update the preferences timestamp")?;
+    }
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update subscriber preferences")?;
+
+    Ok(HttpResponse::SeeOther()
+        .insert_header((
+            actix_web::http::header::LOCATION,
+            format!(
+                "/preferences?subscription_token={}",
+                update.subscription_token
+            ),
+        ))
+        .finish())
+}
+
+#[tracing::instrument(name = "Retrieve subscriber preferences", skip(transaction))]
+async fn get_preferences(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Preferences, sqlx::Error> {
+    let subscriber = sqlx::query!(
+        r#"SELECT email, name, status, digest_frequency FROM subscriptions WHERE id = $1"#,
+        subscriber_id,
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
+
+    let lists = sqlx::query!(
+        r#"SELECT l.id, l.slug, l.name, m.status AS "status?"
+           FROM lists l
+           LEFT JOIN list_memberships m ON m.list_id = l.id AND m.subscriber_id = $1
+           ORDER BY l.name"#,
+        subscriber_id,
+    )
+    .fetch_all(&mut **transaction)
+    .await?
+    .into_iter()
+    .map(|row| ListPreference {
+        id: row.id,
+        slug: row.slug,
+        name: row.name,
+        subscribed: matches!(
+            row.status.as_deref(),
+            Some("confirmed") | Some("pending_confirmation")
+        ),
+    })
+    .collect();
+
+    Ok(Preferences {
+        email: subscriber.email,
+        name: subscriber.name,
+        status: subscriber.status,
+        digest_frequency: subscriber.digest_frequency,
+        lists,
+    })
+}
+
+/// Confirms the subscriber's membership of the list and enrols them in its sequences.
+#[tracing::instrument(name = "Join a list", skip(transaction))]
+async fn join_list(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"INSERT INTO list_memberships (subscriber_id, list_id, status, subscribed_at)
+           VALUES ($1, $2, 'confirmed', $3)
+           ON CONFLICT (subscriber_id, list_id) DO UPDATE SET status = EXCLUDED.status"#,
+        subscriber_id,
+        list_id,
+        Utc::now(),
+    )
+    .execute(&mut **transaction)
+    .await?;
+    enrol_in_sequences(transaction, subscriber_id, Some(list_id)).await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Record a preference change", skip(transaction))]
+async fn record_preference_change(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    field: &str,
+    value: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"INSERT INTO preference_changes (id, subscriber_id, field, value, changed_at)
+           VALUES ($1, $2, $3, $4, $5)"#,
+        Uuid::new_v4(),
+        subscriber_id,
+        field,
+        value,
+        Utc::now(),
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
+
+fn render_preferences(subscription_token: &str, preferences: &Preferences) -> String {
+    let lists: String = preferences
+        .lists
+        .iter()
+        .map(|list| {
+            format!(
+                r#"<label><input type="checkbox" name="lists" value="{}"{}> {}</label><br>"#,
+                escape_html(&list.slug),
+                if list.subscribed { " checked" } else { "" },
+                escape_html(&list.name),
+            )
+        })
+        .collect();
+
+    let frequencies: String = DigestFrequency::ALL
+        .iter()
+        .map(|frequency| {
+            format!(
+                r#"<option value="{0}"{1}>{0}</option>"#,
+                frequency.as_str(),
+                if frequency.as_str() == preferences.digest_frequency {
+                    " selected"
+                } else {
+                    ""
+                },
+            )
+        })
+        .collect();
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><title>Your preferences</title></head>
+<body>
+<h1>Preferences for {email}</h1>
+<form action="/preferences" method="post">
+<input type="hidden" name="subscription_token" value="{token}">
+<label>Name <input type="text" name="name" value="{name}"></label><br>
+<fieldset><legend>Topics</legend>{lists}</fieldset>
+<label>Digest frequency <select name="digest_frequency">{frequencies}</select></label><br>
+<label><input type="checkbox" name="unsubscribe" value="true"> Unsubscribe from everything</label><br>
+<button type="submit">Save</button>
+</form>
+</body>
+</html>"#,
+        email = escape_html(&preferences.email),
+        token = escape_html(subscription_token),
+        name = escape_html(&preferences.name),
+        lists = lists,
+        frequencies = frequencies,
+    )
+}
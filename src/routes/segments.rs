@@ -0,0 +1,340 @@
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{PgConnection, PgPool, Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use crate::{
+    domain::{Comparison, CustomFieldType, CustomFieldValue, Literal, SegmentExpression},
+    routes::{
+        error_chain_fmt, find_definition, get_custom_field_definitions, CustomFieldDefinition,
+    },
+};
+
+const DRY_RUN_SAMPLE_SIZE: i64 = 10;
+
+#[derive(thiserror::Error)]
+pub enum SegmentError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("{0}")]
+    ConflictError(String),
+    #[error("{0}")]
+    MissingSegmentError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SegmentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SegmentError {
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        match self {
+            Self::ValidationError(_) => actix_web::http::StatusCode::BAD_REQUEST,
+            Self::ConflictError(_) => actix_web::http::StatusCode::CONFLICT,
+            Self::MissingSegmentError(_) => actix_web::http::StatusCode::NOT_FOUND,
+            Self::UnexpectedError(_) => actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct SegmentData {
+    name: String,
+    expression: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct Segment {
+    id: Uuid,
+    name: String,
+    expression: String,
+}
+
+#[tracing::instrument(name = "Creating a segment", skip(body, pool), fields(segment_name = %body.name))]
+pub async fn create_segment(
+    body: web::Json<SegmentData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SegmentError> {
+    if body.name.trim().is_empty() {
+        return Err(SegmentError::ValidationError(
+            "A segment must have a name.".to_string(),
+        ));
+    }
+
+    let mut connection = pool
+        .acquire()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let definitions = get_custom_field_definitions(&mut connection)
+        .await
+        .context("Failed to retrieve custom field definitions")?;
+    let expression =
+        SegmentExpression::parse(&body.expression).map_err(SegmentError::ValidationError)?;
+    push_segment_filter(&mut QueryBuilder::new(""), &expression, &definitions)
+        .map_err(SegmentError::ValidationError)?;
+
+    let segment = Segment {
+        id: Uuid::new_v4(),
+        name: body.name.trim().to_string(),
+        expression: body.expression.clone(),
+    };
+    let inserted = sqlx::query!(
+        r#"INSERT INTO segments (id, name, expression, created_at)
+           VALUES ($1, $2, $3, $4)
+           ON CONFLICT (name) DO NOTHING"#,
+        segment.id,
+        segment.name,
+        segment.expression,
+        Utc::now(),
+    )
+    .execute(&mut *connection)
+    .await
+    .context("Failed to insert a segment in the database.")?;
+
+    if inserted.rows_affected() == 0 {
+        return Err(SegmentError::ConflictError(format!(
+            "A segment named {} already exists.",
+            segment.name
+        )));
+    }
+
+    Ok(HttpResponse::Ok().json(segment))
+}
+
+#[tracing::instrument(name = "Listing segments", skip(pool))]
+pub async fn list_segments(pool: web::Data<PgPool>) -> Result<HttpResponse, SegmentError> {
+    let segments = sqlx::query_as!(
+        Segment,
+        r#"SELECT id, name, expression FROM segments ORDER BY name"#
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve segments")?;
+
+    Ok(HttpResponse::Ok().json(segments))
+}
+
+#[derive(serde::Deserialize)]
+pub struct DryRunData {
+    expression: Option<String>,
+    segment_id: Option<Uuid>,
+}
+
+#[derive(serde::Serialize)]
+pub struct DryRunReport {
+    /// Every subscriber matching the expression.
+    count: i64,
+    /// The subscribers a newsletter sent to this segment would reach.
+    confirmed_count: i64,
+    sample: Vec<SegmentMember>,
+}
+
+#[derive(serde::Serialize, sqlx::FromRow)]
+pub struct SegmentMember {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+}
+
+/// Evaluates either an ad-hoc `expression` or a saved `segment_id` without sending anything.
+#[tracing::instrument(name = "Dry-running a segment", skip(body, pool))]
+pub async fn dry_run_segment(
+    body: web::Json<DryRunData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SegmentError> {
+    let mut connection = pool
+        .acquire()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    let expression = match (&body.expression, body.segment_id) {
+        (Some(expression), None) => {
+            SegmentExpression::parse(expression).map_err(SegmentError::ValidationError)?
+        }
+        (None, Some(segment_id)) => get_segment_expression(&mut connection, segment_id)
+            .await
+            .context("Failed to retrieve segment")?
+            .ok_or_else(|| {
+                SegmentError::MissingSegmentError(format!("No segment with id {}", segment_id))
+            })?
+            .map_err(SegmentError::ValidationError)?,
+        _ => {
+            return Err(SegmentError::ValidationError(
+                "Provide either an expression or a segment_id.".to_string(),
+            ))
+        }
+    };
+    let definitions = get_custom_field_definitions(&mut connection)
+        .await
+        .context("Failed to retrieve custom field definitions")?;
+
+    let mut builder = QueryBuilder::new(
+        "SELECT COUNT(*), COUNT(*) FILTER (WHERE s.status = 'confirmed') FROM subscriptions s WHERE ",
+    );
+    push_segment_filter(&mut builder, &expression, &definitions)
+        .map_err(SegmentError::ValidationError)?;
+    let (count, confirmed_count): (i64, i64) = builder
+        .build_query_as()
+        .fetch_one(&mut *connection)
+        .await
+        .context("Failed to count segment members")?;
+
+    let mut builder =
+        QueryBuilder::new("SELECT s.id, s.email, s.name, s.status FROM subscriptions s WHERE ");
+    push_segment_filter(&mut builder, &expression, &definitions)
+        .map_err(SegmentError::ValidationError)?;
+    builder
+        .push(" ORDER BY s.subscribed_at, s.id LIMIT ")
+        .push_bind(DRY_RUN_SAMPLE_SIZE);
+    let sample: Vec<SegmentMember> = builder
+        .build_query_as()
+        .fetch_all(&mut *connection)
+        .await
+        .context("Failed to sample segment members")?;
+
+    Ok(HttpResponse::Ok().json(DryRunReport {
+        count,
+        confirmed_count,
+        sample,
+    }))
+}
+
+/// Returns `None` for an unknown segment and an error if the stored expression no longer parses.
+#[tracing::instrument(name = "Retrieving a segment expression", skip(connection))]
+pub async fn get_segment_expression(
+    connection: &mut PgConnection,
+    segment_id: Uuid,
+) -> Result<Option<Result<SegmentExpression, String>>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT expression FROM segments WHERE id = $1"#,
+        segment_id
+    )
+    .fetch_optional(connection)
+    .await?;
+
+    Ok(row.map(|row| SegmentExpression::parse(&row.expression)))
+}
+
+/// Compiles a segment expression into a parenthesised SQL condition over `subscriptions s`.
+/// Every literal is bound as a parameter.
+pub fn push_segment_filter(
+    builder: &mut QueryBuilder<'_, Postgres>,
+    expression: &SegmentExpression,
+    definitions: &[CustomFieldDefinition],
+) -> Result<(), String> {
+    builder.push("(");
+    match expression {
+        SegmentExpression::And(left, right) | SegmentExpression::Or(left, right) => {
+            push_segment_filter(builder, left, definitions)?;
+            builder.push(match expression {
+                SegmentExpression::And(..) => " AND ",
+                _ => " OR ",
+            });
+            push_segment_filter(builder, right, definitions)?;
+        }
+        SegmentExpression::Not(inner) => {
+            builder.push("NOT ");
+            push_segment_filter(builder, inner, definitions)?;
+        }
+        SegmentExpression::Status(comparison, status) => {
+            builder
+                .push("s.status ")
+                .push(comparison.as_sql())
+                .push(" ")
+                .push_bind(status.as_str());
+        }
+        SegmentExpression::Tag(comparison, tag) => {
+            if *comparison == Comparison::Ne {
+                builder.push("NOT ");
+            }
+            builder
+                .push("EXISTS (SELECT 1 FROM subscriber_tags t WHERE t.subscriber_id = s.id AND t.tag = ")
+                .push_bind(tag.as_ref().to_string())
+                .push(")");
+        }
+        SegmentExpression::Field(key, comparison, literal) => {
+            let definition = find_definition(definitions, key.as_ref())?;
+            // Values of other fields never reach the cast thanks to the CASE guard.
+            builder
+                .push("EXISTS (SELECT 1 FROM subscriber_custom_fields f WHERE f.subscriber_id = s.id AND f.field_id = ")
+                .push_bind(definition.id)
+                .push(" AND CASE WHEN f.field_id = ")
+                .push_bind(definition.id);
+            match (definition.field_type(), literal) {
+                (CustomFieldType::String, Literal::String(s)) => {
+                    builder
+                        .push(" THEN f.value END ")
+                        .push(comparison.as_sql())
+                        .push(" ")
+                        .push_bind(s.clone());
+                }
+                (CustomFieldType::Number, Literal::Number(n)) => {
+                    builder
+                        .push(" THEN f.value::double precision END ")
+                        .push(comparison.as_sql())
+                        .push(" ")
+                        .push_bind(*n);
+                }
+                (CustomFieldType::Date, Literal::String(s)) => {
+                    let date = match CustomFieldValue::parse(CustomFieldType::Date, s)? {
+                        CustomFieldValue::Date(date) => date,
+                        _ => unreachable!(),
+                    };
+                    builder
+                        .push(" THEN f.value::date END ")
+                        .push(comparison.as_sql())
+                        .push(" ")
+                        .push_bind(date);
+                }
+                (CustomFieldType::Boolean, Literal::Boolean(b)) if comparison.is_equality() => {
+                    builder
+                        .push(" THEN f.value::boolean END ")
+                        .push(comparison.as_sql())
+                        .push(" ")
+                        .push_bind(*b);
+                }
+                (field_type, literal) => {
+                    return Err(format!(
+                        "field.{} is a {} field and cannot be compared with {:?} using '{}'.",
+                        key,
+                        field_type.as_str(),
+                        literal,
+                        comparison.as_sql()
+                    ))
+                }
+            }
+            builder.push(")");
+        }
+        SegmentExpression::SubscribedAt(comparison, date) => {
+            builder
+                .push("s.subscribed_at::date ")
+                .push(comparison.as_sql())
+                .push(" ")
+                .push_bind(*date);
+        }
+        SegmentExpression::LastEngagedAt(comparison, date) => {
+            // Subscribers that never engaged do not match, so NOT selects them.
+            builder
+                .push("COALESCE(s.last_engaged_at::date ")
+                .push(comparison.as_sql())
+                .push(" ")
+                .push_bind(*date)
+                .push(", FALSE)");
+        }
+        SegmentExpression::EngagedWithin(days) => {
+            builder
+                .push("COALESCE(s.last_engaged_at >= now() - make_interval(days => ")
+                .push_bind(i32::try_from(*days).map_err(|_| "Too many days.".to_string())?)
+                .push("), FALSE)");
+        }
+    }
+    builder.push(")");
+
+    Ok(())
+}
@@ -0,0 +1,381 @@
+use std::collections::HashMap;
+
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    domain::{ListSlug, SequenceStep, SequenceSteps},
+    routes::{error_chain_fmt, get_list_id},
+};
+
+#[derive(thiserror::Error)]
+pub enum SequenceError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("{0}")]
+    ConflictError(String),
+    #[error("{0}")]
+    MissingListError(String),
+    #[error("{0}")]
+    MissingSequenceError(String),
+    #[error("{0}")]
+    MissingSubscriberError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SequenceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SequenceError {
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        match self {
+            Self::ValidationError(_) => actix_web::http::StatusCode::BAD_REQUEST,
+            Self::ConflictError(_) => actix_web::http::StatusCode::CONFLICT,
+            Self::MissingListError(_)
+            | Self::MissingSequenceError(_)
+            | Self::MissingSubscriberError(_) => actix_web::http::StatusCode::NOT_FOUND,
+            Self::UnexpectedError(_) => actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct SequenceStepData {
+    delay_minutes: u32,
+    subject: String,
+    html: String,
+    text: String,
+}
+
+/// Subscribers are enrolled when they confirm, either to any list or, if `list`
+/// is set, to that list.
+#[derive(serde::Deserialize)]
+pub struct SequenceData {
+    name: String,
+    list: Option<String>,
+    steps: Vec<SequenceStepData>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SequenceStepSummary {
+    position: i32,
+    delay_minutes: i32,
+    subject: String,
+    /// Subscribers this step was successfully sent to.
+    sent: i64,
+}
+
+#[derive(serde::Serialize)]
+pub struct Sequence {
+    id: Uuid,
+    name: String,
+    list_id: Option<Uuid>,
+    active: bool,
+    steps: Vec<SequenceStepSummary>,
+    /// Enrolments still waiting for a step.
+    in_progress: i64,
+    completed: i64,
+    exited: i64,
+}
+
+#[tracing::instrument(name = "Creating an automation sequence", skip(body, pool), fields(sequence_name = %body.name))]
+pub async fn create_sequence(
+    body: web::Json<SequenceData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SequenceError> {
+    let body = body.into_inner();
+    let name = body.name.trim().to_string();
+    if name.is_empty() {
+        return Err(SequenceError::ValidationError(
+            "A sequence needs a name.".to_string(),
+        ));
+    }
+    let steps = SequenceSteps::parse(
+        body.steps
+            .into_iter()
+            .map(|step| SequenceStep {
+                delay_minutes: step.delay_minutes,
+                subject: step.subject,
+                html: step.html,
+                text: step.text,
+            })
+            .collect(),
+    )
+    .map_err(SequenceError::ValidationError)?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let list_id = match body.list {
+        Some(slug) => {
+            let slug = ListSlug::parse(slug).map_err(SequenceError::ValidationError)?;
+            let list_id = get_list_id(&mut transaction, &slug)
+                .await
+                .context("Failed to look up a mailing list.")?
+                .ok_or_else(|| {
+                    SequenceError::MissingListError(format!("{} is not a known list.", slug))
+                })?;
+            Some(list_id)
+        }
+        None => None,
+    };
+
+    let sequence_id = Uuid::new_v4();
+    let inserted = sqlx::query!(
+        r#"INSERT INTO automation_sequences (id, name, list_id, created_at)
+           VALUES ($1, $2, $3, $4)
+           ON CONFLICT (name) DO NOTHING"#,
+        sequence_id,
+        name,
+        list_id,
+        Utc::now(),
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to insert an automation sequence in the database.")?;
+    if inserted.rows_affected() == 0 {
+        return Err(SequenceError::ConflictError(format!(
+            "A sequence named {} already exists.",
+            name
+        )));
+    }
+    for (position, step) in steps.as_ref().iter().enumerate() {
+        sqlx::query!(
+            r#"INSERT INTO automation_steps
+               (id, sequence_id, position, delay_minutes, subject, html_content, text_content)
+               VALUES ($1, $2, $3, $4, $5, $6, $7)"#,
+            Uuid::new_v4(),
+            sequence_id,
+            position as i32,
+            step.delay_minutes as i32,
+            step.subject,
+            step.html,
+            step.text,
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to insert an automation step in the database.")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to create an automation sequence.")?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "sequence_id": sequence_id })))
+}
+
+#[tracing::instrument(name = "Listing automation sequences", skip(pool))]
+pub async fn list_sequences(pool: web::Data<PgPool>) -> Result<HttpResponse, SequenceError> {
+    let rows = sqlx::query!(
+        r#"SELECT s.id, s.name, s.list_id, s.active,
+               COUNT(e.subscriber_id) FILTER (
+                   WHERE e.completed_at IS NULL AND e.exited_at IS NULL
+               ) AS "in_progress!",
+               COUNT(e.subscriber_id) FILTER (WHERE e.completed_at IS NOT NULL) AS "completed!",
+               COUNT(e.subscriber_id) FILTER (WHERE e.exited_at IS NOT NULL) AS "exited!"
+           FROM automation_sequences s
+           LEFT JOIN automation_enrolments e ON e.sequence_id = s.id
+           GROUP BY s.id
+           ORDER BY s.created_at"#
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve automation sequences")?;
+    let steps = sqlx::query!(
+        r#"SELECT st.sequence_id, st.position, st.delay_minutes, st.subject,
+               COUNT(d.id) FILTER (WHERE d.status = 'sent') AS "sent!"
+           FROM automation_steps st
+           LEFT JOIN automation_deliveries d ON d.step_id = st.id
+           GROUP BY st.id
+           ORDER BY st.sequence_id, st.position"#
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve automation steps")?;
+    let mut steps_by_sequence: HashMap<Uuid, Vec<SequenceStepSummary>> = HashMap::new();
+    for step in steps {
+        steps_by_sequence
+            .entry(step.sequence_id)
+            .or_default()
+            .push(SequenceStepSummary {
+                position: step.position,
+                delay_minutes: step.delay_minutes,
+                subject: step.subject,
+                sent: step.sent,
+            });
+    }
+
+    let sequences: Vec<Sequence> = rows
+        .into_iter()
+        .map(|row| Sequence {
+            steps: steps_by_sequence.remove(&row.id).unwrap_or_default(),
+            id: row.id,
+            name: row.name,
+            list_id: row.list_id,
+            active: row.active,
+            in_progress: row.in_progress,
+            completed: row.completed,
+            exited: row.exited,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(sequences))
+}
+
+/// Paused sequences enrol nobody new and hold back their pending steps.
+#[derive(serde::Deserialize)]
+pub struct SequenceSettingsData {
+    active: bool,
+}
+
+#[tracing::instrument(name = "Updating an automation sequence", skip(body, pool))]
+pub async fn update_sequence(
+    sequence_id: web::Path<Uuid>,
+    body: web::Json<SequenceSettingsData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SequenceError> {
+    let sequence_id = sequence_id.into_inner();
+    let updated = sqlx::query!(
+        r#"UPDATE automation_sequences SET active = $1 WHERE id = $2"#,
+        body.active,
+        sequence_id,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update an automation sequence in the database.")?;
+
+    if updated.rows_affected() == 0 {
+        return Err(SequenceError::MissingSequenceError(format!(
+            "No sequence with id {}",
+            sequence_id
+        )));
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(serde::Serialize)]
+pub struct SequenceProgress {
+    sequence_id: Uuid,
+    name: String,
+    enrolled_at: DateTime<Utc>,
+    steps_sent: i64,
+    total_steps: i64,
+    next_send_at: Option<DateTime<Utc>>,
+    completed_at: Option<DateTime<Utc>>,
+    exited_at: Option<DateTime<Utc>>,
+    exit_reason: Option<String>,
+}
+
+#[tracing::instrument(name = "Retrieving a subscriber's sequence progress", skip(pool))]
+pub async fn get_subscriber_sequences(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SequenceError> {
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE id = $1"#,
+        subscriber_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to look up a subscriber.")?;
+    if subscriber.is_none() {
+        return Err(SequenceError::MissingSubscriberError(format!(
+            "No subscriber with id {}",
+            subscriber_id
+        )));
+    }
+
+    let progress = sqlx::query_as!(
+        SequenceProgress,
+        r#"SELECT e.sequence_id, s.name, e.enrolled_at, e.next_send_at, e.completed_at,
+               e.exited_at, e.exit_reason,
+               (SELECT COUNT(*) FROM automation_deliveries d
+                JOIN automation_steps st ON st.id = d.step_id
+                WHERE st.sequence_id = e.sequence_id
+                  AND d.subscriber_id = e.subscriber_id
+                  AND d.status = 'sent') AS "steps_sent!",
+               (SELECT COUNT(*) FROM automation_steps st
+                WHERE st.sequence_id = e.sequence_id) AS "total_steps!"
+           FROM automation_enrolments e
+           JOIN automation_sequences s ON s.id = e.sequence_id
+           WHERE e.subscriber_id = $1
+           ORDER BY e.enrolled_at"#,
+        subscriber_id
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve sequence enrolments")?;
+
+    Ok(HttpResponse::Ok().json(progress))
+}
+
+/// Enrols a subscriber into every active sequence that is not bound to a list, or is
+/// bound to one of their confirmed lists, or only into those bound to `list_id` when
+/// they just joined it. Called whenever a subscriber or one of their list memberships
+/// becomes confirmed. Nobody is enrolled twice in the same sequence, even after
+/// unsubscribing and confirming again.
+#[tracing::instrument(name = "Enrolling a subscriber in sequences", skip(transaction))]
+pub async fn enrol_in_sequences(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Option<Uuid>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"INSERT INTO automation_enrolments
+           (sequence_id, subscriber_id, enrolled_at, next_step_position, next_send_at)
+           SELECT s.id, $1, now(), 0, now() + make_interval(mins => st.delay_minutes)
+           FROM automation_sequences s
+           JOIN automation_steps st ON st.sequence_id = s.id AND st.position = 0
+           WHERE s.active
+             AND ($2::uuid IS NULL OR s.list_id = $2)
+             AND (s.list_id IS NULL OR EXISTS (
+                SELECT 1 FROM list_memberships m
+                WHERE m.subscriber_id = $1 AND m.list_id = s.list_id AND m.status = 'confirmed'
+             ))
+           ON CONFLICT DO NOTHING"#,
+        subscriber_id,
+        list_id,
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
+
+/// Ends the subscriber's pending enrolments, in every sequence or only in those
+/// bound to `list_id`.
+#[tracing::instrument(name = "Exiting a subscriber from sequences", skip(transaction))]
+pub async fn exit_sequences(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Option<Uuid>,
+    reason: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE automation_enrolments e
+           SET exited_at = now(), exit_reason = $3, next_send_at = NULL
+           FROM automation_sequences s
+           WHERE s.id = e.sequence_id
+             AND e.subscriber_id = $1
+             AND ($2::uuid IS NULL OR s.list_id = $2)
+             AND e.completed_at IS NULL
+             AND e.exited_at IS NULL"#,
+        subscriber_id,
+        list_id,
+        reason,
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,646 @@
+use std::collections::HashMap;
+
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, QueryBuilder, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    domain::{
+        CustomFieldValue, Locale, NewSubscriber, SubscriberEmail, SubscriberName, SubscriberTag,
+        SubscriptionStatus,
+    },
+    email_client::EmailClient,
+    routes::{
+        enrol_in_sequences, error_chain_fmt, generate_subscription_token,
+        get_custom_field_definitions, get_suppressed_emails, is_suppressed,
+        send_confirmation_email, store_token, CustomFieldDefinition,
+    },
+    startup::ApplicationBaseUrl,
+};
+
+#[derive(thiserror::Error)]
+pub enum SubscriberAdminError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("{0}")]
+    MissingSubscriberError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SubscriberAdminError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SubscriberAdminError {
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        match self {
+            Self::ValidationError(_) => actix_web::http::StatusCode::BAD_REQUEST,
+            Self::MissingSubscriberError(_) => actix_web::http::StatusCode::NOT_FOUND,
+            Self::UnexpectedError(_) => actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriberRecord {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    tags: Vec<String>,
+    custom_fields: HashMap<String, serde_json::Value>,
+}
+
+const DEFAULT_PAGE_SIZE: i64 = 100;
+const MAX_PAGE_SIZE: i64 = 1000;
+
+/// Lists subscribers, optionally filtered by `status`, any number of `tag`s (all must match)
+/// and `field.<key>=<value>` equality on custom fields.
+#[tracing::instrument(name = "Listing subscribers", skip(query, pool))]
+pub async fn list_subscribers(
+    query: web::Query<Vec<(String, String)>>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SubscriberAdminError> {
+    let mut connection = pool
+        .acquire()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let definitions = get_custom_field_definitions(&mut connection)
+        .await
+        .context("Failed to retrieve custom field definitions")?;
+
+    let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(
+        "SELECT s.id, s.email, s.name, s.status, s.subscribed_at FROM subscriptions s WHERE TRUE",
+    );
+    let mut limit = DEFAULT_PAGE_SIZE;
+    let mut offset = 0;
+
+    for (key, value) in query.into_inner() {
+        match key.as_str() {
+            "status" => {
+                let status = SubscriptionStatus::parse(&value)
+                    .map_err(SubscriberAdminError::ValidationError)?;
+                builder.push(" AND s.status = ").push_bind(status.as_str());
+            }
+            "tag" => {
+                let tag =
+                    SubscriberTag::parse(value).map_err(SubscriberAdminError::ValidationError)?;
+                builder
+                    .push(" AND EXISTS (SELECT 1 FROM subscriber_tags t WHERE t.subscriber_id = s.id AND t.tag = ")
+                    .push_bind(tag.as_ref().to_string())
+                    .push(")");
+            }
+            "limit" => {
+                limit = value
+                    .parse::<i64>()
+                    .ok()
+                    .filter(|l| (1..=MAX_PAGE_SIZE).contains(l))
+                    .ok_or_else(|| {
+                        SubscriberAdminError::ValidationError(format!(
+                            "limit must be between 1 and {}.",
+                            MAX_PAGE_SIZE
+                        ))
+                    })?;
+            }
+            "offset" => {
+                offset = value
+                    .parse::<i64>()
+                    .ok()
+                    .filter(|o| *o >= 0)
+                    .ok_or_else(|| {
+                        SubscriberAdminError::ValidationError(
+                            "offset must be a positive number.".to_string(),
+                        )
+                    })?;
+            }
+            key => {
+                let Some(field_key) = key.strip_prefix("field.") else {
+                    return Err(SubscriberAdminError::ValidationError(format!(
+                        "{} is not a supported filter.",
+                        key
+                    )));
+                };
+                let definition = find_definition(&definitions, field_key)
+                    .map_err(SubscriberAdminError::ValidationError)?;
+                let value = CustomFieldValue::parse(definition.field_type(), &value)
+                    .map_err(SubscriberAdminError::ValidationError)?;
+                builder
+                    .push(" AND EXISTS (SELECT 1 FROM subscriber_custom_fields f WHERE f.subscriber_id = s.id AND f.field_id = ")
+                    .push_bind(definition.id)
+                    .push(" AND f.value = ")
+                    .push_bind(value.to_canonical_string())
+                    .push(")");
+            }
+        }
+    }
+
+    builder
+        .push(" ORDER BY s.subscribed_at, s.id LIMIT ")
+        .push_bind(limit)
+        .push(" OFFSET ")
+        .push_bind(offset);
+
+    let rows: Vec<(Uuid, String, String, String, DateTime<Utc>)> = builder
+        .build_query_as()
+        .fetch_all(&mut *connection)
+        .await
+        .context("Failed to list subscribers")?;
+
+    let ids: Vec<Uuid> = rows.iter().map(|row| row.0).collect();
+    let mut tags: HashMap<Uuid, Vec<String>> = HashMap::new();
+    for row in sqlx::query!(
+        r#"SELECT subscriber_id, tag FROM subscriber_tags WHERE subscriber_id = ANY($1) ORDER BY tag"#,
+        &ids
+    )
+    .fetch_all(&mut *connection)
+    .await
+    .context("Failed to retrieve subscriber tags")?
+    {
+        tags.entry(row.subscriber_id).or_default().push(row.tag);
+    }
+
+    let mut custom_fields: HashMap<Uuid, HashMap<String, serde_json::Value>> = HashMap::new();
+    for row in sqlx::query!(
+        r#"SELECT f.subscriber_id, f.field_id, f.value
+           FROM subscriber_custom_fields f
+           WHERE f.subscriber_id = ANY($1)"#,
+        &ids
+    )
+    .fetch_all(&mut *connection)
+    .await
+    .context("Failed to retrieve subscriber custom fields")?
+    {
+        let Some(definition) = definitions.iter().find(|d| d.id == row.field_id) else {
+            continue;
+        };
+        let value = CustomFieldValue::parse(definition.field_type(), &row.value)
+            .map(|v| v.to_json())
+            .unwrap_or(serde_json::Value::String(row.value));
+        custom_fields
+            .entry(row.subscriber_id)
+            .or_default()
+            .insert(definition.key.clone(), value);
+    }
+
+    let subscribers: Vec<SubscriberRecord> = rows
+        .into_iter()
+        .map(
+            |(id, email, name, status, subscribed_at)| SubscriberRecord {
+                id,
+                email,
+                name,
+                status,
+                subscribed_at,
+                tags: tags.remove(&id).unwrap_or_default(),
+                custom_fields: custom_fields.remove(&id).unwrap_or_default(),
+            },
+        )
+        .collect();
+
+    Ok(HttpResponse::Ok().json(subscribers))
+}
+
+#[derive(serde::Deserialize)]
+pub struct SubscriberAttributesData {
+    tags: Option<Vec<String>>,
+    custom_fields: Option<HashMap<String, serde_json::Value>>,
+}
+
+/// Replaces the subscriber's tags when `tags` is given and merges `custom_fields`,
+/// removing any field set to `null`.
+#[tracing::instrument(name = "Updating subscriber attributes", skip(body, pool))]
+pub async fn update_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    body: web::Json<SubscriberAttributesData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SubscriberAdminError> {
+    let subscriber_id = subscriber_id.into_inner();
+    let body = body.into_inner();
+
+    let tags = body
+        .tags
+        .map(|tags| {
+            tags.into_iter()
+                .map(SubscriberTag::parse)
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()
+        .map_err(SubscriberAdminError::ValidationError)?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    let exists = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE id = $1"#,
+        subscriber_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to look up subscriber")?;
+    if exists.is_none() {
+        return Err(SubscriberAdminError::MissingSubscriberError(format!(
+            "No subscriber with id {}",
+            subscriber_id
+        )));
+    }
+
+    let definitions = get_custom_field_definitions(&mut transaction)
+        .await
+        .context("Failed to retrieve custom field definitions")?;
+    let mut fields = Vec::new();
+    for (key, value) in body.custom_fields.unwrap_or_default() {
+        let definition =
+            find_definition(&definitions, &key).map_err(SubscriberAdminError::ValidationError)?;
+        let value = match value {
+            serde_json::Value::Null => None,
+            value => Some(
+                CustomFieldValue::from_json(definition.field_type(), &value).map_err(|e| {
+                    SubscriberAdminError::ValidationError(format!("{}: {}", key, e))
+                })?,
+            ),
+        };
+        fields.push((definition.id, value));
+    }
+
+    if let Some(tags) = tags {
+        sqlx::query!(
+            r#"DELETE FROM subscriber_tags WHERE subscriber_id = $1"#,
+            subscriber_id
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to clear subscriber tags")?;
+        add_subscriber_tags(&mut transaction, subscriber_id, &tags)
+            .await
+            .context("Failed to store subscriber tags")?;
+    }
+
+    for (field_id, value) in fields {
+        match value {
+            Some(value) => set_custom_field(&mut transaction, subscriber_id, field_id, &value)
+                .await
+                .context("Failed to store a custom field")?,
+            None => {
+                sqlx::query!(
+                    r#"DELETE FROM subscriber_custom_fields WHERE subscriber_id = $1 AND field_id = $2"#,
+                    subscriber_id,
+                    field_id
+                )
+                .execute(&mut *transaction)
+                .await
+                .context("Failed to remove a custom field")?;
+            }
+        }
+    }
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update a subscriber")?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(serde::Deserialize)]
+pub struct ImportParameters {
+    #[serde(default)]
+    confirmed: bool,
+}
+
+#[derive(serde::Serialize)]
+pub struct ImportReport {
+    imported: usize,
+    failed: Vec<ImportFailure>,
+    unconfirmed: Vec<ImportFailure>,
+}
+
+#[derive(serde::Serialize)]
+pub struct ImportFailure {
+    line: u64,
+    error: String,
+}
+
+/// Imports subscribers from a CSV body with an `email` and `name` column, an optional
+/// `tags` column (separated by `;`) and one column per custom field key.
+///
+/// New subscribers are stored pending confirmation and sent a confirmation email, unless
+/// the import is marked `confirmed=true` because the addresses opted in elsewhere. Those
+/// are enrolled in sequences straight away, as if they had confirmed.
+/// Invalid rows are skipped and reported back, as are confirmations that could not be sent.
+#[tracing::instrument(
+    name = "Importing subscribers",
+    skip(body, parameters, pool, email_client, base_url)
+)]
+pub async fn import_subscribers(
+    body: String,
+    parameters: web::Query<ImportParameters>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, SubscriberAdminError> {
+    let status = if parameters.confirmed {
+        SubscriptionStatus::Confirmed
+    } else {
+        SubscriptionStatus::PendingConfirmation
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(body.as_bytes());
+    let headers = reader
+        .headers()
+        .map_err(|e| SubscriberAdminError::ValidationError(format!("Invalid CSV header: {}", e)))?
+        .clone();
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let definitions = get_custom_field_definitions(&mut transaction)
+        .await
+        .context("Failed to retrieve custom field definitions")?;
+
+    let mut columns = Vec::with_capacity(headers.len());
+    for header in headers.iter() {
+        let column = match header {
+            "email" => ImportColumn::Email,
+            "name" => ImportColumn::Name,
+            "tags" => ImportColumn::Tags,
+            key => ImportColumn::CustomField(
+                find_definition(&definitions, key)
+                    .map_err(SubscriberAdminError::ValidationError)?,
+            ),
+        };
+        columns.push(column);
+    }
+    if !columns.iter().any(|c| matches!(c, ImportColumn::Email))
+        || !columns.iter().any(|c| matches!(c, ImportColumn::Name))
+    {
+        return Err(SubscriberAdminError::ValidationError(
+            "The CSV header must contain an email and a name column.".to_string(),
+        ));
+    }
+
+    let mut report = ImportReport {
+        imported: 0,
+        failed: Vec::new(),
+        unconfirmed: Vec::new(),
+    };
+    let mut confirmations = Vec::new();
+
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                report.failed.push(ImportFailure {
+                    line: e.position().map(|p| p.line()).unwrap_or_default(),
+                    error: e.to_string(),
+                });
+                continue;
+            }
+        };
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+
+        let row = match parse_import_row(&columns, &record) {
+            Ok(row) => row,
+            Err(error) => {
+                report.failed.push(ImportFailure { line, error });
+                continue;
+            }
+        };
+
+        let (subscriber_id, inserted) =
+            upsert_imported_subscriber(&mut transaction, &row.email, &row.name, status)
+                .await
+                .context("Failed to store an imported subscriber")?;
+        add_subscriber_tags(&mut transaction, subscriber_id, &row.tags)
+            .await
+            .context("Failed to store subscriber tags")?;
+        for (field_id, value) in &row.fields {
+            set_custom_field(&mut transaction, subscriber_id, *field_id, value)
+                .await
+                .context("Failed to store a custom field")?;
+        }
+        if inserted && status == SubscriptionStatus::Confirmed {
+            enrol_in_sequences(&mut transaction, subscriber_id, None)
+                .await
+                .context("Failed to enrol an imported subscriber in sequences")?;
+        }
+        // Addresses that were already known keep their status and are not asked again.
+        if inserted && status == SubscriptionStatus::PendingConfirmation {
+            let subscription_token = generate_subscription_token();
+            store_token(&mut transaction, subscriber_id, &subscription_token, &[])
+                .await
+                .context("Failed to store the confirmation token for an imported subscriber")?;
+            confirmations.push((line, row.email, row.name, subscription_token));
+        }
+        report.imported += 1;
+    }
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to import subscribers")?;
+
+    let emails: Vec<_> = confirmations.iter().map(|(_, email, _, _)| email).collect();
+    let suppressed = get_suppressed_emails(&pool, &emails)
+        .await
+        .context("Failed to check the suppression list")?;
+    for (line, email, name, subscription_token) in confirmations {
+        if is_suppressed(&suppressed, &email) {
+            continue;
+        }
+        let new_subscriber = NewSubscriber {
+            email,
+            name,
+            lists: Vec::new(),
+            locale: Locale::default(),
+            custom_fields: Vec::new(),
+        };
+        if let Err(e) = send_confirmation_email(
+            &email_client,
+            new_subscriber,
+            &base_url.0,
+            &subscription_token,
+            false,
+        )
+        .await
+        {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                "Failed to send a confirmation email to an imported subscriber"
+            );
+            report.unconfirmed.push(ImportFailure {
+                line,
+                error: e.to_string(),
+            });
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(report))
+}
+
+enum ImportColumn<'a> {
+    Email,
+    Name,
+    Tags,
+    CustomField(&'a CustomFieldDefinition),
+}
+
+struct ImportRow {
+    email: SubscriberEmail,
+    name: SubscriberName,
+    tags: Vec<SubscriberTag>,
+    fields: Vec<(Uuid, CustomFieldValue)>,
+}
+
+fn parse_import_row(
+    columns: &[ImportColumn],
+    record: &csv::StringRecord,
+) -> Result<ImportRow, String> {
+    let mut email = None;
+    let mut name = None;
+    let mut tags = Vec::new();
+    let mut fields = Vec::new();
+
+    for (column, value) in columns.iter().zip(record.iter()) {
+        match column {
+            ImportColumn::Email => email = Some(SubscriberEmail::parse(value.to_string())?),
+            ImportColumn::Name => name = Some(SubscriberName::parse(value.to_string())?),
+            ImportColumn::Tags => {
+                for tag in value.split(';').filter(|t| !t.trim().is_empty()) {
+                    tags.push(SubscriberTag::parse(tag.to_string())?);
+                }
+            }
+            ImportColumn::CustomField(definition) => {
+                if value.is_empty() {
+                    continue;
+                }
+                let value = CustomFieldValue::parse(definition.field_type(), value)
+                    .map_err(|e| format!("{}: {}", definition.key, e))?;
+                fields.push((definition.id, value));
+            }
+        }
+    }
+
+    Ok(ImportRow {
+        email: email.ok_or("Missing email.")?,
+        name: name.ok_or("Missing name.")?,
+        tags,
+        fields,
+    })
+}
+
+pub fn find_definition<'a>(
+    definitions: &'a [CustomFieldDefinition],
+    key: &str,
+) -> Result<&'a CustomFieldDefinition, String> {
+    definitions
+        .iter()
+        .find(|d| d.key == key)
+        .ok_or_else(|| format!("{} is not a known custom field.", key))
+}
+
+#[tracing::instrument(
+    name = "Storing an imported subscriber",
+    skip(transaction, email, name)
+)]
+async fn upsert_imported_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+    name: &SubscriberName,
+    status: SubscriptionStatus,
+) -> Result<(Uuid, bool), sqlx::Error> {
+    // `xmax` is only zero on a freshly inserted row, not on one updated by the conflict.
+    let row = sqlx::query!(
+        r#"INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+           VALUES ($1, $2, $3, $4, $5)
+           ON CONFLICT (email) DO UPDATE SET name = EXCLUDED.name
+           RETURNING id, (xmax = 0) AS "inserted!""#,
+        Uuid::new_v4(),
+        email.as_ref(),
+        name.as_ref(),
+        Utc::now(),
+        status.as_str(),
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
+
+    Ok((row.id, row.inserted))
+}
+
+#[tracing::instrument(name = "Tagging a subscriber", skip(transaction, tags))]
+pub async fn add_subscriber_tags(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    tags: &[SubscriberTag],
+) -> Result<(), sqlx::Error> {
+    for tag in tags {
+        sqlx::query!(
+            r#"INSERT INTO subscriber_tags (subscriber_id, tag, created_at)
+               VALUES ($1, $2, $3)
+               ON CONFLICT (subscriber_id, tag) DO NOTHING"#,
+            subscriber_id,
+            tag.as_ref(),
+            Utc::now(),
+        )
+        .execute(&mut **transaction)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Sets the field unless the subscriber already has a value for it, for changes
+/// that nobody has confirmed yet.
+#[tracing::instrument(name = "Filling a subscriber custom field", skip(transaction, value))]
+pub async fn fill_custom_field(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    field_id: Uuid,
+    value: &CustomFieldValue,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"INSERT INTO subscriber_custom_fields (subscriber_id, field_id, value, updated_at)
+           VALUES ($1, $2, $3, $4)
+           ON CONFLICT (subscriber_id, field_id) DO NOTHING"#,
+        subscriber_id,
+        field_id,
+        value.to_canonical_string(),
+        Utc::now(),
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Setting a subscriber custom field", skip(transaction, value))]
+pub async fn set_custom_field(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    field_id: Uuid,
+    value: &CustomFieldValue,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"INSERT INTO subscriber_custom_fields (subscriber_id, field_id, value, updated_at)
+           VALUES ($1, $2, $3, $4)
+           ON CONFLICT (subscriber_id, field_id) DO UPDATE
+           SET value = EXCLUDED.value, updated_at = EXCLUDED.updated_at"#,
+        subscriber_id,
+        field_id,
+        value.to_canonical_string(),
+        Utc::now(),
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
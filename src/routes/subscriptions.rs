@@ -1,13 +1,24 @@
-use actix_web::{web, HttpResponse, ResponseError};
+use std::collections::HashMap;
+
+use actix_web::{
+    http::header::{self, HeaderValue},
+    web, HttpRequest, HttpResponse, ResponseError,
+};
 use anyhow::Context;
 use chrono::Utc;
 use sqlx::{Executor, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 use crate::{
-    domain::{NewSubscriber, SubscriberEmail, SubscriberName},
-    email_client::EmailClient,
-    routes::error_chain_fmt,
+    domain::{
+        CustomFieldKey, CustomFieldValue, ListSlug, Locale, Mailbox, NewSubscriber,
+        SubscriberEmail, SubscriberName, SubscriptionStatus, SystemEmail,
+    },
+    email_client::{EmailClient, EmailClientError, DEFAULT_RETRY_AFTER},
+    routes::{
+        error_chain_fmt, exit_sequences, fill_custom_field, find_definition,
+        get_custom_field_definitions, get_list_id, get_suppressed_emails, is_suppressed,
+    },
     startup::ApplicationBaseUrl,
 };
 
@@ -38,6 +49,8 @@ impl std::fmt::Display for StoreTokenError {
 pub enum SubscribeError {
     #[error("{0}")]
     ValidationError(String),
+    #[error("The confirmation email could not be sent right now.")]
+    EmailUnavailableError(#[source] EmailClientError),
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -50,9 +63,23 @@ impl std::fmt::Debug for SubscribeError {
 
 impl ResponseError for StoreTokenError {}
 impl ResponseError for SubscribeError {
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        response.insert_header(header::ContentType::plaintext());
+        if let Self::EmailUnavailableError(error) = self {
+            let retry_after = error.retry_after().unwrap_or(DEFAULT_RETRY_AFTER);
+            response.insert_header((
+                header::RETRY_AFTER,
+                HeaderValue::from(retry_after.as_secs().max(1)),
+            ));
+        }
+        response.body(self.to_string())
+    }
+
     fn status_code(&self) -> actix_web::http::StatusCode {
         match self {
             Self::ValidationError(_) => actix_web::http::StatusCode::BAD_REQUEST,
+            Self::EmailUnavailableError(_) => actix_web::http::StatusCode::SERVICE_UNAVAILABLE,
             Self::UnexpectedError(_) => actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -62,6 +89,12 @@ impl ResponseError for SubscribeError {
 pub struct FormData {
     name: String,
     email: String,
+    lists: Option<String>,
+    /// A language tag, the `Accept-Language` header decides when missing or unsupported.
+    locale: Option<String>,
+    /// Custom fields are submitted as `field.<key>=<value>`.
+    #[serde(flatten)]
+    extra: HashMap<String, String>,
 }
 
 impl TryFrom<FormData> for NewSubscriber {
@@ -70,57 +103,160 @@ impl TryFrom<FormData> for NewSubscriber {
     fn try_from(value: FormData) -> Result<Self, Self::Error> {
         let name = SubscriberName::parse(value.name)?;
         let email = SubscriberEmail::parse(value.email)?;
+        let lists = match value.lists {
+            Some(lists) => ListSlug::parse_many(&lists)?,
+            None => Vec::new(),
+        };
+        let locale = value
+            .locale
+            .and_then(|locale| Locale::parse(&locale).ok())
+            .unwrap_or_default();
+        let custom_fields = value
+            .extra
+            .into_iter()
+            .filter_map(|(key, value)| {
+                key.strip_prefix("field.")
+                    .map(|key| CustomFieldKey::parse(key.to_string()).map(|key| (key, value)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
-        Ok(Self { email, name })
+        Ok(Self {
+            email,
+            name,
+            lists,
+            locale,
+            custom_fields,
+        })
     }
 }
 
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, pool, email_client, base_url),
+    skip(request, form, pool, email_client, base_url),
     fields(
         subscriber_email = %form.email,
         subscriber_name = %form.name
     )
 )]
 pub async fn subscribe(
+    request: HttpRequest,
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
 ) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    let form = form.into_inner();
+    // An unsupported `locale` is ignored rather than failing the signup.
+    let negotiated_locale = match form.locale.as_deref().map(Locale::parse) {
+        Some(Ok(_)) => None,
+        Some(Err(_)) | None => request
+            .headers()
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Locale::from_accept_language),
+    };
+    let mut new_subscriber: NewSubscriber =
+        form.try_into().map_err(SubscribeError::ValidationError)?;
+    if let Some(locale) = negotiated_locale {
+        new_subscriber.locale = locale;
+    }
     let mut transaction = pool
         .begin()
         .await
         .context("Failed to acquire a Postgres connection from the pool")?;
 
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
+    let mut list_ids = Vec::with_capacity(new_subscriber.lists.len());
+    for slug in &new_subscriber.lists {
+        let list_id = get_list_id(&mut transaction, slug)
+            .await
+            .context("Failed to look up a mailing list.")?
+            .ok_or_else(|| {
+                SubscribeError::ValidationError(format!("{} is not a known list.", slug))
+            })?;
+        list_ids.push(list_id);
+    }
+
+    let definitions = get_custom_field_definitions(&mut transaction)
+        .await
+        .context("Failed to retrieve custom field definitions")?;
+    let mut custom_fields = Vec::with_capacity(new_subscriber.custom_fields.len());
+    for (key, value) in &new_subscriber.custom_fields {
+        // Only fields marked as public by an admin can be set from the subscribe form.
+        let definition = find_definition(&definitions, key.as_ref())
+            .ok()
+            .filter(|d| d.public)
+            .ok_or_else(|| {
+                SubscribeError::ValidationError(format!("{} cannot be set on signup.", key))
+            })?;
+        let value = CustomFieldValue::parse(definition.field_type(), value)
+            .map_err(SubscribeError::ValidationError)?;
+        custom_fields.push((definition.id, value));
+    }
+
+    let (subscriber_id, previous_status) = insert_subscriber(&mut transaction, &new_subscriber)
         .await
         .context("Failed to insert a new subscriber in the database.")?;
 
-    let subscription_token = generate_subscription_token();
-    store_token(&mut transaction, subscriber_id, &subscription_token)
+    let pending_memberships = insert_list_memberships(&mut transaction, subscriber_id, &list_ids)
         .await
-        .context("Failed to store the confirmation token for a new subscriber")?;
+        .context("Failed to add the new subscriber to the requested lists.")?;
+    let already_subscribed =
+        previous_status == Some(SubscriptionStatus::Confirmed) && pending_memberships == 0;
+
+    for (field_id, value) in &custom_fields {
+        // Anyone can sign an address up, so the fields it already has are kept.
+        fill_custom_field(&mut transaction, subscriber_id, *field_id, value)
+            .await
+            .context("Failed to store a custom field for the new subscriber.")?;
+    }
+
+    let subscription_token = generate_subscription_token();
+    store_token(
+        &mut transaction,
+        subscriber_id,
+        &subscription_token,
+        &list_ids,
+    )
+    .await
+    .context("Failed to store the confirmation token for a new subscriber")?;
 
     transaction
         .commit()
         .await
         .context("Failed to commit SQL transaction to store a new subscriber")?;
 
+    let suppressed = get_suppressed_emails(&pool, &[&new_subscriber.email])
+        .await
+        .context("Failed to check the suppression list")?;
+    if is_suppressed(&suppressed, &new_subscriber.email) {
+        tracing::info!("Not sending a confirmation email to a suppressed address.");
+        return Ok(HttpResponse::Ok().finish());
+    }
+
+    // The subscriber is stored either way, subscribing again resends the email.
     send_confirmation_email(
         &email_client,
         new_subscriber,
         &base_url.0,
         &subscription_token,
+        already_subscribed,
     )
     .await
-    .context("Failed to send confirmation email to new subscriber")?;
+    .map_err(|e| match e {
+        EmailClientError::InvalidRecipient(_) => SubscribeError::ValidationError(
+            "The email provider cannot deliver to this address.".to_string(),
+        ),
+        e if e.is_retryable() => SubscribeError::EmailUnavailableError(e),
+        e => SubscribeError::UnexpectedError(
+            anyhow::Error::new(e).context("Failed to send confirmation email to new subscriber"),
+        ),
+    })?;
 
     Ok(HttpResponse::Ok().finish())
 }
 
+/// Sends the confirmation link in the subscriber's language, or a link to their
+/// preferences when they are `already_subscribed` and have nothing to confirm.
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
     skip(email_client, new_subscriber)
@@ -130,47 +266,175 @@ pub async fn send_confirmation_email(
     new_subscriber: NewSubscriber,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
-    let confirmation_link = format!(
-        "{}/subscriptions/confirm?subscription_token={}",
-        base_url, subscription_token
-    );
-    let plain_body = format!(
-        "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
-        confirmation_link
-    );
-    let html_body = format!(
-        "Welcome to our newsletter! Visit <a href=\"{}\">here</a> to confirm your subscription.",
-        confirmation_link
-    );
+    already_subscribed: bool,
+) -> Result<(), EmailClientError> {
+    let link;
+    let email = if already_subscribed {
+        link = format!(
+            "{}/preferences?subscription_token={}",
+            base_url, subscription_token
+        );
+        SystemEmail::AlreadySubscribed {
+            preferences_link: &link,
+        }
+    } else {
+        link = format!(
+            "{}/subscriptions/confirm?subscription_token={}",
+            base_url, subscription_token
+        );
+        SystemEmail::Confirmation {
+            confirmation_link: &link,
+        }
+    };
 
+    let recipient = Mailbox::named(new_subscriber.email, new_subscriber.name);
     email_client
-        .send_email(&new_subscriber.email, "Welcome!", &html_body, &plain_body)
-        .await
+        .send(&email.message(recipient, new_subscriber.locale))
+        .await?;
+
+    Ok(())
 }
 
 #[tracing::instrument(
     name = "Saving a new subscripter details in the database",
     skip(new_subscriber, transaction)
 )]
+/// Returns the subscriber's id, and their status before when the address was known.
 pub async fn insert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
     new_subscriber: &NewSubscriber,
-) -> Result<Uuid, sqlx::Error> {
+) -> Result<(Uuid, Option<SubscriptionStatus>), StatusTransitionError> {
+    // Subscribing to another list with a known address reuses the existing subscriber,
+    // asking them to confirm again if they had left. Their stored details are kept,
+    // whoever signed the address up this time has not proven they own it.
+    let existing = sqlx::query!(
+        r#"SELECT id, status FROM subscriptions WHERE email = $1"#,
+        new_subscriber.email.as_ref(),
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+
+    if let Some(existing) = existing {
+        let status = SubscriptionStatus::parse(&existing.status)
+            .map_err(StatusTransitionError::IllegalTransition)?;
+        if status.can_transition_to(SubscriptionStatus::PendingConfirmation) {
+            transition_subscription_status(
+                transaction,
+                existing.id,
+                SubscriptionStatus::PendingConfirmation,
+            )
+            .await?;
+        }
+        return Ok((existing.id, Some(status)));
+    }
+
     let subscriber_id = Uuid::new_v4();
     let query = sqlx::query!(
-        r#"INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-           VALUES ($1, $2, $3, $4, $5)"#,
+        r#"INSERT INTO subscriptions (id, email, name, subscribed_at, status, locale)
+           VALUES ($1, $2, $3, $4, $5, $6)"#,
         subscriber_id,
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
         Utc::now(),
-        "pending_confirmation",
+        SubscriptionStatus::PendingConfirmation.as_str(),
+        new_subscriber.locale.as_str(),
     );
 
-    transaction.execute(query).await.map_err(|e| e)?;
+    transaction.execute(query).await?;
+
+    Ok((subscriber_id, None))
+}
+
+#[derive(thiserror::Error)]
+pub enum StatusTransitionError {
+    #[error("{0}")]
+    IllegalTransition(String),
+    #[error("A database error occurred while changing a subscription status.")]
+    DatabaseError(#[from] sqlx::Error),
+}
+
+impl std::fmt::Debug for StatusTransitionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+/// The single place where a subscriber's status changes. Moving to the current status
+/// is a no-op; moves not allowed by `SubscriptionStatus` are rejected.
+/// Returns the status the subscriber had before.
+#[tracing::instrument(name = "Change a subscription status", skip(transaction))]
+pub async fn transition_subscription_status(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    next: SubscriptionStatus,
+) -> Result<SubscriptionStatus, StatusTransitionError> {
+    let row = sqlx::query!(
+        r#"SELECT status FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        subscriber_id,
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
+    let current =
+        SubscriptionStatus::parse(&row.status).map_err(StatusTransitionError::IllegalTransition)?;
+
+    if current == next {
+        return Ok(current);
+    }
+    current
+        .transition_to(next)
+        .map_err(StatusTransitionError::IllegalTransition)?;
+
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = $1 WHERE id = $2"#,
+        next.as_str(),
+        subscriber_id,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    if current == SubscriptionStatus::Confirmed {
+        exit_sequences(transaction, subscriber_id, None, next.as_str()).await?;
+    }
 
-    Ok(subscriber_id)
+    Ok(current)
+}
+
+#[tracing::instrument(
+    name = "Adding a subscriber to mailing lists",
+    skip(transaction, list_ids)
+)]
+/// Returns how many memberships now wait for a confirmation.
+pub async fn insert_list_memberships(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_ids: &[Uuid],
+) -> Result<u64, sqlx::Error> {
+    for list_id in list_ids {
+        let query = sqlx::query!(
+            r#"INSERT INTO list_memberships (subscriber_id, list_id, status, subscribed_at)
+               VALUES ($1, $2, $3, $4)
+               ON CONFLICT (subscriber_id, list_id) DO UPDATE
+               SET status = EXCLUDED.status, subscribed_at = EXCLUDED.subscribed_at
+               WHERE list_memberships.status = 'unsubscribed'"#,
+            subscriber_id,
+            list_id,
+            "pending_confirmation",
+            Utc::now(),
+        );
+
+        transaction.execute(query).await?;
+    }
+
+    // Memberships still pending from an earlier signup need confirming too.
+    let pending = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM list_memberships
+           WHERE subscriber_id = $1 AND list_id = ANY($2) AND status = 'pending_confirmation'"#,
+        subscriber_id,
+        list_ids,
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
+
+    Ok(pending as u64)
 }
 
 #[tracing::instrument(
@@ -181,6 +445,7 @@ pub async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
     subscription_token: &str,
+    list_ids: &[Uuid],
 ) -> Result<(), StoreTokenError> {
     let query = sqlx::query!(
         r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id)
@@ -189,14 +454,22 @@ pub async fn store_token(
         subscriber_id,
     );
 
-    transaction
-        .execute(query)
-        .await
-        .map_err(|e| StoreTokenError(e))?;
+    transaction.execute(query).await.map_err(StoreTokenError)?;
+
+    for list_id in list_ids {
+        let query = sqlx::query!(
+            r#"INSERT INTO subscription_token_lists (subscription_token, list_id)
+               VALUES ($1, $2)"#,
+            subscription_token,
+            list_id,
+        );
+
+        transaction.execute(query).await.map_err(StoreTokenError)?;
+    }
 
     Ok(())
 }
 
-fn generate_subscription_token() -> String {
+pub fn generate_subscription_token() -> String {
     Uuid::new_v4().to_string()
 }
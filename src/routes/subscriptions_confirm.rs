@@ -1,9 +1,14 @@
 use actix_web::{web, HttpResponse, ResponseError};
 use anyhow::Context;
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
-use crate::routes::error_chain_fmt;
+use crate::{
+    domain::SubscriptionStatus,
+    routes::{
+        enrol_in_sequences, error_chain_fmt, transition_subscription_status, StatusTransitionError,
+    },
+};
 
 #[derive(serde::Deserialize)]
 pub struct Parameters {
@@ -14,6 +19,8 @@ pub struct Parameters {
 pub enum ConfirmError {
     #[error("{0}")]
     MissingSubscriberError(String),
+    #[error("{0}")]
+    ConflictError(String),
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -28,6 +35,7 @@ impl ResponseError for ConfirmError {
     fn status_code(&self) -> actix_web::http::StatusCode {
         match self {
             Self::MissingSubscriberError(_) => actix_web::http::StatusCode::NOT_FOUND,
+            Self::ConflictError(_) => actix_web::http::StatusCode::CONFLICT,
             Self::UnexpectedError(_) => actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -48,22 +56,58 @@ pub async fn confirm(
         ));
     }
 
-    confirm_subscriber(&pool, subscriber_id.unwrap())
+    let mut transaction = pool
+        .begin()
         .await
-        .context("Failed to confirm subscriber")?;
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    confirm_subscriber(
+        &mut transaction,
+        subscriber_id.unwrap(),
+        &parameters.subscription_token,
+    )
+    .await
+    .map_err(|e| match e {
+        StatusTransitionError::IllegalTransition(e) => ConfirmError::ConflictError(e),
+        e => ConfirmError::UnexpectedError(
+            anyhow::Error::new(e).context("Failed to confirm subscriber"),
+        ),
+    })?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to confirm a subscriber")?;
 
     Ok(HttpResponse::Ok().finish())
 }
 
-#[tracing::instrument(name = "Mark a subscriber as confirmed", skip(subscriber_id, pool))]
-pub async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
+#[tracing::instrument(
+    name = "Mark a subscriber as confirmed",
+    skip(subscriber_id, transaction, subscription_token)
+)]
+pub async fn confirm_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    subscription_token: &str,
+) -> Result<(), StatusTransitionError> {
+    transition_subscription_status(transaction, subscriber_id, SubscriptionStatus::Confirmed)
+        .await?;
+
+    // Only the lists requested alongside this token are confirmed by it.
     sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+        r#"UPDATE list_memberships SET status = 'confirmed'
+           WHERE subscriber_id = $1
+             AND status = 'pending_confirmation'
+             AND list_id IN (
+                SELECT list_id FROM subscription_token_lists WHERE subscription_token = $2
+             )"#,
         subscriber_id,
+        subscription_token,
     )
-    .execute(pool)
-    .await
-    .map_err(|e| e)?;
+    .execute(&mut **transaction)
+    .await?;
+    enrol_in_sequences(transaction, subscriber_id, None).await?;
 
     Ok(())
 }
@@ -81,8 +125,7 @@ pub async fn get_subscriber_id_from_token(
         subscription_token,
     )
     .fetch_optional(pool)
-    .await
-    .map_err(|e| e)?;
+    .await?;
 
     Ok(result.map(|r| r.subscriber_id))
 }
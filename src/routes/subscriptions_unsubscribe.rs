@@ -0,0 +1,214 @@
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    domain::{
+        ListSlug, Locale, Mailbox, SubscriberEmail, SubscriberName, SubscriptionStatus, SystemEmail,
+    },
+    email_client::EmailClient,
+    routes::{
+        error_chain_fmt, exit_sequences, get_list_id, get_subscriber_id_from_token,
+        get_suppressed_emails, is_suppressed, transition_subscription_status,
+        StatusTransitionError,
+    },
+};
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    subscription_token: String,
+    list: Option<String>,
+}
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("{0}")]
+    MissingSubscriberError(String),
+    #[error("{0}")]
+    ConflictError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for UnsubscribeError {
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        match self {
+            Self::ValidationError(_) => actix_web::http::StatusCode::BAD_REQUEST,
+            Self::MissingSubscriberError(_) => actix_web::http::StatusCode::NOT_FOUND,
+            Self::ConflictError(_) => actix_web::http::StatusCode::CONFLICT,
+            Self::UnexpectedError(_) => actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Unsubscribes from a single list when `list` is given, from everything otherwise.
+/// Leaving everything is acknowledged by email, in the subscriber's language.
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber",
+    skip(parameters, pool, email_client)
+)]
+pub async fn unsubscribe(
+    parameters: web::Query<UnsubscribeParameters>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    let list = parameters
+        .list
+        .clone()
+        .map(ListSlug::parse)
+        .transpose()
+        .map_err(UnsubscribeError::ValidationError)?;
+
+    let subscriber_id = get_subscriber_id_from_token(&pool, &parameters.subscription_token)
+        .await
+        .context("Unable to retrieve subscriber id from token")?
+        .ok_or_else(|| {
+            UnsubscribeError::MissingSubscriberError("Invalid subscription token".to_string())
+        })?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    let mut left_everything = false;
+    match list {
+        Some(slug) => {
+            let list_id = get_list_id(&mut transaction, &slug)
+                .await
+                .context("Failed to look up a mailing list.")?
+                .ok_or_else(|| {
+                    UnsubscribeError::ValidationError(format!("{} is not a known list.", slug))
+                })?;
+
+            unsubscribe_from_list(&mut transaction, subscriber_id, list_id)
+                .await
+                .context("Failed to unsubscribe from list")?;
+        }
+        None => {
+            left_everything = get_subscription_status(&mut transaction, subscriber_id)
+                .await
+                .context("Failed to retrieve the subscriber's status")?
+                != SubscriptionStatus::Unsubscribed;
+            unsubscribe_from_all(&mut transaction, subscriber_id)
+                .await
+                .map_err(|e| match e {
+                    StatusTransitionError::IllegalTransition(e) => {
+                        UnsubscribeError::ConflictError(e)
+                    }
+                    e => UnsubscribeError::UnexpectedError(
+                        anyhow::Error::new(e).context("Failed to unsubscribe from all lists"),
+                    ),
+                })?;
+        }
+    }
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to unsubscribe a subscriber")?;
+
+    if left_everything {
+        // The subscriber is gone either way, a failure is only logged.
+        if let Err(error) = send_unsubscribe_confirmation(&pool, &email_client, subscriber_id).await
+        {
+            tracing::warn!(error.cause_chain = ?error, "Failed to send an unsubscribe confirmation.");
+        }
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+async fn get_subscription_status(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<SubscriptionStatus, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT status FROM subscriptions WHERE id = $1"#,
+        subscriber_id,
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
+    SubscriptionStatus::parse(&row.status).map_err(anyhow::Error::msg)
+}
+
+#[tracing::instrument(name = "Send an unsubscribe confirmation", skip(pool, email_client))]
+async fn send_unsubscribe_confirmation(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    subscriber_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    let subscriber = sqlx::query!(
+        r#"SELECT email, name, locale FROM subscriptions WHERE id = $1"#,
+        subscriber_id,
+    )
+    .fetch_one(pool)
+    .await?;
+    let email = SubscriberEmail::parse(subscriber.email).map_err(anyhow::Error::msg)?;
+    let suppressed = get_suppressed_emails(pool, &[&email]).await?;
+    if is_suppressed(&suppressed, &email) {
+        return Ok(());
+    }
+    let recipient = match SubscriberName::parse(subscriber.name) {
+        Ok(name) => Mailbox::named(email, name),
+        Err(_) => Mailbox::new(email),
+    };
+    let locale = Locale::parse(&subscriber.locale).unwrap_or_default();
+
+    email_client
+        .send(&SystemEmail::UnsubscribeConfirmation.message(recipient, locale))
+        .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Mark a list membership as unsubscribed", skip(transaction))]
+pub async fn unsubscribe_from_list(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE list_memberships SET status = 'unsubscribed'
+           WHERE subscriber_id = $1 AND list_id = $2"#,
+        subscriber_id,
+        list_id,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    exit_sequences(
+        transaction,
+        subscriber_id,
+        Some(list_id),
+        SubscriptionStatus::Unsubscribed.as_str(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Mark a subscriber as unsubscribed", skip(transaction))]
+pub async fn unsubscribe_from_all(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), StatusTransitionError> {
+    sqlx::query!(
+        r#"UPDATE list_memberships SET status = 'unsubscribed' WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    transition_subscription_status(transaction, subscriber_id, SubscriptionStatus::Unsubscribed)
+        .await?;
+
+    Ok(())
+}
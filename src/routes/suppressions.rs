@@ -0,0 +1,186 @@
+use std::collections::HashSet;
+
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+
+use crate::{domain::SubscriberEmail, routes::error_chain_fmt};
+
+#[derive(thiserror::Error)]
+pub enum SuppressionError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("{0}")]
+    ConflictError(String),
+    #[error("{0}")]
+    MissingSuppressionError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SuppressionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SuppressionError {
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        match self {
+            Self::ValidationError(_) => actix_web::http::StatusCode::BAD_REQUEST,
+            Self::ConflictError(_) => actix_web::http::StatusCode::CONFLICT,
+            Self::MissingSuppressionError(_) => actix_web::http::StatusCode::NOT_FOUND,
+            Self::UnexpectedError(_) => actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct SuppressionData {
+    email: String,
+    reason: String,
+    source: Option<String>,
+}
+
+/// An address that is never emailed, whatever its subscription status.
+#[derive(serde::Serialize)]
+pub struct Suppression {
+    email: String,
+    reason: String,
+    /// Where the suppression came from, `admin` unless stated otherwise.
+    source: String,
+    created_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Suppressing an email address", skip(body, pool))]
+pub async fn create_suppression(
+    body: web::Json<SuppressionData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SuppressionError> {
+    let email =
+        SubscriberEmail::parse(body.email.clone()).map_err(SuppressionError::ValidationError)?;
+    let reason = body.reason.trim();
+    if reason.is_empty() {
+        return Err(SuppressionError::ValidationError(
+            "A suppression must have a reason.".to_string(),
+        ));
+    }
+    let source = body.source.as_deref().map(str::trim).unwrap_or("admin");
+    if source.is_empty() {
+        return Err(SuppressionError::ValidationError(
+            "The source of a suppression cannot be empty.".to_string(),
+        ));
+    }
+
+    let suppression = Suppression {
+        email: normalise_email(email.as_ref()),
+        reason: reason.to_string(),
+        source: source.to_string(),
+        created_at: Utc::now(),
+    };
+    let inserted = sqlx::query!(
+        r#"INSERT INTO suppressions (email, reason, source, created_at)
+           VALUES ($1, $2, $3, $4)
+           ON CONFLICT (email) DO NOTHING"#,
+        suppression.email,
+        suppression.reason,
+        suppression.source,
+        suppression.created_at,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to insert a suppression in the database.")?;
+
+    if inserted.rows_affected() == 0 {
+        return Err(SuppressionError::ConflictError(format!(
+            "{} is already suppressed.",
+            suppression.email
+        )));
+    }
+
+    Ok(HttpResponse::Ok().json(suppression))
+}
+
+#[tracing::instrument(name = "Listing suppressions", skip(pool))]
+pub async fn list_suppressions(pool: web::Data<PgPool>) -> Result<HttpResponse, SuppressionError> {
+    let suppressions = sqlx::query_as!(
+        Suppression,
+        r#"SELECT email, reason, source, created_at FROM suppressions ORDER BY email"#
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve suppressions")?;
+
+    Ok(HttpResponse::Ok().json(suppressions))
+}
+
+#[tracing::instrument(name = "Lifting a suppression", skip(pool))]
+pub async fn delete_suppression(
+    email: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SuppressionError> {
+    let email = normalise_email(&email);
+    let deleted = sqlx::query!(r#"DELETE FROM suppressions WHERE email = $1"#, email)
+        .execute(pool.get_ref())
+        .await
+        .context("Failed to delete a suppression from the database.")?;
+
+    if deleted.rows_affected() == 0 {
+        return Err(SuppressionError::MissingSuppressionError(format!(
+            "{} is not suppressed.",
+            email
+        )));
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Returns the addresses among `emails` that must not be emailed.
+/// Matching ignores case; the returned addresses are lowercased.
+#[tracing::instrument(name = "Checking the suppression list", skip(pool, emails))]
+pub async fn get_suppressed_emails(
+    pool: &PgPool,
+    emails: &[&SubscriberEmail],
+) -> Result<HashSet<String>, sqlx::Error> {
+    let emails: Vec<String> = emails.iter().map(|e| normalise_email(e.as_ref())).collect();
+    let suppressed = sqlx::query_scalar!(
+        r#"SELECT email FROM suppressions WHERE email = ANY($1)"#,
+        &emails
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(suppressed.into_iter().collect())
+}
+
+/// Suppresses `email` on behalf of `source`, keeping any earlier suppression.
+#[tracing::instrument(name = "Suppressing an email address", skip(transaction))]
+pub async fn suppress_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+    reason: &str,
+    source: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"INSERT INTO suppressions (email, reason, source, created_at)
+           VALUES ($1, $2, $3, $4)
+           ON CONFLICT (email) DO NOTHING"#,
+        normalise_email(email),
+        reason,
+        source,
+        Utc::now(),
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
+
+pub fn is_suppressed(suppressed: &HashSet<String>, email: &SubscriberEmail) -> bool {
+    suppressed.contains(&normalise_email(email.as_ref()))
+}
+
+fn normalise_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
@@ -0,0 +1,286 @@
+use actix_web::{http::header, web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{domain::ClickToken, routes::error_chain_fmt, startup::HmacSecret};
+
+/// A transparent 1x1 GIF.
+const TRACKING_PIXEL: [u8; 43] = [
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+#[derive(thiserror::Error)]
+pub enum TrackingError {
+    #[error("{0}")]
+    MissingLinkError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for TrackingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for TrackingError {
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        match self {
+            Self::MissingLinkError(_) => actix_web::http::StatusCode::NOT_FOUND,
+            Self::UnexpectedError(_) => actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Serves the open tracking pixel. Unknown tokens get the same image so that
+/// tokens cannot be probed.
+#[tracing::instrument(name = "Tracking an open", skip(token, request, pool))]
+pub async fn track_open(
+    token: web::Path<String>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, TrackingError> {
+    let user_agent = request
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok());
+
+    record_open(&pool, &token, user_agent)
+        .await
+        .context("Failed to record an open")?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("image/gif")
+        .insert_header((header::CACHE_CONTROL, "no-store, no-cache, must-revalidate"))
+        .body(TRACKING_PIXEL.as_slice()))
+}
+
+#[tracing::instrument(name = "Recording an open", skip(pool, token))]
+async fn record_open(
+    pool: &PgPool,
+    token: &str,
+    user_agent: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+    let mut transaction = pool.begin().await?;
+
+    let Some(delivery) = sqlx::query!(
+        r#"UPDATE deliveries SET first_opened_at = COALESCE(first_opened_at, $1)
+           WHERE tracking_token = $2
+           RETURNING id, subscriber_id"#,
+        now,
+        token,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    else {
+        return Ok(());
+    };
+
+    sqlx::query!(
+        r#"INSERT INTO delivery_opens (id, delivery_id, opened_at, user_agent)
+           VALUES ($1, $2, $3, $4)"#,
+        Uuid::new_v4(),
+        delivery.id,
+        now,
+        user_agent,
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    sqlx::query!(
+        r#"UPDATE subscriptions SET last_engaged_at = $1 WHERE id = $2"#,
+        now,
+        delivery.subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await
+}
+
+/// Records a click on a tracked link and redirects to its original URL. The target
+/// always comes from the issue's links, never from the request, and the token must
+/// carry a valid signature.
+#[tracing::instrument(name = "Tracking a click", skip(token, request, pool, hmac_secret))]
+pub async fn track_click(
+    token: web::Path<String>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, TrackingError> {
+    let token = ClickToken::verify(&token, &hmac_secret.0).map_err(|e| {
+        tracing::warn!("Rejected a click token: {}", e);
+        TrackingError::MissingLinkError("Unknown link.".to_string())
+    })?;
+    let user_agent = request
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok());
+
+    let url = record_click(&pool, &token, user_agent)
+        .await
+        .context("Failed to record a click")?
+        .ok_or_else(|| TrackingError::MissingLinkError("Unknown link.".to_string()))?;
+
+    Ok(HttpResponse::Found()
+        .insert_header((header::LOCATION, url))
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .finish())
+}
+
+/// Returns the link's URL, or `None` if the link no longer exists.
+#[tracing::instrument(name = "Recording a click", skip(pool))]
+async fn record_click(
+    pool: &PgPool,
+    token: &ClickToken,
+    user_agent: Option<&str>,
+) -> Result<Option<String>, sqlx::Error> {
+    let now = Utc::now();
+    let mut transaction = pool.begin().await?;
+
+    let Some(link) = sqlx::query!(
+        r#"SELECT url FROM issue_links WHERE id = $1"#,
+        token.link_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let delivery = sqlx::query!(
+        r#"SELECT subscriber_id FROM deliveries WHERE id = $1"#,
+        token.delivery_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    if let Some(delivery) = delivery {
+        sqlx::query!(
+            r#"INSERT INTO link_clicks (id, link_id, delivery_id, clicked_at, user_agent)
+               VALUES ($1, $2, $3, $4, $5)"#,
+            Uuid::new_v4(),
+            token.link_id,
+            token.delivery_id,
+            now,
+            user_agent,
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"UPDATE subscriptions SET last_engaged_at = $1 WHERE id = $2"#,
+            now,
+            delivery.subscriber_id,
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(Some(link.url))
+}
+
+pub fn generate_tracking_token() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+/// Adds the open tracking pixel just before `</body>`, or at the end of a fragment.
+pub fn with_open_pixel(html: &str, base_url: &str, tracking_token: &str) -> String {
+    let pixel = format!(
+        r#"<img src="{}/t/o/{}.gif" width="1" height="1" alt="" style="border:0">"#,
+        base_url, tracking_token
+    );
+
+    match html.to_ascii_lowercase().rfind("</body>") {
+        Some(index) => format!("{}{}{}", &html[..index], pixel, &html[index..]),
+        None => format!("{}{}", html, pixel),
+    }
+}
+
+/// The distinct `http(s)` links of `href` attributes, in order of appearance.
+pub fn trackable_links(html: &str) -> Vec<String> {
+    let mut links: Vec<String> = Vec::new();
+    for (start, end) in href_values(html) {
+        let url = unescape_href(&html[start..end]);
+        if is_trackable(&url) && !links.contains(&url) {
+            links.push(url);
+        }
+    }
+    links
+}
+
+/// Replaces every trackable `href` with the URL returned by `tracked_url`,
+/// leaving the link untouched when it returns `None`.
+pub fn with_tracked_links(html: &str, tracked_url: impl Fn(&str) -> Option<String>) -> String {
+    let mut rewritten = String::with_capacity(html.len());
+    let mut copied_up_to = 0;
+    for (start, end) in href_values(html) {
+        let url = unescape_href(&html[start..end]);
+        if !is_trackable(&url) {
+            continue;
+        }
+        if let Some(tracked) = tracked_url(&url) {
+            rewritten.push_str(&html[copied_up_to..start]);
+            rewritten.push_str(&tracked);
+            copied_up_to = end;
+        }
+    }
+    rewritten.push_str(&html[copied_up_to..]);
+    rewritten
+}
+
+/// Byte ranges of quoted `href` attribute values.
+pub(crate) fn href_values(html: &str) -> Vec<(usize, usize)> {
+    // ASCII lowercasing keeps byte offsets identical.
+    let lowercase = html.to_ascii_lowercase();
+    let bytes = html.as_bytes();
+    let mut values = Vec::new();
+    let mut from = 0;
+
+    while let Some(found) = lowercase[from..].find("href") {
+        let attribute_start = from + found;
+        from = attribute_start + 4;
+        if attribute_start == 0 || !bytes[attribute_start - 1].is_ascii_whitespace() {
+            continue;
+        }
+
+        let mut position = from;
+        while position < bytes.len() && bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        if bytes.get(position) != Some(&b'=') {
+            continue;
+        }
+        position += 1;
+        while position < bytes.len() && bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        let quote = match bytes.get(position) {
+            Some(&quote) if quote == b'"' || quote == b'\'' => quote,
+            _ => continue,
+        };
+        let value_start = position + 1;
+        let Some(length) = bytes[value_start..].iter().position(|b| *b == quote) else {
+            break;
+        };
+        values.push((value_start, value_start + length));
+        from = value_start + length + 1;
+    }
+
+    values
+}
+
+pub(crate) fn unescape_href(value: &str) -> String {
+    value.trim().replace("&amp;", "&")
+}
+
+fn is_trackable(url: &str) -> bool {
+    let url = url.to_ascii_lowercase();
+    url.starts_with("http://") || url.starts_with("https://")
+}
@@ -0,0 +1,234 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    configuration::Settings,
+    domain::{DeliveryStatus, EmailMessage, Mailbox, SubscriberEmail, SubscriptionStatus},
+    email_client::EmailClient,
+    issue_delivery_worker::ExecutionOutcome,
+    routes::{get_suppressed_emails, is_suppressed},
+    startup::get_connection_pool,
+};
+
+struct DueStep {
+    sequence_id: Uuid,
+    subscriber_id: Uuid,
+    next_step_position: i32,
+    list_id: Option<Uuid>,
+    step_id: Uuid,
+    subject: String,
+    html_content: String,
+    text_content: String,
+}
+
+/// Sends the next step of one enrolment that is due, then schedules the step after
+/// it. Subscribers who can no longer be mailed are exited from the sequence instead.
+#[tracing::instrument(skip_all, fields(sequence_id = tracing::field::Empty, subscriber_id = tracing::field::Empty), err)]
+pub async fn try_send_sequence_step(
+    pool: &PgPool,
+    email_client: &EmailClient,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let Some(due) = sqlx::query_as!(
+        DueStep,
+        r#"SELECT e.sequence_id, e.subscriber_id, e.next_step_position, s.list_id,
+               st.id AS step_id, st.subject, st.html_content, st.text_content
+           FROM automation_enrolments e
+           JOIN automation_sequences s ON s.id = e.sequence_id
+           JOIN automation_steps st
+               ON st.sequence_id = e.sequence_id AND st.position = e.next_step_position
+           WHERE s.active
+             AND e.completed_at IS NULL
+             AND e.exited_at IS NULL
+             AND e.next_send_at <= now()
+           ORDER BY e.next_send_at
+           FOR UPDATE OF e
+           SKIP LOCKED
+           LIMIT 1"#,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    tracing::Span::current()
+        .record("sequence_id", tracing::field::display(due.sequence_id))
+        .record("subscriber_id", tracing::field::display(due.subscriber_id));
+
+    match get_sequence_recipient(pool, &due).await? {
+        Ok(email) => {
+            send_step(&mut transaction, email_client, &due, &email).await?;
+            schedule_next_step(&mut transaction, &due).await?;
+        }
+        Err(reason) => {
+            tracing::info!(
+                exit_reason = reason,
+                "Exiting a subscriber who can no longer be mailed from a sequence."
+            );
+            sqlx::query!(
+                r#"UPDATE automation_enrolments
+                   SET exited_at = now(), exit_reason = $3, next_send_at = NULL
+                   WHERE sequence_id = $1 AND subscriber_id = $2"#,
+                due.sequence_id,
+                due.subscriber_id,
+                reason,
+            )
+            .execute(&mut *transaction)
+            .await?;
+        }
+    }
+    transaction.commit().await?;
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// The subscriber's address, or why they should leave the sequence. Exits are
+/// normally recorded when subscribers leave, this catches every other way out.
+async fn get_sequence_recipient(
+    pool: &PgPool,
+    due: &DueStep,
+) -> Result<Result<SubscriberEmail, &'static str>, anyhow::Error> {
+    let subscriber = sqlx::query!(
+        r#"SELECT s.email, s.status, m.status AS "list_status?"
+           FROM subscriptions s
+           LEFT JOIN list_memberships m ON m.subscriber_id = s.id AND m.list_id = $2
+           WHERE s.id = $1"#,
+        due.subscriber_id,
+        due.list_id,
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let status = SubscriptionStatus::parse(&subscriber.status).map_err(anyhow::Error::msg)?;
+    if status != SubscriptionStatus::Confirmed {
+        return Ok(Err(status.as_str()));
+    }
+    if due.list_id.is_some() && subscriber.list_status.as_deref() != Some("confirmed") {
+        return Ok(Err(SubscriptionStatus::Unsubscribed.as_str()));
+    }
+    let Ok(email) = SubscriberEmail::parse(subscriber.email) else {
+        return Ok(Err("invalid_email"));
+    };
+    let suppressed = get_suppressed_emails(pool, &[&email]).await?;
+    if is_suppressed(&suppressed, &email) {
+        return Ok(Err("suppressed"));
+    }
+
+    Ok(Ok(email))
+}
+
+/// Steps go out from the newsletter identity. A provider failure is recorded as
+/// a failed delivery and the sequence moves on.
+async fn send_step(
+    transaction: &mut Transaction<'_, Postgres>,
+    email_client: &EmailClient,
+    due: &DueStep,
+    email: &SubscriberEmail,
+) -> Result<(), anyhow::Error> {
+    let message = EmailMessage::new(
+        Mailbox::new(email.clone()),
+        &due.subject,
+        &due.html_content,
+        &due.text_content,
+    )
+    .with_sender(email_client.senders().newsletter().clone());
+    let outcome = email_client.send(&message).await;
+    let (status, sent, error) = match outcome {
+        Ok(sent) => (DeliveryStatus::Sent, Some(sent), None),
+        Err(error) => {
+            tracing::warn!(error.cause_chain = ?error, "Failed to send a sequence step.");
+            (DeliveryStatus::Failed, None, Some(error.to_string()))
+        }
+    };
+
+    sqlx::query!(
+        r#"INSERT INTO automation_deliveries
+           (id, step_id, subscriber_id, status, provider, provider_message_id, error,
+            attempted_at)
+           VALUES ($1, $2, $3, $4, $5, $6, $7, now())"#,
+        Uuid::new_v4(),
+        due.step_id,
+        due.subscriber_id,
+        status.as_str(),
+        sent.as_ref().map(|sent| sent.provider.as_str()),
+        sent.as_ref().and_then(|sent| sent.message_id.as_deref()),
+        error,
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to record a sequence delivery")?;
+
+    Ok(())
+}
+
+/// Delays are counted from enrolment, so a late step does not push back the rest.
+async fn schedule_next_step(
+    transaction: &mut Transaction<'_, Postgres>,
+    due: &DueStep,
+) -> Result<(), sqlx::Error> {
+    let next_position = due.next_step_position + 1;
+    let next_step = sqlx::query!(
+        r#"SELECT delay_minutes FROM automation_steps WHERE sequence_id = $1 AND position = $2"#,
+        due.sequence_id,
+        next_position,
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+
+    match next_step {
+        Some(step) => {
+            sqlx::query!(
+                r#"UPDATE automation_enrolments
+                   SET next_step_position = $3,
+                       next_send_at = enrolled_at + make_interval(mins => $4)
+                   WHERE sequence_id = $1 AND subscriber_id = $2"#,
+                due.sequence_id,
+                due.subscriber_id,
+                next_position,
+                step.delay_minutes,
+            )
+            .execute(&mut **transaction)
+            .await?;
+        }
+        None => {
+            sqlx::query!(
+                r#"UPDATE automation_enrolments
+                   SET next_step_position = $3, next_send_at = NULL, completed_at = now()
+                   WHERE sequence_id = $1 AND subscriber_id = $2"#,
+                due.sequence_id,
+                due.subscriber_id,
+                next_position,
+            )
+            .execute(&mut **transaction)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn sequence_loop(pool: PgPool, email_client: Arc<EmailClient>) -> Result<(), anyhow::Error> {
+    loop {
+        match try_send_sequence_step(&pool, &email_client).await {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+pub async fn run_sequence_worker_until_stopped(
+    configuration: Settings,
+    email_client: Arc<EmailClient>,
+) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+
+    sequence_loop(connection_pool, email_client).await
+}
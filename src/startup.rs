@@ -4,8 +4,9 @@ use actix_web::{
     web::{self, Data},
     App, HttpServer,
 };
+use secrecy::Secret;
 use sqlx::{postgres::PgPoolOptions, PgPool};
-use std::net::TcpListener;
+use std::{net::TcpListener, sync::Arc};
 use tracing_actix_web::TracingLogger;
 
 use crate::{
@@ -22,22 +23,22 @@ pub struct Application {
 #[derive(Clone)]
 pub struct ApplicationBaseUrl(pub String);
 
+#[derive(Clone)]
+pub struct WebhookSecret(pub Secret<String>);
+
+#[derive(Clone)]
+pub struct HmacSecret(pub Secret<String>);
+
 impl Application {
-    pub async fn build(configuration: Settings) -> Result<Self, std::io::Error> {
+    /// `email_client` is shared with the background workers, so that they all go
+    /// through the same rate limiters and circuit breakers.
+    pub async fn build(
+        configuration: Settings,
+        email_client: Arc<EmailClient>,
+    ) -> Result<Self, std::io::Error> {
         let connection_pool = get_connection_pool(&configuration.database);
 
-        let sender_email = configuration
-            .email_client
-            .sender()
-            .expect("Invalid sender email address.");
-        let timeout = configuration.email_client.timeout();
-        let email_client = EmailClient::new(
-            configuration.email_client.base_url,
-            sender_email,
-            configuration.email_client.api_key_public,
-            configuration.email_client.api_key_private,
-            timeout,
-        );
+        let webhook_secret = configuration.email_client.webhook_secret.clone();
 
         let address = format!(
             "{}:{}",
@@ -51,6 +52,8 @@ impl Application {
             connection_pool,
             email_client,
             configuration.application.base_url,
+            webhook_secret,
+            configuration.application.hmac_secret,
         )?;
 
         Ok(Self { server, port })
@@ -72,12 +75,16 @@ pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
 pub fn run(
     listener: TcpListener,
     connection: PgPool,
-    email_client: EmailClient,
+    email_client: Arc<EmailClient>,
     base_url: String,
+    webhook_secret: Secret<String>,
+    hmac_secret: Secret<String>,
 ) -> Result<Server, std::io::Error> {
     let connection_pool = Data::new(connection);
-    let email_client = Data::new(email_client);
+    let email_client = Data::from(email_client);
     let base_url = Data::new(ApplicationBaseUrl(base_url));
+    let webhook_secret = Data::new(WebhookSecret(webhook_secret));
+    let hmac_secret = Data::new(HmacSecret(hmac_secret));
 
     let server = HttpServer::new(move || {
         App::new()
@@ -86,9 +93,60 @@ pub fn run(
             .route("/newsletters", web::post().to(publish_newsletter))
             .route("/subscriptions", web::post().to(subscribe))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route("/subscriptions/unsubscribe", web::get().to(unsubscribe))
+            .route("/t/o/{token}.gif", web::get().to(track_open))
+            .route("/t/c/{token}", web::get().to(track_click))
+            .route("/archive", web::get().to(archive))
+            .route("/archive/{slug}", web::get().to(archived_issue))
+            .route("/feed.rss", web::get().to(rss_feed))
+            .route("/feed.atom", web::get().to(atom_feed))
+            .route("/preferences", web::get().to(preferences_form))
+            .route("/preferences", web::post().to(update_preferences))
+            .route(
+                "/webhooks/email-events",
+                web::post().to(receive_email_events),
+            )
+            .route("/admin/drafts", web::get().to(list_drafts))
+            .route("/admin/drafts/{id}/publish", web::post().to(publish_draft))
+            .route("/admin/feed_sources", web::get().to(list_feed_sources))
+            .route("/admin/feed_sources", web::post().to(create_feed_source))
+            .route("/admin/issues/{id}", web::patch().to(update_issue))
+            .route("/admin/issues/{id}/stats", web::get().to(get_issue_stats))
+            .route("/admin/issues/{id}/links", web::get().to(get_issue_links))
+            .route("/admin/lists", web::post().to(create_list))
+            .route("/admin/lists/{slug}", web::patch().to(update_list))
+            .route("/admin/custom_fields", web::get().to(list_custom_fields))
+            .route("/admin/custom_fields", web::post().to(create_custom_field))
+            .route("/admin/segments", web::get().to(list_segments))
+            .route("/admin/segments", web::post().to(create_segment))
+            .route("/admin/segments/dry_run", web::post().to(dry_run_segment))
+            .route("/admin/sequences", web::get().to(list_sequences))
+            .route("/admin/sequences", web::post().to(create_sequence))
+            .route("/admin/sequences/{id}", web::patch().to(update_sequence))
+            .route("/admin/suppressions", web::get().to(list_suppressions))
+            .route("/admin/suppressions", web::post().to(create_suppression))
+            .route(
+                "/admin/suppressions/{email}",
+                web::delete().to(delete_suppression),
+            )
+            .route("/admin/subscribers", web::get().to(list_subscribers))
+            .route(
+                "/admin/subscribers/import",
+                web::post().to(import_subscribers),
+            )
+            .route(
+                "/admin/subscribers/{id}",
+                web::patch().to(update_subscriber),
+            )
+            .route(
+                "/admin/subscribers/{id}/sequences",
+                web::get().to(get_subscriber_sequences),
+            )
             .app_data(connection_pool.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(webhook_secret.clone())
+            .app_data(hmac_secret.clone())
     })
     .listen(listener)?
     .run();
@@ -0,0 +1,298 @@
+use wiremock::{matchers::any, Mock};
+
+use crate::helpers::{
+    create_confirmed_subscriber, create_list, email_sent_response, spawn_app, TestApp,
+};
+
+async fn create_confirmed_subscribers(app: &TestApp, count: usize) {
+    for i in 0..count {
+        let email = format!("reader{}%40example.com", i);
+        create_confirmed_subscriber(app, &email, "").await;
+    }
+}
+
+fn ab_test_newsletter(ab_test: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<html><body><p>Newsletter body as HTML</p></body></html>",
+        },
+        "ab_test": ab_test,
+    })
+}
+
+/// The subjects of the emails sent since the mock was mounted, sorted.
+async fn sent_subjects(app: &TestApp, skip: usize) -> Vec<String> {
+    let mut subjects: Vec<String> = app
+        .sent_emails()
+        .await
+        .iter()
+        .skip(skip)
+        .map(|email| email["Subject"].as_str().unwrap().to_string())
+        .collect();
+    subjects.sort();
+    subjects
+}
+
+#[tokio::test]
+async fn ab_tests_send_each_variant_to_the_sample_and_hold_back_the_rest() {
+    let mut app = spawn_app().await;
+    create_confirmed_subscribers(&app, 4).await;
+    let already_sent = app.sent_emails().await.len();
+
+    let _mock_guard = Mock::given(any())
+        .respond_with(email_sent_response())
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = app
+        .post_newsletters(ab_test_newsletter(serde_json::json!({
+            "subjects": ["Subject A", "Subject B"],
+            "sample_percentage": 50,
+            "wait_minutes": 60,
+            "metric": "opens",
+        })))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        sent_subjects(&app, already_sent).await,
+        vec!["Subject A", "Subject B"]
+    );
+    let holdouts = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM ab_test_holdouts"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(holdouts.count, 2);
+
+    // The wait window is not over yet.
+    app.decide_due_ab_tests().await;
+    app.dispatch_all_pending_emails().await;
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn an_issue_whose_ab_test_cannot_be_stored_is_not_published() {
+    let mut app = spawn_app().await;
+    create_confirmed_subscribers(&app, 4).await;
+    sqlx::query!("ALTER TABLE ab_test_holdouts DROP COLUMN subscriber_id;")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let _mock_guard = Mock::given(any())
+        .respond_with(email_sent_response())
+        .expect(0)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = app
+        .post_newsletters(ab_test_newsletter(serde_json::json!({
+            "subjects": ["Subject A", "Subject B"],
+            "sample_percentage": 50,
+            "wait_minutes": 60,
+            "metric": "opens",
+        })))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 500);
+    let issues = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, 0);
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn the_winning_subject_is_sent_to_the_rest_once_the_wait_window_is_over() {
+    let mut app = spawn_app().await;
+    create_confirmed_subscribers(&app, 4).await;
+    Mock::given(any())
+        .respond_with(email_sent_response())
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletters(ab_test_newsletter(serde_json::json!({
+        "subjects": ["Subject A", "Subject B"],
+        "sample_percentage": 50,
+        "wait_minutes": 60,
+        "metric": "opens",
+    })))
+    .await
+    .error_for_status()
+    .unwrap();
+    let already_sent = app.sent_emails().await.len();
+
+    let opened = sqlx::query!(
+        r#"SELECT d.tracking_token AS "tracking_token!"
+           FROM deliveries d
+           JOIN ab_test_variants v ON v.id = d.variant_id
+           WHERE v.subject = 'Subject B'"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    app.get_open_pixel(&opened.tracking_token).await;
+    sqlx::query!("UPDATE ab_tests SET decide_at = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    app.decide_due_ab_tests().await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(
+        sent_subjects(&app, already_sent).await,
+        vec!["Subject B", "Subject B"]
+    );
+    let deliveries = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM deliveries"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(deliveries.count, 4);
+    let queued = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count, 0);
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn subscribers_who_unsubscribe_during_the_wait_window_are_skipped() {
+    let mut app = spawn_app().await;
+    create_confirmed_subscribers(&app, 4).await;
+    Mock::given(any())
+        .respond_with(email_sent_response())
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletters(ab_test_newsletter(serde_json::json!({
+        "subjects": ["Subject A", "Subject B"],
+        "sample_percentage": 1,
+        "wait_minutes": 60,
+        "metric": "clicks",
+    })))
+    .await
+    .error_for_status()
+    .unwrap();
+    let already_sent = app.sent_emails().await.len();
+
+    sqlx::query!(
+        "UPDATE subscriptions SET status = 'unsubscribed'
+         WHERE id IN (SELECT subscriber_id FROM ab_test_holdouts)"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!("UPDATE ab_tests SET decide_at = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.decide_due_ab_tests().await;
+    app.dispatch_all_pending_emails().await;
+
+    assert!(sent_subjects(&app, already_sent).await.is_empty());
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn subscribers_who_leave_the_list_during_the_wait_window_are_skipped() {
+    let mut app = spawn_app().await;
+    create_list(&app, "rust").await;
+    for i in 0..4 {
+        let email = format!("reader{}%40example.com", i);
+        create_confirmed_subscriber(&app, &email, "rust").await;
+    }
+    Mock::given(any())
+        .respond_with(email_sent_response())
+        .mount(&app.email_server)
+        .await;
+    let mut newsletter = ab_test_newsletter(serde_json::json!({
+        "subjects": ["Subject A", "Subject B"],
+        "sample_percentage": 1,
+        "wait_minutes": 60,
+        "metric": "clicks",
+    }));
+    newsletter["list"] = "rust".into();
+    app.post_newsletters(newsletter)
+        .await
+        .error_for_status()
+        .unwrap();
+    let already_sent = app.sent_emails().await.len();
+
+    sqlx::query!(
+        "UPDATE list_memberships SET status = 'unsubscribed'
+         WHERE subscriber_id IN (SELECT subscriber_id FROM ab_test_holdouts)"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!("UPDATE ab_tests SET decide_at = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.decide_due_ab_tests().await;
+    app.dispatch_all_pending_emails().await;
+
+    assert!(sent_subjects(&app, already_sent).await.is_empty());
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn publishing_returns_400_for_invalid_ab_test_settings() {
+    let mut app = spawn_app().await;
+    create_list(&app, "untracked").await;
+    app.patch_list("untracked", serde_json::json!({ "open_tracking": false }))
+        .await
+        .error_for_status()
+        .unwrap();
+    let valid = serde_json::json!({
+        "subjects": ["Subject A", "Subject B"],
+        "sample_percentage": 20,
+        "wait_minutes": 60,
+        "metric": "clicks",
+    });
+    let with = |key: &str, value: serde_json::Value| {
+        let mut ab_test = valid.clone();
+        ab_test[key] = value;
+        ab_test_newsletter(ab_test)
+    };
+    let mut opens_without_tracking = with("metric", "opens".into());
+    opens_without_tracking["list"] = "untracked".into();
+    let test_cases = vec![
+        (
+            with("subjects", serde_json::json!(["Only one"])),
+            "a single subject",
+        ),
+        (
+            with("subjects", serde_json::json!(["A", " "])),
+            "an empty subject",
+        ),
+        (with("sample_percentage", 0.into()), "an empty sample"),
+        (with("sample_percentage", 100.into()), "no holdout"),
+        (with("wait_minutes", 0.into()), "no wait window"),
+        (with("metric", "replies".into()), "an unknown metric"),
+        (
+            opens_without_tracking,
+            "opens on a list without open tracking",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        let response = app.post_newsletters(body).await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 Bad Request when the A/B test had {}.",
+            description
+        );
+    }
+
+    app.drop().await;
+}
@@ -0,0 +1,280 @@
+use crate::helpers::{create_list, create_subscriber, publish_issue, spawn_app, TestApp};
+
+/// Publishes an issue to nobody and returns its id and archive slug.
+async fn publish(app: &TestApp, title: &str, html: &str, visibility: &str) -> (String, String) {
+    let issue_id = publish_issue(
+        app,
+        serde_json::json!({
+            "title": title,
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": html,
+            },
+            "visibility": visibility,
+        }),
+    )
+    .await;
+
+    let issue = sqlx::query!(
+        "SELECT slug FROM newsletter_issues WHERE id = $1",
+        uuid::Uuid::parse_str(&issue_id).unwrap()
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    (issue_id, issue.slug)
+}
+
+#[tokio::test]
+async fn the_archive_lists_issues_newest_first_without_hidden_ones() {
+    let mut app = spawn_app().await;
+    publish(&app, "First issue", "<p>One</p>", "public").await;
+    publish(&app, "Members issue", "<p>Two</p>", "subscribers_only").await;
+    publish(&app, "Secret issue", "<p>Three</p>", "hidden").await;
+    publish(&app, "Latest issue", "<p>Four</p>", "public").await;
+
+    let response = app.get_archive(&[]).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    let first = html.find("First issue").unwrap();
+    let members = html.find("Members issue").unwrap();
+    let latest = html.find("Latest issue").unwrap();
+    assert!(latest < members && members < first);
+    assert!(html.contains("Subscribers only"));
+    assert!(!html.contains("Secret issue"));
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn the_archive_is_paginated() {
+    let mut app = spawn_app().await;
+    for i in 1..=21 {
+        publish(
+            &app,
+            &format!("Issue number {}", i),
+            "<p>Body</p>",
+            "public",
+        )
+        .await;
+    }
+
+    let first_page = app.get_archive(&[]).await.text().await.unwrap();
+    let second_page = app
+        .get_archive(&[("page", "2")])
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    assert!(first_page.contains("Issue number 21"));
+    assert!(!first_page.contains("Issue number 1<"));
+    assert!(first_page.contains(r#"href="/archive?page=2""#));
+    assert!(second_page.contains("Issue number 1<"));
+    assert!(!second_page.contains("Issue number 2<"));
+    assert!(second_page.contains(r#"href="/archive?page=1""#));
+    assert!(!second_page.contains(r#"href="/archive?page=3""#));
+    assert_eq!(
+        app.get_archive(&[("page", "0")]).await.status().as_u16(),
+        400
+    );
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn issues_with_the_same_title_get_distinct_slugs() {
+    let mut app = spawn_app().await;
+
+    let (_, first) = publish(&app, "Weekly news!", "<p>One</p>", "public").await;
+    let (_, second) = publish(&app, "Weekly news!", "<p>Two</p>", "public").await;
+
+    assert_eq!(first, "weekly-news");
+    assert_eq!(second, "weekly-news-2");
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn archived_issues_render_their_body_without_personal_links() {
+    let mut app = spawn_app().await;
+    let html = r#"<html><body><p>Read <a href="https://example.com/post">the post</a>.</p>
+<p><a href="http://127.0.0.1/subscriptions/unsubscribe?subscription_token=abc">Unsubscribe</a>
+<a href='http://127.0.0.1/preferences?subscription_token=abc'>Preferences</a></p></body></html>"#;
+    let (_, slug) = publish(&app, "Linked issue", html, "public").await;
+
+    let response = app.get_archived_issue(&slug, None).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/html; charset=utf-8"
+    );
+    let page = response.text().await.unwrap();
+    assert!(page.contains("<h1>Linked issue</h1>"));
+    assert!(page.contains(r#"<a href="https://example.com/post">the post</a>"#));
+    assert!(!page.contains("Unsubscribe"));
+    assert!(!page.contains("Preferences"));
+    assert!(!page.contains("subscription_token"));
+    assert_eq!(page.matches("<body>").count(), 1);
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn subscribers_only_issues_need_a_confirmed_subscribers_token() {
+    let mut app = spawn_app().await;
+    let links = create_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+    let (_, slug) = publish(&app, "Members issue", "<p>Members</p>", "subscribers_only").await;
+
+    let anonymous = app.get_archived_issue(&slug, None).await;
+    let pending = app
+        .get_archived_issue(&slug, Some(&links.subscription_token()))
+        .await;
+    reqwest::get(links.html.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let confirmed = app
+        .get_archived_issue(&slug, Some(&links.subscription_token()))
+        .await;
+
+    assert_eq!(anonymous.status().as_u16(), 403);
+    assert_eq!(pending.status().as_u16(), 403);
+    assert_eq!(confirmed.status().as_u16(), 200);
+    assert!(confirmed.text().await.unwrap().contains("<p>Members</p>"));
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn hidden_and_unknown_issues_return_a_404() {
+    let mut app = spawn_app().await;
+    let (_, slug) = publish(&app, "Secret issue", "<p>Secret</p>", "hidden").await;
+
+    for slug in [slug.as_str(), "no-such-issue", "Not%20a%20slug"] {
+        let response = app.get_archived_issue(slug, None).await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            404,
+            "The archive did not return 404 for {}",
+            slug
+        );
+    }
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn changing_an_issues_visibility_updates_the_archive() {
+    let mut app = spawn_app().await;
+    let (issue_id, slug) = publish(&app, "Retracted issue", "<p>Oops</p>", "public").await;
+
+    let response = app
+        .patch_issue(&issue_id, serde_json::json!({ "visibility": "hidden" }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        app.get_archived_issue(&slug, None).await.status().as_u16(),
+        404
+    );
+    let invalid = app
+        .patch_issue(&issue_id, serde_json::json!({ "visibility": "secret" }))
+        .await;
+    assert_eq!(invalid.status().as_u16(), 400);
+    let unknown = app
+        .patch_issue(
+            &uuid::Uuid::new_v4().to_string(),
+            serde_json::json!({ "visibility": "public" }),
+        )
+        .await;
+    assert_eq!(unknown.status().as_u16(), 404);
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn issues_sent_to_a_list_are_left_out_of_the_archive_by_default() {
+    let mut app = spawn_app().await;
+    create_list(&app, "rust").await;
+
+    app.post_newsletters(serde_json::json!({
+        "title": "List issue",
+        "content": { "html": "<p>For the list</p>" },
+        "list": "rust",
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.post_newsletters(serde_json::json!({
+        "title": "Everyone issue",
+        "content": { "html": "<p>For everyone</p>" },
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let html = app.get_archive(&[]).await.text().await.unwrap();
+    assert!(html.contains("Everyone issue"));
+    assert!(!html.contains("List issue"));
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn issues_with_the_same_title_published_at_once_get_distinct_slugs() {
+    let mut app = spawn_app().await;
+    let body = serde_json::json!({
+        "title": "Same title",
+        "content": { "html": "<p>Body</p>" },
+    });
+
+    let responses = tokio::join!(
+        app.post_newsletters(body.clone()),
+        app.post_newsletters(body.clone()),
+        app.post_newsletters(body.clone()),
+        app.post_newsletters(body),
+    );
+
+    for response in [responses.0, responses.1, responses.2, responses.3] {
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    let mut slugs: Vec<String> = sqlx::query!("SELECT slug FROM newsletter_issues")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| row.slug)
+        .collect();
+    slugs.sort();
+    assert_eq!(
+        slugs,
+        vec!["same-title", "same-title-2", "same-title-3", "same-title-4"]
+    );
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn publishing_with_an_invalid_visibility_returns_a_400() {
+    let mut app = spawn_app().await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            },
+            "visibility": "everyone",
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.drop().await;
+}
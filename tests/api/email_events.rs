@@ -0,0 +1,230 @@
+use wiremock::{matchers::any, Mock};
+
+use crate::helpers::{create_confirmed_subscriber, email_sent_response, spawn_app, TestApp};
+
+async fn subscriber_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.")
+        .status
+}
+
+fn hard_bounce() -> serde_json::Value {
+    serde_json::json!({
+        "event": "bounce",
+        "time": 1430812195,
+        "MessageID": 13792286917004336u64,
+        "Message_GUID": "1ab23cd4-e567-8901-2345-6789f0gh1i2j",
+        "email": "ursula_le_guin@gmail.com",
+        "mj_campaign_id": 0,
+        "mj_contact_id": 0,
+        "customcampaign": "",
+        "CustomID": "",
+        "Payload": "",
+        "blocked": false,
+        "hard_bounce": true,
+        "error_related_to": "recipient",
+        "error": "user unknown"
+    })
+}
+
+#[tokio::test]
+async fn requests_without_the_shared_secret_are_rejected() {
+    let mut app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/webhooks/email-events", &app.address))
+        .json(&hard_bounce())
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        response.headers()["WWW-Authenticate"],
+        r#"Basic realm="email-events""#
+    );
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/webhooks/email-events", &app.address))
+        .basic_auth("mailjet", Some("not-the-secret"))
+        .json(&hard_bounce())
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn hard_bounces_stop_newsletters_to_the_subscriber() {
+    let mut app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+
+    let response = app.post_email_events(hard_bounce()).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "bounced");
+
+    let saved = sqlx::query!("SELECT subscriber_id, event_type, payload FROM email_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved email event.");
+    assert!(saved.subscriber_id.is_some());
+    assert_eq!(saved.event_type, "bounce");
+    assert_eq!(saved.payload["error"], "user unknown");
+
+    Mock::given(any())
+        .respond_with(email_sent_response())
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn spam_reports_mark_the_subscriber_as_complained() {
+    let mut app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+
+    let response = app
+        .post_email_events(serde_json::json!({
+            "event": "spam",
+            "time": 1430812195,
+            "MessageID": 13792286917004336u64,
+            "email": "ursula_le_guin@gmail.com",
+            "mj_campaign_id": 1234,
+            "mj_contact_id": 5678,
+            "customcampaign": "",
+            "source": "JMRPP"
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "complained");
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn bounced_and_complaining_addresses_are_not_emailed_when_signed_up_again() {
+    let mut app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+    let response = app.post_email_events(hard_bounce()).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app
+        .post_email_events(serde_json::json!({
+            "event": "spam",
+            "time": 1430812195,
+            "email": "octavia_butler@gmail.com",
+            "source": "JMRPP"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    Mock::given(any())
+        .respond_with(email_sent_response())
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    for email in ["ursula_le_guin%40gmail.com", "octavia_butler%40gmail.com"] {
+        let response = app
+            .post_subscriptions(format!("name=le%20guin&email={}", email))
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    let suppressions =
+        sqlx::query!("SELECT email, reason, source FROM suppressions ORDER BY email")
+            .fetch_all(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(suppressions.len(), 2);
+    assert_eq!(suppressions[0].email, "octavia_butler@gmail.com");
+    assert_eq!(suppressions[0].reason, "Spam complaint");
+    assert_eq!(suppressions[1].email, "ursula_le_guin@gmail.com");
+    assert_eq!(suppressions[1].reason, "Hard bounce");
+    assert_eq!(suppressions[1].source, "mailjet");
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn grouped_events_are_recorded_without_changing_active_subscribers() {
+    let mut app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+
+    let response = app
+        .post_email_events(serde_json::json!([
+            {
+                "event": "bounce",
+                "time": 1430812195,
+                "email": "ursula_le_guin@gmail.com",
+                "hard_bounce": false,
+                "error_related_to": "mailbox",
+                "error": "quota exceeded"
+            },
+            {
+                "event": "bounce",
+                "time": 1430812196,
+                "email": "someone_else@gmail.com",
+                "hard_bounce": true,
+                "error_related_to": "recipient",
+                "error": "user unknown"
+            },
+            {
+                "event": "open",
+                "time": 1430812197,
+                "email": "ursula_le_guin@gmail.com",
+                "ip": "127.0.0.1"
+            }
+        ]))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+    let saved = sqlx::query!("SELECT email, subscriber_id FROM email_events ORDER BY occurred_at")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved email events.");
+    assert_eq!(saved.len(), 2);
+    assert!(saved[0].subscriber_id.is_some());
+    assert_eq!(saved[1].email, "someone_else@gmail.com");
+    assert!(saved[1].subscriber_id.is_none());
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn malformed_events_are_skipped_without_rejecting_the_batch() {
+    let mut app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+
+    let response = app
+        .post_email_events(serde_json::json!([
+            {"event": "bounce", "time": "yesterday"},
+            hard_bounce()
+        ]))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "bounced");
+    let saved = sqlx::query!("SELECT event_type FROM email_events")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved email events.");
+    assert_eq!(saved.len(), 1);
+
+    app.drop().await;
+}
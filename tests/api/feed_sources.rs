@@ -0,0 +1,292 @@
+use wiremock::{
+    matchers::{any, header, method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use crate::helpers::{
+    create_confirmed_subscriber, create_list, email_sent_response, spawn_app, TestApp,
+};
+
+fn rss(items: &[(&str, &str)]) -> String {
+    let items: String = items
+        .iter()
+        .map(|(guid, title)| {
+            format!(
+                "<item><guid>{guid}</guid><title>{title}</title>\
+                 <link>https://blog.example.com/{guid}</link>\
+                 <description>&lt;p&gt;About {title}&lt;/p&gt;</description>\
+                 <pubDate>Fri, 05 Apr 2024 10:00:00 +0000</pubDate></item>"
+            )
+        })
+        .collect();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0"><channel><title>Blog</title><link>https://blog.example.com</link>
+<description>Posts</description>{items}</channel></rss>"#
+    )
+}
+
+/// Serves `body` at `/feed.xml` until the returned guard is dropped.
+async fn serve_feed(feed_server: &MockServer, body: String) -> wiremock::MockGuard {
+    Mock::given(method("GET"))
+        .and(path("/feed.xml"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("Content-Type", "application/rss+xml")
+                .set_body_string(body),
+        )
+        .mount_as_scoped(feed_server)
+        .await
+}
+
+async fn create_feed_source(app: &TestApp, feed_server: &MockServer, mode: &str) {
+    app.post_feed_sources(serde_json::json!({
+        "url": format!("{}/feed.xml", feed_server.uri()),
+        "mode": mode,
+        "subject_template": "New post: {{title}}",
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+}
+
+/// Makes every feed source due again.
+async fn wait_for_next_poll(app: &TestApp) {
+    sqlx::query!("UPDATE feed_sources SET last_polled_at = now() - interval '1 week'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn the_first_poll_only_records_existing_items() {
+    let mut app = spawn_app().await;
+    let feed_server = MockServer::start().await;
+    create_feed_source(&app, &feed_server, "draft").await;
+    let _feed = serve_feed(&feed_server, rss(&[("post-1", "Old post")])).await;
+
+    app.poll_feed_sources().await;
+
+    let drafts: serde_json::Value = app.get_drafts().await.json().await.unwrap();
+    assert_eq!(drafts, serde_json::json!([]));
+    let sources: serde_json::Value = app.get_feed_sources().await.json().await.unwrap();
+    assert!(sources[0]["last_polled_at"].is_string());
+    assert!(sources[0]["last_error"].is_null());
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn new_items_become_drafts_built_from_the_templates() {
+    let mut app = spawn_app().await;
+    let feed_server = MockServer::start().await;
+    create_feed_source(&app, &feed_server, "draft").await;
+    {
+        let _feed = serve_feed(&feed_server, rss(&[("post-1", "Old post")])).await;
+        app.poll_feed_sources().await;
+    }
+
+    let _feed = serve_feed(
+        &feed_server,
+        rss(&[("post-2", "Fish &amp; chips"), ("post-1", "Old post")]),
+    )
+    .await;
+    wait_for_next_poll(&app).await;
+    app.poll_feed_sources().await;
+
+    let drafts: serde_json::Value = app.get_drafts().await.json().await.unwrap();
+    assert_eq!(drafts.as_array().unwrap().len(), 1);
+    let draft = &drafts[0];
+    assert_eq!(draft["title"], "New post: Fish & chips");
+    let html = draft["html_content"].as_str().unwrap();
+    assert!(html.contains("<h1>Fish &amp; chips</h1>"));
+    assert!(html.contains("<p>About Fish & chips</p>"));
+    assert!(html.contains(r#"href="https://blog.example.com/post-2""#));
+    let text = draft["text_content"].as_str().unwrap();
+    assert!(text.contains("About Fish & chips"));
+    assert!(!text.contains("<p>"));
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn publishing_a_draft_sends_it_and_removes_it() {
+    let mut app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+    let feed_server = MockServer::start().await;
+    create_feed_source(&app, &feed_server, "draft").await;
+    {
+        let _feed = serve_feed(&feed_server, rss(&[])).await;
+        app.poll_feed_sources().await;
+    }
+    let _feed = serve_feed(&feed_server, rss(&[("post-1", "Fresh post")])).await;
+    wait_for_next_poll(&app).await;
+    app.poll_feed_sources().await;
+    let drafts: serde_json::Value = app.get_drafts().await.json().await.unwrap();
+    let draft_id = drafts[0]["id"].as_str().unwrap();
+
+    let _mock_guard = Mock::given(any())
+        .respond_with(email_sent_response())
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = app.publish_draft(draft_id).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let drafts: serde_json::Value = app.get_drafts().await.json().await.unwrap();
+    assert_eq!(drafts, serde_json::json!([]));
+    assert_eq!(app.publish_draft(draft_id).await.status().as_u16(), 404);
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn send_mode_publishes_each_new_item_once() {
+    let mut app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+    let feed_server = MockServer::start().await;
+    create_feed_source(&app, &feed_server, "send").await;
+    {
+        let _feed = serve_feed(&feed_server, rss(&[])).await;
+        app.poll_feed_sources().await;
+    }
+    let _feed = serve_feed(&feed_server, rss(&[("post-1", "Fresh post")])).await;
+
+    let _mock_guard = Mock::given(any())
+        .respond_with(email_sent_response())
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    for _ in 0..2 {
+        wait_for_next_poll(&app).await;
+        app.poll_feed_sources().await;
+    }
+    // Polling only queues the issue, so far only the confirmation went out.
+    assert_eq!(app.email_server.received_requests().await.unwrap().len(), 1);
+    app.dispatch_all_pending_emails().await;
+
+    let body = app.sent_emails().await.pop().unwrap();
+    assert_eq!(body["Subject"], "New post: Fresh post");
+    let issue = sqlx::query!("SELECT issue_id FROM feed_source_items WHERE guid = 'post-1'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(issue.issue_id.is_some());
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn feeds_are_fetched_conditionally() {
+    let mut app = spawn_app().await;
+    let feed_server = MockServer::start().await;
+    create_feed_source(&app, &feed_server, "draft").await;
+    {
+        let _feed = Mock::given(path("/feed.xml"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", r#""v1""#)
+                    .set_body_string(rss(&[("post-1", "Old post")])),
+            )
+            .mount_as_scoped(&feed_server)
+            .await;
+        app.poll_feed_sources().await;
+    }
+
+    let _not_modified = Mock::given(path("/feed.xml"))
+        .and(header("If-None-Match", r#""v1""#))
+        .respond_with(ResponseTemplate::new(304))
+        .expect(1)
+        .mount_as_scoped(&feed_server)
+        .await;
+    wait_for_next_poll(&app).await;
+    app.poll_feed_sources().await;
+
+    let sources: serde_json::Value = app.get_feed_sources().await.json().await.unwrap();
+    assert!(sources[0]["last_error"].is_null());
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn failed_polls_are_reported_on_the_source() {
+    let mut app = spawn_app().await;
+    let feed_server = MockServer::start().await;
+    create_feed_source(&app, &feed_server, "draft").await;
+    {
+        let _feed = Mock::given(path("/feed.xml"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("not a feed"))
+            .mount_as_scoped(&feed_server)
+            .await;
+        app.poll_feed_sources().await;
+    }
+
+    let sources: serde_json::Value = app.get_feed_sources().await.json().await.unwrap();
+    assert!(sources[0]["last_error"].is_string());
+
+    // The first successful fetch is still the baseline.
+    let _feed = serve_feed(&feed_server, rss(&[("post-1", "Old post")])).await;
+    wait_for_next_poll(&app).await;
+    app.poll_feed_sources().await;
+    let drafts: serde_json::Value = app.get_drafts().await.json().await.unwrap();
+    assert_eq!(drafts, serde_json::json!([]));
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn creating_a_feed_source_validates_its_settings() {
+    let mut app = spawn_app().await;
+    create_list(&app, "blog").await;
+    let valid = serde_json::json!({
+        "url": "https://blog.example.com/feed.xml",
+        "mode": "send",
+        "list": "blog",
+    });
+    let with = |key: &str, value: serde_json::Value| {
+        let mut body = valid.clone();
+        body[key] = value;
+        body
+    };
+    let test_cases = vec![
+        (
+            with("url", "ftp://blog.example.com/feed".into()),
+            400,
+            "a non-HTTP URL",
+        ),
+        (with("url", "not a url".into()), 400, "an invalid URL"),
+        (with("mode", "publish".into()), 400, "an unknown mode"),
+        (
+            with("html_template", "{{author}}".into()),
+            400,
+            "an unknown placeholder",
+        ),
+        (
+            with("poll_interval_minutes", 1.into()),
+            400,
+            "a too short interval",
+        ),
+        (with("list", "podcast".into()), 404, "an unknown list"),
+    ];
+
+    for (body, status, description) in test_cases {
+        let response = app.post_feed_sources(body).await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            status,
+            "The API did not fail with {} when the payload had {}.",
+            status,
+            description
+        );
+    }
+
+    assert_eq!(
+        app.post_feed_sources(valid.clone()).await.status().as_u16(),
+        200
+    );
+    assert_eq!(app.post_feed_sources(valid).await.status().as_u16(), 409);
+
+    app.drop().await;
+}
@@ -0,0 +1,147 @@
+use crate::helpers::{publish_issue, spawn_app, TestApp};
+
+async fn publish(app: &TestApp, title: &str, visibility: &str) -> String {
+    publish_issue(
+        app,
+        serde_json::json!({
+            "title": title,
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<html><body><p>Body &amp; soul</p></body></html>",
+            },
+            "visibility": visibility,
+        }),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn the_rss_feed_contains_public_issues_only() {
+    let mut app = spawn_app().await;
+    let issue_id = publish(&app, "Public <issue>", "public").await;
+    publish(&app, "Members issue", "subscribers_only").await;
+    publish(&app, "Secret issue", "hidden").await;
+
+    let response = app.get_feed("/feed.rss", &[]).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/rss+xml; charset=utf-8"
+    );
+    let feed = response.text().await.unwrap();
+    assert!(feed.starts_with(r#"<?xml version="1.0" encoding="UTF-8"?>"#));
+    assert!(feed.contains("<title>Public &lt;issue&gt;</title>"));
+    assert!(feed.contains(&format!(
+        r#"<guid isPermaLink="false">urn:uuid:{}</guid>"#,
+        issue_id
+    )));
+    assert!(feed.contains("/archive/public-issue</link>"));
+    assert!(feed.contains("<pubDate>"));
+    assert!(
+        feed.contains("<content:encoded>&lt;p&gt;Body &amp;amp; soul&lt;/p&gt;</content:encoded>")
+    );
+    assert!(!feed.contains("Members issue"));
+    assert!(!feed.contains("Secret issue"));
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn the_atom_feed_contains_public_issues_only() {
+    let mut app = spawn_app().await;
+    let issue_id = publish(&app, "Public issue", "public").await;
+    publish(&app, "Secret issue", "hidden").await;
+
+    let response = app.get_feed("/feed.atom", &[]).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/atom+xml; charset=utf-8"
+    );
+    let feed = response.text().await.unwrap();
+    assert!(feed.contains(r#"<feed xmlns="http://www.w3.org/2005/Atom">"#));
+    assert!(feed.contains(&format!("<id>urn:uuid:{}</id>", issue_id)));
+    assert!(feed.contains("<published>"));
+    assert!(
+        feed.contains(r#"<content type="html">&lt;p&gt;Body &amp;amp; soul&lt;/p&gt;</content>"#)
+    );
+    assert!(!feed.contains("Secret issue"));
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn feeds_without_issues_are_still_valid() {
+    let mut app = spawn_app().await;
+
+    for path in ["/feed.rss", "/feed.atom"] {
+        let response = app.get_feed(path, &[]).await;
+
+        assert_eq!(response.status().as_u16(), 200);
+        assert!(response.headers().get("Last-Modified").is_none());
+        assert!(response
+            .text()
+            .await
+            .unwrap()
+            .contains("Newsletter archive"));
+    }
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn unchanged_feeds_answer_conditional_requests_with_a_304() {
+    let mut app = spawn_app().await;
+    publish(&app, "Public issue", "public").await;
+
+    for path in ["/feed.rss", "/feed.atom"] {
+        let response = app.get_feed(path, &[]).await;
+        let etag = response.headers()["ETag"].to_str().unwrap().to_string();
+        let last_modified = response.headers()["Last-Modified"]
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let by_etag = app.get_feed(path, &[("If-None-Match", &etag)]).await;
+        let by_date = app
+            .get_feed(path, &[("If-Modified-Since", &last_modified)])
+            .await;
+
+        assert_eq!(by_etag.status().as_u16(), 304);
+        assert_eq!(by_etag.headers()["ETag"].to_str().unwrap(), etag);
+        assert!(by_etag.text().await.unwrap().is_empty());
+        assert_eq!(by_date.status().as_u16(), 304);
+    }
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn feeds_change_their_etag_when_an_issue_is_published_or_hidden() {
+    let mut app = spawn_app().await;
+    let issue_id = publish(&app, "First issue", "public").await;
+    let etag =
+        |response: &reqwest::Response| response.headers()["ETag"].to_str().unwrap().to_string();
+    let first = etag(&app.get_feed("/feed.rss", &[]).await);
+
+    publish(&app, "Second issue", "public").await;
+    let second = app
+        .get_feed("/feed.rss", &[("If-None-Match", &first)])
+        .await;
+    assert_eq!(second.status().as_u16(), 200);
+    let second = etag(&second);
+
+    app.patch_issue(&issue_id, serde_json::json!({ "visibility": "hidden" }))
+        .await
+        .error_for_status()
+        .unwrap();
+    let third = app
+        .get_feed("/feed.rss", &[("If-None-Match", &second)])
+        .await;
+    assert_eq!(third.status().as_u16(), 200);
+    assert!(!third.text().await.unwrap().contains("First issue"));
+
+    app.drop().await;
+}
@@ -1,3 +1,6 @@
+use wiremock::{matchers::any, Mock, ResponseTemplate};
+use zero2prod::domain::SubscriberEmail;
+
 use crate::helpers::spawn_app;
 
 #[tokio::test]
@@ -15,7 +18,38 @@ async fn health_check_works() {
 
     // Assert
     assert!(response.status().is_success());
-    assert_eq!(Some(0), response.content_length());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["email_providers"][0]["name"], "mailjet");
+    assert_eq!(body["email_providers"][0]["circuit"], "closed");
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn health_check_reports_circuits_opened_by_the_background_workers() {
+    let mut app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    // The client the workers send through, the API shares it.
+    let recipient = SubscriberEmail::parse("ursula_le_guin@gmail.com".into()).unwrap();
+    for _ in 0..5 {
+        let outcome = app
+            .email_client
+            .send_email(&recipient, "Subject", "<p>Body</p>", "Body")
+            .await;
+        assert!(outcome.is_err());
+    }
+
+    let body: serde_json::Value = reqwest::get(format!("{}/health_check", app.address))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["email_providers"][0]["circuit"], "open");
 
     app.drop().await;
 }
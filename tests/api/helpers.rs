@@ -1,10 +1,21 @@
+use std::sync::Arc;
+
 use once_cell::sync::Lazy;
-use secrecy::ExposeSecret;
+use secrecy::{ExposeSecret, Secret};
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use uuid::Uuid;
-use wiremock::MockServer;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, Request, Respond, ResponseTemplate,
+};
 use zero2prod::{
     configuration::{get_configuration, DatabaseSettings},
+    email_client::EmailClient,
+    feed_poller::try_poll_feed_source,
+    issue_delivery_worker::{
+        try_decide_ab_test, try_execute_task, try_send_digest, ExecutionOutcome, IssueSender,
+    },
+    sequence_worker::try_send_sequence_step,
     startup::{get_connection_pool, Application},
     telemetry::{get_subscriber, init_subscriber},
 };
@@ -26,6 +37,16 @@ pub struct ConfirmationLinks {
     pub plain_text: reqwest::Url,
 }
 
+impl ConfirmationLinks {
+    pub fn subscription_token(&self) -> String {
+        self.html
+            .query_pairs()
+            .find(|(key, _)| key == "subscription_token")
+            .map(|(_, value)| value.into_owned())
+            .unwrap()
+    }
+}
+
 pub struct TestApp {
     pub address: String,
     pub port: u16,
@@ -33,6 +54,10 @@ pub struct TestApp {
     pub db_name: String,
     pub connection_string: String,
     pub email_server: MockServer,
+    pub webhook_secret: String,
+    pub email_client: Arc<EmailClient>,
+    pub base_url: String,
+    pub hmac_secret: Secret<String>,
 }
 
 impl TestApp {
@@ -49,6 +74,71 @@ impl TestApp {
         tracing::info!("Dropped test database");
     }
 
+    pub async fn decide_due_ab_tests(&self) {
+        while let ExecutionOutcome::TaskCompleted = try_decide_ab_test(&self.db_pool).await.unwrap()
+        {
+        }
+    }
+
+    /// Every message the email server received, one per email even when they were
+    /// sent in batches.
+    pub async fn sent_emails(&self) -> Vec<serde_json::Value> {
+        self.email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .flat_map(|request| {
+                let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+                match body["Messages"].as_array() {
+                    Some(messages) => messages.clone(),
+                    None => vec![body],
+                }
+            })
+            .collect()
+    }
+
+    pub async fn dispatch_all_pending_emails(&self) {
+        let sender = IssueSender {
+            pool: &self.db_pool,
+            email_client: &self.email_client,
+            base_url: &self.base_url,
+            hmac_secret: &self.hmac_secret,
+        };
+        while let ExecutionOutcome::TaskCompleted = try_execute_task(&sender).await.unwrap() {}
+    }
+
+    pub async fn send_due_digests(&self) {
+        let sender = IssueSender {
+            pool: &self.db_pool,
+            email_client: &self.email_client,
+            base_url: &self.base_url,
+            hmac_secret: &self.hmac_secret,
+        };
+        while let ExecutionOutcome::TaskCompleted = try_send_digest(&sender).await.unwrap() {}
+    }
+
+    pub async fn poll_feed_sources(&self) {
+        let sender = IssueSender {
+            pool: &self.db_pool,
+            email_client: &self.email_client,
+            base_url: &self.base_url,
+            hmac_secret: &self.hmac_secret,
+        };
+        let http_client = reqwest::Client::new();
+        while let ExecutionOutcome::TaskCompleted =
+            try_poll_feed_source(&sender, &http_client).await.unwrap()
+        {}
+    }
+
+    pub async fn send_due_sequence_steps(&self) {
+        while let ExecutionOutcome::TaskCompleted =
+            try_send_sequence_step(&self.db_pool, &self.email_client)
+                .await
+                .unwrap()
+        {}
+    }
+
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         reqwest::Client::new()
             .post(&format!("{}/subscriptions", &self.address))
@@ -68,6 +158,301 @@ impl TestApp {
             .expect("Failed to execute request")
     }
 
+    pub async fn patch_issue(&self, issue_id: &str, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .patch(format!("{}/admin/issues/{}", self.address, issue_id))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_archive(&self, query: &[(&str, &str)]) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/archive", self.address))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_archived_issue(
+        &self,
+        slug: &str,
+        subscription_token: Option<&str>,
+    ) -> reqwest::Response {
+        let mut query = vec![];
+        if let Some(subscription_token) = subscription_token {
+            query.push(("subscription_token", subscription_token));
+        }
+
+        reqwest::Client::new()
+            .get(format!("{}/archive/{}", self.address, slug))
+            .query(&query)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_feed(&self, path: &str, headers: &[(&str, &str)]) -> reqwest::Response {
+        let mut request = reqwest::Client::new().get(format!("{}{}", self.address, path));
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+
+        request.send().await.expect("Failed to execute request")
+    }
+
+    pub async fn get_issue_stats(&self, issue_id: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/admin/issues/{}/stats", self.address, issue_id))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_issue_links(&self, issue_id: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/admin/issues/{}/links", self.address, issue_id))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_feed_sources(&self, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/admin/feed_sources", self.address))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_feed_sources(&self) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/admin/feed_sources", self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_drafts(&self) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/admin/drafts", self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn publish_draft(&self, draft_id: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!(
+                "{}/admin/drafts/{}/publish",
+                self.address, draft_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_sequences(&self, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/admin/sequences", self.address))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_sequences(&self) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/admin/sequences", self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn patch_sequence(
+        &self,
+        sequence_id: &str,
+        body: serde_json::Value,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .patch(format!("{}/admin/sequences/{}", self.address, sequence_id))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_subscriber_sequences(&self, subscriber_id: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!(
+                "{}/admin/subscribers/{}/sequences",
+                self.address, subscriber_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_lists(&self, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/admin/lists", self.address))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn patch_list(&self, slug: &str, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .patch(format!("{}/admin/lists/{}", self.address, slug))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_open_pixel(&self, tracking_token: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/t/o/{}.gif", self.address, tracking_token))
+            .header("User-Agent", "Mozilla/5.0 (test mail client)")
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_unsubscribe(
+        &self,
+        subscription_token: &str,
+        list: Option<&str>,
+    ) -> reqwest::Response {
+        let mut query = vec![("subscription_token", subscription_token)];
+        if let Some(list) = list {
+            query.push(("list", list));
+        }
+
+        reqwest::Client::new()
+            .get(format!("{}/subscriptions/unsubscribe", self.address))
+            .query(&query)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_email_events(&self, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/webhooks/email-events", &self.address))
+            .basic_auth("mailjet", Some(&self.webhook_secret))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_suppressions(&self, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/admin/suppressions", &self.address))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_suppressions(&self) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/admin/suppressions", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn delete_suppression(&self, email: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .delete(format!("{}/admin/suppressions/{}", &self.address, email))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_preferences(&self, body: String) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/preferences", &self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_custom_fields(&self, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/admin/custom_fields", self.address))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_subscribers(&self, query: &[(&str, &str)]) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/admin/subscribers", self.address))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn patch_subscriber(
+        &self,
+        subscriber_id: &str,
+        body: serde_json::Value,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .patch(format!(
+                "{}/admin/subscribers/{}",
+                self.address, subscriber_id
+            ))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_subscribers_import(
+        &self,
+        query: &[(&str, &str)],
+        csv: &str,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/admin/subscribers/import", self.address))
+            .query(query)
+            .header("Content-Type", "text/csv")
+            .body(csv.to_string())
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_segments(&self, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/admin/segments", self.address))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_segments_dry_run(&self, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/admin/segments/dry_run", self.address))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
     pub fn get_confirmation_link(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
 
@@ -94,6 +479,92 @@ impl TestApp {
     }
 }
 
+pub async fn create_list(app: &TestApp, slug: &str) {
+    app.post_lists(serde_json::json!({ "slug": slug, "name": slug }))
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+/// A successful Mailjet send response carrying a fresh message id.
+/// Accepts every message of the request, whether it is a single email or a batch.
+pub struct EmailSentResponse;
+
+impl Respond for EmailSentResponse {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap_or_default();
+        let n_messages = body["Messages"].as_array().map_or(1, Vec::len);
+        let messages: Vec<_> = (0..n_messages)
+            .map(|_| {
+                let message_id = rand::random::<u32>() as u64;
+                serde_json::json!({
+                    "Status": "success",
+                    "To": [{
+                        "Email": "recipient@example.com",
+                        "MessageUUID": Uuid::new_v4(),
+                        "MessageID": message_id,
+                        "MessageHref": format!("https://api.mailjet.com/v3/message/{}", message_id)
+                    }]
+                })
+            })
+            .collect();
+        ResponseTemplate::new(200).set_body_json(serde_json::json!({ "Messages": messages }))
+    }
+}
+
+pub fn email_sent_response() -> EmailSentResponse {
+    EmailSentResponse
+}
+
+pub async fn create_subscriber(app: &TestApp, email: &str, lists: &str) -> ConfirmationLinks {
+    let body = format!("name=le%20guin&email={}&lists={}", email, lists);
+
+    let _mock_guard = Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+
+    app.get_confirmation_link(email_request)
+}
+
+/// Creates a subscriber and confirms them through the link they were emailed.
+pub async fn create_confirmed_subscriber(
+    app: &TestApp,
+    email: &str,
+    lists: &str,
+) -> ConfirmationLinks {
+    let links = create_subscriber(app, email, lists).await;
+    reqwest::get(links.html.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    links
+}
+
+/// Publishes an issue and returns its id.
+pub async fn publish_issue(app: &TestApp, body: serde_json::Value) -> String {
+    let response = app.post_newsletters(body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["issue_id"].as_str().unwrap().to_string()
+}
+
 pub async fn spawn_app() -> TestApp {
     Lazy::force(&TRACING);
 
@@ -110,7 +581,8 @@ pub async fn spawn_app() -> TestApp {
 
     configure_database(&configuration.database).await;
 
-    let application = Application::build(configuration.clone())
+    let email_client = Arc::new(configuration.email_client.clone().client());
+    let application = Application::build(configuration.clone(), email_client.clone())
         .await
         .expect("Failed to build application");
     let port = application.port();
@@ -132,6 +604,14 @@ pub async fn spawn_app() -> TestApp {
         db_name,
         connection_string,
         email_server,
+        webhook_secret: configuration
+            .email_client
+            .webhook_secret
+            .expose_secret()
+            .clone(),
+        email_client,
+        base_url: configuration.application.base_url.clone(),
+        hmac_secret: configuration.application.hmac_secret.clone(),
     };
 }
 
@@ -0,0 +1,199 @@
+use wiremock::{matchers::any, Mock, ResponseTemplate};
+
+use crate::helpers::{
+    create_confirmed_subscriber, email_sent_response, publish_issue, spawn_app, TestApp,
+};
+
+async fn publish(app: &TestApp) -> String {
+    publish_issue(
+        app,
+        serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }),
+    )
+    .await
+}
+
+async fn stats(app: &TestApp, issue_id: &str) -> serde_json::Value {
+    app.get_issue_stats(issue_id)
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn every_recipient_gets_a_delivery_with_the_provider_message_id() {
+    let mut app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+    create_confirmed_subscriber(&app, "octavia_butler%40gmail.com", "").await;
+
+    // Both recipients go out in a single batch.
+    Mock::given(any())
+        .respond_with(email_sent_response())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let issue_id = publish(&app).await;
+
+    let stats = stats(&app, &issue_id).await;
+    assert_eq!(stats["title"], "Newsletter title");
+    assert_eq!(stats["recipients"], 2);
+    assert_eq!(stats["sent"], 2);
+    assert_eq!(stats["failed"], 0);
+    assert_eq!(stats["bounced"], 0);
+
+    let deliveries = sqlx::query!("SELECT provider, provider_message_id, error FROM deliveries")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch deliveries.");
+    assert!(deliveries
+        .iter()
+        .all(|d| d.provider.as_deref() == Some("mailjet")
+            && d.provider_message_id.is_some()
+            && d.error.is_none()));
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn rate_limited_recipients_are_retried_by_the_background_worker() {
+    let mut app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "60"))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(any())
+        .respond_with(email_sent_response())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let issue_id = publish(&app).await;
+    assert_eq!(stats(&app, &issue_id).await["sent"], 0);
+    // Not before the provider's Retry-After.
+    app.dispatch_all_pending_emails().await;
+    assert_eq!(stats(&app, &issue_id).await["sent"], 0);
+
+    sqlx::query!("UPDATE issue_delivery_queue SET execute_after = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let stats = stats(&app, &issue_id).await;
+    assert_eq!(stats["sent"], 1);
+    assert_eq!(stats["failed"], 0);
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn failed_recipients_are_recorded_and_do_not_stop_the_issue() {
+    let mut app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+    create_confirmed_subscriber(&app, "octavia_butler%40gmail.com", "").await;
+
+    // The provider rejects the first message of the batch and sends the second.
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+            "Messages": [
+                {
+                    "Status": "error",
+                    "Errors": [{
+                        "ErrorCode": "mj-0013",
+                        "ErrorMessage": "\"ursula_le_guin@gmail.com\" is an invalid email address.",
+                        "ErrorRelatedTo": ["To[0].Email"]
+                    }]
+                },
+                {
+                    "Status": "success",
+                    "To": [{ "Email": "octavia_butler@gmail.com", "MessageID": 1 }]
+                }
+            ]
+        })))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let issue_id = publish(&app).await;
+
+    let stats = stats(&app, &issue_id).await;
+    assert_eq!(stats["recipients"], 2);
+    assert_eq!(stats["sent"], 1);
+    assert_eq!(stats["failed"], 1);
+
+    let failed =
+        sqlx::query!("SELECT provider_message_id, error FROM deliveries WHERE status = 'failed'")
+            .fetch_one(&app.db_pool)
+            .await
+            .expect("Failed to fetch the failed delivery.");
+    assert!(failed.provider_message_id.is_none());
+    assert!(failed.error.is_some());
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn bounce_events_mark_the_matching_delivery_as_bounced() {
+    let mut app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+
+    Mock::given(any())
+        .respond_with(email_sent_response())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let issue_id = publish(&app).await;
+    let message_id = sqlx::query!("SELECT provider_message_id FROM deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the delivery.")
+        .provider_message_id
+        .unwrap();
+
+    app.post_email_events(serde_json::json!({
+        "event": "bounce",
+        "time": 1430812195,
+        "MessageID": message_id.parse::<u64>().unwrap(),
+        "email": "ursula_le_guin@gmail.com",
+        "hard_bounce": false,
+        "error_related_to": "mailbox",
+        "error": "quota exceeded"
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let stats = stats(&app, &issue_id).await;
+    assert_eq!(stats["recipients"], 1);
+    assert_eq!(stats["sent"], 0);
+    assert_eq!(stats["bounced"], 1);
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn stats_for_an_unknown_issue_return_a_404() {
+    let mut app = spawn_app().await;
+
+    let response = app
+        .get_issue_stats("4b2bd5e6-0a6d-4a8e-9a3b-5f0c9a6f3f2e")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 404);
+
+    app.drop().await;
+}
@@ -0,0 +1,178 @@
+use wiremock::{
+    matchers::{any, method, path},
+    Mock,
+};
+
+use crate::helpers::{create_list, create_subscriber, email_sent_response, spawn_app};
+
+fn newsletter_for(list: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "list": list,
+        "content": {
+            "html": "<p>Newsletter body as HTML</p>",
+            "text": "Newsletter body as plain text",
+        }
+    })
+}
+
+#[tokio::test]
+async fn creating_a_list_twice_returns_a_409() {
+    let mut app = spawn_app().await;
+
+    create_list(&app, "rust").await;
+    let response = app
+        .post_lists(serde_json::json!({ "slug": "rust", "name": "Rust again" }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 409);
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn subscribe_to_lists_persists_pending_memberships() {
+    let mut app = spawn_app().await;
+    create_list(&app, "rust").await;
+    create_list(&app, "go").await;
+
+    create_subscriber(&app, "ursula_le_guin%40gmail.com", "rust,go").await;
+
+    let memberships = sqlx::query!("SELECT status FROM list_memberships")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch list memberships.");
+
+    assert_eq!(memberships.len(), 2);
+    assert!(memberships
+        .iter()
+        .all(|m| m.status == "pending_confirmation"));
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn subscribe_to_an_unknown_list_returns_a_400() {
+    let mut app = spawn_app().await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com&lists=nope".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn newsletters_for_a_list_are_only_delivered_to_its_confirmed_members() {
+    let mut app = spawn_app().await;
+    create_list(&app, "rust").await;
+    create_list(&app, "go").await;
+
+    let rust_links = create_subscriber(&app, "rustacean%40gmail.com", "rust").await;
+    let go_links = create_subscriber(&app, "gopher%40gmail.com", "go").await;
+    for links in [rust_links, go_links] {
+        reqwest::get(links.html)
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(newsletter_for("rust")).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn newsletters_for_an_unknown_list_return_a_404() {
+    let mut app = spawn_app().await;
+
+    let response = app.post_newsletters(newsletter_for("nope")).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn unsubscribed_list_members_do_not_receive_newsletters_for_that_list() {
+    let mut app = spawn_app().await;
+    create_list(&app, "rust").await;
+    create_list(&app, "go").await;
+
+    let links = create_subscriber(&app, "ursula_le_guin%40gmail.com", "rust,go").await;
+    let token = links.subscription_token();
+    reqwest::get(links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    app.get_unsubscribe(&token, Some("rust"))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(any())
+        .respond_with(email_sent_response())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(newsletter_for("rust")).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_newsletters(newsletter_for("go")).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn unsubscribing_from_everything_stops_all_newsletters() {
+    let mut app = spawn_app().await;
+
+    let links = create_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+    let token = links.subscription_token();
+    reqwest::get(links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    app.get_unsubscribe(&token, None)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(any())
+        .respond_with(email_sent_response())
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "html": "<p>Newsletter body as HTML</p>",
+                "text": "Newsletter body as plain text",
+            }
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.drop().await;
+}
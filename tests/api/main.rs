@@ -1,5 +1,18 @@
+mod ab_tests;
+mod archive;
+mod email_events;
+mod feed_sources;
+mod feeds;
 mod health_check;
 mod helpers;
+mod issues;
+mod lists;
+mod newsletter;
+mod preferences;
+mod segments;
+mod sequences;
+mod subscribers;
 mod subscriptions;
 mod subscriptions_confirm;
-mod newsletter;
+mod suppressions;
+mod tracking;
@@ -1,9 +1,11 @@
 use wiremock::{
     matchers::{any, method, path},
-    Mock, ResponseTemplate,
+    Mock,
 };
 
-use crate::helpers::{spawn_app, ConfirmationLinks, TestApp};
+use crate::helpers::{
+    create_subscriber, email_sent_response, spawn_app, ConfirmationLinks, TestApp,
+};
 
 #[tokio::test]
 async fn newsletters_are_not_delivered_to_unsubscribed_subscribers() {
@@ -12,7 +14,7 @@ async fn newsletters_are_not_delivered_to_unsubscribed_subscribers() {
     create_unconfirmed_subscriber(&app).await;
 
     Mock::given(any())
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(email_sent_response())
         .expect(0)
         .mount(&app.email_server)
         .await;
@@ -39,7 +41,7 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
 
     Mock::given(path("/v3.1/send"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(email_sent_response())
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -59,12 +61,202 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     app.drop().await;
 }
 
+#[tokio::test]
+async fn newsletters_are_sent_from_the_chosen_sender_identity() {
+    let mut app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .mount(&app.email_server)
+        .await;
+    let issue = |sender: Option<&str>| {
+        serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "html": "<p>Newsletter body as HTML</p>",
+                "text": "Newsletter body as plain text",
+            },
+            "sender": sender,
+        })
+    };
+    let last_sender = || async {
+        let emails = app.sent_emails().await;
+        emails.last().unwrap()["From"]["Email"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    };
+    // Confirmation emails are transactional.
+    assert_eq!(last_sender().await, "noreply@gmail.com");
+
+    let response = app.post_newsletters(issue(None)).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(last_sender().await, "editor@gmail.com");
+
+    let response = app.post_newsletters(issue(Some("noreply"))).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(last_sender().await, "noreply@gmail.com");
+
+    let response = app.post_newsletters(issue(Some("marketing"))).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn newsletter_html_is_sanitised_and_the_text_generated_when_missing() {
+    let mut app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "html": "<style>p { color: red; }</style>\
+                    <p onclick=\"steal()\">Newsletter body<script>steal()</script></p>",
+            }
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let emails = app.sent_emails().await;
+    let body = emails.last().unwrap();
+    let html = body["HtmlPart"].as_str().unwrap();
+    assert!(
+        html.starts_with(r#"<p style="color:red">Newsletter body</p>"#),
+        "{}",
+        html
+    );
+    assert!(!html.contains("steal"), "{}", html);
+    assert_eq!(body["TextPart"].as_str().unwrap().trim(), "Newsletter body");
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn subscribers_receive_the_translation_in_their_language() {
+    let mut app = spawn_app().await;
+    for email in ["ursula_le_guin%40gmail.com", "octavia_butler%40gmail.com"] {
+        let links = create_subscriber(&app, email, "").await;
+        reqwest::get(links.html)
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+    sqlx::query!("UPDATE subscriptions SET locale = 'fr' WHERE email = 'octavia_butler@gmail.com'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let already_sent = app.sent_emails().await.len();
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": { "html": "<p>Newsletter body</p>" },
+            "translations": {
+                "fr-FR": {
+                    "title": "Titre de la newsletter",
+                    "content": { "html": "<p>Corps de la newsletter</p>" },
+                },
+                "de": {
+                    "title": "Titel des Newsletters",
+                    "content": { "html": "<p>Inhalt des Newsletters</p>" },
+                },
+            },
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let mut sent: Vec<_> = app
+        .sent_emails()
+        .await
+        .iter()
+        .skip(already_sent)
+        .map(|body| {
+            (
+                body["To"][0]["Email"].as_str().unwrap().to_string(),
+                body["Subject"].as_str().unwrap().to_string(),
+                body["TextPart"].as_str().unwrap().trim().to_string(),
+            )
+        })
+        .collect();
+    sent.sort();
+    assert_eq!(
+        sent,
+        vec![
+            (
+                "octavia_butler@gmail.com".to_string(),
+                "Titre de la newsletter".to_string(),
+                "Corps de la newsletter".to_string()
+            ),
+            (
+                "ursula_le_guin@gmail.com".to_string(),
+                "Newsletter title".to_string(),
+                "Newsletter body".to_string()
+            ),
+        ]
+    );
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn an_issue_whose_translations_cannot_be_stored_is_not_published() {
+    let mut app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!("ALTER TABLE issue_translations DROP COLUMN title;")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    Mock::given(any())
+        .respond_with(email_sent_response())
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": { "html": "<p>Newsletter body</p>" },
+            "translations": {
+                "fr": {
+                    "title": "Titre de la newsletter",
+                    "content": { "html": "<p>Corps de la newsletter</p>" },
+                },
+            },
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 500);
+    let issues = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, 0);
+
+    app.drop().await;
+}
+
 async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
 
     let _mock_guard = Mock::given(path("/v3.1/send"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(email_sent_response())
         .named("Create unconfirmed subscriber")
         .expect(1)
         .mount_as_scoped(&app.email_server)
@@ -111,6 +303,57 @@ async fn newsletters_returns_400_for_invalid_data() {
             "missing title",
         ),
         (serde_json::json!({"title":"Newsletter"}), "missing content"),
+        (
+            serde_json::json!({
+                "title": "Newsletter",
+                "content": { "html": "<script>steal()</script>" }
+            }),
+            "no HTML left once sanitised",
+        ),
+        (
+            serde_json::json!({
+                "title": "Newsletter",
+                "content": { "html": "<style>p { color: red</style><p>Hi</p>" }
+            }),
+            "an unbalanced stylesheet",
+        ),
+        (
+            serde_json::json!({
+                "title": "Newsletter",
+                "content": { "html": "<p>Hi</p>" },
+                "translations": {
+                    "tlh": { "title": "Newsletter", "content": { "html": "<p>nuqneH</p>" } }
+                }
+            }),
+            "a translation to an unsupported language",
+        ),
+        (
+            serde_json::json!({
+                "title": "Newsletter",
+                "content": { "html": "<p>Hi</p>" },
+                "translations": {
+                    "fr": { "title": "Newsletter", "content": { "html": "<p>Salut</p>" } },
+                    "fr-CA": { "title": "Newsletter", "content": { "html": "<p>Allô</p>" } }
+                }
+            }),
+            "two translations to the same language",
+        ),
+        (
+            serde_json::json!({
+                "title": "Newsletter",
+                "content": { "html": "<p>Hi</p>" },
+                "ab_test": {
+                    "subjects": ["Newsletter", "Read this"],
+                    "sample_percentage": 20,
+                    "wait_minutes": 60,
+                    "metric": "clicks"
+                },
+                "translations": {
+                    "fr": { "title": "Newsletter", "content": { "html": "<p>Salut</p>" } }
+                }
+            }),
+            "an A/B test and translations",
+        ),
     ];
 
     for (invalid_body, error_message) in test_cases {
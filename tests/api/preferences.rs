@@ -0,0 +1,365 @@
+use wiremock::{
+    matchers::{any, body_string_contains, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{
+    create_confirmed_subscriber, create_list, create_subscriber, email_sent_response, spawn_app,
+    TestApp,
+};
+
+#[tokio::test]
+async fn preferences_with_an_invalid_token_are_rejected_with_a_404() {
+    let mut app = spawn_app().await;
+
+    let response = reqwest::get(format!(
+        "{}/preferences?subscription_token=not-a-token",
+        app.address
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status().as_u16(), 404);
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn the_preferences_page_shows_the_current_preferences() {
+    let mut app = spawn_app().await;
+    create_list(&app, "rust").await;
+    let links = create_subscriber(&app, "ursula_le_guin%40gmail.com", "rust").await;
+
+    let response = reqwest::get(format!(
+        "{}/preferences?subscription_token={}",
+        app.address,
+        links.subscription_token()
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let page = response.text().await.unwrap();
+    assert!(page.contains(r#"value="le guin""#));
+    assert!(page.contains(r#"value="rust" checked"#));
+    assert!(page.contains(r#"value="immediate" selected"#));
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn updating_preferences_persists_and_records_the_changes() {
+    let mut app = spawn_app().await;
+    create_list(&app, "rust").await;
+    create_list(&app, "go").await;
+    let links = create_subscriber(&app, "ursula_le_guin%40gmail.com", "rust").await;
+    reqwest::get(links.html.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let body = format!(
+        "subscription_token={}&name=Ursula&lists=go&digest_frequency=weekly",
+        links.subscription_token()
+    );
+    let response = app.post_preferences(body).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved =
+        sqlx::query!("SELECT name, digest_frequency, preferences_updated_at FROM subscriptions")
+            .fetch_one(&app.db_pool)
+            .await
+            .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.name, "Ursula");
+    assert_eq!(saved.digest_frequency, "weekly");
+    assert!(saved.preferences_updated_at.is_some());
+
+    let memberships = sqlx::query!(
+        "SELECT l.slug, m.status FROM list_memberships m JOIN lists l ON l.id = m.list_id ORDER BY l.slug"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .expect("Failed to fetch list memberships.");
+    let memberships: Vec<_> = memberships
+        .into_iter()
+        .map(|m| (m.slug, m.status))
+        .collect();
+    assert_eq!(
+        memberships,
+        vec![
+            ("go".to_string(), "confirmed".to_string()),
+            ("rust".to_string(), "unsubscribed".to_string())
+        ]
+    );
+
+    let changes = sqlx::query!("SELECT field FROM preference_changes ORDER BY field")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch preference changes.");
+    let changes: Vec<_> = changes.into_iter().map(|c| c.field).collect();
+    assert_eq!(
+        changes,
+        vec!["digest_frequency", "list:go", "list:rust", "name"]
+    );
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn updating_preferences_with_invalid_data_returns_a_400() {
+    let mut app = spawn_app().await;
+    let links = create_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+    let token = links.subscription_token();
+
+    let test_cases = vec![
+        (
+            format!("subscription_token={}&name=&digest_frequency=daily", token),
+            "empty name",
+        ),
+        (
+            format!(
+                "subscription_token={}&name=%3Cscript%3E&digest_frequency=daily",
+                token
+            ),
+            "invalid name",
+        ),
+        (
+            format!(
+                "subscription_token={}&name=Ursula&digest_frequency=hourly",
+                token
+            ),
+            "invalid digest frequency",
+        ),
+        (
+            format!(
+                "subscription_token={}&name=Ursula&digest_frequency=daily&lists=nope",
+                token
+            ),
+            "unknown list",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        let response = app.post_preferences(body).await;
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not return a 400 BAD REQUEST when the payload had an {}.",
+            description
+        );
+    }
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn unsubscribing_from_the_preferences_page_unsubscribes_the_subscriber() {
+    let mut app = spawn_app().await;
+    let links = create_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+
+    let body = format!(
+        "subscription_token={}&name=le%20guin&digest_frequency=immediate&unsubscribe=true",
+        links.subscription_token()
+    );
+    app.post_preferences(body).await.error_for_status().unwrap();
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "unsubscribed");
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn unconfirmed_subscribers_cannot_join_lists_from_the_preferences_page() {
+    let mut app = spawn_app().await;
+    create_list(&app, "rust").await;
+    let links = create_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+
+    let body = format!(
+        "subscription_token={}&name=le%20guin&lists=rust&digest_frequency=immediate",
+        links.subscription_token()
+    );
+    let response = app.post_preferences(body).await;
+
+    assert_eq!(response.status().as_u16(), 409);
+    let memberships = sqlx::query!("SELECT status FROM list_memberships")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch list memberships.");
+    assert!(memberships.is_empty());
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn digest_subscribers_get_their_issues_together_once_the_digest_is_due() {
+    let mut app = spawn_app().await;
+    let links = create_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+    reqwest::get(links.html.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let body = format!(
+        "subscription_token={}&name=le%20guin&digest_frequency=weekly",
+        links.subscription_token()
+    );
+    app.post_preferences(body).await.error_for_status().unwrap();
+
+    for title in ["First issue", "Second issue"] {
+        app.post_newsletters(serde_json::json!({
+            "title": title,
+            "content": { "html": format!("<p>{} body</p>", title) },
+        }))
+        .await
+        .error_for_status()
+        .unwrap();
+    }
+    app.dispatch_all_pending_emails().await;
+    app.send_due_digests().await;
+    // Only the confirmation was sent so far.
+    assert_eq!(app.email_server.received_requests().await.unwrap().len(), 1);
+
+    sqlx::query!("UPDATE digest_items SET enqueued_at = now() - interval '8 days'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.send_due_digests().await;
+
+    let digest = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let digest: serde_json::Value = serde_json::from_slice(&digest.body).unwrap();
+    assert_eq!(digest["Subject"], "First issue | Second issue");
+    let html = digest["HtmlPart"].as_str().unwrap();
+    assert!(html.find("First issue body").unwrap() < html.find("Second issue body").unwrap());
+    let deliveries = sqlx::query!("SELECT status FROM deliveries")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(deliveries.len(), 2);
+
+    app.drop().await;
+}
+
+async fn create_digest_subscriber(app: &TestApp, email: &str) {
+    let links = create_confirmed_subscriber(app, email, "").await;
+    let body = format!(
+        "subscription_token={}&name=le%20guin&digest_frequency=weekly",
+        links.subscription_token()
+    );
+    app.post_preferences(body).await.error_for_status().unwrap();
+}
+
+/// Publishes an issue and makes the digests holding it due.
+async fn publish_digest_issue(app: &TestApp) {
+    app.post_newsletters(serde_json::json!({
+        "title": "First issue",
+        "content": { "html": "<p>First issue body</p>" },
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    sqlx::query!("UPDATE digest_items SET enqueued_at = now() - interval '8 days'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn digests_that_cannot_be_sent_yet_wait_for_the_retry_after() {
+    let mut app = spawn_app().await;
+    create_digest_subscriber(&app, "octavia_butler%40gmail.com").await;
+    create_digest_subscriber(&app, "ursula_le_guin%40gmail.com").await;
+    publish_digest_issue(&app).await;
+    // The oldest digest goes first, before the provider asks us to slow down.
+    sqlx::query!(
+        r#"UPDATE digest_items SET enqueued_at = enqueued_at - interval '1 hour'
+           WHERE subscriber_id = (
+               SELECT id FROM subscriptions WHERE email = 'octavia_butler@gmail.com'
+           )"#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    Mock::given(body_string_contains("ursula_le_guin@gmail.com"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "60"))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(any())
+        .respond_with(email_sent_response())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.send_due_digests().await;
+
+    let deliveries = sqlx::query!(
+        "SELECT s.email FROM deliveries d JOIN subscriptions s ON s.id = d.subscriber_id"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].email, "octavia_butler@gmail.com");
+    let held = sqlx::query!(
+        r#"SELECT n_retries, execute_after > now() + interval '50 seconds' AS "waits!"
+           FROM digest_items"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(held.n_retries, 1);
+    assert!(held.waits);
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn digests_out_of_retries_are_recorded_as_failed_and_dropped() {
+    let mut app = spawn_app().await;
+    create_digest_subscriber(&app, "ursula_le_guin%40gmail.com").await;
+    publish_digest_issue(&app).await;
+    sqlx::query!("UPDATE digest_items SET n_retries = 5")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "60"))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.send_due_digests().await;
+
+    let deliveries = sqlx::query!("SELECT status FROM deliveries")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].status, "failed");
+    let held = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM digest_items"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(held.count, 0);
+
+    app.drop().await;
+}
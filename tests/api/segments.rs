@@ -0,0 +1,200 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock,
+};
+
+use crate::helpers::{create_subscriber, email_sent_response, spawn_app, TestApp};
+
+/// Creates a confirmed subscriber carrying the given tags and returns their id.
+async fn create_tagged_subscriber(app: &TestApp, email: &str, tags: &[&str]) -> String {
+    let links = create_subscriber(app, email, "").await;
+    reqwest::get(links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let email = email.replace("%40", "@");
+    let subscribers: Vec<serde_json::Value> = app
+        .get_subscribers(&[("limit", "1000")])
+        .await
+        .json()
+        .await
+        .unwrap();
+    let id = subscribers
+        .iter()
+        .find(|s| s["email"] == serde_json::json!(email))
+        .unwrap()["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    app.patch_subscriber(&id, serde_json::json!({ "tags": tags }))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    id
+}
+
+async fn create_segment(app: &TestApp, expression: &str) -> String {
+    let segment: serde_json::Value = app
+        .post_segments(serde_json::json!({ "name": expression, "expression": expression }))
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    segment["id"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn invalid_segments_are_rejected() {
+    let mut app = spawn_app().await;
+    create_segment(&app, r#"tag = "vip""#).await;
+
+    let test_cases = vec![
+        (r#"tag = "vip" and"#, 400, "a malformed expression"),
+        ("field.unknown = 1", 400, "an unknown custom field"),
+        (r#"status = "sleeping""#, 400, "an unknown status"),
+        (r#"tag = "vip""#, 409, "a duplicate name"),
+    ];
+
+    for (expression, status, description) in test_cases {
+        let response = app
+            .post_segments(serde_json::json!({ "name": expression, "expression": expression }))
+            .await;
+
+        assert_eq!(
+            status,
+            response.status().as_u16(),
+            "The API did not return {} for {}.",
+            status,
+            description
+        );
+    }
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn dry_runs_return_the_matching_count_and_a_sample() {
+    let mut app = spawn_app().await;
+    create_tagged_subscriber(&app, "ursula%40example.com", &["vip", "beta"]).await;
+    create_tagged_subscriber(&app, "octavia%40example.com", &["vip"]).await;
+    create_tagged_subscriber(&app, "ada%40example.com", &[]).await;
+
+    let report: serde_json::Value = app
+        .post_segments_dry_run(serde_json::json!({
+            "expression": r#"tag = "vip" and not tag = "beta""#
+        }))
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(report["count"], serde_json::json!(1));
+    assert_eq!(report["confirmed_count"], serde_json::json!(1));
+    assert_eq!(
+        report["sample"][0]["email"],
+        serde_json::json!("octavia@example.com")
+    );
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn dry_runs_can_use_a_saved_segment() {
+    let mut app = spawn_app().await;
+    let id = create_tagged_subscriber(&app, "ursula%40example.com", &[]).await;
+    create_tagged_subscriber(&app, "octavia%40example.com", &[]).await;
+    sqlx::query!(
+        "UPDATE subscriptions SET last_engaged_at = now() WHERE id = $1",
+        uuid::Uuid::parse_str(&id).unwrap()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let segment_id = create_segment(&app, "not engaged_within 30").await;
+
+    let report: serde_json::Value = app
+        .post_segments_dry_run(serde_json::json!({ "segment_id": segment_id }))
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(report["count"], serde_json::json!(1));
+    assert_eq!(
+        report["sample"][0]["email"],
+        serde_json::json!("octavia@example.com")
+    );
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn newsletters_for_a_segment_are_only_delivered_to_its_members() {
+    let mut app = spawn_app().await;
+    create_tagged_subscriber(&app, "ursula%40example.com", &["vip"]).await;
+    create_tagged_subscriber(&app, "octavia%40example.com", &["vip"]).await;
+    create_tagged_subscriber(&app, "ada%40example.com", &[]).await;
+    let segment_id = create_segment(&app, r#"tag = "vip""#).await;
+
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "segment_id": segment_id,
+            "content": {
+                "html": "<p>Newsletter body as HTML</p>",
+                "text": "Newsletter body as plain text",
+            }
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let recipients: Vec<_> = app
+        .sent_emails()
+        .await
+        .iter()
+        .filter(|email| email["Subject"] == "Newsletter title")
+        .map(|email| email["To"][0]["Email"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(recipients.len(), 2);
+    assert!(!recipients.contains(&"ada@example.com".to_string()));
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn newsletters_for_an_unknown_segment_return_a_404() {
+    let mut app = spawn_app().await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "segment_id": uuid::Uuid::new_v4(),
+            "content": {
+                "html": "<p>Newsletter body as HTML</p>",
+                "text": "Newsletter body as plain text",
+            }
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 404);
+
+    app.drop().await;
+}
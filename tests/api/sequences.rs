@@ -0,0 +1,382 @@
+use wiremock::{matchers::any, Mock};
+
+use crate::helpers::{
+    create_confirmed_subscriber, create_list, create_subscriber, email_sent_response, spawn_app,
+    TestApp,
+};
+
+fn welcome_sequence(list: Option<&str>) -> serde_json::Value {
+    let step = |delay_minutes: u32, subject: &str| {
+        serde_json::json!({
+            "delay_minutes": delay_minutes,
+            "subject": subject,
+            "html": format!("<p>{}</p>", subject),
+            "text": subject,
+        })
+    };
+    serde_json::json!({
+        "name": "Welcome",
+        "list": list,
+        "steps": [
+            step(0, "Welcome"),
+            step(3 * 24 * 60, "Tips"),
+            step(14 * 24 * 60, "Survey"),
+        ],
+    })
+}
+
+async fn create_sequence(app: &TestApp, body: serde_json::Value) -> String {
+    let response = app.post_sequences(body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["sequence_id"].as_str().unwrap().to_string()
+}
+
+/// Subscribes and confirms, returning the subscriber's id and token.
+async fn create_enrolled_subscriber(app: &TestApp, lists: &str) -> (String, String) {
+    let links = create_confirmed_subscriber(app, "ursula_le_guin%40gmail.com", lists).await;
+    let subscriber = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    (subscriber.id.to_string(), links.subscription_token())
+}
+
+/// Makes every pending step due now.
+async fn skip_ahead(app: &TestApp) {
+    sqlx::query!(
+        "UPDATE automation_enrolments SET next_send_at = now() WHERE next_send_at IS NOT NULL"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+/// The subjects of the emails sent after the first `skip` requests.
+async fn sent_subjects(app: &TestApp, skip: usize) -> Vec<String> {
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .skip(skip)
+        .map(|request| {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            body["Subject"].as_str().unwrap().to_string()
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_receive_each_step_in_order() {
+    let mut app = spawn_app().await;
+    create_sequence(&app, welcome_sequence(None)).await;
+    let (subscriber_id, _) = create_enrolled_subscriber(&app, "").await;
+    Mock::given(any())
+        .respond_with(email_sent_response())
+        .mount(&app.email_server)
+        .await;
+    let already_sent = app.email_server.received_requests().await.unwrap().len();
+
+    app.send_due_sequence_steps().await;
+    assert_eq!(sent_subjects(&app, already_sent).await, vec!["Welcome"]);
+    // The tips are not due for another three days.
+    app.send_due_sequence_steps().await;
+    assert_eq!(sent_subjects(&app, already_sent).await.len(), 1);
+    let progress: serde_json::Value = app
+        .get_subscriber_sequences(&subscriber_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(progress[0]["steps_sent"], 1);
+    assert_eq!(progress[0]["total_steps"], 3);
+    assert!(progress[0]["next_send_at"].is_string());
+
+    for _ in 0..2 {
+        skip_ahead(&app).await;
+        app.send_due_sequence_steps().await;
+    }
+
+    assert_eq!(
+        sent_subjects(&app, already_sent).await,
+        vec!["Welcome", "Tips", "Survey"]
+    );
+    let progress: serde_json::Value = app
+        .get_subscriber_sequences(&subscriber_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(progress[0]["steps_sent"], 3);
+    assert!(progress[0]["completed_at"].is_string());
+    assert!(progress[0]["next_send_at"].is_null());
+    let sequences: serde_json::Value = app.get_sequences().await.json().await.unwrap();
+    assert_eq!(sequences[0]["completed"], 1);
+    assert_eq!(sequences[0]["steps"][1]["sent"], 1);
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn pending_subscribers_are_not_enrolled() {
+    let mut app = spawn_app().await;
+    create_sequence(&app, welcome_sequence(None)).await;
+    create_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+
+    let enrolments = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM automation_enrolments"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    assert_eq!(enrolments.count, 0);
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn subscribers_imported_as_confirmed_are_enrolled() {
+    let mut app = spawn_app().await;
+    create_sequence(&app, welcome_sequence(None)).await;
+
+    app.post_subscribers_import(
+        &[("confirmed", "true")],
+        "email,name\nursula@example.com,Ursula\n",
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    app.post_subscribers_import(&[], "email,name\noctavia@example.com,Octavia\n")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let enrolled = sqlx::query!(
+        "SELECT s.email FROM automation_enrolments e JOIN subscriptions s ON s.id = e.subscriber_id"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(enrolled.len(), 1);
+    assert_eq!(enrolled[0].email, "ursula@example.com");
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn unsubscribing_exits_the_sequence() {
+    let mut app = spawn_app().await;
+    create_sequence(&app, welcome_sequence(None)).await;
+    let (subscriber_id, token) = create_enrolled_subscriber(&app, "").await;
+    Mock::given(any())
+        .respond_with(email_sent_response())
+        .mount(&app.email_server)
+        .await;
+    app.send_due_sequence_steps().await;
+
+    app.get_unsubscribe(&token, None)
+        .await
+        .error_for_status()
+        .unwrap();
+    // Leaving is confirmed by email.
+    let already_sent = app.email_server.received_requests().await.unwrap().len();
+    skip_ahead(&app).await;
+    app.send_due_sequence_steps().await;
+
+    assert!(sent_subjects(&app, already_sent).await.is_empty());
+    let progress: serde_json::Value = app
+        .get_subscriber_sequences(&subscriber_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(progress[0]["exit_reason"], "unsubscribed");
+    assert!(progress[0]["exited_at"].is_string());
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn subscribers_who_can_no_longer_be_mailed_are_exited_at_send_time() {
+    let mut app = spawn_app().await;
+    create_sequence(&app, welcome_sequence(None)).await;
+    let (subscriber_id, _) = create_enrolled_subscriber(&app, "").await;
+    let already_sent = app.email_server.received_requests().await.unwrap().len();
+
+    app.post_suppressions(serde_json::json!({
+        "email": "ursula_le_guin@gmail.com",
+        "reason": "Asked to stop all email",
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.send_due_sequence_steps().await;
+
+    assert!(sent_subjects(&app, already_sent).await.is_empty());
+    let progress: serde_json::Value = app
+        .get_subscriber_sequences(&subscriber_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(progress[0]["exit_reason"], "suppressed");
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn list_sequences_only_enrol_and_keep_members_of_that_list() {
+    let mut app = spawn_app().await;
+    create_list(&app, "rust").await;
+    create_list(&app, "go").await;
+    create_sequence(&app, welcome_sequence(Some("rust"))).await;
+    let mut go_sequence = welcome_sequence(Some("go"));
+    go_sequence["name"] = "Go welcome".into();
+    create_sequence(&app, go_sequence).await;
+    let (subscriber_id, token) = create_enrolled_subscriber(&app, "rust").await;
+
+    let progress: serde_json::Value = app
+        .get_subscriber_sequences(&subscriber_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(progress.as_array().unwrap().len(), 1);
+    assert_eq!(progress[0]["name"], "Welcome");
+
+    app.get_unsubscribe(&token, Some("rust"))
+        .await
+        .error_for_status()
+        .unwrap();
+    let progress: serde_json::Value = app
+        .get_subscriber_sequences(&subscriber_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(progress[0]["exit_reason"], "unsubscribed");
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn joining_a_list_from_the_preferences_page_enrols_in_its_sequences() {
+    let mut app = spawn_app().await;
+    create_list(&app, "rust").await;
+    create_sequence(&app, welcome_sequence(Some("rust"))).await;
+    let (subscriber_id, token) = create_enrolled_subscriber(&app, "").await;
+
+    let body = format!(
+        "subscription_token={}&name=le%20guin&lists=rust&digest_frequency=immediate",
+        token
+    );
+    app.post_preferences(body).await.error_for_status().unwrap();
+
+    let progress: serde_json::Value = app
+        .get_subscriber_sequences(&subscriber_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(progress.as_array().unwrap().len(), 1);
+    assert_eq!(progress[0]["name"], "Welcome");
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn paused_sequences_enrol_nobody_and_hold_back_their_steps() {
+    let mut app = spawn_app().await;
+    let sequence_id = create_sequence(&app, welcome_sequence(None)).await;
+    let (subscriber_id, _) = create_enrolled_subscriber(&app, "").await;
+    let already_sent = app.email_server.received_requests().await.unwrap().len();
+
+    let response = app
+        .patch_sequence(&sequence_id, serde_json::json!({ "active": false }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.send_due_sequence_steps().await;
+    assert!(sent_subjects(&app, already_sent).await.is_empty());
+
+    Mock::given(any())
+        .respond_with(email_sent_response())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.patch_sequence(&sequence_id, serde_json::json!({ "active": true }))
+        .await
+        .error_for_status()
+        .unwrap();
+    app.send_due_sequence_steps().await;
+    let progress: serde_json::Value = app
+        .get_subscriber_sequences(&subscriber_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(progress[0]["steps_sent"], 1);
+    let unknown = app
+        .patch_sequence(
+            &uuid::Uuid::new_v4().to_string(),
+            serde_json::json!({ "active": true }),
+        )
+        .await;
+    assert_eq!(unknown.status().as_u16(), 404);
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn creating_a_sequence_validates_its_steps() {
+    let mut app = spawn_app().await;
+    let with = |key: &str, value: serde_json::Value| {
+        let mut body = welcome_sequence(None);
+        body[key] = value;
+        body
+    };
+    let test_cases = vec![
+        (with("name", " ".into()), 400, "no name"),
+        (with("steps", serde_json::json!([])), 400, "no steps"),
+        (
+            with(
+                "steps",
+                serde_json::json!([
+                    { "delay_minutes": 60, "subject": "Later", "html": "<p>Later</p>", "text": "Later" },
+                    { "delay_minutes": 0, "subject": "Sooner", "html": "<p>Sooner</p>", "text": "Sooner" },
+                ]),
+            ),
+            400,
+            "decreasing delays",
+        ),
+        (with("list", "podcast".into()), 404, "an unknown list"),
+    ];
+
+    for (body, status, description) in test_cases {
+        let response = app.post_sequences(body).await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            status,
+            "The API did not fail with {} when the payload had {}.",
+            status,
+            description
+        );
+    }
+
+    assert_eq!(
+        app.post_sequences(welcome_sequence(None))
+            .await
+            .status()
+            .as_u16(),
+        200
+    );
+    assert_eq!(
+        app.post_sequences(welcome_sequence(None))
+            .await
+            .status()
+            .as_u16(),
+        409
+    );
+
+    app.drop().await;
+}
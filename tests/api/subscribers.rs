@@ -0,0 +1,343 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock,
+};
+
+use crate::helpers::{create_subscriber, email_sent_response, spawn_app, TestApp};
+
+async fn create_custom_field(app: &TestApp, key: &str, field_type: &str, public: bool) {
+    app.post_custom_fields(serde_json::json!({
+        "key": key,
+        "field_type": field_type,
+        "public": public,
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+}
+
+async fn list_subscribers(app: &TestApp, query: &[(&str, &str)]) -> Vec<serde_json::Value> {
+    app.get_subscribers(query)
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn custom_field_definitions_are_validated() {
+    let mut app = spawn_app().await;
+    create_custom_field(&app, "company", "string", false).await;
+
+    let test_cases = vec![
+        (
+            serde_json::json!({"key": "Company Name", "field_type": "string"}),
+            400,
+            "invalid key",
+        ),
+        (
+            serde_json::json!({"key": "seats", "field_type": "integer"}),
+            400,
+            "invalid type",
+        ),
+        (
+            serde_json::json!({"key": "company", "field_type": "string"}),
+            409,
+            "duplicate key",
+        ),
+    ];
+
+    for (body, status, description) in test_cases {
+        let response = app.post_custom_fields(body).await;
+        assert_eq!(
+            status,
+            response.status().as_u16(),
+            "The API did not return {} for an {}.",
+            status,
+            description
+        );
+    }
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn public_custom_fields_can_be_set_on_signup() {
+    let mut app = spawn_app().await;
+    create_custom_field(&app, "signup_source", "string", true).await;
+
+    create_subscriber(
+        &app,
+        "ursula_le_guin%40gmail.com&field.signup_source=homepage",
+        "",
+    )
+    .await;
+
+    let subscribers = list_subscribers(&app, &[("field.signup_source", "homepage")]).await;
+    assert_eq!(subscribers.len(), 1);
+    assert_eq!(
+        subscribers[0]["custom_fields"]["signup_source"],
+        serde_json::json!("homepage")
+    );
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn signing_up_a_known_address_again_does_not_change_its_details() {
+    let mut app = spawn_app().await;
+    create_custom_field(&app, "signup_source", "string", true).await;
+    create_custom_field(&app, "referrer", "string", true).await;
+    create_subscriber(
+        &app,
+        "ursula_le_guin%40gmail.com&field.signup_source=homepage&locale=fr",
+        "",
+    )
+    .await;
+
+    create_subscriber(
+        &app,
+        "ursula_le_guin%40gmail.com&field.signup_source=attacker&field.referrer=blog&locale=de",
+        "",
+    )
+    .await;
+
+    let subscribers = list_subscribers(&app, &[]).await;
+    assert_eq!(subscribers.len(), 1);
+    assert_eq!(
+        subscribers[0]["custom_fields"],
+        serde_json::json!({ "signup_source": "homepage", "referrer": "blog" })
+    );
+    let saved = sqlx::query!("SELECT locale FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.locale, "fr");
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn private_unknown_or_invalid_custom_fields_are_rejected_on_signup() {
+    let mut app = spawn_app().await;
+    create_custom_field(&app, "plan", "string", false).await;
+    create_custom_field(&app, "seats", "number", true).await;
+
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let test_cases = vec![
+        ("field.plan=enterprise", "private field"),
+        ("field.nope=1", "unknown field"),
+        ("field.seats=many", "invalid number"),
+    ];
+
+    for (field, description) in test_cases {
+        let body = format!("name=le%20guin&email=ursula_le_guin%40gmail.com&{}", field);
+        let response = app.post_subscriptions(body).await;
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not return a 400 BAD REQUEST for a {}.",
+            description
+        );
+    }
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn admins_can_tag_subscribers_and_filter_listings() {
+    let mut app = spawn_app().await;
+    create_custom_field(&app, "seats", "number", false).await;
+    create_custom_field(&app, "renewal", "date", false).await;
+    create_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+    create_subscriber(&app, "octavia_butler%40gmail.com", "").await;
+
+    let subscribers = list_subscribers(&app, &[]).await;
+    assert_eq!(subscribers.len(), 2);
+    let id = subscribers[0]["id"].as_str().unwrap();
+
+    app.patch_subscriber(
+        id,
+        serde_json::json!({
+            "tags": ["VIP", "beta"],
+            "custom_fields": {"seats": 12, "renewal": "2025-01-31"}
+        }),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let tagged = list_subscribers(&app, &[("tag", "vip"), ("tag", "beta")]).await;
+    assert_eq!(tagged.len(), 1);
+    assert_eq!(tagged[0]["id"], serde_json::json!(id));
+    assert_eq!(tagged[0]["tags"], serde_json::json!(["beta", "vip"]));
+    assert_eq!(tagged[0]["custom_fields"]["seats"], serde_json::json!(12.0));
+    assert_eq!(
+        tagged[0]["custom_fields"]["renewal"],
+        serde_json::json!("2025-01-31")
+    );
+
+    let by_field = list_subscribers(&app, &[("field.seats", "12")]).await;
+    assert_eq!(by_field.len(), 1);
+
+    app.patch_subscriber(id, serde_json::json!({"custom_fields": {"seats": null}}))
+        .await
+        .error_for_status()
+        .unwrap();
+    let by_field = list_subscribers(&app, &[("field.seats", "12")]).await;
+    assert!(by_field.is_empty());
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn invalid_subscriber_updates_are_rejected() {
+    let mut app = spawn_app().await;
+    create_custom_field(&app, "seats", "number", false).await;
+    create_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+    let subscribers = list_subscribers(&app, &[]).await;
+    let id = subscribers[0]["id"].as_str().unwrap();
+
+    let response = app
+        .patch_subscriber(id, serde_json::json!({"custom_fields": {"seats": "lots"}}))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app
+        .patch_subscriber(
+            id,
+            serde_json::json!({"custom_fields": {"company": "Acme"}}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app
+        .patch_subscriber(
+            &uuid::Uuid::new_v4().to_string(),
+            serde_json::json!({"tags": ["vip"]}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn csv_imports_store_valid_rows_and_report_invalid_ones() {
+    let mut app = spawn_app().await;
+    create_custom_field(&app, "company", "string", false).await;
+    create_custom_field(&app, "trial", "boolean", false).await;
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let csv = "email,name,tags,company,trial\n\
+               ursula@example.com,Ursula,vip;beta,Acme,yes\n\
+               not-an-email,Nobody,,,\n\
+               octavia@example.com,Octavia,,Initech,maybe\n\
+               ada@example.com,Ada,,,\n";
+    let report: serde_json::Value = app
+        .post_subscribers_import(&[], csv)
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(report["imported"], serde_json::json!(2));
+    let failed_lines: Vec<_> = report["failed"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|f| f["line"].as_u64().unwrap())
+        .collect();
+    assert_eq!(failed_lines, vec![3, 4]);
+
+    let imported = list_subscribers(&app, &[("tag", "vip"), ("field.trial", "true")]).await;
+    assert_eq!(imported.len(), 1);
+    assert_eq!(
+        imported[0]["email"],
+        serde_json::json!("ursula@example.com")
+    );
+    assert_eq!(
+        imported[0]["status"],
+        serde_json::json!("pending_confirmation")
+    );
+    assert_eq!(report["unconfirmed"], serde_json::json!([]));
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn csv_imports_marked_as_confirmed_skip_the_confirmation_email() {
+    let mut app = spawn_app().await;
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscribers_import(
+        &[("confirmed", "true")],
+        "email,name\nursula@example.com,Ursula\n",
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let imported = list_subscribers(&app, &[]).await;
+    assert_eq!(imported[0]["status"], serde_json::json!("confirmed"));
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn csv_imports_keep_the_status_of_known_subscribers() {
+    let mut app = spawn_app().await;
+    let links = create_subscriber(&app, "ursula%40example.com", "").await;
+    reqwest::get(links.html.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    app.post_subscribers_import(&[], "email,name\nursula@example.com,Ursula\n")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let imported = list_subscribers(&app, &[]).await;
+    assert_eq!(imported[0]["status"], serde_json::json!("confirmed"));
+    assert_eq!(imported[0]["name"], serde_json::json!("Ursula"));
+    // Only the confirmation for the original signup was sent.
+    assert_eq!(app.email_server.received_requests().await.unwrap().len(), 1);
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn csv_imports_with_unknown_columns_are_rejected() {
+    let mut app = spawn_app().await;
+
+    let response = app
+        .post_subscribers_import(&[], "email,name,shoe_size\nursula@example.com,Ursula,9\n")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.drop().await;
+}
@@ -3,7 +3,7 @@ use wiremock::{
     Mock, ResponseTemplate,
 };
 
-use crate::helpers::spawn_app;
+use crate::helpers::{create_list, create_subscriber, email_sent_response, spawn_app, TestApp};
 
 #[tokio::test]
 async fn subscribe_returns_a_200_for_valid_form_data() {
@@ -15,7 +15,7 @@ async fn subscribe_returns_a_200_for_valid_form_data() {
 
     Mock::given(path("/v3.1/send"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(email_sent_response())
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -35,7 +35,7 @@ async fn subscribe_persists_the_new_subscriber() {
 
     Mock::given(path("/v3.1/send"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(email_sent_response())
         .mount(&app.email_server)
         .await;
 
@@ -111,7 +111,7 @@ async fn subscribe_sends_a_confirmation_email_for_valid_data() {
 
     Mock::given(path("/v3.1/send"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(email_sent_response())
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -130,7 +130,7 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
 
     Mock::given(path("/v3.1/send"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(email_sent_response())
         .mount(&app.email_server)
         .await;
 
@@ -160,3 +160,248 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
 
     app.drop().await;
 }
+
+#[tokio::test]
+async fn subscribe_returns_a_503_with_retry_after_when_the_provider_rate_limits() {
+    let mut app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "30"))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(response.status().as_u16(), 503);
+    assert_eq!(response.headers()["Retry-After"], "30");
+    assert_eq!(
+        response.text().await.unwrap(),
+        "The confirmation email could not be sent right now."
+    );
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_400_when_the_provider_rejects_the_address() {
+    let mut app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+            "Messages": [{
+                "Status": "error",
+                "Errors": [{
+                    "ErrorCode": "send-0003",
+                    "StatusCode": 400,
+                    "ErrorMessage": "The recipient address is invalid.",
+                    "ErrorRelatedTo": ["To[0].Email"],
+                }],
+            }]
+        })))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(!response.headers().contains_key("Retry-After"));
+    assert!(!response.text().await.unwrap().is_empty());
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn subscribe_fails_fast_once_the_provider_keeps_failing() {
+    let mut app = spawn_app().await;
+
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(5)
+        .mount(&app.email_server)
+        .await;
+
+    for i in 0..5 {
+        let body = format!("name=le%20guin&email=ursula_le_guin_{}%40gmail.com", i);
+        app.post_subscriptions(body).await;
+    }
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 503);
+    assert!(response.headers().contains_key("Retry-After"));
+    let health: serde_json::Value = reqwest::get(format!("{}/health_check", app.address))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(health["email_providers"][0]["circuit"], "open");
+
+    app.drop().await;
+}
+
+/// The subject of the last email sent.
+async fn last_subject(app: &TestApp) -> String {
+    let requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&requests.last().unwrap().body).unwrap();
+    body["Subject"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn system_emails_are_sent_in_the_subscribers_language() {
+    let mut app = spawn_app().await;
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .mount(&app.email_server)
+        .await;
+    let subscribe = |body: &'static str, accept_language: &'static str| {
+        reqwest::Client::new()
+            .post(format!("{}/subscriptions", &app.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("Accept-Language", accept_language)
+            .body(body)
+            .send()
+    };
+
+    subscribe(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com",
+        "pt-BR, fr-CA;q=0.8, en;q=0.5",
+    )
+    .await
+    .unwrap()
+    .error_for_status()
+    .unwrap();
+    assert_eq!(last_subject(&app).await, "Bienvenue !");
+    // The form wins over the browser.
+    subscribe(
+        "name=butler&email=octavia_butler%40gmail.com&locale=de",
+        "fr",
+    )
+    .await
+    .unwrap()
+    .error_for_status()
+    .unwrap();
+    assert_eq!(last_subject(&app).await, "Willkommen!");
+    // An unsupported locale is ignored.
+    subscribe(
+        "name=delany&email=samuel_delany%40gmail.com&locale=klingon",
+        "fr",
+    )
+    .await
+    .unwrap()
+    .error_for_status()
+    .unwrap();
+    assert_eq!(last_subject(&app).await, "Bienvenue !");
+
+    let locales: Vec<_> = sqlx::query!("SELECT locale FROM subscriptions ORDER BY email DESC")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| row.locale)
+        .collect();
+    assert_eq!(locales, vec!["fr", "fr", "de"]);
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_are_told_they_are_already_subscribed() {
+    let mut app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&locale=es";
+    let links = create_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+    reqwest::get(links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&requests.last().unwrap().body).unwrap();
+    assert_eq!(body["Subject"], "Ya estás suscrito");
+    assert!(body["TextPart"]
+        .as_str()
+        .unwrap()
+        .contains("/preferences?subscription_token="));
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_with_pending_lists_are_asked_to_confirm_again() {
+    let mut app = spawn_app().await;
+    create_list(&app, "rust").await;
+    let links = create_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+    reqwest::get(links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // The second signup finds the rust membership still pending.
+    for _ in 0..2 {
+        app.post_subscriptions(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&lists=rust&locale=es".into(),
+        )
+        .await
+        .error_for_status()
+        .unwrap();
+        assert_eq!(last_subject(&app).await, "¡Bienvenido!");
+    }
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn unsubscribing_from_everything_is_confirmed_by_email() {
+    let mut app = spawn_app().await;
+    let links = create_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+    let token = links.subscription_token();
+    sqlx::query!("UPDATE subscriptions SET locale = 'fr'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    for _ in 0..2 {
+        app.get_unsubscribe(&token, None)
+            .await
+            .error_for_status()
+            .unwrap();
+    }
+
+    assert_eq!(last_subject(&app).await, "Votre désabonnement est confirmé");
+
+    app.drop().await;
+}
@@ -1,7 +1,7 @@
 use wiremock::matchers::{method, path};
-use wiremock::{Mock, ResponseTemplate};
+use wiremock::Mock;
 
-use crate::helpers::spawn_app;
+use crate::helpers::{create_subscriber, email_sent_response, spawn_app};
 
 #[tokio::test]
 async fn confirmations_without_token_are_rejected_with_a_400() {
@@ -25,7 +25,7 @@ async fn the_link_returned_by_subscribe_returns_a_200_if_called() {
 
     Mock::given(path("/v3.1/send"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(email_sent_response())
         .mount(&app.email_server)
         .await;
 
@@ -48,7 +48,7 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
 
     Mock::given(path("/v3.1/send"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(email_sent_response())
         .mount(&app.email_server)
         .await;
 
@@ -82,7 +82,7 @@ async fn subscribing_fatal_fails_returns_a_500() {
 
     Mock::given(path("/v3.1/send"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(email_sent_response())
         .mount(&app.email_server)
         .await;
 
@@ -102,3 +102,75 @@ async fn subscribing_fatal_fails_returns_a_500() {
 
     app.drop().await;
 }
+
+#[tokio::test]
+async fn confirming_after_unsubscribing_returns_a_409() {
+    let mut app = spawn_app().await;
+    let links = create_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+    let token = links.subscription_token();
+
+    app.get_unsubscribe(&token, None)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let response = reqwest::get(links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 409);
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "unsubscribed");
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn subscribing_again_after_unsubscribing_requires_a_new_confirmation() {
+    let mut app = spawn_app().await;
+    let links = create_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+    reqwest::get(links.html.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.get_unsubscribe(&links.subscription_token(), None)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let new_links = create_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "pending_confirmation");
+
+    reqwest::get(new_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "confirmed");
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn the_database_rejects_unknown_statuses() {
+    let mut app = spawn_app().await;
+    create_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+
+    let result = sqlx::query!("UPDATE subscriptions SET status = 'sleeping'")
+        .execute(&app.db_pool)
+        .await;
+    assert!(result.is_err());
+
+    app.drop().await;
+}
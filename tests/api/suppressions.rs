@@ -0,0 +1,169 @@
+use wiremock::{
+    matchers::{any, method, path},
+    Mock,
+};
+
+use crate::helpers::{create_subscriber, email_sent_response, spawn_app};
+
+fn suppression(email: &str) -> serde_json::Value {
+    serde_json::json!({
+        "email": email,
+        "reason": "Legal request",
+        "source": "support"
+    })
+}
+
+#[tokio::test]
+async fn suppressions_can_be_created_listed_and_lifted() {
+    let mut app = spawn_app().await;
+
+    let response = app
+        .post_suppressions(suppression("Ursula@Example.com"))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_suppressions(serde_json::json!({
+            "email": "postmaster@example.com",
+            "reason": "Role account"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let suppressions: serde_json::Value = app.get_suppressions().await.json().await.unwrap();
+    assert_eq!(suppressions.as_array().unwrap().len(), 2);
+    assert_eq!(suppressions[0]["email"], "postmaster@example.com");
+    assert_eq!(suppressions[0]["source"], "admin");
+    assert_eq!(suppressions[1]["email"], "ursula@example.com");
+    assert_eq!(suppressions[1]["reason"], "Legal request");
+    assert_eq!(suppressions[1]["source"], "support");
+
+    let response = app.delete_suppression("URSULA@example.com").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let suppressions: serde_json::Value = app.get_suppressions().await.json().await.unwrap();
+    assert_eq!(suppressions.as_array().unwrap().len(), 1);
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn suppressing_an_address_twice_returns_a_409() {
+    let mut app = spawn_app().await;
+
+    app.post_suppressions(suppression("ursula@example.com"))
+        .await
+        .error_for_status()
+        .unwrap();
+    let response = app
+        .post_suppressions(suppression("URSULA@example.com"))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 409);
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn invalid_suppressions_are_rejected_with_a_400() {
+    let mut app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({"email": "not-an-email", "reason": "Trap"}),
+            "invalid email",
+        ),
+        (
+            serde_json::json!({"email": "ursula@example.com", "reason": " "}),
+            "empty reason",
+        ),
+        (
+            serde_json::json!({"email": "ursula@example.com", "reason": "Trap", "source": ""}),
+            "empty source",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        let response = app.post_suppressions(body).await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not reject a suppression with an {}.",
+            description
+        );
+    }
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn lifting_an_unknown_suppression_returns_a_404() {
+    let mut app = spawn_app().await;
+
+    let response = app.delete_suppression("ursula@example.com").await;
+
+    assert_eq!(response.status().as_u16(), 404);
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn suppressed_addresses_do_not_get_a_confirmation_email() {
+    let mut app = spawn_app().await;
+    app.post_suppressions(suppression("ursula_le_guin@gmail.com"))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(path("/v3.1/send"))
+        .and(method("POST"))
+        .respond_with(email_sent_response())
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_suppressed_addresses() {
+    let mut app = spawn_app().await;
+    for email in ["ursula_le_guin%40gmail.com", "octavia_butler%40gmail.com"] {
+        let links = create_subscriber(&app, email, "").await;
+        reqwest::get(links.html)
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+    app.post_suppressions(suppression("octavia_butler@gmail.com"))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(any())
+        .respond_with(email_sent_response())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = app.sent_emails().await.pop().unwrap();
+    assert_eq!(body["To"][0]["Email"], "ursula_le_guin@gmail.com");
+
+    app.drop().await;
+}
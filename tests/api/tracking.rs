@@ -0,0 +1,279 @@
+use wiremock::{matchers::any, Mock};
+
+use crate::helpers::{
+    create_confirmed_subscriber, create_list, email_sent_response, publish_issue, spawn_app,
+    TestApp,
+};
+
+const HTML: &str = "<html><body><p>Newsletter body as HTML</p></body></html>";
+
+/// Publishes an issue and returns the email sent to the email provider.
+async fn publish(app: &TestApp, list: Option<&str>, html: &str) -> serde_json::Value {
+    let _mock_guard = Mock::given(any())
+        .respond_with(email_sent_response())
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    publish_issue(
+        app,
+        serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": html,
+            },
+            "list": list,
+        }),
+    )
+    .await;
+
+    app.sent_emails().await.pop().unwrap()
+}
+
+async fn tracking_token(app: &TestApp) -> Option<String> {
+    sqlx::query!("SELECT tracking_token FROM deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the delivery.")
+        .tracking_token
+}
+
+#[tokio::test]
+async fn newsletters_embed_an_open_pixel_in_the_html_part_only() {
+    let mut app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+
+    let body = publish(&app, None, HTML).await;
+
+    let token = tracking_token(&app).await.unwrap();
+    let html = body["HtmlPart"].as_str().unwrap();
+    assert!(html.contains(&format!("/t/o/{}.gif", token)));
+    assert!(html.ends_with("</body></html>"));
+    assert_eq!(body["TextPart"], "Newsletter body as plain text");
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn opening_a_newsletter_records_every_open() {
+    let mut app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+    publish(&app, None, HTML).await;
+    let token = tracking_token(&app).await.unwrap();
+
+    for _ in 0..2 {
+        let response = app.get_open_pixel(&token).await;
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(response.headers()["Content-Type"], "image/gif");
+        assert_eq!(response.bytes().await.unwrap().len(), 43);
+    }
+
+    let opens = sqlx::query!("SELECT user_agent FROM delivery_opens")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch opens.");
+    assert_eq!(opens.len(), 2);
+    assert_eq!(
+        opens[0].user_agent.as_deref(),
+        Some("Mozilla/5.0 (test mail client)")
+    );
+
+    let delivery = sqlx::query!("SELECT issue_id, first_opened_at FROM deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the delivery.");
+    assert!(delivery.first_opened_at.is_some());
+    let stats: serde_json::Value = app
+        .get_issue_stats(&delivery.issue_id.to_string())
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(stats["opened"], 1);
+
+    let subscriber = sqlx::query!("SELECT last_engaged_at FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the subscriber.");
+    assert!(subscriber.last_engaged_at.is_some());
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn unknown_tracking_tokens_still_get_a_pixel() {
+    let mut app = spawn_app().await;
+
+    let response = app.get_open_pixel("not-a-token").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "image/gif");
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn lists_can_opt_out_of_open_tracking() {
+    let mut app = spawn_app().await;
+    create_list(&app, "rust").await;
+    create_confirmed_subscriber(&app, "ursula_le_guin%40gmail.com", "rust").await;
+
+    app.patch_list("rust", serde_json::json!({ "open_tracking": false }))
+        .await
+        .error_for_status()
+        .unwrap();
+    let body = publish(&app, Some("rust"), HTML).await;
+
+    assert!(!body["HtmlPart"].as_str().unwrap().contains("/t/o/"));
+    assert!(tracking_token(&app).await.is_none());
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn updating_an_unknown_list_returns_a_404() {
+    let mut app = spawn_app().await;
+
+    let response = app
+        .patch_list("rust", serde_json::json!({ "open_tracking": false }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 404);
+
+    app.drop().await;
+}
+
+fn tracked_links(app: &TestApp, body: &serde_json::Value) -> Vec<reqwest::Url> {
+    linkify::LinkFinder::new()
+        .links(body["HtmlPart"].as_str().unwrap())
+        .filter(|l| l.as_str().contains("/t/c/"))
+        .map(|l| {
+            let mut url = reqwest::Url::parse(l.as_str()).unwrap();
+            url.set_port(Some(app.port)).unwrap();
+            url
+        })
+        .collect()
+}
+
+async fn click(url: reqwest::Url) -> reqwest::Response {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+        .get(url)
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+#[tokio::test]
+async fn web_links_are_rewritten_to_tracked_redirects() {
+    let mut app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+
+    let body = publish(
+        &app,
+        None,
+        r#"<p><a href="https://example.com/a?x=1&amp;y=2">A</a>
+           <a class="button" HREF='https://example.com/b'>B</a>
+           <a href="https://example.com/b">B again</a>
+           <a href="mailto:editor@example.com">Write to us</a></p>"#,
+    )
+    .await;
+
+    let html = body["HtmlPart"].as_str().unwrap();
+    assert!(!html.contains("https://example.com"));
+    assert!(html.contains(r#"href="mailto:editor@example.com""#));
+    assert_eq!(tracked_links(&app, &body).len(), 3);
+    assert_eq!(body["TextPart"], "Newsletter body as plain text");
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn clicking_a_tracked_link_records_the_click_and_redirects() {
+    let mut app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+    let body = publish(
+        &app,
+        None,
+        r#"<a href="https://example.com/a?x=1&amp;y=2">A</a> <a href="https://example.com/b">B</a>"#,
+    )
+    .await;
+    let links = tracked_links(&app, &body);
+
+    for _ in 0..2 {
+        let response = click(links[0].clone()).await;
+        assert_eq!(response.status().as_u16(), 302);
+        assert_eq!(
+            response.headers()["Location"],
+            "https://example.com/a?x=1&y=2"
+        );
+    }
+
+    let issue_id = sqlx::query!("SELECT id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the issue.")
+        .id;
+    let clicks: serde_json::Value = app
+        .get_issue_links(&issue_id.to_string())
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(
+        clicks,
+        serde_json::json!([
+            {"url": "https://example.com/a?x=1&y=2", "clicks": 2, "unique_clicks": 1},
+            {"url": "https://example.com/b", "clicks": 0, "unique_clicks": 0},
+        ])
+    );
+
+    let subscriber = sqlx::query!("SELECT last_engaged_at FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the subscriber.");
+    assert!(subscriber.last_engaged_at.is_some());
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn tampered_click_tokens_are_not_redirected() {
+    let mut app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin%40gmail.com", "").await;
+    let body = publish(&app, None, r#"<a href="https://example.com/a">A</a>"#).await;
+    let mut link = tracked_links(&app, &body).pop().unwrap();
+
+    let mut tampered = link.path().to_string();
+    let last = tampered.pop().unwrap();
+    tampered.push(if last == 'A' { 'B' } else { 'A' });
+    link.set_path(&tampered);
+
+    let response = click(link).await;
+    assert_eq!(response.status().as_u16(), 404);
+    assert!(response.headers().get("Location").is_none());
+
+    let clicks = sqlx::query!("SELECT id FROM link_clicks")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch clicks.");
+    assert!(clicks.is_empty());
+
+    app.drop().await;
+}
+
+#[tokio::test]
+async fn link_clicks_for_an_unknown_issue_return_a_404() {
+    let mut app = spawn_app().await;
+
+    let response = app
+        .get_issue_links("4b2bd5e6-0a6d-4a8e-9a3b-5f0c9a6f3f2e")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 404);
+
+    app.drop().await;
+}